@@ -0,0 +1,103 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The alternate data streams of a file on NTFS, for --ads, by name, leaving
+// out the unnamed one that holds the file's usual contents. They're invisible
+// to anything that only reads directories, which is exactly why they're worth
+// looking at, since they're a favorite for hiding things in, and also where
+// Windows keeps metadata like the Zone.Identifier of downloaded files.
+#[cfg(windows)]
+pub fn streams(path: &Path) -> io::Result<Vec<OsString>> {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    // WIN32_FIND_STREAM_DATA, with its name of MAX_PATH + 36 wide chars.
+    #[repr(C)]
+    struct FindStreamData {
+        size: i64,
+        name: [u16; 296],
+    }
+
+    const FIND_STREAM_INFO_STANDARD: i32 = 0;
+    const ERROR_HANDLE_EOF: i32 = 38;
+    const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn FindFirstStreamW(
+            file_name: *const u16,
+            info_level: i32,
+            data: *mut FindStreamData,
+            flags: u32,
+        ) -> *mut c_void;
+        fn FindNextStreamW(handle: *mut c_void, data: *mut FindStreamData) -> i32;
+        fn FindClose(handle: *mut c_void) -> i32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+
+    let mut data = FindStreamData {
+        size: 0,
+        name: [0; 296],
+    };
+
+    let handle =
+        unsafe { FindFirstStreamW(wide.as_ptr(), FIND_STREAM_INFO_STANDARD, &mut data, 0) };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return match io::Error::last_os_error() {
+            // Every file has its unnamed stream, so this is a filesystem
+            // without streams at all, like FAT.
+            e if e.raw_os_error() == Some(ERROR_HANDLE_EOF) => Ok(Vec::new()),
+            e => Err(e),
+        };
+    }
+
+    // Names come as :name:$DATA, and the unnamed stream as ::$DATA. They're
+    // picked apart as they are, since they needn't be valid UTF-16.
+    let suffix: Vec<u16> = ":$DATA".encode_utf16().collect();
+    let mut streams = Vec::new();
+
+    loop {
+        let length = data
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.name.len());
+
+        if let Some(name) = data.name[..length]
+            .strip_prefix(&[b':' as u16])
+            .and_then(|name| name.strip_suffix(&suffix[..]))
+            .filter(|name| !name.is_empty())
+        {
+            streams.push(OsString::from_wide(name));
+        }
+
+        if unsafe { FindNextStreamW(handle, &mut data) } == 0 {
+            let e = io::Error::last_os_error();
+            unsafe { FindClose(handle) };
+
+            return match e.raw_os_error() {
+                Some(ERROR_HANDLE_EOF) => Ok(streams),
+                _ => Err(e),
+            };
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn streams(_: &Path) -> io::Result<Vec<OsString>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "alternate data streams are only supported on Windows",
+    ))
+}
+
+// The path a stream is opened by, and listed under, which is file:stream.
+pub fn stream_path(path: &Path, name: &OsStr) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(":");
+    path.push(name);
+    PathBuf::from(path)
+}
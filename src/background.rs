@@ -0,0 +1,62 @@
+// Lowers the priority of the whole process, for --background, as far as the
+// platform allows without special privileges: the lowest CPU priority, and
+// reads that only go through once nothing else wants the disk. It has to be
+// done before any threads are started, since on Linux both priorities are
+// really per thread, and threads only inherit them from whoever spawned them.
+#[cfg(target_os = "linux")]
+pub fn lower_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    nice();
+
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0 as libc::c_long,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        )
+    };
+
+    if result != 0 {
+        warn("the idle I/O class", std::io::Error::last_os_error());
+    }
+}
+
+// Darwin has a background mode of its own, which throttles I/O as well as
+// lowering the CPU priority.
+#[cfg(target_os = "macos")]
+pub fn lower_priority() {
+    nice();
+
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, libc::PRIO_DARWIN_BG) } != 0 {
+        warn("background mode", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+pub fn lower_priority() {
+    nice();
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() {
+    crate::log::warn("--background isn't supported on this platform, running at normal priority.");
+}
+
+#[cfg(unix)]
+fn nice() {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } != 0 {
+        warn("the lowest CPU priority", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(unix)]
+fn warn(what: &str, e: std::io::Error) {
+    crate::log::warn(format_args!(
+        "Couldn't switch to {} for --background: {}",
+        what, e
+    ));
+}
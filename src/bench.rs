@@ -0,0 +1,291 @@
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jwalk::WalkDirGeneric;
+use rayon::iter::*;
+
+use crate::diagnostic::Diagnostic;
+use crate::hashutil::*;
+use crate::output::SizeStyle;
+use crate::progress::{format_duration, Progress};
+use crate::sink::Sink;
+
+const ALGORITHMS: [(&str, HashAlgorithm); 6] = [
+    ("xxh3", HashAlgorithm::Xxh3),
+    ("md5", HashAlgorithm::Md5),
+    ("sha224", HashAlgorithm::Sha224),
+    ("sha256", HashAlgorithm::Sha256),
+    ("sha384", HashAlgorithm::Sha384),
+    ("sha512", HashAlgorithm::Sha512),
+];
+
+const BUFFER_SIZES: [(&str, usize); 6] = [
+    ("16K", 16 * 1024),
+    ("64K", 64 * 1024),
+    ("128K", 128 * 1024),
+    ("512K", 512 * 1024),
+    ("1M", 1024 * 1024),
+    ("4M", 4 * 1024 * 1024),
+];
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let started = Instant::now();
+    let result = f();
+    (result, started.elapsed())
+}
+
+fn per_second(count: u64, elapsed: Duration) -> f64 {
+    count as f64 / elapsed.as_secs_f64().max(0.000_001)
+}
+
+fn walk(dir: &str, metadata: bool) -> u64 {
+    WalkDirGeneric::<((), Option<Metadata>)>::new(dir)
+        .process_read_dir(move |_, _, _, children| {
+            if metadata {
+                children
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|entry| entry.client_state = entry.metadata().ok());
+            }
+        })
+        .into_iter()
+        .filter(Result::is_ok)
+        .count() as u64
+}
+
+// The files to hash, in walk order, up to sample_bytes of them in total.
+fn sample_files(dir: &str, sample_bytes: u64) -> (Vec<PathBuf>, u64) {
+    let mut files = Vec::new();
+    let mut bytes = 0;
+
+    for entry in WalkDirGeneric::<((), Option<Metadata>)>::new(dir)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+    {
+        if bytes >= sample_bytes {
+            break;
+        }
+
+        bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+        files.push(entry.path());
+    }
+
+    (files, bytes)
+}
+
+fn hash_all(
+    files: &[PathBuf],
+    algorithm: &HashAlgorithm,
+    read: &ReadOptions,
+    threads: usize,
+) -> Duration {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap_or_else(|e| {
+            Diagnostic::new(format!("couldn't start {} threads: {}", threads, e)).exit(1)
+        });
+
+    timed(|| {
+        pool.install(|| {
+            files.par_iter().for_each(|path| {
+                let _ = hash_file!(algorithm, path, read);
+            })
+        })
+    })
+    .1
+}
+
+// Measures what jw can do on this machine, with this directory: walking it,
+// with and without stat calls, and hashing a sample of its files with every
+// algorithm, across thread counts and read sizes. Only the first walk and read
+// are likely to hit the disk, everything after that is mostly served from the
+// page cache, which is what's worth comparing settings on.
+pub fn run(dir: &str, sample_bytes: u64) {
+    let path = Path::new(dir);
+
+    if !path.is_dir() {
+        let mut problem =
+            Diagnostic::new("isn't a directory, which is what jw bench walks and hashes")
+                .about(dir);
+
+        // A file someone wanted to know how fast it hashes is in a directory
+        // that can be measured instead.
+        if let Some(parent) = path.parent().filter(|_| path.is_file()) {
+            let parent = match parent.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => parent,
+            };
+
+            problem = problem.tip(format!(
+                "to measure the directory it's in, run jw bench {}",
+                parent.display()
+            ));
+        }
+
+        problem.exit(1);
+    }
+
+    let mut out = Sink::new(std::io::stdout().lock(), true);
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let line = |out: &mut Sink<_>, what: &str, rate: String, elapsed: Duration| {
+        writeln!(
+            out,
+            "{:<36} {:>16}  in {}",
+            what,
+            rate,
+            format_duration_fine(elapsed)
+        );
+        out.flush();
+    };
+
+    writeln!(out, "Benchmarking {} with {} CPUs.\n", dir, cpus);
+
+    let (entries, elapsed) = timed(|| walk(dir, false));
+    line(
+        &mut out,
+        "Walk, first",
+        format!("{:.0} entries/s", per_second(entries, elapsed)),
+        elapsed,
+    );
+
+    let (entries, elapsed) = timed(|| walk(dir, false));
+    line(
+        &mut out,
+        "Walk, again",
+        format!("{:.0} entries/s", per_second(entries, elapsed)),
+        elapsed,
+    );
+
+    let (entries, elapsed) = timed(|| walk(dir, true));
+    line(
+        &mut out,
+        "Walk with stat",
+        format!("{:.0} entries/s", per_second(entries, elapsed)),
+        elapsed,
+    );
+
+    let (files, bytes) = sample_files(dir, sample_bytes);
+
+    if files.is_empty() {
+        writeln!(out, "\nNo files to hash under {}.", dir);
+        return;
+    }
+
+    writeln!(
+        out,
+        "\nHashing {} files, {} in total.\n",
+        files.len(),
+        SizeStyle::Binary.format(bytes)
+    );
+
+    let mut read = ReadOptions {
+        backend: IoBackend::Std,
+        noatime: true,
+        no_cache_pollution: false,
+        direct_io: false,
+        bwlimit: None,
+        progress: Arc::new(Progress::new(true, None)),
+        buffer_size: DEFAULT_BUFFER_SIZE,
+        retries: 0,
+        rehash: 0,
+        allow_special: false,
+        mmap: true,
+    };
+
+    let rate =
+        |elapsed: Duration| SizeStyle::Binary.format(per_second(bytes, elapsed) as u64) + "/s";
+
+    let elapsed = hash_all(&files, &HashAlgorithm::Xxh3, &read, cpus);
+    line(&mut out, "xxh3, first read", rate(elapsed), elapsed);
+
+    let mut fastest_other: Option<(&str, Duration)> = None;
+
+    for (name, algorithm) in &ALGORITHMS {
+        let elapsed = hash_all(&files, algorithm, &read, cpus);
+        line(&mut out, name, rate(elapsed), elapsed);
+
+        if *name != "xxh3" && fastest_other.is_none_or(|(_, best)| elapsed < best) {
+            fastest_other = Some((name, elapsed));
+        }
+    }
+
+    writeln!(out);
+
+    // Doubling up to twice the CPUs, since with reads waiting on the disk,
+    // more threads than CPUs can still help.
+    let mut thread_counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|&n| n <= cpus * 2)
+        .collect();
+
+    if !thread_counts.contains(&cpus) {
+        thread_counts.push(cpus);
+        thread_counts.sort();
+    }
+
+    let mut best_threads = (cpus, Duration::MAX);
+
+    for threads in thread_counts {
+        let elapsed = hash_all(&files, &HashAlgorithm::Xxh3, &read, threads);
+        line(
+            &mut out,
+            &format!(
+                "xxh3, {} thread{}",
+                threads,
+                if threads == 1 { "" } else { "s" }
+            ),
+            rate(elapsed),
+            elapsed,
+        );
+
+        if elapsed < best_threads.1 {
+            best_threads = (threads, elapsed);
+        }
+    }
+
+    writeln!(out);
+
+    let mut best_buffer = ("128K", Duration::MAX);
+
+    for (name, size) in BUFFER_SIZES {
+        read.buffer_size = size;
+        let elapsed = hash_all(&files, &HashAlgorithm::Xxh3, &read, best_threads.0);
+        line(
+            &mut out,
+            &format!("xxh3, best thread count, {} reads", name),
+            rate(elapsed),
+            elapsed,
+        );
+
+        if elapsed < best_buffer.1 {
+            best_buffer = (name, elapsed);
+        }
+    }
+
+    writeln!(
+        out,
+        "\nRecommended: jw -c -j {} --buffer-size {}",
+        best_threads.0, best_buffer.0
+    );
+
+    if let Some((name, _)) = fastest_other {
+        writeln!(
+            out,
+            "Other than xxh3, {} was the fastest algorithm, with -C {}.",
+            name, name
+        );
+    }
+}
+
+// Benchmarks are mostly well under a second, where whole seconds say nothing.
+fn format_duration_fine(duration: Duration) -> String {
+    if duration < Duration::from_secs(60) {
+        format!("{:.3}s", duration.as_secs_f64())
+    } else {
+        format_duration(duration)
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::hashutil::*;
+use crate::manifest;
+use crate::mutate;
+
+// A content addressed store, for --cas-export: every file goes in under its
+// own hash, as <dir>/<first two digits>/<hash>, so a file whose contents are
+// already in there takes up no more space, no matter how many times or under
+// how many names it's exported. What was exported under which path is
+// appended to <dir>/manifest, with lines just like --checksum prints.
+#[derive(Debug)]
+pub struct Store {
+    root: PathBuf,
+    link: bool,
+    algorithm: HashAlgorithm,
+    // Not opened at all with --dry-run.
+    manifest: Option<Mutex<BufWriter<File>>>,
+    pub stored: AtomicUsize,
+    pub stored_bytes: AtomicU64,
+    pub present: AtomicUsize,
+    // Two files with the same contents can be on their way into the store at
+    // the same moment, so each copy gets a temporary name of its own.
+    copies: AtomicUsize,
+}
+
+impl Store {
+    pub fn open(root: &Path, link: bool, algorithm: HashAlgorithm) -> io::Result<Self> {
+        mutate::create_dir_all(root)?;
+
+        let manifest = mutate::perform(
+            || Ok(None),
+            || {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(root.join("manifest"))
+                    .map(|file| Some(Mutex::new(BufWriter::new(file))))
+            },
+        )?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            link,
+            algorithm,
+            manifest,
+            stored: AtomicUsize::new(0),
+            stored_bytes: AtomicU64::new(0),
+            present: AtomicUsize::new(0),
+            copies: AtomicUsize::new(0),
+        })
+    }
+
+    fn object(&self, hash: &str) -> PathBuf {
+        self.root.join(&hash[..2.min(hash.len())]).join(hash)
+    }
+
+    // Puts the file that hashed to the given hash in the store, unless it's
+    // there already, and adds it to the manifest.
+    pub fn add(&self, path: &Path, hash: &str, read: &ReadOptions) -> io::Result<()> {
+        let object = self.object(hash);
+
+        if object.symlink_metadata().is_ok() {
+            self.present.fetch_add(1, Ordering::Relaxed);
+        } else {
+            mutate::create_dir_all(object.parent().unwrap())?;
+
+            let bytes = mutate::perform(
+                || Ok(if self.link { 0 } else { path.metadata()?.len() }),
+                || match self.link {
+                    true => self.link_in(path, &object, hash, read),
+                    false => self.copy_in(path, &object, hash, read),
+                },
+            )?;
+
+            self.stored.fetch_add(1, Ordering::Relaxed);
+            self.stored_bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+
+        match &self.manifest {
+            Some(file) => {
+                let line = manifest::line(hash, path.as_os_str().as_encoded_bytes());
+                file.lock().unwrap().write_all(line.as_bytes())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // With --cas-link, a hard link rather than a copy, which takes no space at
+    // all, but is only as safe as the original is from being written to, since
+    // they're the same file. Failing that, on another filesystem say, it's
+    // copied after all.
+    fn link_in(
+        &self,
+        path: &Path,
+        object: &Path,
+        hash: &str,
+        read: &ReadOptions,
+    ) -> io::Result<u64> {
+        match std::fs::hard_link(path, object) {
+            Ok(()) => Ok(0),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(0),
+            Err(_) => self.copy_in(path, object, hash, read),
+        }
+    }
+
+    // The copy is hashed again before it's renamed into place, since the file
+    // could have been written to since it was hashed, and an object that isn't
+    // what its name says would be worse than none.
+    fn copy_in(
+        &self,
+        path: &Path,
+        object: &Path,
+        hash: &str,
+        read: &ReadOptions,
+    ) -> io::Result<u64> {
+        let temporary = object.with_file_name(format!(
+            ".{}.jw-{}-{}",
+            hash,
+            std::process::id(),
+            self.copies.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let copied = std::fs::copy(path, &temporary).and_then(|bytes| {
+            if hash_file!(&self.algorithm, &temporary, read)? != hash {
+                return Err(io::Error::other(
+                    "changed between being hashed and being copied into the store",
+                ));
+            }
+
+            std::fs::rename(&temporary, object)?;
+            Ok(bytes)
+        });
+
+        if copied.is_err() {
+            let _ = std::fs::remove_file(&temporary);
+        }
+
+        copied
+    }
+
+    pub fn finish(&self) -> io::Result<()> {
+        match &self.manifest {
+            Some(manifest) => manifest.lock().unwrap().flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use memmap2::Mmap;
+
+use crate::hashutil::*;
+use crate::manifest;
+
+// For --checkpoint, a manifest that's written to as files are hashed, rather
+// than once they all have been, so that a run which is interrupted, or killed,
+// can be picked up where it left off by giving it the same file again. Files
+// that are in it already are skipped, and whatever hashes next is appended, so
+// once a run does get to the end, it holds the manifest of the whole thing.
+#[derive(Debug)]
+pub struct Checkpoint {
+    done: HashSet<PathBuf>,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl Checkpoint {
+    pub fn open(path: &Path, algorithm: &HashAlgorithm) -> io::Result<Self> {
+        let digest_length = algorithm.digest_size() * 2;
+
+        let mut manifest = match std::fs::read(path) {
+            Ok(manifest) => manifest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        // Being killed halfway through a line leaves the rest of it missing,
+        // and that file is hashed again.
+        let complete = manifest.iter().rposition(|&byte| byte == b'\n');
+        manifest.truncate(complete.map_or(0, |end| end + 1));
+
+        let done = manifest
+            .split(|&byte| byte == b'\n')
+            .filter_map(|line| manifest::parse(line, digest_length))
+            .filter(|(_, path)| !path.is_empty())
+            .map(|(_, path)| crate::path_from_bytes(&path))
+            .collect();
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+
+        file.set_len(manifest.len() as u64)?;
+        io::Seek::seek(&mut file, io::SeekFrom::End(0))?;
+
+        Ok(Self {
+            done,
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn done(&self, path: &Path) -> bool {
+        self.done.contains(path)
+    }
+
+    // A file and its forks are written together, so that either all of them
+    // are in the checkpoint or none are.
+    pub fn record<'a>(&self, lines: impl Iterator<Item = (&'a Path, &'a str)>) -> io::Result<()> {
+        let buffer: String = lines
+            .map(|(path, hash)| manifest::line(hash, path.as_os_str().as_encoded_bytes()))
+            .collect();
+
+        self.file.lock().unwrap().write_all(buffer.as_bytes())
+    }
+
+    pub fn finish(&self) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.flush()?;
+        file.get_ref().sync_all()
+    }
+}
+
+// For jw verify, the manifest it hashes the directories into, which nothing
+// else gets a hand in: it's always created anew, rather than opened through
+// whatever's at its path in the temp directory already, which could be a link
+// to anywhere. On Unix it's unlinked as soon as it's been created, the same as
+// the runs spilled when sorting, and elsewhere it's removed once it's dropped.
+#[derive(Debug)]
+pub struct Scratch {
+    file: File,
+    #[cfg(not(unix))]
+    path: PathBuf,
+}
+
+impl Scratch {
+    pub fn create() -> io::Result<Self> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "jw-verify-{}-{}",
+                std::process::id(),
+                CREATED.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            let file = match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            };
+
+            #[cfg(unix)]
+            std::fs::remove_file(&path)?;
+
+            return Ok(Self {
+                file,
+                #[cfg(not(unix))]
+                path,
+            });
+        }
+    }
+
+    // Nothing's been hashed into it yet, so there's nothing to skip.
+    pub fn checkpoint(&self) -> io::Result<Checkpoint> {
+        Ok(Checkpoint {
+            done: HashSet::new(),
+            file: Mutex::new(BufWriter::new(self.file.try_clone()?)),
+        })
+    }
+
+    // Once the checkpoint's been finished, and nothing's being written to it.
+    pub fn map(&self) -> io::Result<Mmap> {
+        unsafe { Mmap::map(&self.file) }
+    }
+}
+
+#[cfg(not(unix))]
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+use crate::record::Record;
+
+// What dircolors hands out when LS_COLORS isn't set.
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:or=40;31;01:mi=00:pi=40;33:so=01;35:\
+                                 do=01;35:bd=40;33;01:cd=40;33;01:su=37;41:sg=30;43:\
+                                 tw=30;42:ow=34;42:st=37;44:ex=01;32";
+
+#[derive(Debug, Clone)]
+pub struct LsColors {
+    types: HashMap<String, String>,
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    pub fn from_env() -> Self {
+        let spec = std::env::var("LS_COLORS")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| DEFAULT_LS_COLORS.to_string());
+
+        let mut colors = Self {
+            types: HashMap::new(),
+            suffixes: Vec::new(),
+        };
+
+        for (key, code) in spec.split(':').filter_map(|pair| pair.split_once('=')) {
+            if let Some(suffix) = key.strip_prefix('*') {
+                colors
+                    .suffixes
+                    .push((suffix.to_lowercase(), code.to_string()));
+            } else {
+                colors.types.insert(key.to_string(), code.to_string());
+            }
+        }
+
+        // Longest suffix first, so *.tar.gz wins over *.gz
+        colors
+            .suffixes
+            .sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        colors
+    }
+
+    fn code(&self, record: &Record) -> Option<&str> {
+        let file_type = record.entry.file_type();
+        let get = |key: &str| self.types.get(key).map(String::as_str);
+
+        if file_type.is_symlink() {
+            return if std::fs::metadata(record.path()).is_err() {
+                get("or").or_else(|| get("ln"))
+            } else {
+                get("ln")
+            };
+        }
+
+        let mode = record.mode().unwrap_or(0);
+
+        if file_type.is_dir() {
+            return match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => get("tw"),
+                (false, true) => get("ow"),
+                (true, false) => get("st"),
+                (false, false) => None,
+            }
+            .or_else(|| get("di"));
+        }
+
+        if !file_type.is_file() {
+            return special_code(self, file_type);
+        }
+
+        if mode & 0o4000 != 0 {
+            if let Some(code) = get("su") {
+                return Some(code);
+            }
+        }
+
+        if mode & 0o2000 != 0 {
+            if let Some(code) = get("sg") {
+                return Some(code);
+            }
+        }
+
+        if mode & 0o111 != 0 {
+            if let Some(code) = get("ex") {
+                return Some(code);
+            }
+        }
+
+        let name = record.entry.file_name().to_string_lossy().to_lowercase();
+
+        self.suffixes
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .map(|(_, code)| code.as_str())
+            .or_else(|| get("fi"))
+    }
+
+    pub fn paint(&self, record: &Record, text: &str) -> String {
+        match self.code(record).filter(|code| !code.is_empty()) {
+            Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn special_code(colors: &LsColors, file_type: std::fs::FileType) -> Option<&str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let key = if file_type.is_fifo() {
+        "pi"
+    } else if file_type.is_socket() {
+        "so"
+    } else if file_type.is_block_device() {
+        "bd"
+    } else if file_type.is_char_device() {
+        "cd"
+    } else {
+        return None;
+    };
+
+    colors.types.get(key).map(String::as_str)
+}
+
+#[cfg(not(unix))]
+fn special_code(_colors: &LsColors, _file_type: std::fs::FileType) -> Option<&str> {
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl From<&String> for ColorChoice {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "auto" => Self::Auto,
+            "always" => Self::Always,
+            "never" => Self::Never,
+            _ => panic!("Invalid color choice! '{}'", s),
+        }
+    }
+}
+
+impl ColorChoice {
+    pub fn resolve(&self) -> Option<LsColors> {
+        let enabled = match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        };
+
+        enabled.then(LsColors::from_env)
+    }
+}
@@ -0,0 +1,644 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::{Arg, ArgAction, Command};
+
+use crate::diagnostic::{self, Diagnostic};
+
+// A setting from the config file, which is any of the long options, written
+// without the --, e.g. `checksum-with = "sha256"`, `threads = 8`, `exclude =
+// ["dot", "other"]`, or `stats = true`. Booleans are kept apart from strings,
+// since only they make sense for flags.
+#[derive(Debug, Clone, PartialEq)]
+struct Setting {
+    line: usize,
+    key: String,
+    values: Vec<String>,
+    boolean: bool,
+}
+
+// What the settings set, so that the few places that tell an option left at
+// its default from one that was given can count these as given.
+static GIVEN: OnceLock<HashSet<String>> = OnceLock::new();
+
+pub fn given(id: &str) -> bool {
+    GIVEN.get().is_some_and(|given| given.contains(id))
+}
+
+// The file they were read from, for --explain to say which it was.
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn file() -> Option<&'static Path> {
+    FILE.get().map(PathBuf::as_path)
+}
+
+// ~/.config/jw/config.toml, or wherever XDG_CONFIG_HOME points instead, and
+// %APPDATA%\jw\config.toml on Windows.
+fn default_path() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let dir = std::env::var_os("APPDATA").map(PathBuf::from);
+
+    #[cfg(not(windows))]
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    dir.map(|dir| dir.join("jw").join("config.toml"))
+}
+
+// --config has to be known before the arguments are parsed, since what's in
+// the file changes how they are, so it's looked for by hand. The last one
+// wins, the same as it would for clap, and nothing after a -- is an option.
+// Without one, JW_CONFIG is next in line.
+fn given_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut path = None;
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+
+        if arg == "--config" {
+            path = args.next().map(PathBuf::from);
+        } else if let Some(value) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            path = Some(PathBuf::from(value));
+        }
+    }
+
+    path.or_else(|| {
+        std::env::var_os("JW_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+// Every setting becomes the default value of the option it names, so that
+// anything given on the command line still takes precedence, and a default
+// algorithm doesn't mean every run hashes, the way -C on its own does.
+// Without --config, a missing file is the same as an empty one.
+pub fn apply(mut command: Command) -> Command {
+    let args: Vec<OsString> = std::env::args_os().collect();
+
+    let (path, required) = match given_path(&args) {
+        Some(path) => (path, true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return command,
+        },
+    };
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return command,
+        Err(e) => Diagnostic::new(format!("couldn't be read as a config file: {}", e))
+            .about(path.display())
+            .exit(2),
+    };
+
+    let configured = |line: usize, e: String| {
+        Diagnostic::new(format!("line {}: {}", line, e)).about(path.display())
+    };
+
+    let settings = parse(&text).unwrap_or_else(|(line, e)| configured(line, e).exit(2));
+
+    let mut given = HashSet::new();
+
+    for setting in settings {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(setting.key.as_str()))
+            .filter(|arg| arg.get_long() != Some("config"))
+            .cloned()
+        else {
+            let problem = configured(
+                setting.line,
+                format!("{} isn't one of the options", setting.key),
+            );
+
+            let options = command
+                .get_arguments()
+                .filter_map(Arg::get_long)
+                .filter(|long| *long != "config");
+
+            match diagnostic::closest(setting.key.trim_start_matches('-'), options) {
+                Some(closest) if closest != setting.key => {
+                    problem.tip(format!("did you mean {}?", closest)).exit(2)
+                }
+                _ => problem
+                    .tip("settings are the long options, without their --, e.g. threads = 8")
+                    .exit(2),
+            }
+        };
+
+        check(&arg, &setting).unwrap_or_else(|e| configured(setting.line, e).exit(2));
+
+        given.insert(arg.get_id().to_string());
+
+        // clap only takes defaults that live as long as the program does,
+        // which these do anyway, being read once at the start.
+        let values: Vec<&'static str> = setting
+            .values
+            .iter()
+            .map(|value| &*value.clone().leak())
+            .collect();
+
+        command = command.mut_arg(arg.get_id().clone(), |arg| arg.default_values(values));
+    }
+
+    let _ = GIVEN.set(given);
+    let _ = FILE.set(path);
+    command
+}
+
+// Defaults that wouldn't parse are a panic in clap, rather than an error, so
+// each value is tried out on an option of its own first, with the same
+// parser, which says what's wrong with it the same way it would have been
+// said for the command line.
+fn check(arg: &Arg, setting: &Setting) -> Result<(), String> {
+    let flag = matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse);
+
+    if flag != setting.boolean {
+        return Err(match flag {
+            true => format!("{} is a flag, so it's either true or false", setting.key),
+            false => format!("{} takes a value, rather than true or false", setting.key),
+        });
+    }
+
+    if flag {
+        return Ok(());
+    }
+
+    let many = matches!(arg.get_action(), ArgAction::Append)
+        || arg
+            .get_num_args()
+            .is_some_and(|range| range.max_values() > 1);
+
+    if setting.values.len() != 1 && !many {
+        return Err(format!(
+            "{} takes a single value, rather than a list",
+            setting.key
+        ));
+    }
+
+    let mut trial = Arg::new("trial")
+        .long(&*setting.key.clone().leak())
+        .action(ArgAction::Append)
+        .value_parser(arg.get_value_parser().clone());
+
+    if let Some(names) = arg.get_value_names() {
+        trial = trial.value_name(names[0].clone());
+    }
+
+    let args = std::iter::once("jw".to_string()).chain(
+        setting
+            .values
+            .iter()
+            .map(|value| format!("--{}={}", setting.key, value)),
+    );
+
+    Command::new("jw")
+        .arg(trial)
+        .try_get_matches_from(args)
+        .map(|_| ())
+        .map_err(|e| {
+            let e = e.to_string();
+            let e = e.lines().next().unwrap_or_default();
+            e.strip_prefix("error: ").unwrap_or(e).to_string()
+        })
+}
+
+// The part of TOML that settings need: key = value pairs, one to a line,
+// where a value is a string, either "basic" or 'literal', a number, a
+// boolean, or an array of those, which can go on for as many lines as it
+// likes. Tables are left out, since every setting is at the top level.
+fn parse(text: &str) -> Result<Vec<Setting>, (usize, String)> {
+    let mut settings: Vec<Setting> = Vec::new();
+    let mut cursor = Cursor {
+        rest: text,
+        line: 1,
+    };
+
+    loop {
+        cursor.skip_blank(true);
+
+        if cursor.rest.is_empty() {
+            return Ok(settings);
+        }
+
+        let line = cursor.line;
+        let fail = |e: String| (line, e);
+
+        if cursor.rest.starts_with('[') {
+            return Err(fail(
+                "tables aren't supported; every setting goes at the top level".to_string(),
+            ));
+        }
+
+        let key = cursor.key().map_err(fail)?;
+
+        cursor.skip_blank(false);
+
+        if !cursor.eat('=') {
+            return Err(fail(format!("expected = after {}", key)));
+        }
+
+        cursor.skip_blank(false);
+
+        let (values, boolean) = match cursor.rest.starts_with('[') {
+            true => (cursor.array()?, false),
+            false => {
+                let (value, boolean) = cursor.scalar().map_err(fail)?;
+                (vec![value], boolean)
+            }
+        };
+
+        cursor.skip_blank(false);
+
+        if !cursor.rest.is_empty() && !cursor.eat('\n') {
+            return Err(fail(format!(
+                "expected the line to end after the value of {}",
+                key
+            )));
+        }
+
+        if settings.iter().any(|setting| setting.key == key) {
+            return Err(fail(format!("{} is set more than once", key)));
+        }
+
+        settings.push(Setting {
+            line,
+            key,
+            values,
+            boolean,
+        });
+    }
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                self.line += (c == '\n') as usize;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Spaces, tabs and comments, and newlines as well, if they're allowed
+    // where the cursor is.
+    fn skip_blank(&mut self, newlines: bool) {
+        loop {
+            let trimmed = self.rest.trim_start_matches([' ', '\t', '\r']);
+            self.rest = trimmed;
+
+            if self.rest.starts_with('#') {
+                self.rest = &self.rest[self.rest.find('\n').unwrap_or(self.rest.len())..];
+            } else if !(newlines && self.eat('\n')) {
+                return;
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        if self.rest.starts_with(['"', '\'']) {
+            return self.string();
+        }
+
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(self.rest.len());
+
+        if end == 0 {
+            return Err("expected the name of a setting".to_string());
+        }
+
+        let (key, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Ok(key.to_string())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.rest.starts_with("\"\"\"") || self.rest.starts_with("'''") {
+            return Err("multi-line strings aren't supported".to_string());
+        }
+
+        // Literal strings are taken as they are, which is the easier way of
+        // writing Windows paths and --filter globs.
+        if let Some(rest) = self.rest.strip_prefix('\'') {
+            let end = rest
+                .find(['\'', '\n'])
+                .filter(|&end| rest[end..].starts_with('\''))
+                .ok_or("a string is missing its closing '")?;
+
+            self.rest = &rest[end + 1..];
+            return Ok(rest[..end].to_string());
+        }
+
+        let mut chars = self.rest[1..].char_indices().peekable();
+        let mut string = String::new();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 2..];
+                    return Ok(string);
+                }
+                '\n' => break,
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(u @ ('u' | 'U')) => {
+                            let wanted = if u == 'u' { 4 } else { 8 };
+
+                            // Only the hex digits are taken, so that one cut
+                            // short, like \u00", doesn't take the quote and
+                            // the rest of the line with it.
+                            let digits: String = (0..wanted)
+                                .map_while(|_| chars.next_if(|(_, c)| c.is_ascii_hexdigit()))
+                                .map(|(_, c)| c)
+                                .collect();
+
+                            u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == wanted)
+                                .and_then(char::from_u32)
+                                .ok_or(format!("\\{}{} isn't a character", u, digits))?
+                        }
+                        Some(c) => return Err(format!("\\{} isn't an escape", c)),
+                        None => break,
+                    };
+
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+
+        Err("a string is missing its closing \"".to_string())
+    }
+
+    // Anything that isn't a string is a bare word, which has to be a number
+    // or a boolean, and is passed on the way it's written, apart from the
+    // underscores TOML allows between digits.
+    fn scalar(&mut self) -> Result<(String, bool), String> {
+        if self.rest.starts_with(['"', '\'']) {
+            return Ok((self.string()?, false));
+        }
+
+        let end = self
+            .rest
+            .find([',', ']', '#', '\n', ' ', '\t', '\r'])
+            .unwrap_or(self.rest.len());
+
+        let (word, rest) = self.rest.split_at(end);
+
+        let value = match word {
+            "true" | "false" => {
+                self.rest = rest;
+                return Ok((word.to_string(), true));
+            }
+            word if word.parse::<f64>().is_ok() || word.replace('_', "").parse::<i64>().is_ok() => {
+                word.replace('_', "")
+            }
+            "" => return Err("expected a value".to_string()),
+            word => {
+                return Err(format!(
+                    "{} isn't a string, a number, or a boolean; strings are quoted",
+                    word
+                ))
+            }
+        };
+
+        self.rest = rest;
+        Ok((value, false))
+    }
+
+    fn array(&mut self) -> Result<Vec<String>, (usize, String)> {
+        let mut values = Vec::new();
+
+        self.eat('[');
+
+        loop {
+            self.skip_blank(true);
+
+            if self.eat(']') {
+                return Ok(values);
+            }
+
+            let line = self.line;
+
+            if self.rest.is_empty() {
+                return Err((line, "an array is missing its closing ]".to_string()));
+            }
+
+            match self.scalar().map_err(|e| (line, e))? {
+                (_, true) => {
+                    return Err((line, "arrays are only for strings and numbers".to_string()))
+                }
+                (value, false) => values.push(value),
+            }
+
+            self.skip_blank(true);
+
+            if !self.eat(',') && !self.rest.starts_with(']') {
+                return Err((
+                    self.line,
+                    "expected a , or a ] after a value in an array".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<(String, Vec<String>, bool)> {
+        parse(text)
+            .unwrap()
+            .into_iter()
+            .map(|setting| (setting.key, setting.values, setting.boolean))
+            .collect()
+    }
+
+    fn value(text: &str) -> String {
+        let mut settings = values(text);
+        assert_eq!(settings.len(), 1, "{:?} isn't one setting", text);
+        let (_, mut values, _) = settings.remove(0);
+        assert_eq!(values.len(), 1, "{:?} isn't one value", text);
+        values.remove(0)
+    }
+
+    fn error(text: &str) -> (usize, String) {
+        parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn basic_strings() {
+        assert_eq!(value(r#"checksum-with = "sha256""#), "sha256");
+        assert_eq!(value(r#"name = """#), "");
+        assert_eq!(value(r#"name = "two words""#), "two words");
+        assert_eq!(value(r#""quoted-key" = "x""#), "x");
+        assert_eq!(value("name = \"\u{e9}t\u{e9}\""), "\u{e9}t\u{e9}");
+    }
+
+    #[test]
+    fn literal_strings() {
+        assert_eq!(value(r"path = 'C:\dir\file'"), r"C:\dir\file");
+        assert_eq!(value(r#"filter = '*."quoted"'"#), r#"*."quoted""#);
+        assert_eq!(value("name = ''"), "");
+        assert_eq!(
+            error("name = 'open\nmore = 1"),
+            (1, "a string is missing its closing '".to_string())
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(value(r#"name = "a\\b\"c\"""#), r#"a\b"c""#);
+        assert_eq!(value(r#"name = "\n\t\r""#), "\n\t\r");
+        assert_eq!(value(r#"name = "\u00e9""#), "\u{e9}");
+        assert_eq!(value(r#"name = "\U0001F600""#), "\u{1f600}");
+
+        assert_eq!(error(r#"name = "\q""#).1, "\\q isn't an escape");
+        assert_eq!(error(r#"name = "\uD800""#).1, "\\uD800 isn't a character");
+        assert_eq!(error(r#"name = "\u00zz""#).1, "\\u00 isn't a character");
+    }
+
+    #[test]
+    fn truncated_escapes_stop_at_the_quote() {
+        assert_eq!(error("name = \"\\u00\"\n").1, "\\u00 isn't a character");
+        assert_eq!(error("name = \"\\U12\"\n").1, "\\U12 isn't a character");
+        assert_eq!(error("name = \"\\u\"").1, "\\u isn't a character");
+        assert_eq!(error("name = \"\\").1, "a string is missing its closing \"");
+    }
+
+    #[test]
+    fn numbers_and_booleans() {
+        assert_eq!(
+            values("threads = 8\nsize = 1_000\nratio = 0.5\nstats = true\nquiet = false"),
+            vec![
+                ("threads".to_string(), vec!["8".to_string()], false),
+                ("size".to_string(), vec!["1000".to_string()], false),
+                ("ratio".to_string(), vec!["0.5".to_string()], false),
+                ("stats".to_string(), vec!["true".to_string()], true),
+                ("quiet".to_string(), vec!["false".to_string()], true),
+            ]
+        );
+
+        assert_eq!(
+            error("name = sha256").1,
+            "sha256 isn't a string, a number, or a boolean; strings are quoted"
+        );
+        assert_eq!(error("name =").1, "expected a value");
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            values(r#"exclude = ["dot", 'other', 3]"#),
+            vec![(
+                "exclude".to_string(),
+                vec!["dot".to_string(), "other".to_string(), "3".to_string()],
+                false
+            )]
+        );
+
+        let text = "exclude = [\n  \"dot\", # hidden files\n\n  \"other\",\n]\nthreads = 2";
+        let settings = parse(text).unwrap();
+        assert_eq!(settings[0].values, ["dot", "other"]);
+        assert_eq!((settings[1].line, settings[1].key.as_str()), (6, "threads"));
+
+        assert_eq!(values("exclude = []")[0].1, Vec::<String>::new());
+        assert_eq!(
+            error("exclude = [\"a\"\n\"b\"]"),
+            (
+                2,
+                "expected a , or a ] after a value in an array".to_string()
+            )
+        );
+        assert_eq!(
+            error("exclude = [\"a\",\n"),
+            (2, "an array is missing its closing ]".to_string())
+        );
+        assert_eq!(
+            error("exclude = [true]").1,
+            "arrays are only for strings and numbers"
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let text = "# jw's settings\n\n\r\nthreads = 4 # a comment\n\t# indented\nstats = true\n";
+        let settings = parse(text).unwrap();
+        assert_eq!(settings.len(), 2);
+        assert_eq!((settings[0].line, settings[0].values[0].as_str()), (4, "4"));
+        assert_eq!((settings[1].line, settings[1].key.as_str()), (6, "stats"));
+
+        assert_eq!(
+            value(r##"name = "# isn't a comment""##),
+            "# isn't a comment"
+        );
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("# nothing but a comment").unwrap().is_empty());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(
+            error("threads = 4\nstats = true\nthreads = 8"),
+            (3, "threads is set more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert_eq!(
+            error("threads = 4\n[table]"),
+            (
+                2,
+                "tables aren't supported; every setting goes at the top level".to_string()
+            )
+        );
+        assert_eq!(
+            error("\nthreads 4"),
+            (2, "expected = after threads".to_string())
+        );
+        assert_eq!(
+            error("= 4"),
+            (1, "expected the name of a setting".to_string())
+        );
+        assert_eq!(
+            error("threads = 4 8"),
+            (
+                1,
+                "expected the line to end after the value of threads".to_string()
+            )
+        );
+        assert_eq!(
+            error(r#"name = """multi""""#),
+            (1, "multi-line strings aren't supported".to_string())
+        );
+        assert_eq!(
+            error("name = \"open\nthreads = 4"),
+            (1, "a string is missing its closing \"".to_string())
+        );
+    }
+}
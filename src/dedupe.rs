@@ -0,0 +1,381 @@
+use std::cmp::Reverse;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::dupes::{Candidate, Group};
+use crate::mutate;
+use crate::output::SizeStyle;
+use crate::sink::Sink;
+use crate::stats::Errors;
+use crate::verify::Verify;
+
+// What --dupes does about each duplicate, other than listing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Hardlink,
+    Reflink,
+    Delete,
+    // Delete, but with --trash, into the trash instead.
+    Trash,
+}
+
+impl From<&String> for Action {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "hardlink" => Self::Hardlink,
+            "reflink" => Self::Reflink,
+            "delete" => Self::Delete,
+            _ => panic!("Invalid action! '{}'", s),
+        }
+    }
+}
+
+impl Action {
+    fn verb(&self, done: bool) -> &'static str {
+        match (self, done) {
+            (Self::Hardlink, false) => "Would link",
+            (Self::Hardlink, true) => "Linked",
+            (Self::Reflink, false) => "Would dedupe",
+            (Self::Reflink, true) => "Deduped",
+            (Self::Delete, false) => "Would delete",
+            (Self::Delete, true) => "Deleted",
+            (Self::Trash, false) => "Would trash",
+            (Self::Trash, true) => "Trashed",
+        }
+    }
+
+    fn line(&self, done: bool, duplicate: &Path, kept: &Path) -> String {
+        let verb = self.verb(done).to_lowercase();
+
+        match self {
+            Self::Hardlink => format!("{} {} to {}", verb, duplicate.display(), kept.display()),
+            Self::Reflink => format!(
+                "{} {} against {}",
+                verb,
+                duplicate.display(),
+                kept.display()
+            ),
+            Self::Delete | Self::Trash => format!(
+                "{} {}, keeping {}",
+                verb,
+                duplicate.display(),
+                kept.display()
+            ),
+        }
+    }
+}
+
+// Which copy of each group is the one that's kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keep {
+    // The one under the root given first, and of those, the first by path.
+    FirstRoot,
+    Oldest,
+    Newest,
+}
+
+impl From<&String> for Keep {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "first-root" => Self::FirstRoot,
+            "oldest" => Self::Oldest,
+            "newest" => Self::Newest,
+            _ => panic!("Invalid keep policy! '{}'", s),
+        }
+    }
+}
+
+impl Keep {
+    // Ties go to the first by path, as do files without an mtime, so the same
+    // copy is picked every time.
+    fn pick<'a>(&self, group: &'a Group) -> &'a Candidate {
+        let by_root = |file: &&Candidate| (file.root, file.path.clone());
+
+        match self {
+            Self::FirstRoot => group.files.iter().min_by_key(by_root),
+            Self::Oldest => group
+                .files
+                .iter()
+                .min_by_key(|file| (file.mtime.is_none(), file.mtime, file.path.clone())),
+            Self::Newest => group
+                .files
+                .iter()
+                .min_by_key(|file| (Reverse(file.mtime), file.path.clone())),
+        }
+        .unwrap()
+    }
+}
+
+// How many duplicates were, or would have been, taken care of.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Outcome {
+    pub files: usize,
+    pub bytes: u64,
+    pub failed: usize,
+}
+
+impl Outcome {
+    pub fn describe(&self, action: Action, done: bool, size_style: SizeStyle) -> String {
+        let mut text = format!(
+            "{} {} files, reclaiming {}.",
+            action.verb(done),
+            self.files,
+            size_style.format(self.bytes)
+        );
+
+        if self.failed > 0 {
+            let _ = write!(text, " Failed on {} files.", self.failed);
+        }
+
+        if mutate::dry_run() {
+            text.push_str(" Nothing was changed, because of --dry-run.");
+        } else if !done {
+            text.push_str(" Nothing was changed; run again with --yes to go through with it.");
+        }
+
+        text
+    }
+}
+
+// One copy of every group is kept, and every other one is acted on, with a
+// line each for what happened to it, or without --yes, only what would have.
+// Anything that fails is counted as an error, and the rest carry on. With
+// --verify-after, a duplicate that's been linked is read back through its own
+// name, and has to hash the same as the group did, which has to be xxh3 since
+// that's what groups are found by.
+pub fn apply<W: io::Write>(
+    groups: &[Group],
+    action: Action,
+    keep: Keep,
+    execute: bool,
+    verify: Option<&Verify>,
+    errors: &Errors,
+    out: &mut Sink<W>,
+) -> Outcome {
+    let mut outcome = Outcome::default();
+
+    for group in groups {
+        let kept = keep.pick(group);
+
+        for duplicate in group.files.iter().filter(|file| file.path != kept.path) {
+            let (kept, duplicate) = (&kept.path, &duplicate.path);
+
+            let result = mutate::perform(
+                || Ok(()),
+                || match action {
+                    _ if !execute => Ok(()),
+                    Action::Hardlink => hardlink(kept, duplicate),
+                    Action::Reflink => reflink(kept, duplicate),
+                    Action::Delete => delete(kept, duplicate, false),
+                    Action::Trash => delete(kept, duplicate, true),
+                },
+            )
+            .and_then(|_| match (verify, action) {
+                (Some(verify), Action::Hardlink | Action::Reflink) if execute => {
+                    verify.check(duplicate, &group.hash)
+                }
+                _ => Ok(()),
+            });
+
+            match result {
+                Ok(()) => {
+                    outcome.files += 1;
+                    outcome.bytes += group.size;
+
+                    writeln!(out, "{}", action.line(execute, duplicate, kept));
+                }
+                Err(e) => {
+                    outcome.failed += 1;
+                    errors.add(Some(duplicate), e);
+                }
+            }
+        }
+    }
+
+    outcome
+}
+
+// Only once the copy that's kept is known to still be there, and the same.
+fn delete(kept: &Path, duplicate: &Path, trash: bool) -> io::Result<()> {
+    if !same_contents(kept, duplicate)? {
+        return Err(changed(kept));
+    }
+
+    match trash {
+        true => crate::trash::trash(duplicate),
+        false => std::fs::remove_file(duplicate),
+    }
+}
+
+// Replaces the duplicate with a link to the copy that's kept, so it's the kept
+// copy's inode, with its own mtime, permissions and owner, that both names end
+// up pointing to. The link is made under a temporary name next to the
+// duplicate first, and then renamed over it, so there's never a moment where
+// the duplicate's name doesn't exist. The two are compared byte for byte just
+// before, since a hash match is only as good as the hash, and either could
+// have changed since they were hashed.
+fn hardlink(kept: &Path, duplicate: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if kept.symlink_metadata()?.dev() != duplicate.symlink_metadata()?.dev() {
+            return Err(io::Error::other(format!(
+                "on a different filesystem from {}",
+                kept.display()
+            )));
+        }
+    }
+
+    if !same_contents(kept, duplicate)? {
+        return Err(changed(kept));
+    }
+
+    let temporary = temporary_name(duplicate);
+    std::fs::hard_link(kept, &temporary)?;
+
+    std::fs::rename(&temporary, duplicate).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+// struct file_dedupe_range from linux/fs.h, with room for the one destination
+// it's ever given here.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct DedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+// _IOWR(0x94, 54, struct file_dedupe_range), which libc doesn't have.
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: libc::c_ulong = 0xc018_9436;
+
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+// How much the kernel is asked to dedupe at once; it may well do less, and
+// says how much it did, so the offset only moves on by that much.
+#[cfg(target_os = "linux")]
+const DEDUPE_CHUNK: u64 = 16 * 1024 * 1024;
+
+// Has the kernel point the duplicate's extents at the kept file's, on
+// filesystems that can share them, like Btrfs and XFS. Both files stay what
+// they were, with their own inodes and metadata, except they no longer take up
+// space twice. The kernel compares the ranges itself, with both files locked,
+// and refuses any that differ, so there's nothing to check first.
+#[cfg(target_os = "linux")]
+fn reflink(kept: &Path, duplicate: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = File::open(kept)?;
+
+    // Writable if possible, since kernels before 4.19 insist on it, but the
+    // owner of a read-only file can still dedupe it on newer ones.
+    let destination = match std::fs::OpenOptions::new().write(true).open(duplicate) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => File::open(duplicate)?,
+        result => result?,
+    };
+
+    let length = source.metadata()?.len();
+    let mut offset = 0;
+
+    while offset < length {
+        let mut range = DedupeRange {
+            src_offset: offset,
+            src_length: DEDUPE_CHUNK.min(length - offset),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            dest_fd: destination.as_raw_fd() as i64,
+            dest_offset: offset,
+            bytes_deduped: 0,
+            status: 0,
+            reserved: 0,
+        };
+
+        if unsafe { libc::ioctl(source.as_raw_fd(), FIDEDUPERANGE as _, &mut range) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        match range.status {
+            FILE_DEDUPE_RANGE_DIFFERS => return Err(changed(kept)),
+            status if status < 0 => return Err(io::Error::from_raw_os_error(-status)),
+            _ if range.bytes_deduped == 0 => {
+                return Err(io::Error::other("the kernel stopped deduping partway"))
+            }
+            _ => offset += range.bytes_deduped,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "deduping extents is only supported on Linux",
+    ))
+}
+
+pub fn temporary_name(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".jw-{}", std::process::id()));
+    path.with_file_name(name)
+}
+
+// Either of them could have been written to since they were hashed.
+fn changed(kept: &Path) -> io::Error {
+    io::Error::other(format!("no longer the same as {}", kept.display()))
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut buffer_a = vec![0; 128 * 1024];
+    let mut buffer_b = vec![0; 128 * 1024];
+
+    loop {
+        let read_a = fill(&mut a, &mut buffer_a)?;
+        let read_b = fill(&mut b, &mut buffer_b)?;
+
+        if buffer_a[..read_a] != buffer_b[..read_b] {
+            return Ok(false);
+        }
+
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// As much as fits in the buffer, short only at the end of the file.
+fn fill(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+
+    Ok(filled)
+}
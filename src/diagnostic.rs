@@ -0,0 +1,129 @@
+use std::fmt;
+use std::io::{IsTerminal, Write};
+
+use crate::log;
+use crate::progress;
+use crate::tui;
+
+// What's wrong, when it's something jw can't go on from, laid out the way
+// clap lays out what's wrong with the arguments, since it's often much the
+// same kind of thing: what it's about, such as the argument or file that has
+// to change, why it won't do, and, where there's a likely fix, what that is.
+//
+//   error: '/data': isn't a manifest, which is a file jw -c wrote
+//
+//     tip: did you mean to check the directory against a manifest? That's
+//     jw verify <manifest> /data
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    about: Option<String>,
+    reason: String,
+    tip: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(reason: impl fmt::Display) -> Self {
+        Self {
+            about: None,
+            reason: reason.to_string(),
+            tip: None,
+        }
+    }
+
+    pub fn about(mut self, about: impl fmt::Display) -> Self {
+        self.about = Some(about.to_string());
+        self
+    }
+
+    pub fn tip(mut self, tip: impl fmt::Display) -> Self {
+        self.tip = Some(tip.to_string());
+        self
+    }
+
+    // Colored like clap colors its errors, and under the same conditions,
+    // which is only on a terminal, unless NO_COLOR, or JW_NO_COLOR, says not.
+    fn render(&self, styled: bool) -> String {
+        let paint = |code: &str, text: &str| match styled {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text.to_string(),
+        };
+
+        let mut text = paint("1;31", "error:");
+
+        if let Some(about) = &self.about {
+            text.push_str(&format!(" {}:", paint("33", &format!("'{}'", about))));
+        }
+
+        text.push(' ');
+        text.push_str(&self.reason);
+
+        if let Some(tip) = &self.tip {
+            text.push_str(&format!("\n\n  {} {}", paint("32", "tip:"), tip));
+        }
+
+        text
+    }
+
+    // Printed to stderr, and to --log-file too, without the colors. While
+    // the --tui screen is up, it's held along with the rest of stderr.
+    pub fn print(&self) {
+        let plain = self.render(false);
+        log::to_file(log::Level::Error, &plain);
+
+        if tui::hold(&plain) {
+            return;
+        }
+
+        let styled = std::io::stderr().is_terminal()
+            && ["NO_COLOR", "JW_NO_COLOR"]
+                .iter()
+                .all(|name| std::env::var_os(name).is_none_or(|value| value.is_empty()));
+
+        let mut stderr = std::io::stderr().lock();
+        progress::clear_line(&mut stderr);
+        let _ = writeln!(stderr, "{}", self.render(styled));
+    }
+
+    // Then jw exits, with 2 for what was asked of it, and 1 for what went
+    // wrong, once the --tui screen is closed, or the terminal would be left
+    // to it.
+    pub fn exit(self, code: i32) -> ! {
+        tui::close();
+        self.print();
+        tui::exit(code);
+    }
+}
+
+// The one of the names that's the fewest edits away from what was written,
+// within a third of its length, which is close enough to be a typo of it,
+// for a tip that asks whether that's what was meant.
+pub fn closest<'a>(written: &str, names: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let most = (written.chars().count() / 3).max(1);
+
+    names
+        .map(|name| (edits(written, name), name))
+        .filter(|&(edits, _)| edits <= most)
+        .min_by_key(|&(edits, _)| edits)
+        .map(|(_, name)| name)
+}
+
+// Levenshtein distance, a row at a time.
+fn edits(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &b) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + (a != b) as usize)
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::output::{csv_field, json_string, tsv_field, Format, SizeStyle};
+use crate::sink::Sink;
+use crate::sort::{Sort, SortKey};
+use crate::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Usage {
+    // What's actually allocated on disk, the way du counts by default, which
+    // is less for sparse files and more for files that don't fill a block.
+    Allocated,
+    // The size of the contents, as ls -l shows it.
+    Apparent,
+    // The size of the contents of regular files alone, leaving out what
+    // directories and symlinks take up, for --summarize-dirs.
+    Files,
+}
+
+impl Usage {
+    #[cfg(unix)]
+    fn of(&self, metadata: &Metadata) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+
+        match self {
+            Self::Allocated => metadata.blocks() * 512,
+            Self::Apparent => metadata.len(),
+            Self::Files if metadata.is_file() => metadata.len(),
+            Self::Files => 0,
+        }
+    }
+
+    // Without a portable way to get at the allocation size, it's only ever
+    // the apparent size.
+    #[cfg(not(unix))]
+    fn of(&self, metadata: &Metadata) -> u64 {
+        match self {
+            Self::Files if !metadata.is_file() => 0,
+            _ => metadata.len(),
+        }
+    }
+}
+
+// A directory's total, of everything below it.
+#[derive(Default)]
+pub struct Total {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+// Files are counted once per inode, like du does, so every name of a hard
+// linked file doesn't count toward the total over again.
+#[cfg(unix)]
+fn first_link(seen: &mut HashSet<(u64, u64)>, metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() <= 1 || seen.insert((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn first_link(_: &mut HashSet<(u64, u64)>, _: &Metadata) -> bool {
+    true
+}
+
+// Every entry's size goes to its parent directory, and once the walk is done,
+// every directory's total goes to its own parent, deepest first, so each ends
+// up with the size of everything below it. Siblings arrive together, so their
+// sizes are summed up before their parent has to be looked up at all. Regular
+// files are counted along the way, by name, so a hard linked file is one file
+// in every directory it's in, but its size only goes to the first.
+pub fn tally(entries: impl Iterator<Item = Entry>, usage: Usage) -> Vec<Total> {
+    let mut dirs: HashMap<PathBuf, Total> = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut siblings: Option<(Arc<Path>, usize, u64, u64)> = None;
+
+    let add = |dirs: &mut HashMap<PathBuf, Total>,
+               path: PathBuf,
+               depth: usize,
+               files: u64,
+               bytes: u64| {
+        let dir = dirs.entry(path).or_default();
+        dir.depth = depth;
+        dir.files += files;
+        dir.bytes += bytes;
+    };
+
+    for entry in entries {
+        let bytes = entry
+            .client_state
+            .as_deref()
+            .filter(|metadata| first_link(&mut seen, metadata))
+            .map_or(0, |metadata| usage.of(metadata));
+
+        let files = entry.file_type().is_file() as u64;
+
+        // A root that isn't a directory is reported on its own, as du does.
+        if entry.file_type().is_dir() || entry.depth() == 0 {
+            add(&mut dirs, entry.path(), entry.depth(), files, bytes);
+            continue;
+        }
+
+        match &mut siblings {
+            Some((parent, _, file_total, byte_total))
+                if Arc::ptr_eq(parent, &entry.parent_path) =>
+            {
+                *file_total += files;
+                *byte_total += bytes;
+            }
+            _ => {
+                if let Some((parent, depth, files, bytes)) = siblings.take() {
+                    add(&mut dirs, parent.to_path_buf(), depth, files, bytes);
+                }
+
+                siblings = Some((entry.parent_path.clone(), entry.depth() - 1, files, bytes));
+            }
+        }
+    }
+
+    if let Some((parent, depth, files, bytes)) = siblings {
+        add(&mut dirs, parent.to_path_buf(), depth, files, bytes);
+    }
+
+    let mut totals: Vec<Total> = dirs
+        .into_iter()
+        .map(|(path, dir)| Total { path, ..dir })
+        .collect();
+
+    totals.sort_by_key(|total| std::cmp::Reverse(total.depth));
+
+    let index: HashMap<PathBuf, usize> = totals
+        .iter()
+        .enumerate()
+        .map(|(i, total)| (total.path.clone(), i))
+        .collect();
+
+    for i in 0..totals.len() {
+        let (files, bytes) = (totals[i].files, totals[i].bytes);
+        let parent = totals[i].path.parent().and_then(|parent| index.get(parent));
+
+        if let Some(&parent) = parent {
+            totals[parent].files += files;
+            totals[parent].bytes += bytes;
+        }
+    }
+
+    totals
+}
+
+// No deeper than max_depth unless that's 0, in path order, or by size with
+// --sort size.
+fn arrange(totals: &mut Vec<Total>, max_depth: usize, sort: Option<Sort>) {
+    if max_depth != 0 {
+        totals.retain(|total| total.depth <= max_depth);
+    }
+
+    match sort {
+        Some(Sort {
+            key: SortKey::Size, ..
+        }) => totals.sort_by(|a, b| a.bytes.cmp(&b.bytes).then_with(|| a.path.cmp(&b.path))),
+        _ => totals.sort_by(|a, b| a.path.cmp(&b.path)),
+    }
+
+    if sort.is_some_and(|sort| sort.reverse) {
+        totals.reverse();
+    }
+}
+
+// A line per directory, of its total and its path.
+pub fn report<W: std::io::Write>(
+    mut totals: Vec<Total>,
+    max_depth: usize,
+    sort: Option<Sort>,
+    size_style: SizeStyle,
+    out: &mut Sink<W>,
+) {
+    arrange(&mut totals, max_depth, sort);
+
+    for total in totals {
+        writeln!(
+            out,
+            "{}\t{}",
+            size_style.format(total.bytes),
+            total.path.display()
+        );
+    }
+}
+
+// Printed once before any of the roots, the same as --format's own header.
+pub fn summary_header(format: Format) -> Option<String> {
+    match format {
+        Format::Csv => Some("path,files,bytes\n".to_string()),
+        Format::Tsv => Some("path\tfiles\tbytes\n".to_string()),
+        _ => None,
+    }
+}
+
+// For --summarize-dirs, a row per directory of its path, file count and byte
+// total, in whichever format --format asks for, with plain being the same
+// columns separated by tabs.
+pub fn summarize<W: std::io::Write>(
+    mut totals: Vec<Total>,
+    max_depth: usize,
+    sort: Option<Sort>,
+    format: Format,
+    size_style: SizeStyle,
+    out: &mut Sink<W>,
+) {
+    arrange(&mut totals, max_depth, sort);
+
+    let mut line = String::new();
+
+    for total in totals {
+        line.clear();
+
+        let path = total.path.to_string_lossy();
+
+        match format {
+            Format::Jsonl => {
+                line.push_str("{\"path\":");
+                json_string(&mut line, &path);
+                let _ = write!(
+                    line,
+                    ",\"files\":{},\"bytes\":{}}}",
+                    total.files, total.bytes
+                );
+            }
+            Format::Csv => {
+                csv_field(&mut line, &path);
+                let _ = write!(line, ",{},{}", total.files, total.bytes);
+            }
+            Format::Tsv => {
+                tsv_field(&mut line, &path);
+                let _ = write!(line, "\t{}\t{}", total.files, total.bytes);
+            }
+            _ => {
+                let _ = write!(
+                    line,
+                    "{}\t{}\t{}",
+                    path,
+                    total.files,
+                    size_style.format(total.bytes)
+                );
+            }
+        }
+
+        writeln!(out, "{}", line);
+    }
+}
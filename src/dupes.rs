@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use rayon::prelude::*;
+
+use crate::hashutil::{hash_file, hexlify, Hasher, ReadOptions, Xxh3Default};
+use crate::output::{json_string, Format, SizeStyle};
+use crate::sink::Sink;
+use crate::stats::Errors;
+use crate::Entry;
+
+// How much of each file is hashed first, which is usually enough to tell apart
+// files that only happen to be the same size, without reading all of them.
+const PREFIX_BYTES: u64 = 4096;
+
+// A regular file that might have a duplicate somewhere, taken from the
+// metadata the walk prefetched, along with which of the roots it was under.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: Option<SystemTime>,
+    pub root: usize,
+    inode: Option<(u64, u64)>,
+}
+
+impl Candidate {
+    // Empty files are all the same, but there's nothing to be reclaimed from
+    // them, so they're left out.
+    pub fn of(entry: &Entry, root: usize) -> Option<Self> {
+        let metadata = entry.client_state.as_deref()?;
+
+        if !entry.file_type().is_file() || metadata.len() == 0 {
+            return None;
+        }
+
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            Some((metadata.dev(), metadata.ino()))
+        };
+
+        #[cfg(not(unix))]
+        let inode = None;
+
+        Some(Self {
+            path: entry.path(),
+            size: metadata.len(),
+            mtime: metadata.modified().ok(),
+            root,
+            inode,
+        })
+    }
+}
+
+// Files with the same contents, in path order.
+pub struct Group {
+    pub size: u64,
+    pub hash: String,
+    pub files: Vec<Candidate>,
+}
+
+impl Group {
+    // What keeping only one of them would free up.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+// How much all the groups waste between them, in a line.
+pub fn summary(groups: &[Group], size_style: SizeStyle) -> String {
+    let copies: usize = groups.iter().map(|group| group.files.len() - 1).sum();
+    let total: u64 = groups.iter().map(Group::wasted).sum();
+
+    format!(
+        "{} redundant copies in {} groups, {} reclaimable.",
+        copies,
+        groups.len(),
+        size_style.format(total)
+    )
+}
+
+// For --dupes, the paths of each group, a line each, with a blank line between
+// groups, as fdupes prints them, or with --format jsonl, an object per group of
+// its size, hash and paths.
+pub fn write<W: std::io::Write>(groups: &[Group], format: Format, out: &mut Sink<W>) {
+    let mut text = String::new();
+
+    for (i, group) in groups.iter().enumerate() {
+        text.clear();
+
+        if format == Format::Jsonl {
+            let _ = write!(
+                text,
+                "{{\"size\":{},\"hash\":\"{}\",\"paths\":[",
+                group.size, group.hash
+            );
+
+            for (i, file) in group.files.iter().enumerate() {
+                if i > 0 {
+                    text.push(',');
+                }
+
+                json_string(&mut text, &file.path.to_string_lossy());
+            }
+
+            text.push_str("]}\n");
+        } else {
+            if i > 0 {
+                text.push('\n');
+            }
+
+            for file in &group.files {
+                let _ = writeln!(text, "{}", file.path.display());
+            }
+        }
+
+        out.write_str(&text);
+    }
+}
+
+// The usual pipeline: files are grouped by size, which takes no reading at
+// all, then by a hash of their first few kilobytes, and only whatever still
+// shares a group after that is hashed in full. Names of the same hard linked
+// file are the same file rather than duplicates, so only the first counts. The
+// groups come out with the most space wasted first.
+pub fn find(candidates: &[Candidate], read: &ReadOptions, errors: &Errors) -> Vec<Group> {
+    let mut by_size: HashMap<u64, Vec<&Candidate>> = HashMap::new();
+
+    for candidate in candidates {
+        by_size.entry(candidate.size).or_default().push(candidate);
+    }
+
+    let by_size: Vec<Vec<&Candidate>> = by_size
+        .into_values()
+        .filter_map(|mut group| {
+            group.sort_by(|a, b| a.path.cmp(&b.path));
+
+            let mut inodes = std::collections::HashSet::new();
+            group.retain(|c| c.inode.is_none_or(|inode| inodes.insert(inode)));
+
+            (group.len() > 1).then_some(group)
+        })
+        .collect();
+
+    let by_prefix = refine(by_size, errors, |candidate| prefix_hash(candidate, read));
+
+    // A file no bigger than the prefix is already hashed in full.
+    let (whole, partial): (Vec<_>, Vec<_>) = by_prefix
+        .into_iter()
+        .partition(|(_, group)| group[0].size <= PREFIX_BYTES);
+
+    let partial = partial.into_iter().map(|(_, group)| group).collect();
+
+    let by_contents = refine(partial, errors, |candidate| {
+        hash_file::<Xxh3Default>(&candidate.path, read)
+    });
+
+    let mut groups: Vec<Group> = whole
+        .into_iter()
+        .chain(by_contents)
+        .map(|(hash, group)| Group {
+            size: group[0].size,
+            hash,
+            files: group.into_iter().cloned().collect(),
+        })
+        .collect();
+
+    groups.sort_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+
+    groups
+}
+
+// Splits every group up further by key, with the keys worked out in parallel
+// across all the groups at once, since a single group can be most of them.
+// Files that can't be read are counted as errors and dropped, along with any
+// group that's down to one file.
+fn refine<'a, F>(
+    groups: Vec<Vec<&'a Candidate>>,
+    errors: &Errors,
+    key: F,
+) -> Vec<(String, Vec<&'a Candidate>)>
+where
+    F: Fn(&Candidate) -> std::io::Result<String> + Sync,
+{
+    let flattened: Vec<(usize, &Candidate)> = groups
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, group)| group.into_iter().map(move |c| (i, c)))
+        .collect();
+
+    let keyed: Vec<(usize, String, &Candidate)> = flattened
+        .into_par_iter()
+        .filter_map(|(i, candidate)| match key(candidate) {
+            Ok(key) => Some((i, key, candidate)),
+            Err(e) => {
+                errors.add(Some(&candidate.path), e);
+                None
+            }
+        })
+        .collect();
+
+    let mut split: HashMap<(usize, String), Vec<&Candidate>> = HashMap::new();
+
+    for (i, key, candidate) in keyed {
+        split.entry((i, key)).or_default().push(candidate);
+    }
+
+    split
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|((_, key), group)| (key, group))
+        .collect()
+}
+
+// Opened plainly, since O_DIRECT would want an aligned buffer for so little.
+fn prefix_hash(candidate: &Candidate, read: &ReadOptions) -> std::io::Result<String> {
+    let file = std::fs::File::open(&candidate.path)?;
+    let mut prefix = Vec::with_capacity(PREFIX_BYTES as usize);
+    file.take(PREFIX_BYTES).read_to_end(&mut prefix)?;
+
+    read.chunk_read(prefix.len());
+
+    let mut hasher = <Xxh3Default as Hasher>::create();
+    Hasher::update(&mut hasher, &prefix);
+    Ok(hexlify(Hasher::finalize(hasher)))
+}
@@ -0,0 +1,561 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::mutate;
+use crate::stats::Errors;
+
+// What a placeholder in a command stands for, for an entry at /a/b/c.txt.
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    // {} is /a/b/c.txt
+    Path,
+    // {.} is /a/b/c
+    Stem,
+    // {/} is c.txt
+    Name,
+    // {//} is /a/b
+    Parent,
+    // {/.} is c
+    NameStem,
+}
+
+const PLACEHOLDERS: [(&str, Piece); 5] = [
+    ("{//}", Piece::Parent),
+    ("{/.}", Piece::NameStem),
+    ("{/}", Piece::Name),
+    ("{.}", Piece::Stem),
+    ("{}", Piece::Path),
+];
+
+// The command given to --exec, split up into words once, the way a shell
+// would, with whatever placeholders are in each word picked out. Paths are
+// passed to the command as arguments of their own, so nothing in them is ever
+// interpreted by a shell.
+#[derive(Clone, Debug)]
+pub struct Template {
+    words: Vec<Vec<Piece>>,
+}
+
+pub fn parse_exec(s: &str) -> Result<Template, String> {
+    let mut words: Vec<Vec<Piece>> = split(s)?.iter().map(|word| pieces(word)).collect();
+
+    if words.is_empty() {
+        return Err("the command is empty".to_string());
+    }
+
+    // Like find -exec, and xargs, a command without any placeholders gets the
+    // path at the end.
+    if words
+        .iter()
+        .flatten()
+        .all(|piece| matches!(piece, Piece::Text(_)))
+    {
+        words.push(vec![Piece::Path]);
+    }
+
+    Ok(Template { words })
+}
+
+// Words are separated by whitespace; single quotes keep everything between
+// them as it is, double quotes keep everything but a backslash escaping a
+// double quote or another backslash, and a backslash outside of quotes keeps
+// whatever comes after it.
+fn split(s: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated single quote in '{}'", s)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("unterminated double quote in '{}'", s)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated double quote in '{}'", s)),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => return Err(format!("trailing backslash in '{}'", s)),
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+fn pieces(word: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = word;
+
+    'outer: while !rest.is_empty() {
+        for (placeholder, piece) in &PLACEHOLDERS {
+            if let Some(after) = rest.strip_prefix(placeholder) {
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+
+                pieces.push(piece.clone());
+                rest = after;
+                continue 'outer;
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !text.is_empty() || pieces.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+
+    pieces
+}
+
+impl Template {
+    fn word(pieces: &[Piece], path: &Path) -> OsString {
+        let mut word = OsString::new();
+
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => word.push(text),
+                Piece::Path => word.push(path),
+                Piece::Stem => word.push(path.with_extension("")),
+                Piece::Name => word.push(path.file_name().unwrap_or(path.as_os_str())),
+                Piece::Parent => word.push(match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                }),
+                Piece::NameStem => word.push(path.file_stem().unwrap_or(path.as_os_str())),
+            }
+        }
+
+        word
+    }
+
+    fn command(&self, path: &Path) -> Command {
+        let mut words = self.words.iter().map(|pieces| Self::word(pieces, path));
+        let mut command = Command::new(words.next().unwrap());
+        command.args(words);
+        command
+    }
+
+    // Runs the command for a path, with its output captured and then written
+    // out all at once, so that commands running alongside each other don't
+    // interleave their lines. Nothing can be read from stdin, since it would
+    // be anyone's guess which of them got it.
+    pub fn run(&self, path: &Path) -> io::Result<()> {
+        mutate::perform(|| show(&self.command(path)), || capture(self.command(path)))
+    }
+
+    // Words with a placeholder in them are repeated for every path.
+    fn batch_command(&self, paths: &[PathBuf]) -> Command {
+        let mut command = Command::new(Self::word(&self.words[0], Path::new("")));
+
+        for pieces in &self.words[1..] {
+            if pieces.iter().all(|piece| matches!(piece, Piece::Text(_))) {
+                command.arg(Self::word(pieces, Path::new("")));
+            } else {
+                command.args(paths.iter().map(|path| Self::word(pieces, path)));
+            }
+        }
+
+        command
+    }
+}
+
+fn capture(mut command: Command) -> io::Result<()> {
+    let output = command.stdin(Stdio::null()).output().map_err(|e| {
+        let program = command.get_program().to_string_lossy();
+        io::Error::new(e.kind(), format!("couldn't run {}: {}", program, e))
+    })?;
+
+    if !output.stdout.is_empty() {
+        let _ = io::stdout().lock().write_all(&output.stdout);
+    }
+
+    if !output.stderr.is_empty() {
+        let _ = io::stderr().lock().write_all(&output.stderr);
+    }
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(failure(output.status))),
+    }
+}
+
+// With --dry-run, the command that would have run, quoted so that it could be
+// pasted into a shell as it is.
+fn show(command: &Command) -> io::Result<()> {
+    let mut line = quote(command.get_program());
+
+    for argument in command.get_args() {
+        line.push(' ');
+        line.push_str(&quote(argument));
+    }
+
+    line.push('\n');
+    io::stdout().lock().write_all(line.as_bytes())
+}
+
+fn quote(word: &OsStr) -> String {
+    let word = word.to_string_lossy();
+
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+
+    match plain {
+        true => word.into_owned(),
+        false => format!("'{}'", word.replace('\'', "'\\''")),
+    }
+}
+
+// For --exec-batch, a single command gets as many paths as fit on its command
+// line. Words with a placeholder in them are repeated for every path, so
+// `chmod -x {}` gets them all at the end, and `tar --add-file={}` gets one
+// --add-file= each, while the rest of the words only appear once.
+pub struct Batch<'a> {
+    template: &'a Template,
+    errors: &'a Errors,
+    space: usize,
+    fixed: usize,
+    used: usize,
+    paths: Vec<PathBuf>,
+    pub commands: usize,
+    pub failures: usize,
+}
+
+impl<'a> Batch<'a> {
+    pub fn new(template: &'a Template, errors: &'a Errors) -> Self {
+        let fixed = template
+            .words
+            .iter()
+            .filter(|pieces| pieces.iter().all(|piece| matches!(piece, Piece::Text(_))))
+            .map(|pieces| argument_size(&Template::word(pieces, Path::new(""))))
+            .sum();
+
+        Self {
+            template,
+            errors,
+            space: argument_space(),
+            fixed,
+            used: fixed,
+            paths: Vec::new(),
+            commands: 0,
+            failures: 0,
+        }
+    }
+
+    // Runs what's already in the batch first, whenever the path wouldn't fit
+    // alongside it. A path too long to fit even on its own still gets a command
+    // to itself, which the system is then free to refuse.
+    pub fn push(&mut self, path: PathBuf) {
+        let size: usize = self
+            .template
+            .words
+            .iter()
+            .filter(|pieces| pieces.iter().any(|piece| !matches!(piece, Piece::Text(_))))
+            .map(|pieces| argument_size(&Template::word(pieces, &path)))
+            .sum();
+
+        if !self.paths.is_empty() && self.used + size > self.space {
+            self.run();
+        }
+
+        self.used += size;
+        self.paths.push(path);
+    }
+
+    pub fn finish(&mut self) {
+        if !self.paths.is_empty() {
+            self.run();
+        }
+    }
+
+    // Only one batch runs at a time, with jw's own stdin, stdout and stderr, as
+    // xargs does, since commands that take many files at once, like tar, tend
+    // to be writing to the one place.
+    fn run(&mut self) {
+        let paths = std::mem::take(&mut self.paths);
+        self.used = self.fixed;
+        self.commands += 1;
+
+        let status = mutate::perform(
+            || show(&self.template.batch_command(&paths)).map(|_| ExitStatus::default()),
+            || self.template.batch_command(&paths).status(),
+        );
+
+        let result = match status {
+            Ok(status) if status.success() => return,
+            Ok(status) => failure(status),
+            Err(e) => format!(
+                "couldn't run {}: {}",
+                Template::word(&self.template.words[0], Path::new("")).to_string_lossy(),
+                e
+            ),
+        };
+
+        self.failures += 1;
+
+        self.errors.add(
+            None,
+            format!("{}, for a batch of {} paths", result, paths.len()),
+        );
+    }
+}
+
+// What an argument takes up on the command line, which on unix is the string
+// itself, its terminating NUL, and the pointer to it in argv.
+fn argument_size(argument: &std::ffi::OsStr) -> usize {
+    argument.len() + 1 + std::mem::size_of::<usize>()
+}
+
+// How much of ARG_MAX is left for arguments. The environment is passed to the
+// command in the same space, and POSIX suggests leaving another 2048 bytes
+// spare, as xargs does.
+#[cfg(unix)]
+fn argument_space() -> usize {
+    let arg_max = match unsafe { libc::sysconf(libc::_SC_ARG_MAX) } {
+        max if max > 0 => max as usize,
+        _ => 128 * 1024,
+    };
+
+    let environment: usize = std::env::vars_os()
+        .map(|(key, value)| argument_size(&key) + value.len() + 1)
+        .sum();
+
+    arg_max.saturating_sub(environment + 2048).max(4096)
+}
+
+// Windows limits the whole command line to 32767 characters, quoting and all.
+#[cfg(not(unix))]
+fn argument_space() -> usize {
+    32 * 1024 - 2048
+}
+
+fn failure(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return format!("command was killed by signal {}", signal);
+        }
+    }
+
+    match status.code() {
+        Some(code) => format!("command exited with status {}", code),
+        None => "command failed".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        split(s).unwrap_or_else(|e| panic!("{:?}: {}", s, e))
+    }
+
+    // What the command for a path would actually be run with.
+    fn argv(template: &str, path: &str) -> Vec<String> {
+        let command = parse_exec(template).unwrap().command(Path::new(path));
+
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|word| word.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn words_are_split_on_whitespace() {
+        assert_eq!(words("echo a b"), ["echo", "a", "b"]);
+        assert_eq!(words("  echo \t a\n b  "), ["echo", "a", "b"]);
+        assert_eq!(words(""), Vec::<String>::new());
+        assert_eq!(words("   "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn single_quotes_keep_everything() {
+        assert_eq!(words("echo 'a b'"), ["echo", "a b"]);
+        assert_eq!(words(r"echo 'a\b' '\'"), ["echo", r"a\b", r"\"]);
+        assert_eq!(words(r#"echo '"a"'"#), ["echo", r#""a""#]);
+        assert_eq!(words("echo ''"), ["echo", ""]);
+        assert_eq!(words("echo a'b c'd"), ["echo", "ab cd"]);
+    }
+
+    #[test]
+    fn double_quotes_keep_all_but_escapes() {
+        assert_eq!(words(r#"echo "a b""#), ["echo", "a b"]);
+        assert_eq!(words(r#"echo "a \"b\"""#), ["echo", r#"a "b""#]);
+        assert_eq!(words(r#"echo "a\\b""#), ["echo", r"a\b"]);
+        assert_eq!(words(r#"echo "a\nb\$""#), ["echo", r"a\nb\$"]);
+        assert_eq!(words(r#"echo "'a'""#), ["echo", "'a'"]);
+        assert_eq!(words(r#"echo """#), ["echo", ""]);
+        assert_eq!(words(r#"echo "a"'b'c"#), ["echo", "abc"]);
+    }
+
+    #[test]
+    fn backslashes_keep_what_follows() {
+        assert_eq!(words(r"echo a\ b"), ["echo", "a b"]);
+        assert_eq!(words(r"echo \'a\'"), ["echo", "'a'"]);
+        assert_eq!(words(r#"echo \"a"#), ["echo", r#""a"#]);
+        assert_eq!(words(r"echo \\"), ["echo", r"\"]);
+        assert_eq!(words(r"echo \n"), ["echo", "n"]);
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert_eq!(
+            split("echo 'a b"),
+            Err("unterminated single quote in 'echo 'a b'".to_string())
+        );
+        assert_eq!(
+            split(r#"echo "a b"#),
+            Err(r#"unterminated double quote in 'echo "a b'"#.to_string())
+        );
+        assert_eq!(
+            split(r#"echo "a\"#),
+            Err(r#"unterminated double quote in 'echo "a\'"#.to_string())
+        );
+        assert_eq!(
+            split(r"echo a\"),
+            Err(r"trailing backslash in 'echo a\'".to_string())
+        );
+        assert!(parse_exec("echo 'a").is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(pieces("{}"), [Piece::Path]);
+        assert_eq!(pieces("{.}"), [Piece::Stem]);
+        assert_eq!(pieces("{/}"), [Piece::Name]);
+        assert_eq!(pieces("{//}"), [Piece::Parent]);
+        assert_eq!(pieces("{/.}"), [Piece::NameStem]);
+        assert_eq!(pieces("text"), [Piece::Text("text".to_string())]);
+        assert_eq!(pieces(""), [Piece::Text(String::new())]);
+        assert_eq!(
+            pieces("--out={/.}.png"),
+            [
+                Piece::Text("--out=".to_string()),
+                Piece::NameStem,
+                Piece::Text(".png".to_string())
+            ]
+        );
+        assert_eq!(pieces("{}{}"), [Piece::Path, Piece::Path]);
+        assert_eq!(pieces("{x}"), [Piece::Text("{x}".to_string())]);
+    }
+
+    #[test]
+    fn longer_placeholders_win() {
+        // {//} isn't {/} followed by a /}, and {/.} isn't {/} then .}.
+        assert_eq!(pieces("{//}"), [Piece::Parent]);
+        assert_eq!(pieces("{/.}"), [Piece::NameStem]);
+        assert_eq!(
+            pieces("{//}/{/}"),
+            [Piece::Parent, Piece::Text("/".to_string()), Piece::Name]
+        );
+        assert_eq!(
+            pieces("{/}.}"),
+            [Piece::Name, Piece::Text(".}".to_string())]
+        );
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let path = "/a/b/c.txt";
+
+        assert_eq!(argv("echo {}", path), ["echo", "/a/b/c.txt"]);
+        assert_eq!(argv("echo {.}", path), ["echo", "/a/b/c"]);
+        assert_eq!(argv("echo {/}", path), ["echo", "c.txt"]);
+        assert_eq!(argv("echo {//}", path), ["echo", "/a/b"]);
+        assert_eq!(argv("echo {/.}", path), ["echo", "c"]);
+        assert_eq!(argv("echo {//}", "c.txt"), ["echo", "."]);
+        assert_eq!(
+            argv("convert {} '{//}/thumb {/.}.png'", path),
+            ["convert", "/a/b/c.txt", "/a/b/thumb c.png"]
+        );
+
+        // A path with spaces or quotes in it is still one argument.
+        assert_eq!(argv("echo {}", "/a/it's a b"), ["echo", "/a/it's a b"]);
+    }
+
+    #[test]
+    fn the_path_is_appended_without_placeholders() {
+        assert_eq!(argv("echo", "/a/b"), ["echo", "/a/b"]);
+        assert_eq!(argv("ls -l", "/a/b"), ["ls", "-l", "/a/b"]);
+        assert_eq!(argv("echo {} done", "/a/b"), ["echo", "/a/b", "done"]);
+        assert_eq!(argv("echo '{/}'", "/a/b"), ["echo", "b"]);
+
+        // Quoting a placeholder doesn't keep it from being one.
+        assert_eq!(argv(r#"echo "{}""#, "/a/b"), ["echo", "/a/b"]);
+    }
+
+    #[test]
+    fn empty_commands() {
+        assert_eq!(parse_exec("").unwrap_err(), "the command is empty");
+        assert_eq!(parse_exec("  ").unwrap_err(), "the command is empty");
+    }
+
+    #[test]
+    fn batches_repeat_the_words_with_placeholders() {
+        let template = parse_exec("tar -cf out.tar --add-file={}").unwrap();
+        let paths = [PathBuf::from("/a"), PathBuf::from("/b")];
+        let command = template.batch_command(&paths);
+
+        assert_eq!(command.get_program(), "tar");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-cf", "out.tar", "--add-file=/a", "--add-file=/b"]
+        );
+
+        let command = parse_exec("chmod -x").unwrap().batch_command(&paths);
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-x", "/a", "/b"]);
+    }
+
+    #[test]
+    fn dry_runs_are_quoted_for_a_shell() {
+        assert_eq!(quote(OsStr::new("plain-word_1.txt")), "plain-word_1.txt");
+        assert_eq!(quote(OsStr::new("a b")), "'a b'");
+        assert_eq!(quote(OsStr::new("it's")), r"'it'\''s'");
+        assert_eq!(quote(OsStr::new("")), "''");
+        assert_eq!(quote(OsStr::new("$HOME")), "'$HOME'");
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::Write as _;
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config;
+
+// For --explain, every option that's set, whether that's by the command line,
+// an environment variable, the config file, or its default, with which of
+// them it was, followed by what jw made of all of them together, which is
+// where one option turning on a mode, or a network filesystem changing the
+// defaults, shows. The options are written the way the config file takes
+// them, so they can be pasted into one, apart from --config itself.
+pub fn render(command: &Command, matches: &ArgMatches, resolved: &[(&str, String)]) -> String {
+    let mut text = String::from("# Options, and where each of them came from.\n");
+
+    let arguments = command.get_arguments().filter(|arg| {
+        !arg.is_positional()
+            && !arg.is_hide_set()
+            && !matches!(arg.get_id().as_str(), "help" | "version" | "explain")
+    });
+
+    for arg in arguments {
+        let Some(value) = value(arg, matches) else {
+            continue;
+        };
+
+        let source = match matches.value_source(arg.get_id().as_str()) {
+            Some(ValueSource::CommandLine) => "command line".to_string(),
+            Some(ValueSource::EnvVariable) => {
+                arg.get_env().map_or("environment".to_string(), |env| {
+                    env.to_string_lossy().into_owned()
+                })
+            }
+            Some(ValueSource::DefaultValue) if config::given(arg.get_id().as_str()) => {
+                match config::file() {
+                    Some(file) => format!("config file {}", file.display()),
+                    None => "config file".to_string(),
+                }
+            }
+            _ => "default".to_string(),
+        };
+
+        let long = arg.get_long().unwrap_or_else(|| arg.get_id().as_str());
+
+        let _ = match long {
+            "config" => writeln!(text, "# {} = {}  # {}", long, value, source),
+            _ => writeln!(text, "{} = {}  # {}", long, value, source),
+        };
+    }
+
+    text.push_str("\n# What they come to, taken together.\n");
+
+    let width = resolved
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (name, value) in resolved {
+        let _ = writeln!(text, "{:<width$}  {}", name, value, width = width);
+    }
+
+    text
+}
+
+// The value an option has, as it'd be written in the config file, or None
+// for a flag that's only off, which is every flag that isn't given, and
+// would bury the rest.
+fn value(arg: &Arg, matches: &ArgMatches) -> Option<String> {
+    let id = arg.get_id().as_str();
+
+    if let ArgAction::Count = arg.get_action() {
+        return Some(matches.get_count(id))
+            .filter(|count| *count > 0)
+            .map(|count| count.to_string());
+    }
+
+    let values: Vec<String> = matches
+        .get_raw(id)?
+        .map(|value| value.to_string_lossy().into_owned())
+        .collect();
+
+    if matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse) {
+        let set = values.first()?;
+
+        return match matches.value_source(id) == Some(ValueSource::DefaultValue)
+            && !config::given(id)
+            && set == "false"
+        {
+            true => None,
+            false => Some(set.clone()),
+        };
+    }
+
+    let many = matches!(arg.get_action(), ArgAction::Append)
+        || arg
+            .get_num_args()
+            .is_some_and(|range| range.max_values() > 1);
+
+    let values: Vec<String> = values.iter().map(|value| literal(value)).collect();
+
+    match many {
+        true => Some(format!("[{}]", values.join(", "))),
+        false => values.into_iter().next(),
+    }
+}
+
+// Whole numbers go as they are, and everything else as a "basic" string,
+// which is what the config file takes sizes and durations as anyway.
+fn literal(value: &str) -> String {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return value.to_string();
+    }
+
+    let mut literal = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            }
+            c => literal.push(c),
+        }
+    }
+
+    literal.push('"');
+    literal
+}
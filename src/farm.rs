@@ -0,0 +1,93 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::mutate;
+
+// Symlinks to every entry of a root, laid out under the destination the same
+// way they're laid out under the root, for --link-farm. The links point to
+// where the entries really are, so they keep working from wherever the farm
+// is looked at.
+pub struct Farm {
+    destination: PathBuf,
+    root: PathBuf,
+    canonical_root: PathBuf,
+    // Where the farm is within the root, when it's being built inside the
+    // very tree being walked, which it would otherwise end up farming too.
+    inside: Option<PathBuf>,
+    // The directory the last link went into, which is usually where the next
+    // one goes too, so it isn't created over and over again.
+    last_parent: Option<PathBuf>,
+}
+
+impl Farm {
+    pub fn new(destination: &Path, root: &Path) -> io::Result<Self> {
+        mutate::create_dir_all(destination)?;
+
+        let canonical_root = std::fs::canonicalize(root)?;
+
+        // A destination that doesn't exist yet, with --dry-run, has nothing in
+        // it to leave out.
+        let inside = std::fs::canonicalize(destination).ok().and_then(|dest| {
+            dest.strip_prefix(&canonical_root)
+                .ok()
+                .map(Path::to_path_buf)
+        });
+
+        Ok(Self {
+            destination: destination.to_path_buf(),
+            root: root.to_path_buf(),
+            canonical_root,
+            inside,
+            last_parent: None,
+        })
+    }
+
+    // The link made for the entry, or None for entries that don't get one:
+    // directories, which are only ever created to hold links, the root itself,
+    // and anything in the farm.
+    pub fn link(&mut self, path: &Path, is_dir: bool) -> io::Result<Option<PathBuf>> {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Ok(None);
+        };
+
+        let in_farm = self
+            .inside
+            .as_ref()
+            .is_some_and(|inside| relative.starts_with(inside));
+
+        if is_dir || relative.as_os_str().is_empty() || in_farm {
+            return Ok(None);
+        }
+
+        let link = self.destination.join(relative);
+
+        if let Some(parent) = link.parent() {
+            if self.last_parent.as_deref() != Some(parent) {
+                mutate::create_dir_all(parent)?;
+                self.last_parent = Some(parent.to_path_buf());
+            }
+        }
+
+        let target = self.canonical_root.join(relative);
+        mutate::perform(|| mutate::vacant(&link), || symlink(&target, &link))?;
+        Ok(Some(link))
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks aren't supported on this platform",
+    ))
+}
@@ -0,0 +1,825 @@
+use std::time::SystemTime;
+
+use crate::record::{days_from_civil, from_unix_seconds, Record};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Name,
+    Path,
+    Ext,
+    Size,
+    Depth,
+    Mtime,
+    Atime,
+    Ctime,
+    Btime,
+    Type,
+    Owner,
+    Group,
+    Inode,
+    Dev,
+    Nlink,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Glob,
+    NotGlob,
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    Num(u64),
+    Time(SystemTime),
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Field, Op, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(Op),
+    Word(String),
+    Str(String),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "name" => Self::Name,
+            "path" => Self::Path,
+            "ext" => Self::Ext,
+            "size" => Self::Size,
+            "depth" => Self::Depth,
+            "mtime" => Self::Mtime,
+            "atime" => Self::Atime,
+            "ctime" => Self::Ctime,
+            "btime" => Self::Btime,
+            "type" => Self::Type,
+            "owner" => Self::Owner,
+            "group" => Self::Group,
+            "inode" => Self::Inode,
+            "dev" => Self::Dev,
+            "nlink" => Self::Nlink,
+            _ => return None,
+        })
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '&' | '|' => {
+                chars.next();
+
+                if chars.next() != Some(c) {
+                    return Err(format!("expected '{}{}'", c, c));
+                }
+
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+            }
+            '!' => {
+                chars.next();
+
+                tokens.push(match chars.peek() {
+                    Some('=') => {
+                        chars.next();
+                        Token::Op(Op::Ne)
+                    }
+                    Some('~') => {
+                        chars.next();
+                        Token::Op(Op::NotGlob)
+                    }
+                    _ => Token::Not,
+                });
+            }
+            '=' => {
+                chars.next();
+
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                }
+
+                tokens.push(Token::Op(Op::Eq));
+            }
+            '<' | '>' => {
+                chars.next();
+                let or_equal = chars.peek() == Some(&'=');
+
+                if or_equal {
+                    chars.next();
+                }
+
+                tokens.push(Token::Op(match (c, or_equal) {
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    (_, false) => Op::Gt,
+                    (_, true) => Op::Ge,
+                }));
+            }
+            '~' => {
+                chars.next();
+                tokens.push(Token::Op(Op::Glob));
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => string.push(escaped),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(ch) if ch == c => break,
+                        Some(ch) => string.push(ch),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+
+                tokens.push(Token::Str(string));
+            }
+            _ => {
+                let mut word = String::new();
+
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "()&|!=<>~\"'".contains(ch) {
+                        break;
+                    }
+
+                    word.push(ch);
+                    chars.next();
+                }
+
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.parse_and()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_unary()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.parse_unary()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Word(name)) => {
+                let field = Field::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown field '{}', expected one of: name, path, ext, size, depth, \
+                         mtime, atime, ctime, btime, type, owner, group, inode, dev, nlink",
+                        name
+                    )
+                })?;
+
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => return Err(format!("expected a comparison operator after '{}'", name)),
+                };
+
+                let literal = match self.next() {
+                    Some(Token::Word(word)) | Some(Token::Str(word)) => word,
+                    _ => return Err(format!("expected a value to compare '{}' against", name)),
+                };
+
+                let value = parse_value(field, op, &literal)?;
+                Ok(Expr::Cmp(field, op, value))
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+    };
+
+    let expr = parser.parse_or()?;
+
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected trailing {:?}", token)),
+    }
+}
+
+fn parse_value(field: Field, op: Op, literal: &str) -> Result<Value, String> {
+    let is_glob = matches!(op, Op::Glob | Op::NotGlob);
+
+    match field {
+        Field::Name | Field::Path | Field::Ext => Ok(Value::Str(literal.to_string())),
+
+        Field::Type => match literal {
+            "file" | "dir" | "symlink" | "other" if !is_glob => Ok(Value::Str(literal.to_string())),
+            _ if is_glob => Err("type can't be glob matched".to_string()),
+            _ => Err(format!(
+                "unknown type '{}', expected file, dir, symlink, or other",
+                literal
+            )),
+        },
+
+        _ if is_glob => Err("glob matching only applies to name, path, and ext".to_string()),
+
+        Field::Size => parse_size(literal).map(Value::Num),
+        Field::Depth | Field::Inode | Field::Dev | Field::Nlink => literal
+            .parse::<u64>()
+            .map(Value::Num)
+            .map_err(|e| format!("invalid number '{}': {}", literal, e)),
+
+        Field::Mtime | Field::Atime | Field::Ctime | Field::Btime => {
+            parse_date(literal).map(Value::Time)
+        }
+
+        Field::Owner | Field::Group => match literal.parse::<u64>() {
+            Ok(id) => Ok(Value::Num(id)),
+            Err(_) => resolve_id(field, literal).map(Value::Num),
+        },
+    }
+}
+
+// Bare K/M/G/T/P are binary like most people mean when typing "100M",
+// KB/MB/.. are decimal, and KiB/MiB/.. are there for the pedantic.
+pub fn parse_size(literal: &str) -> Result<u64, String> {
+    let split = literal
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(literal.len());
+
+    let (number, suffix) = literal.split_at(split);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size '{}'", literal))?;
+
+    let (base, suffix) = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => return Ok(number as u64),
+        s if s.ends_with("ib") => (1024f64, s[..s.len() - 2].to_string()),
+        s if s.len() == 2 && s.ends_with('b') => (1000f64, s[..1].to_string()),
+        s => (1024f64, s.to_string()),
+    };
+
+    let exponent = match suffix.as_str() {
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        "p" => 5,
+        _ => return Err(format!("invalid size suffix in '{}'", literal)),
+    };
+
+    Ok((number * base.powi(exponent)) as u64)
+}
+
+// YYYY-MM-DD with an optional THH:MM[:SS], interpreted as UTC.
+fn parse_date(literal: &str) -> Result<SystemTime, String> {
+    let invalid = || {
+        format!(
+            "invalid date '{}', expected YYYY-MM-DD[THH:MM[:SS]]",
+            literal
+        )
+    };
+
+    let (date, time) = literal.split_once('T').unwrap_or((literal, "00:00"));
+
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|n| n.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|n| n.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+
+    let [year, month, day] = date[..] else {
+        return Err(invalid());
+    };
+
+    let (hour, minute, second) = match time[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return Err(invalid()),
+    };
+
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return Err(invalid());
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    Ok(from_unix_seconds(seconds, 0))
+}
+
+#[cfg(unix)]
+fn resolve_id(field: Field, name: &str) -> Result<u64, String> {
+    let c_name = std::ffi::CString::new(name).map_err(|e| e.to_string())?;
+
+    // Both return pointers into static storage; fine since this only ever
+    // runs on the main thread while parsing arguments.
+    let id = unsafe {
+        if field == Field::Owner {
+            let passwd = libc::getpwnam(c_name.as_ptr());
+            (!passwd.is_null()).then(|| (*passwd).pw_uid as u64)
+        } else {
+            let group = libc::getgrnam(c_name.as_ptr());
+            (!group.is_null()).then(|| (*group).gr_gid as u64)
+        }
+    };
+
+    id.ok_or_else(|| {
+        format!(
+            "no such {}: '{}'",
+            if field == Field::Owner {
+                "user"
+            } else {
+                "group"
+            },
+            name
+        )
+    })
+}
+
+#[cfg(not(unix))]
+fn resolve_id(_field: Field, _name: &str) -> Result<u64, String> {
+    Err("owner and group aren't supported on this platform".to_string())
+}
+
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn string(record: &Record, field: Field) -> String {
+    match field {
+        Field::Name => record.entry.file_name().to_string_lossy().into_owned(),
+        Field::Path => record.path().to_string_lossy().into_owned(),
+        Field::Ext => record
+            .path()
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        _ => record.type_name().to_string(),
+    }
+}
+
+fn number(record: &Record, field: Field) -> Option<u64> {
+    match field {
+        Field::Depth => Some(record.entry.depth() as u64),
+        Field::Size => record.size(),
+        Field::Owner => record.uid().map(u64::from),
+        Field::Group => record.gid().map(u64::from),
+        Field::Inode => record.ino(),
+        Field::Dev => record.dev(),
+        Field::Nlink => record.nlink(),
+        _ => None,
+    }
+}
+
+fn time(record: &Record, field: Field) -> Option<SystemTime> {
+    match field {
+        Field::Mtime => record.mtime(),
+        Field::Atime => record.atime(),
+        Field::Ctime => record.ctime(),
+        Field::Btime => record.btime(),
+        _ => None,
+    }
+}
+
+fn compare<T: PartialOrd>(lhs: T, op: Op, rhs: T) -> bool {
+    match op {
+        Op::Eq => lhs == rhs,
+        Op::Ne => lhs != rhs,
+        Op::Lt => lhs < rhs,
+        Op::Le => lhs <= rhs,
+        Op::Gt => lhs > rhs,
+        Op::Ge => lhs >= rhs,
+        Op::Glob | Op::NotGlob => false,
+    }
+}
+
+impl Expr {
+    pub fn needs_metadata(&self) -> bool {
+        match self {
+            Self::And(lhs, rhs) | Self::Or(lhs, rhs) => {
+                lhs.needs_metadata() || rhs.needs_metadata()
+            }
+            Self::Not(expr) => expr.needs_metadata(),
+            Self::Cmp(field, _, _) => !matches!(
+                field,
+                Field::Name | Field::Path | Field::Ext | Field::Depth | Field::Type
+            ),
+        }
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.matches(record) && rhs.matches(record),
+            Self::Or(lhs, rhs) => lhs.matches(record) || rhs.matches(record),
+            Self::Not(expr) => !expr.matches(record),
+
+            // Missing metadata never satisfies a comparison, so an entry that
+            // vanished mid-walk won't sneak through a `size > 1G` filter.
+            Self::Cmp(field, op, value) => match value {
+                Value::Str(expected) => {
+                    let actual = string(record, *field);
+
+                    match op {
+                        Op::Glob => glob_match(expected.as_bytes(), actual.as_bytes()),
+                        Op::NotGlob => !glob_match(expected.as_bytes(), actual.as_bytes()),
+                        _ => compare(actual.as_str(), *op, expected.as_str()),
+                    }
+                }
+                Value::Num(expected) => {
+                    number(record, *field).is_some_and(|actual| compare(actual, *op, *expected))
+                }
+                Value::Time(expected) => {
+                    time(record, *field).is_some_and(|actual| compare(actual, *op, *expected))
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // The parsed expression written out with every grouping in brackets, so
+    // that what binds tighter than what can be read off of it.
+    fn shape(expr: &Expr) -> String {
+        match expr {
+            Expr::And(lhs, rhs) => format!("({} and {})", shape(lhs), shape(rhs)),
+            Expr::Or(lhs, rhs) => format!("({} or {})", shape(lhs), shape(rhs)),
+            Expr::Not(expr) => format!("!{}", shape(expr)),
+            Expr::Cmp(field, op, value) => {
+                let value = match value {
+                    Value::Str(string) => format!("{:?}", string),
+                    Value::Num(number) => number.to_string(),
+                    Value::Time(time) => {
+                        format!("@{}", time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+                    }
+                };
+
+                format!("{:?}{:?}{}", field, op, value)
+            }
+        }
+    }
+
+    fn parsed(input: &str) -> String {
+        shape(&parse(input).unwrap_or_else(|e| panic!("{:?}: {}", input, e)))
+    }
+
+    fn error(input: &str) -> String {
+        match parse(input) {
+            Ok(expr) => panic!("{:?} parsed as {}", input, shape(&expr)),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parsed("depth=1 or depth=2 and depth=3"),
+            "(DepthEq1 or (DepthEq2 and DepthEq3))"
+        );
+        assert_eq!(
+            parsed("depth=1 and depth=2 or depth=3"),
+            "((DepthEq1 and DepthEq2) or DepthEq3)"
+        );
+        assert_eq!(
+            parsed("(depth=1 or depth=2) and depth=3"),
+            "((DepthEq1 or DepthEq2) and DepthEq3)"
+        );
+        assert_eq!(
+            parsed("depth=1 || depth=2 && depth=3"),
+            "(DepthEq1 or (DepthEq2 and DepthEq3))"
+        );
+        assert_eq!(
+            parsed("depth=1 or depth=2 or depth=3"),
+            "((DepthEq1 or DepthEq2) or DepthEq3)"
+        );
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        assert_eq!(
+            parsed("not depth=1 and depth=2"),
+            "(!DepthEq1 and DepthEq2)"
+        );
+        assert_eq!(parsed("!depth=1 or depth=2"), "(!DepthEq1 or DepthEq2)");
+        assert_eq!(parsed("!(depth=1 or depth=2)"), "!(DepthEq1 or DepthEq2)");
+        assert_eq!(parsed("not not depth=1"), "!!DepthEq1");
+
+        // A ! that's followed by = or ~ is an operator instead.
+        assert_eq!(parsed("depth!=1"), "DepthNe1");
+        assert_eq!(parsed("name!~'*.o'"), "NameNotGlob\"*.o\"");
+        assert_eq!(
+            error("name ! ~ x"),
+            "expected a comparison operator after 'name'"
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(parsed("depth=1"), "DepthEq1");
+        assert_eq!(parsed("depth == 1"), "DepthEq1");
+        assert_eq!(parsed("depth<1"), "DepthLt1");
+        assert_eq!(parsed("depth<=1"), "DepthLe1");
+        assert_eq!(parsed("depth>1"), "DepthGt1");
+        assert_eq!(parsed("depth >= 1"), "DepthGe1");
+        assert_eq!(parsed("name~*.rs"), "NameGlob\"*.rs\"");
+        assert_eq!(parsed("type=dir"), "TypeEq\"dir\"");
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(parsed("name = \"two words\""), "NameEq\"two words\"");
+        assert_eq!(parsed("name = 'two words'"), "NameEq\"two words\"");
+        assert_eq!(parsed(r#"name = 'it\'s'"#), "NameEq\"it's\"");
+        assert_eq!(parsed(r#"name = "a \"b\"""#), "NameEq\"a \\\"b\\\"\"");
+        assert_eq!(parsed(r#"name = "back\\slash""#), "NameEq\"back\\\\slash\"");
+        assert_eq!(parsed("name = 'and or not'"), "NameEq\"and or not\"");
+        assert_eq!(parsed("name = \"(x)\""), "NameEq\"(x)\"");
+
+        assert_eq!(error("name = 'open"), "unterminated string");
+        assert_eq!(error("name = \"open\\"), "unterminated string");
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("100B"), Ok(100));
+        assert_eq!(parse_size("1K"), Ok(1024));
+        assert_eq!(parse_size("1k"), Ok(1024));
+        assert_eq!(parse_size("1KB"), Ok(1000));
+        assert_eq!(parse_size("1KiB"), Ok(1024));
+        assert_eq!(parse_size("100M"), Ok(100 * 1024 * 1024));
+        assert_eq!(parse_size("100MB"), Ok(100_000_000));
+        assert_eq!(parse_size("2MiB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("1GB"), Ok(1_000_000_000));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+        assert_eq!(parse_size("1P"), Ok(1 << 50));
+        assert_eq!(parse_size("1.5K"), Ok(1536));
+        assert_eq!(parse_size("0.5MB"), Ok(500_000));
+
+        assert_eq!(parse_size("M"), Err("invalid size 'M'".to_string()));
+        assert_eq!(parse_size(""), Err("invalid size ''".to_string()));
+        assert_eq!(
+            parse_size("1.2.3K"),
+            Err("invalid size '1.2.3K'".to_string())
+        );
+        assert_eq!(
+            parse_size("1X"),
+            Err("invalid size suffix in '1X'".to_string())
+        );
+        assert_eq!(
+            parse_size("1KBB"),
+            Err("invalid size suffix in '1KBB'".to_string())
+        );
+        assert_eq!(
+            parse_size("1XiB"),
+            Err("invalid size suffix in '1XiB'".to_string())
+        );
+
+        assert_eq!(parsed("size > 1M"), "SizeGt1048576");
+        assert_eq!(error("size > big"), "invalid size 'big'");
+    }
+
+    #[test]
+    fn dates() {
+        let at = |literal: &str| {
+            parse_date(literal)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+        };
+
+        assert_eq!(at("1970-01-01"), Duration::ZERO);
+        assert_eq!(at("1970-01-02"), Duration::from_secs(86400));
+        assert_eq!(at("2000-03-01"), Duration::from_secs(951868800));
+        assert_eq!(at("2000-03-01T12:30"), Duration::from_secs(951913800));
+        assert_eq!(at("2000-03-01T12:30:15"), Duration::from_secs(951913815));
+        assert_eq!(at("2016-12-31T23:59:60"), Duration::from_secs(1483228800));
+
+        assert_eq!(
+            parse_date("1969-12-31").unwrap(),
+            UNIX_EPOCH - Duration::from_secs(86400)
+        );
+
+        assert_eq!(parsed("mtime > 1970-01-02"), "MtimeGt@86400");
+    }
+
+    #[test]
+    fn invalid_dates_and_times() {
+        for literal in [
+            "yesterday",
+            "2000",
+            "2000-03",
+            "2000-03-01-02",
+            "2000-00-01",
+            "2000-13-01",
+            "2000-03-00",
+            "2000-03-32",
+            "2000-03-01T",
+            "2000-03-01T12",
+            "2000-03-01T24:00",
+            "2000-03-01T12:60",
+            "2000-03-01T12:30:61",
+            "2000-03-01T12:30:15:00",
+            "2000-03-01 12:30",
+        ] {
+            assert_eq!(
+                parse_date(literal).unwrap_err(),
+                format!(
+                    "invalid date '{}', expected YYYY-MM-DD[THH:MM[:SS]]",
+                    literal
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn globs() {
+        let glob = |pattern: &str, text: &str| glob_match(pattern.as_bytes(), text.as_bytes());
+
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "main.rs.bak"));
+        assert!(glob("?ain.rs", "main.rs"));
+        assert!(!glob("?main.rs", "main.rs"));
+        assert!(glob("*", ""));
+        assert!(glob("", ""));
+        assert!(!glob("", "x"));
+        assert!(glob("x**", "x"));
+
+        // The first a isn't necessarily the one that's followed by a b, so
+        // the * has to go back and take more.
+        assert!(glob("*a*b", "xaxxb"));
+        assert!(glob("*a*b", "aab"));
+        assert!(glob("*a*b", "abab"));
+        assert!(glob("*a*b", "xaybzb"));
+        assert!(!glob("*a*b", "xabx"));
+        assert!(!glob("*a*b", "bbba"));
+        assert!(glob("a*b*c", "abcbc"));
+        assert!(glob("*ab", "aab"));
+        assert!(!glob("*ab", "aba"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error(""), "unexpected end of expression");
+        assert_eq!(error("depth=1 and"), "unexpected end of expression");
+        assert_eq!(error("depth=1 & depth=2"), "expected '&&'");
+        assert_eq!(error("depth=1 | depth=2"), "expected '||'");
+        assert_eq!(error("(depth=1"), "missing closing parenthesis");
+        assert_eq!(error("depth=1)"), "unexpected trailing RParen");
+        assert_eq!(
+            error("depth=1 depth=2"),
+            "unexpected trailing Word(\"depth\")"
+        );
+        assert_eq!(error("and"), "unexpected And");
+        assert_eq!(
+            error("colour=red"),
+            "unknown field 'colour', expected one of: name, path, ext, size, depth, \
+             mtime, atime, ctime, btime, type, owner, group, inode, dev, nlink"
+        );
+        assert_eq!(error("size"), "expected a comparison operator after 'size'");
+        assert_eq!(
+            error("size 1"),
+            "expected a comparison operator after 'size'"
+        );
+        assert_eq!(
+            error("size >"),
+            "expected a value to compare 'size' against"
+        );
+        assert_eq!(
+            error("size > ("),
+            "expected a value to compare 'size' against"
+        );
+        assert_eq!(
+            error("type=pipe"),
+            "unknown type 'pipe', expected file, dir, symlink, or other"
+        );
+        assert_eq!(error("type~d*"), "type can't be glob matched");
+        assert_eq!(
+            error("size~1*"),
+            "glob matching only applies to name, path, and ext"
+        );
+        assert_eq!(
+            error("depth=-1"),
+            "invalid number '-1': invalid digit found in string"
+        );
+    }
+}
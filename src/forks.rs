@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ads;
+
+// Whatever else there is to a file, besides its contents, that a walk never
+// sees, and that's listed and hashed right after it: its alternate data
+// streams with --ads, and with --mac-metadata, its resource fork and the
+// extended attributes the Finder keeps, which are what Finder copies along
+// with a file, and what it would be missing if it came out of an archive that
+// lost them. With --xattrs, every one of its extended attributes.
+pub enum Fork {
+    // Read like any file, from a path of its own.
+    Stream(PathBuf),
+    // Listed as file@name, as far as anyone reading it is concerned, and
+    // hashed by its value.
+    Xattr(PathBuf, Vec<u8>),
+}
+
+impl Fork {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Stream(path) | Self::Xattr(path, _) => path,
+        }
+    }
+}
+
+// The ones that say what a file is, and where it came from, as opposed to
+// those for Spotlight, which any copy gets its own of.
+#[cfg(target_os = "macos")]
+const FINDER_ATTRIBUTES: [&str; 2] = ["com.apple.FinderInfo", "com.apple.quarantine"];
+
+pub fn of(path: &Path, ads: bool, mac_metadata: bool, xattrs: bool) -> io::Result<Vec<Fork>> {
+    let mut forks = Vec::new();
+
+    if ads {
+        for name in ads::streams(path)? {
+            forks.push(Fork::Stream(ads::stream_path(path, &name)));
+        }
+    }
+
+    if mac_metadata {
+        mac(path, !xattrs, &mut forks)?;
+    }
+
+    if xattrs {
+        attributes(path, &mut forks)?;
+    }
+
+    Ok(forks)
+}
+
+// An attribute goes by its file's path, a NUL, and its name, since a NUL is
+// the one byte that neither a path nor an attribute's name can have in it, so
+// the user.x of a is never mistaken for a file that's actually called
+// a@user.x, in a manifest or anywhere else. Manifests write it as \@.
+#[cfg(unix)]
+fn attribute_path(path: &Path, name: &OsStr) -> PathBuf {
+    let mut listed = path.as_os_str().to_os_string();
+    listed.push("\0");
+    listed.push(name);
+    PathBuf::from(listed)
+}
+
+// For what's only ever read, rather than read back, like a listing, or what
+// --diff says is different, where file@name is clearer.
+pub fn shown(path: &Path) -> Cow<'_, Path> {
+    let bytes = path.as_os_str().as_encoded_bytes();
+
+    if !bytes.contains(&0) {
+        return Cow::Borrowed(path);
+    }
+
+    let bytes = bytes
+        .iter()
+        .map(|&byte| if byte == 0 { b'@' } else { byte })
+        .collect();
+
+    // One ASCII byte in place of another leaves the encoding as it was.
+    Cow::Owned(PathBuf::from(unsafe {
+        OsString::from_encoded_bytes_unchecked(bytes)
+    }))
+}
+
+// Sorted by name, since they're listed in whatever order the filesystem keeps
+// them in, which needn't be the same for a copy, and a manifest that shuffles
+// around between runs would be no use to --diff.
+#[cfg(unix)]
+fn attributes(path: &Path, forks: &mut Vec<Fork>) -> io::Result<()> {
+    let mut names = crate::xattr::list(path)?;
+    names.sort();
+
+    for name in names {
+        if let Some(value) = crate::xattr::get(path, &name)? {
+            forks.push(Fork::Xattr(attribute_path(path, &name), value));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn attributes(_: &Path, _: &mut Vec<Fork>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are only supported on Linux and macOS",
+    ))
+}
+
+// The resource fork can be read as a file of its own, at ..namedfork/rsrc
+// under the file, and it's always there, but only worth listing when it isn't
+// empty. The Finder's attributes are left to --xattrs when it's given, which
+// lists them along with all the rest.
+#[cfg(target_os = "macos")]
+fn mac(path: &Path, finder: bool, forks: &mut Vec<Fork>) -> io::Result<()> {
+    let resource_fork = path.join("..namedfork/rsrc");
+
+    if resource_fork
+        .metadata()
+        .is_ok_and(|metadata| metadata.len() > 0)
+    {
+        forks.push(Fork::Stream(resource_fork));
+    }
+
+    for name in FINDER_ATTRIBUTES.iter().filter(|_| finder).map(OsStr::new) {
+        if let Some(value) = crate::xattr::get(path, name)? {
+            forks.push(Fork::Xattr(attribute_path(path, name), value));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn mac(_: &Path, _: bool, _: &mut Vec<Fork>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "resource forks and Finder metadata are only supported on macOS",
+    ))
+}
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+// The name of the network filesystem a path is on, or None if it's local, or
+// that can't be told. FUSE counts, since most of what's mounted with it, sshfs,
+// rclone, s3fs and the like, is somewhere else, and even what isn't is a round
+// trip through a process of its own for everything that's read.
+#[cfg(target_os = "linux")]
+pub fn remote(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NFS: i64 = 0x6969;
+    const SMB: i64 = 0x517b;
+    const CIFS: i64 = 0xff53_4d42;
+    const SMB2: i64 = 0xfe53_4d42;
+    const FUSE: i64 = 0x6573_5546;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    // f_type is an i64 here, but signed and 32 bits wide on some other
+    // architectures, and unsigned on others, where CIFS's magic number
+    // wouldn't fit otherwise.
+    #[allow(clippy::useless_conversion)]
+    let f_type = i64::from(stats.f_type) & 0xffff_ffff;
+
+    match f_type {
+        NFS => Some("NFS"),
+        SMB | CIFS | SMB2 => Some("SMB"),
+        FUSE => Some("FUSE"),
+        _ => None,
+    }
+}
+
+// macOS names the filesystem outright.
+#[cfg(target_os = "macos")]
+pub fn remote(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+
+    match name.to_bytes() {
+        b"nfs" => Some("NFS"),
+        b"smbfs" => Some("SMB"),
+        b"afpfs" => Some("AFP"),
+        b"webdav" => Some("WebDAV"),
+        name if name.starts_with(b"macfuse") || name.starts_with(b"osxfuse") => Some("FUSE"),
+        _ => None,
+    }
+}
+
+// Windows only says whether the drive is a remote one, which covers UNC paths
+// and mapped drives alike.
+#[cfg(windows)]
+pub fn remote(path: &Path) -> Option<&'static str> {
+    use std::os::windows::ffi::OsStrExt;
+
+    const DRIVE_REMOTE: u32 = 4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetVolumePathNameW(path: *const u16, volume: *mut u16, length: u32) -> i32;
+        fn GetDriveTypeW(root: *const u16) -> u32;
+    }
+
+    let path = std::fs::canonicalize(path).ok()?;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut volume = vec![0u16; wide.len().max(261)];
+
+    if unsafe { GetVolumePathNameW(wide.as_ptr(), volume.as_mut_ptr(), volume.len() as u32) } == 0 {
+        return None;
+    }
+
+    match unsafe { GetDriveTypeW(volume.as_ptr()) } {
+        DRIVE_REMOTE => Some("a network drive"),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn remote(_: &Path) -> Option<&'static str> {
+    None
+}
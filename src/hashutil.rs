@@ -1,6 +1,9 @@
 use std::fmt::Write;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use md5::{Context as Md5Context, Digest as Md5Digest};
 
@@ -11,6 +14,18 @@ pub use xxhash_rust::xxh3::Xxh3Default; // <3
 
 use memmap2::Mmap;
 
+use crate::progress::Progress;
+use crate::throttle::Throttle;
+
+#[cfg(target_os = "linux")]
+use crate::uring;
+
+// Files bigger than this get mapped into memory instead of being read.
+const MMAP_THRESHOLD: u64 = (1024 * 1024) * 20;
+
+// Reads queued at once per io_uring, and how much each of them reads.
+const URING_ENTRIES: u32 = 32;
+const URING_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Clone)]
 pub enum HashAlgorithm {
@@ -33,63 +48,659 @@ impl HashAlgorithm {
             Self::Md5 => 16,
         }
     }
+
+    // As it's given to -C.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Xxh3 => "xxh3",
+            Self::Sha224 => "sha224",
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+            Self::Md5 => "md5",
+        }
+    }
+
+    // For what's already in memory, rather than in a file.
+    pub fn hash_bytes(&self, data: &[u8]) -> String {
+        fn hash<H: Hasher>(data: &[u8]) -> String {
+            let mut hasher = H::create();
+            hasher.update(data);
+            hexlify(hasher.finalize())
+        }
+
+        match self {
+            Self::Xxh3 => hash::<Xxh3Default>(data),
+            Self::Sha224 => hash::<Sha224>(data),
+            Self::Sha256 => hash::<Sha256>(data),
+            Self::Sha384 => hash::<Sha384>(data),
+            Self::Sha512 => hash::<Sha512>(data),
+            Self::Md5 => hash::<Md5Context>(data),
+        }
+    }
+}
+
+// Unlike the other choices, which clap makes sure of, an algorithm can come
+// from places other than the command line, so a name that isn't one is an
+// error to report rather than a bug.
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "xxh3" => Ok(Self::Xxh3),
+            "sha224" => Ok(Self::Sha224),
+            "sha256" => Ok(Self::Sha256),
+            "sha384" => Ok(Self::Sha384),
+            "sha512" => Ok(Self::Sha512),
+            "md5" => Ok(Self::Md5),
+            _ => Err(format!(
+                "no such hash algorithm as '{}', it has to be one of xxh3, sha224, sha256, sha384, sha512 or md5",
+                s
+            )),
+        }
+    }
 }
 
-impl From<&String> for HashAlgorithm {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoBackend {
+    Std,
+    Uring,
+}
+
+impl From<&String> for IoBackend {
     fn from(s: &String) -> Self {
         match s.to_lowercase().as_str() {
-            "xxh3" => Self::Xxh3,
-            "sha224" => Self::Sha224,
-            "sha256" => Self::Sha256,
-            "sha384" => Self::Sha384,
-            "sha512" => Self::Sha512,
-            "md5" => Self::Md5,
-            _ => panic!("Invalid hash algorithm! '{}'", s),
+            "std" => Self::Std,
+            "uring" => Self::Uring,
+            _ => panic!("Invalid I/O backend! '{}'", s),
+        }
+    }
+}
+
+// How files are opened and read for hashing.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    pub backend: IoBackend,
+    pub noatime: bool,
+    pub no_cache_pollution: bool,
+    pub direct_io: bool,
+    pub bwlimit: Option<Arc<Throttle>>,
+    pub progress: Arc<Progress>,
+    pub buffer_size: usize,
+    pub retries: u32,
+    pub rehash: u32,
+    pub allow_special: bool,
+    pub mmap: bool,
+}
+
+impl ReadOptions {
+    // O_NOATIME is only permitted on files the user owns, short of being root,
+    // so the file is opened again without it whenever it's refused, and the
+    // same goes for O_DIRECT on a filesystem that doesn't support it.
+    #[cfg(target_os = "linux")]
+    pub fn open(&self, path: &Path) -> std::io::Result<File> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        self.check_special(path)?;
+
+        let open = |flags| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(flags)
+                .open(path)
+        };
+        let refused = |e: &std::io::Error, errno| e.raw_os_error() == Some(errno);
+
+        let direct = if self.direct_io { libc::O_DIRECT } else { 0 };
+
+        let result = match open(direct | if self.noatime { libc::O_NOATIME } else { 0 }) {
+            Err(e) if self.noatime && refused(&e, libc::EPERM) => open(direct),
+            result => result,
+        };
+
+        match result {
+            Err(e) if self.direct_io && refused(&e, libc::EINVAL) => self.buffered().open(path),
+            result => result,
         }
     }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(&self, path: &Path) -> std::io::Result<File> {
+        self.check_special(path)?;
+        File::open(path)
+    }
+
+    // Opening a FIFO waits for something to write to it, which could be
+    // forever, and opening a device can do things, like rewinding a tape, so
+    // what the path is gets checked before it's opened, rather than after.
+    // Whatever was a regular file when it was walked could be something else
+    // by the time it's hashed, too.
+    fn check_special(&self, path: &Path) -> std::io::Result<()> {
+        if self.allow_special {
+            return Ok(());
+        }
+
+        let file_type = path.metadata()?.file_type();
+
+        if file_type.is_file() || file_type.is_dir() {
+            return Ok(());
+        }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "is {}, which is only read with --allow-special",
+                special_kind(&file_type)
+            ),
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn buffered(&self) -> Self {
+        Self {
+            direct_io: false,
+            ..self.clone()
+        }
+    }
+
+    // With --retries, whatever failed in a way that could come right by
+    // itself, as reads on network filesystems do, is tried again, waiting
+    // twice as long each time, from a tenth of a second, up to 10 seconds.
+    // Anything that still fails is reported like any other failure.
+    pub fn retry<T>(
+        &self,
+        path: &Path,
+        first: std::io::Result<T>,
+        mut again: impl FnMut() -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let mut result = first;
+
+        for wait in self.waits() {
+            match &result {
+                Err(e) if transient(e) => {
+                    crate::log::info(format_args!(
+                        "Retrying {} in {:.1}s, after: {}",
+                        path.display(),
+                        wait.as_secs_f64(),
+                        e
+                    ));
+                    std::thread::sleep(wait);
+                    result = again();
+                }
+                _ => return result,
+            }
+        }
+
+        match result {
+            Err(e) if self.retries > 0 && transient(&e) => Err(std::io::Error::new(
+                e.kind(),
+                format!("{}, even after {} retries", e, self.retries),
+            )),
+            result => result,
+        }
+    }
+
+    // A file that changed while it was read is only hashed again with
+    // --rehash, as many times as it says, until a read gets through it
+    // without it changing.
+    pub fn rehash<T>(
+        &self,
+        first: std::io::Result<T>,
+        mut again: impl FnMut() -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let mut result = first;
+
+        for _ in 0..self.rehash {
+            match &result {
+                Err(e) if unstable(e) => result = again(),
+                _ => return result,
+            }
+        }
+
+        match result {
+            Err(e) if self.rehash > 0 && unstable(&e) => Err(std::io::Error::other(format!(
+                "{}, every one of the {} times it was hashed",
+                e,
+                self.rehash + 1
+            ))),
+            result => result,
+        }
+    }
+
+    // How long to wait before each retry there's going to be.
+    pub fn waits(&self) -> impl Iterator<Item = Duration> {
+        std::iter::successors(Some(Duration::from_millis(100)), |wait| {
+            Some((*wait * 2).min(Duration::from_secs(10)))
+        })
+        .take(self.retries as usize)
+    }
+
+    // Called with every chunk that's been read, holding up the thread that
+    // read it for as long as --bwlimit says it should.
+    pub fn chunk_read(&self, bytes: usize) {
+        self.progress.read(bytes);
+
+        if let Some(throttle) = &self.bwlimit {
+            throttle.consume(bytes);
+        }
+    }
+
+    // With no_cache_pollution, the kernel is told that the file is going to
+    // be read sequentially, so it reads ahead further, and then that its pages
+    // won't be needed again once it's been hashed, so they're the first to go
+    // rather than whatever else was in the page cache.
+    pub fn start_reading(&self, file: &File) {
+        #[cfg(target_os = "linux")]
+        if self.no_cache_pollution {
+            advise(file, libc::POSIX_FADV_SEQUENTIAL);
+        }
+
+        let _ = file;
+    }
+
+    pub fn done_reading(&self, file: &File) {
+        #[cfg(target_os = "linux")]
+        if self.no_cache_pollution {
+            advise(file, libc::POSIX_FADV_DONTNEED);
+        }
+
+        let _ = file;
+    }
+}
+
+#[cfg(unix)]
+fn special_kind(file_type: &std::fs::FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        "a FIFO"
+    } else if file_type.is_char_device() {
+        "a character device"
+    } else if file_type.is_block_device() {
+        "a block device"
+    } else if file_type.is_socket() {
+        "a socket"
+    } else {
+        "a special file"
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_: &std::fs::FileType) -> &'static str {
+    "a special file"
+}
+
+// The errors a network filesystem hands out when the server's gone for a
+// moment, as opposed to ones that say something about the file itself.
+pub fn transient(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    if let Some(errno) = e.raw_os_error() {
+        return [
+            libc::EIO,
+            libc::ESTALE,
+            libc::ETIMEDOUT,
+            libc::EAGAIN,
+            libc::EINTR,
+            libc::ECONNRESET,
+            libc::EHOSTDOWN,
+            // Out of file descriptors, for the moment, until other threads
+            // close theirs.
+            libc::EMFILE,
+            libc::ENFILE,
+        ]
+        .contains(&errno);
+    }
+
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::Interrupted
+            | std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+// These are only hints, so there's nothing to be done if one isn't taken.
+#[cfg(target_os = "linux")]
+fn advise(file: &File, advice: libc::c_int) {
+    use std::os::fd::AsRawFd;
+
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) };
 }
 
 macro_rules! hash_file {
-    ($algo:expr, $path:expr) => {
+    ($algo:expr, $path:expr, $read:expr) => {
+        match $algo {
+            HashAlgorithm::Xxh3 => hash_file::<Xxh3Default>($path, $read),
+            HashAlgorithm::Sha224 => hash_file::<Sha224>($path, $read),
+            HashAlgorithm::Sha256 => hash_file::<Sha256>($path, $read),
+            HashAlgorithm::Sha384 => hash_file::<Sha384>($path, $read),
+            HashAlgorithm::Sha512 => hash_file::<Sha512>($path, $read),
+            HashAlgorithm::Md5 => hash_file::<Md5Context>($path, $read),
+        }
+    };
+}
+
+macro_rules! hash_batch {
+    ($algo:expr, $paths:expr, $read:expr) => {
         match $algo {
-            HashAlgorithm::Xxh3 => hash_file::<Xxh3Default>($path),
-            HashAlgorithm::Sha224 => hash_file::<Sha224>($path),
-            HashAlgorithm::Sha256 => hash_file::<Sha256>($path),
-            HashAlgorithm::Sha384 => hash_file::<Sha384>($path),
-            HashAlgorithm::Sha512 => hash_file::<Sha512>($path),
-            HashAlgorithm::Md5 => hash_file::<Md5Context>($path),
+            HashAlgorithm::Xxh3 => hash_batch::<Xxh3Default>($paths, $read),
+            HashAlgorithm::Sha224 => hash_batch::<Sha224>($paths, $read),
+            HashAlgorithm::Sha256 => hash_batch::<Sha256>($paths, $read),
+            HashAlgorithm::Sha384 => hash_batch::<Sha384>($paths, $read),
+            HashAlgorithm::Sha512 => hash_batch::<Sha512>($paths, $read),
+            HashAlgorithm::Md5 => hash_batch::<Md5Context>($paths, $read),
         }
     };
 }
 
-pub fn hash_file<H: Hasher>(path: &String) -> std::io::Result<String> {
-    let mut file = File::open(path)?;
+pub fn hash_file<H: Hasher>(path: &Path, read: &ReadOptions) -> std::io::Result<String> {
+    let hash = || {
+        read.retry(path, hash_file_once::<H>(path, read), || {
+            hash_file_once::<H>(path, read)
+        })
+    };
+    read.rehash(hash(), hash)
+}
+
+// Whatever's writing to a file while it's read leaves a hash of some of the
+// old contents and some of the new, which is no use to anyone, and would only
+// show up in --diff as corruption later on. The size and mtime are compared
+// from before and after reading it, which doesn't catch writes that keep both,
+// but those are rare enough outside of deliberate tampering.
+#[derive(Debug)]
+pub struct Unstable;
+
+impl std::fmt::Display for Unstable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "changed while it was being hashed, so its hash could be of neither version"
+        )
+    }
+}
+
+impl std::error::Error for Unstable {}
+
+pub fn unstable(e: &std::io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Unstable>())
+}
+
+// Such a file is still listed, rather than left out like one that couldn't be
+// read, but with a row of ~ in place of its hash, so that it's flagged as
+// unstable in the manifest, and --diff can say so, instead of calling it a
+// mismatch. It's the same length as a hash, so it reads back like any other.
+pub fn unstable_digest(algorithm: &HashAlgorithm) -> String {
+    "~".repeat(algorithm.digest_size() * 2)
+}
+
+pub fn is_unstable_digest(hash: &str) -> bool {
+    !hash.is_empty() && hash.bytes().all(|byte| byte == b'~')
+}
+
+fn stamp(metadata: &std::fs::Metadata) -> (u64, Option<std::time::SystemTime>) {
+    (metadata.len(), metadata.modified().ok())
+}
+
+fn hash_file_once<H: Hasher>(path: &Path, read: &ReadOptions) -> std::io::Result<String> {
+    read.progress.at(path);
+
+    let mut file = read.open(path)?;
     let mut hasher = H::create();
 
-    let _ = file.seek(SeekFrom::End(0));
-    let file_size = file.stream_position().ok().unwrap();
-    let _ = file.seek(SeekFrom::Start(0));
+    read.start_reading(&file);
 
-    if file_size > (1024*1024)*20 {
+    // FIFOs and devices, with --allow-special, can't be mapped, or their size
+    // known up front, so they're read in chunks until there's nothing left.
+    let metadata = file.metadata()?;
+    let regular = metadata.is_file();
+    let file_size = if regular {
+        metadata.len()
+    } else {
+        read.buffer_size as u64
+    };
+    let before = stamp(&metadata);
+
+    // Page cache and all, mapping the file would undo the point of O_DIRECT.
+    if read.direct_io {
+        read_direct(&mut file, &mut hasher, read)?;
+    } else if read.mmap && regular && file_size > MMAP_THRESHOLD {
         let mmap = unsafe { Mmap::map(&file)? };
-        hasher.update(&mmap);
-    } 
 
-    // Read in 128kb chunks
+        // In pieces, so the throttle and progress have something to work with.
+        for chunk in mmap.chunks(1024 * 1024) {
+            read.chunk_read(chunk.len());
+            hasher.update(chunk);
+        }
+    }
+    // Read in chunks of buffer_size, 128kb by default, or less for files
+    // smaller than that, since zeroing the buffer isn't free either.
     else {
-        let mut reader = BufReader::new(file);
-        let mut buffer = vec![0; 128*1024];
+        let mut reader = BufReader::new(&file);
+        let mut buffer = vec![0; read.buffer_size.min(file_size as usize + 1)];
+
+        // A read that fails partway through has to fail the file, or what's
+        // been read up to there would pass for the hash of all of it.
+        loop {
+            let bytes_read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => bytes_read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            read.chunk_read(bytes_read);
+            hasher.update(&buffer[..bytes_read]);
+        }
+    }
+
+    read.done_reading(&file);
+
+    if regular && stamp(&file.metadata()?) != before {
+        return Err(std::io::Error::other(Unstable));
+    }
+
+    Ok(hexlify(hasher.finalize()))
+}
 
-        while let Ok(bytes_read) = reader.read(&mut buffer) {
-            if bytes_read == 0 {
-                break;
+pub const DEFAULT_BUFFER_SIZE: usize = 128 * 1024;
+
+pub fn parse_buffer_size(s: &str) -> Result<usize, String> {
+    match crate::filter::parse_size(s)? {
+        size if size < 4096 => Err("must be at least 4K".to_string()),
+        size if size > 1024 * 1024 * 1024 => Err("must be at most 1G".to_string()),
+        size => Ok(size as usize),
+    }
+}
+
+// O_DIRECT reads have to go into memory aligned to the device's block size,
+// and be multiples of it in length; 4096 covers any device around. The reads
+// are big, since without the page cache there's no read ahead either.
+#[cfg(target_os = "linux")]
+fn read_direct<H: Hasher>(
+    file: &mut File,
+    hasher: &mut H,
+    read: &ReadOptions,
+) -> std::io::Result<()> {
+    use std::alloc::{alloc, dealloc, Layout};
+
+    let layout = Layout::from_size_align(1024 * 1024, 4096).unwrap();
+    let pointer = unsafe { alloc(layout) };
+
+    if pointer.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+
+    let buffer = unsafe { std::slice::from_raw_parts_mut(pointer, layout.size()) };
+
+    let result = loop {
+        match file.read(buffer) {
+            Ok(0) => break Ok(()),
+            Ok(bytes_read) => {
+                read.chunk_read(bytes_read);
+                hasher.update(&buffer[..bytes_read]);
             }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
 
-            hasher.update(&buffer[..bytes_read]);
+    unsafe { dealloc(pointer, layout) };
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_direct<H: Hasher>(
+    file: &mut File,
+    hasher: &mut H,
+    read: &ReadOptions,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(()),
+            bytes_read => {
+                read.chunk_read(bytes_read);
+                hasher.update(&buffer[..bytes_read]);
+            }
         }
     }
+}
 
-    Ok(hexlify(hasher.finalize()))
+// Hashes a batch of files in one go, which with io_uring means the reads for
+// all of them are queued at once rather than made one after the other.
+pub fn hash_batch<H: Hasher>(
+    paths: &[PathBuf],
+    read: &ReadOptions,
+) -> Vec<std::io::Result<String>> {
+    match read.backend {
+        IoBackend::Std => paths
+            .iter()
+            .map(|path| hash_file::<H>(path, read))
+            .collect(),
+
+        // The ring's buffers aren't aligned for O_DIRECT.
+        IoBackend::Uring if read.direct_io => paths
+            .iter()
+            .map(|path| hash_file::<H>(path, read))
+            .collect(),
+
+        #[cfg(target_os = "linux")]
+        IoBackend::Uring => hash_batch_uring::<H>(paths, read),
+
+        #[cfg(not(target_os = "linux"))]
+        IoBackend::Uring => unreachable!("io_uring is only available on Linux"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn hash_batch_uring<H: Hasher>(
+    paths: &[PathBuf],
+    read: &ReadOptions,
+) -> Vec<std::io::Result<String>> {
+    let mut results: Vec<Option<std::io::Result<String>>> = paths.iter().map(|_| None).collect();
+    let mut queued: Vec<usize> = Vec::new();
+    let mut files: Vec<File> = Vec::new();
+    let mut stamps = Vec::new();
+
+    if let Some(first) = paths.first() {
+        read.progress.at(first);
+    }
+
+    for (i, path) in paths.iter().enumerate() {
+        match read
+            .open(path)
+            .and_then(|file| Ok((file.metadata()?, file)))
+        {
+            // Big files gain nothing from being queued, mmap does them better,
+            // and the ring reads at offsets, which FIFOs don't have.
+            Ok((metadata, _)) if !metadata.is_file() || metadata.len() > MMAP_THRESHOLD => {
+                results[i] = Some(hash_file::<H>(path, read))
+            }
+            Ok((metadata, file)) => {
+                read.start_reading(&file);
+                queued.push(i);
+                files.push(file);
+                stamps.push(stamp(&metadata));
+            }
+            Err(e) => results[i] = Some(Err(e)),
+        }
+    }
+
+    let mut hashers: Vec<Option<H>> = files.iter().map(|_| Some(H::create())).collect();
+
+    let outcome = uring::read_all(
+        URING_ENTRIES,
+        &files,
+        URING_CHUNK_SIZE,
+        |j, chunk| match chunk {
+            Ok([]) => {
+                results[queued[j]] = hashers[j]
+                    .take()
+                    .map(|hasher| Ok(hexlify(hasher.finalize())))
+            }
+            Ok(data) => {
+                if let Some(hasher) = hashers[j].as_mut() {
+                    read.chunk_read(data.len());
+                    hasher.update(data);
+                }
+            }
+            Err(e) => {
+                hashers[j] = None;
+                results[queued[j]] = Some(Err(e));
+            }
+        },
+    );
+
+    for (j, file) in files.iter().enumerate() {
+        read.done_reading(file);
+
+        if matches!(results[queued[j]], Some(Ok(_)))
+            && file.metadata().map(|m| stamp(&m)).ok() != Some(stamps[j])
+        {
+            results[queued[j]] = Some(Err(std::io::Error::other(Unstable)));
+        }
+    }
+
+    // Whatever the ring didn't get to is read the regular way instead.
+    if let Err(e) = outcome {
+        static WARNING: std::sync::Once = std::sync::Once::new();
+        WARNING.call_once(|| {
+            crate::log::warn(format_args!(
+                "io_uring is unavailable ({}), falling back to regular reads.",
+                e
+            ))
+        });
+
+        for &i in &queued {
+            if results[i].is_none() {
+                results[i] = Some(hash_file::<H>(&paths[i], read));
+            }
+        }
+    }
+
+    // Retried, and hashed again, the regular way, since the ring only ever
+    // reads a file once.
+    results
+        .into_iter()
+        .zip(paths)
+        .map(|(result, path)| {
+            let result = result.unwrap_or_else(|| {
+                Err(std::io::Error::other("the ring never finished reading it"))
+            });
+            let hash = || {
+                read.retry(path, hash_file_once::<H>(path, read), || {
+                    hash_file_once::<H>(path, read)
+                })
+            };
+            read.rehash(
+                read.retry(path, result, || hash_file_once::<H>(path, read)),
+                hash,
+            )
+        })
+        .collect()
 }
 
 pub trait Hasher {
@@ -189,10 +800,9 @@ pub fn hexlify(digest: Vec<u8>) -> String {
     })
 }
 
-pub fn get_random_bytes(count: usize) -> Vec<u8> {
-    let file = File::open("/dev/urandom").unwrap();
-    let mut reader = BufReader::new(file);
+// There's no /dev/urandom on Windows, for one.
+pub fn get_random_bytes(count: usize) -> std::io::Result<Vec<u8>> {
     let mut buffer = vec![0; count];
-    let _ = reader.read_exact(&mut buffer);
-    buffer
+    File::open("/dev/urandom")?.read_exact(&mut buffer)?;
+    Ok(buffer)
 }
@@ -0,0 +1,112 @@
+use crate::record::Record;
+
+// Nerd Font codepoints, written as escapes since most editors can't show them.
+mod glyph {
+    pub const DIR: char = '\u{f115}';
+    pub const FILE: char = '\u{f15b}';
+    pub const SYMLINK: char = '\u{f0c1}';
+    pub const EXECUTABLE: char = '\u{f489}';
+    pub const OTHER: char = '\u{f013}';
+    pub const GIT: char = '\u{f1d3}';
+    pub const ARCHIVE: char = '\u{f410}';
+    pub const IMAGE: char = '\u{f1c5}';
+    pub const AUDIO: char = '\u{f1c7}';
+    pub const VIDEO: char = '\u{f1c8}';
+    pub const PDF: char = '\u{f1c1}';
+    pub const TEXT: char = '\u{f15c}';
+    pub const LOCK: char = '\u{f023}';
+    pub const CONFIG: char = '\u{e615}';
+    pub const JSON: char = '\u{e60b}';
+    pub const MARKDOWN: char = '\u{f48a}';
+    pub const SHELL: char = '\u{f489}';
+    pub const RUST: char = '\u{e7a8}';
+    pub const PYTHON: char = '\u{e606}';
+    pub const JAVASCRIPT: char = '\u{e74e}';
+    pub const TYPESCRIPT: char = '\u{e628}';
+    pub const C: char = '\u{e61e}';
+    pub const CPP: char = '\u{e61d}';
+    pub const GO: char = '\u{e626}';
+    pub const JAVA: char = '\u{e738}';
+    pub const RUBY: char = '\u{e21e}';
+    pub const LUA: char = '\u{e620}';
+    pub const VIM: char = '\u{e62b}';
+    pub const HTML: char = '\u{f13b}';
+    pub const CSS: char = '\u{e749}';
+    pub const DOCKER: char = '\u{f308}';
+}
+
+fn by_name(name: &str) -> Option<char> {
+    Some(match name {
+        ".git" | ".gitignore" | ".gitmodules" | ".gitattributes" => glyph::GIT,
+        "Dockerfile" | "docker-compose.yml" | "docker-compose.yaml" => glyph::DOCKER,
+        "Cargo.lock" | "package-lock.json" | "yarn.lock" | "flake.lock" => glyph::LOCK,
+        ".vimrc" | "init.vim" => glyph::VIM,
+        "Makefile" | "makefile" => glyph::SHELL,
+        _ => return None,
+    })
+}
+
+fn by_extension(ext: &str) -> Option<char> {
+    Some(match ext {
+        "rs" => glyph::RUST,
+        "py" | "pyc" | "pyi" => glyph::PYTHON,
+        "js" | "mjs" | "cjs" | "jsx" => glyph::JAVASCRIPT,
+        "ts" | "tsx" => glyph::TYPESCRIPT,
+        "c" | "h" => glyph::C,
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => glyph::CPP,
+        "go" => glyph::GO,
+        "java" | "jar" | "class" => glyph::JAVA,
+        "rb" => glyph::RUBY,
+        "lua" => glyph::LUA,
+        "vim" => glyph::VIM,
+        "html" | "htm" => glyph::HTML,
+        "css" | "scss" | "sass" => glyph::CSS,
+        "sh" | "bash" | "zsh" | "fish" | "ps1" => glyph::SHELL,
+        "md" | "markdown" | "rst" => glyph::MARKDOWN,
+        "json" | "jsonl" => glyph::JSON,
+        "toml" | "yaml" | "yml" | "ini" | "conf" | "cfg" | "xml" => glyph::CONFIG,
+        "lock" => glyph::LOCK,
+        "txt" | "log" | "csv" | "tsv" => glyph::TEXT,
+        "pdf" => glyph::PDF,
+        "zip" | "tar" | "gz" | "tgz" | "xz" | "bz2" | "zst" | "7z" | "rar" | "iso" => {
+            glyph::ARCHIVE
+        }
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "svg" | "ico" | "tif" | "tiff"
+        | "raw" | "cr2" | "nef" | "arw" | "dng" | "heic" => glyph::IMAGE,
+        "mp3" | "flac" | "wav" | "ogg" | "opus" | "m4a" | "aac" => glyph::AUDIO,
+        "mp4" | "mkv" | "webm" | "avi" | "mov" | "wmv" | "m4v" => glyph::VIDEO,
+        _ => return None,
+    })
+}
+
+pub fn icon(record: &Record) -> char {
+    let file_type = record.entry.file_type();
+    let name = record.entry.file_name().to_string_lossy();
+
+    if file_type.is_symlink() {
+        return glyph::SYMLINK;
+    }
+
+    if file_type.is_dir() {
+        return by_name(&name).unwrap_or(glyph::DIR);
+    }
+
+    if !file_type.is_file() {
+        return glyph::OTHER;
+    }
+
+    by_name(&name)
+        .or_else(|| {
+            record
+                .path()
+                .extension()
+                .and_then(|ext| by_extension(&ext.to_string_lossy().to_lowercase()))
+        })
+        .or_else(|| {
+            record
+                .mode()
+                .filter(|mode| mode & 0o111 != 0)
+                .map(|_| glyph::EXECUTABLE)
+        })
+        .unwrap_or(glyph::FILE)
+}
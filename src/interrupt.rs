@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// The signal that asked jw to stop, or 0 for none yet.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+// Ctrl+C, or SIGTERM, doesn't kill jw on the spot, but stops the walk, so no
+// new work is started, while whatever's already underway is let finish, and
+// everything printed so far is flushed, down to the last complete line, the
+// same as if the walk had simply run out of entries. That takes as long as the
+// slowest file still being hashed, so a second one, in case that's too long,
+// kills jw the usual way.
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handler(signal: libc::c_int) {
+        SIGNAL.store(signal, Ordering::Relaxed);
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // Reads being hashed shouldn't be interrupted by it, and the next
+            // one gets the default action again.
+            action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+// The console runs the handler on a thread of its own, and returning false
+// from it lets the next handler, the one that ends the process, have it.
+#[cfg(windows)]
+pub fn install() {
+    const CTRL_C_EVENT: u32 = 0;
+    const SIGINT: i32 = 2;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    unsafe extern "system" fn handler(event: u32) -> i32 {
+        let first = event == CTRL_C_EVENT && SIGNAL.swap(SIGINT, Ordering::Relaxed) == 0;
+        first as i32
+    }
+
+    unsafe { SetConsoleCtrlHandler(Some(handler), 1) };
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install() {}
+
+pub fn interrupted() -> bool {
+    SIGNAL.load(Ordering::Relaxed) != 0
+}
+
+// What a shell would have reported had the signal killed jw, 130 for Ctrl+C,
+// so scripts can tell a run that was cut short from one that failed.
+pub fn exit_code() -> i32 {
+    128 + SIGNAL.load(Ordering::Relaxed)
+}
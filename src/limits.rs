@@ -0,0 +1,61 @@
+// Every file that's being hashed is open until it's done, and with io_uring, a
+// whole batch of them is, for each thread, on top of a directory for each
+// thread that's walking. The soft limit on open files is 1024 on most Linux
+// systems, and 256 on macOS, which enough threads can run past, so it's raised
+// as far as the hard limit goes before anything's opened, the same as most
+// servers do. What it ends up at is returned, for sizing the batches by.
+#[cfg(unix)]
+pub fn raise_open_files() -> u64 {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return FALLBACK;
+    }
+
+    // macOS reports an unlimited hard limit, but won't take anything past
+    // OPEN_MAX for the soft one.
+    #[cfg(target_os = "macos")]
+    let wanted = limit.rlim_max.min(10240);
+
+    #[cfg(not(target_os = "macos"))]
+    let wanted = limit.rlim_max;
+
+    if wanted > limit.rlim_cur {
+        let raised = libc::rlimit {
+            rlim_cur: wanted,
+            rlim_max: limit.rlim_max,
+        };
+
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 {
+            limit.rlim_cur = wanted;
+        }
+    }
+
+    limit.rlim_cur
+}
+
+// Windows has no limit low enough to matter.
+#[cfg(not(unix))]
+pub fn raise_open_files() -> u64 {
+    u64::MAX
+}
+
+// What's assumed when the limit couldn't even be asked for.
+#[cfg(unix)]
+const FALLBACK: u64 = 256;
+
+// Left over for stdout and stderr, the manifests, checkpoints, and stores
+// being written to, the rings themselves, and whatever else is open besides
+// the files being hashed.
+const RESERVED: u64 = 64;
+
+// How many files each of the hashing threads can have open at once, out of
+// what's left of the limit once every walking thread has a directory open.
+// Never none, since a thread has to open at least the one it's hashing.
+pub fn files_per_thread(open_files: u64, hash_threads: usize, walk_threads: usize) -> usize {
+    let available = open_files.saturating_sub(RESERVED + walk_threads as u64);
+    (available / hash_threads.max(1) as u64).clamp(1, usize::MAX as u64) as usize
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use crate::progress;
+use crate::record::iso8601;
+use crate::tui;
+
+// Everything jw has to say about a run that isn't its output goes through
+// here, rather than straight to stderr, so that how much of it there is can
+// be turned up or down, and it can be sent to a file of its own instead.
+// Errors are what stopped something from being done, warnings are what was
+// done differently than asked, such as falling back to regular reads, or each
+// entry that's skipped, info is each retry, and debug is what jw decided on
+// along the way, and each root as it starts being walked.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl From<&String> for Level {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            _ => panic!("Invalid log level! '{}'", s),
+        }
+    }
+}
+
+impl Level {
+    // What -v and -vv turn it up to, from warn.
+    pub fn verbosity(count: u8) -> Self {
+        match count {
+            0 => Self::Warn,
+            1 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static FILE: OnceLock<Arc<Mutex<File>>> = OnceLock::new();
+
+// Opened while parsing arguments, and appended to, the same as --stats-log.
+pub fn parse_log_file(s: &str) -> Result<Arc<Mutex<File>>, String> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(s)
+        .map(|file| Arc::new(Mutex::new(file)))
+        .map_err(|e| e.to_string())
+}
+
+pub fn init(level: Level, file: Option<Arc<Mutex<File>>>) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+
+    if let Some(file) = file {
+        let _ = FILE.set(file);
+    }
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Error,
+        1 => Level::Warn,
+        2 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+// With --log-file, each line is stamped with when it was written and how
+// serious it was, and only errors are still printed to stderr as well, so a
+// run that's left alone has all of it in one place, and stderr isn't mixed in
+// with the output for anything less.
+fn write(level: Level, message: &dyn fmt::Display) {
+    if !enabled(level) {
+        return;
+    }
+
+    let file = FILE.get();
+    to_file(level, message);
+
+    // With --tui, the screen is where errors and warnings are shown, and
+    // stderr is held until it's closed.
+    if level <= Level::Warn {
+        tui::error(message);
+    }
+
+    if (file.is_none() || level == Level::Error) && !tui::hold(message) {
+        let mut stderr = std::io::stderr().lock();
+        progress::clear_line(&mut stderr);
+        let _ = writeln!(stderr, "{}", message);
+    }
+}
+
+// Only to --log-file, if there is one, for what's printed to stderr some
+// other way, such as what jw stops at.
+pub fn to_file(level: Level, message: &dyn fmt::Display) {
+    if let Some(file) = FILE.get() {
+        let line = format!(
+            "{} {} {}\n",
+            iso8601(SystemTime::now()),
+            level.name(),
+            message
+        );
+
+        let _ = file.lock().unwrap().write_all(line.as_bytes());
+    }
+}
+
+pub fn error(message: impl fmt::Display) {
+    write(Level::Error, &message);
+}
+
+pub fn warn(message: impl fmt::Display) {
+    write(Level::Warn, &message);
+}
+
+pub fn info(message: impl fmt::Display) {
+    write(Level::Info, &message);
+}
+
+pub fn debug(message: impl fmt::Display) {
+    write(Level::Debug, &message);
+}
@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+
+// The digests --find-hash is looking for, and the sizes of the files they're
+// of, where those are known, so files of any other size never need reading.
+#[derive(Debug, Default)]
+pub struct Wanted {
+    digests: HashMap<String, Option<u64>>,
+    sizes: HashSet<u64>,
+    // Any digest without a size means every file could be a match.
+    any_size: bool,
+}
+
+impl Wanted {
+    // Each argument is either a digest, or a file with one on each line.
+    // Digests can be followed by :size, e.g. 70cebeb2...:1024, and anything
+    // else after them on a line is left alone, which means what --checksum
+    // and sha256sum print can be given as it is. Blank lines, and lines
+    // starting with #, are skipped.
+    pub fn parse<'a, I>(arguments: I, digest_length: usize) -> Result<Self, String>
+    where
+        I: Iterator<Item = &'a String>,
+    {
+        let mut wanted = Self::default();
+
+        for argument in arguments {
+            if std::fs::metadata(argument).is_ok_and(|metadata| !metadata.is_dir()) {
+                let text = std::fs::read_to_string(argument)
+                    .map_err(|e| format!("couldn't read {}: {}", argument, e))?;
+
+                for (n, line) in text.lines().enumerate() {
+                    // The \ in front of a line whose path had to be escaped.
+                    let line = line.trim();
+                    let line = line.strip_prefix('\\').unwrap_or(line);
+
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+
+                    wanted
+                        .add(line, digest_length)
+                        .map_err(|e| format!("{}, on line {} of {}", e, n + 1, argument))?;
+                }
+            } else {
+                wanted.add(argument.trim(), digest_length)?;
+            }
+        }
+
+        if wanted.digests.is_empty() {
+            return Err("there aren't any digests to look for".to_string());
+        }
+
+        Ok(wanted)
+    }
+
+    fn add(&mut self, text: &str, digest_length: usize) -> Result<(), String> {
+        let digest = text.get(..digest_length).unwrap_or(text);
+
+        if digest.len() != digest_length || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!(
+                "'{}' isn't a digest of {} hex digits; -C picks the algorithm they're from",
+                text, digest_length
+            ));
+        }
+
+        let rest = &text[digest_length..];
+
+        let size = match rest.strip_prefix(':') {
+            Some(size) => Some(
+                size.split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .parse::<u64>()
+                    .map_err(|e| format!("invalid size in '{}': {}", text, e))?,
+            ),
+            None => None,
+        };
+
+        match size {
+            Some(size) => {
+                self.sizes.insert(size);
+            }
+            None => self.any_size = true,
+        }
+
+        self.digests.insert(digest.to_ascii_lowercase(), size);
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.digests.len()
+    }
+
+    // Whether a file of this size is worth hashing at all.
+    pub fn could_match(&self, size: u64) -> bool {
+        self.any_size || self.sizes.contains(&size)
+    }
+
+    // A size given with a digest has to match too.
+    pub fn matches(&self, digest: &str, size: u64) -> bool {
+        self.digests
+            .get(digest)
+            .is_some_and(|wanted| wanted.is_none_or(|wanted| wanted == size))
+    }
+}
@@ -1,343 +1,2547 @@
 use clap::parser::ValueSource;
 use clap::{self, value_parser, Arg, ArgAction, Command};
-use jwalk::WalkDir;
+use jwalk::{DirEntry, Parallelism, WalkDirGeneric};
+use memmap2::Mmap;
 use rayon::iter::*;
-use std::path::PathBuf;
-use std::process::exit;
+use rayon::slice::ParallelSlice;
+use std::borrow::Cow;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader},
+    io::Read,
 };
 
 #[macro_use]
 pub mod hashutil;
 use hashutil::*;
 
-fn read_stdin() -> Vec<String> {
-    let stdin = std::io::stdin();
-    let mut buffer = String::new();
+pub mod sample;
+use sample::*;
 
-    stdin.read_line(&mut buffer).unwrap();
+pub mod filter;
+use filter::Expr;
+
+pub mod record;
+use record::Record;
+
+pub mod output;
+use output::*;
+
+pub mod template;
+
+pub mod tree;
+use tree::Annotation;
+
+pub mod color;
+use color::ColorChoice;
+
+pub mod icons;
+
+pub mod sort;
+use sort::{Sort, SortKey};
+
+pub mod spill;
+use spill::{ExternalSort, Keyed};
+
+pub mod stats;
+use stats::{Counts, Errors, RootStats, StatsFormat, StatsTarget, Throughput};
+
+pub mod sink;
+use sink::Sink;
+
+pub mod throttle;
+use throttle::Throttle;
+
+pub mod progress;
+use progress::{Every, Progress};
+
+pub mod bench;
+
+pub mod background;
+
+pub mod du;
+use du::Usage;
+
+pub mod dupes;
+
+pub mod dedupe;
+use dedupe::{Action, Keep};
+
+pub mod farm;
+use farm::Farm;
+
+pub mod mutate;
+
+pub mod trash;
+
+pub mod exec;
+
+pub mod relocate;
+
+pub mod mirror;
+
+pub mod cas;
+use cas::Store;
+
+pub mod lookup;
+
+pub mod verify;
+
+pub mod ads;
+
+pub mod forks;
+
+pub mod interrupt;
+
+pub mod checkpoint;
+use checkpoint::{Checkpoint, Scratch};
+
+pub mod manifest;
+
+pub mod limits;
+
+pub mod fstype;
+
+pub mod config;
+
+pub mod man;
+
+pub mod explain;
+
+pub mod log;
+use forks::Fork;
+use log::Level;
+
+pub mod tui;
+use tui::exit;
+
+pub mod diagnostic;
+use diagnostic::Diagnostic;
+
+#[cfg(unix)]
+pub mod xattr;
+
+#[cfg(unix)]
+pub mod tags;
+
+#[cfg(unix)]
+pub mod pick;
+use exec::{Batch, Template};
+use lookup::Wanted;
+use mirror::Synced;
+use relocate::{Collision, Mode, Placed, Relocate};
+use verify::Verify;
+
+pub mod report;
+use report::{Report, ReportKind};
+
+#[cfg(target_os = "linux")]
+pub mod uring;
+
+// Paths to walk, one per line, or with -0, separated by NULs, the way find
+// -print0 puts them, which is the only way to pass along names that have a
+// newline in them. Each one is taken exactly as it is, spaces, bytes that
+// aren't UTF-8, and all, and only empty ones are skipped.
+fn read_paths(mut input: impl Read, null: bool, source: &str) -> Vec<PathBuf> {
+    let mut buffer = Vec::new();
+
+    if let Err(e) = input.read_to_end(&mut buffer) {
+        Diagnostic::new(format!("couldn't be read for the paths to walk: {}", e))
+            .about(source)
+            .exit(1);
+    }
+
+    let separator = if null { b'\0' } else { b'\n' };
 
     buffer
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect::<Vec<_>>()
+        .split(|&byte| byte == separator)
+        .filter(|path| !path.is_empty())
+        .map(path_from_bytes)
+        .collect()
 }
 
+// How a run that got to the end went, for scripts to tell apart. Anything that
+// stops a run short is 1, same as ever, and clap's usage errors are 2.
+const EXIT_ERRORS: i32 = 3;
+const EXIT_EMPTY: i32 = 4;
+
 const EXCLUDE_FILES: usize = 1;
 const EXCLUDE_DIRS: usize = 2;
 const EXCLUDE_HIDDEN: usize = 4;
 const EXCLUDE_OTHER: usize = 8;
 
+// How jwalk spreads reading directories across threads. Shared runs on the
+// same global pool the hashing does, which is jwalk's own default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WalkStrategy {
+    Serial,
+    Shared,
+    Dedicated(usize),
+}
+
+impl WalkStrategy {
+    fn parallelism(&self) -> Option<Parallelism> {
+        match self {
+            Self::Serial | Self::Dedicated(1) => Some(Parallelism::Serial),
+            Self::Shared => None,
+            Self::Dedicated(threads) => Some(Parallelism::RayonNewPool(*threads)),
+        }
+    }
+
+    // How many directories can be open at once, one for each thread walking.
+    fn threads(&self) -> usize {
+        match self {
+            Self::Serial => 1,
+            Self::Shared => rayon::current_num_threads(),
+            // A pool of 0 is one of rayon's default size, a thread per CPU.
+            Self::Dedicated(0) => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            Self::Dedicated(threads) => *threads,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Options {
     live_print: bool,
     checksum: Option<HashAlgorithm>,
+    read: ReadOptions,
     depth: usize,
+    walk_strategy: WalkStrategy,
+    follow_links: bool,
+    strict: bool,
+    // The list given to --files-from, if that's where the roots came from.
+    files_from: Option<String>,
+    // The limit on open files, once it's been raised.
+    open_files: u64,
+    ads: bool,
+    mac_metadata: bool,
+    xattrs: bool,
     exclude: usize,
     silent: bool,
-    directories: Vec<String>,
+    directories: Vec<PathBuf>,
     print_stats: bool,
+    reports: Vec<ReportKind>,
+    stats_to: StatsTarget,
+    stats_format: StatsFormat,
+    stats_log: Option<StatsTarget>,
+    errors: Arc<Errors>,
+    sample: Option<Sample>,
+    newer_than: Option<SystemTime>,
+    filter: Option<Expr>,
+    sort: Option<Sort>,
+    sort_memory: usize,
+    du: Option<Usage>,
+    summarize_dirs: Option<usize>,
+    dupes: bool,
+    pick: bool,
+    action: Option<Action>,
+    keep: Keep,
+    yes: bool,
+    link_farm: Option<PathBuf>,
+    exec: Option<Template>,
+    exec_batch: Option<Template>,
+    relocate: Option<(Mode, PathBuf)>,
+    collision: Collision,
+    // The algorithm --verify-after hashes with, other than for --dupes.
+    verify_after: Option<HashAlgorithm>,
+    mirror: Option<(PathBuf, HashAlgorithm)>,
+    cas: Option<Arc<Store>>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    #[cfg(unix)]
+    tags: Option<Arc<tags::Tags>>,
+    find_hash: Option<(Arc<Wanted>, HashAlgorithm)>,
+    seen: Option<Arc<SeenPaths>>,
+    progress: Arc<Progress>,
+    show_progress: Option<Option<Duration>>,
+    prescan: bool,
+    output: Output,
 }
 
-fn traverse(options: Options) {
-    let exclude = options.exclude;
+// Entries are deduplicated by where they really are, which is worked out from
+// the canonicalized root rather than by resolving every single entry; that's
+// enough since the walk never follows symlinks below the root.
+#[derive(Debug, Default)]
+struct SeenPaths(Mutex<HashSet<PathBuf>>);
 
-    for dir in options.directories {
-        let max_depth = if options.depth == 0 {
-            usize::MAX
-        } else {
-            options.depth
+impl SeenPaths {
+    fn first_sighting(&self, root: &Path, canonical_root: &Path, entry: &Entry) -> bool {
+        let path = entry.path();
+
+        let key = match path.strip_prefix(root) {
+            Ok(relative) => canonical_root.join(relative),
+            Err(_) => path,
         };
 
-        let walker = WalkDir::new(dir)
-            .skip_hidden((options.exclude & EXCLUDE_HIDDEN) != 0)
-            .max_depth(max_depth)
-            .into_iter()
-            .filter_map(|entry| {
-                entry.ok().and_then(|e| {
-                    let path = e.path();
-                    (!((exclude & EXCLUDE_DIRS != 0 && path.is_dir())
-                        || (exclude & EXCLUDE_FILES != 0 && path.is_file())
-                        || (exclude & EXCLUDE_OTHER != 0 && (!path.is_dir() && !path.is_file()))))
-                    .then_some(e)
-                })
-            });
+        self.0.lock().unwrap().insert(key)
+    }
+}
 
-        let mut file_count: usize = 0;
-        let mut dir_count: usize = 0;
-        let mut other_count: usize = 0;
-
-        // The choice to repeat myself by nesting the same for loop under
-        // several branches, rather than putting those branches into the
-        // for loop is a deliberate one. Applying DRY to everything will
-        // result in shittier code in some scenarios. Apply DRY where it
-        // makes sense. In this case, it would reduce performance of each
-        // iteration at a rate of O(N). For what? A handful of fewer lines?
-        if options.live_print {
-            if options.print_stats {
-                for entry in walker {
-                    let path = entry.path();
-
-                    if path.is_file() {
-                        file_count += 1;
-                    } else if path.is_dir() {
-                        dir_count += 1;
-                    } else {
-                        other_count += 1;
-                    }
+// Entries carry their metadata when something downstream is going to need
+// it anyway, so the stat happens on jwalk's threads while reading each dir,
+// rather than one by one on the thread consuming the walk.
+// Boxed, since an Option<Metadata> is most of the size of an entry whether
+// there's any in it or not, and a directory is read into memory as a whole,
+// which for millions of entries is a lot of room kept for nothing.
+type EntryState = ((), Option<Box<Metadata>>);
+type Entry = DirEntry<EntryState>;
 
-                    println!("{}", path.display());
-                }
-            } else {
-                for entry in walker {
-                    println!("{}", entry.path().display());
+impl Options {
+    // What the output looks like doesn't matter with --silent, so it's only
+    // what decides which entries there are that counts then.
+    fn needs_metadata(&self) -> bool {
+        self.checksum.is_some()
+            || self.du.is_some()
+            || self.summarize_dirs.is_some()
+            || self.dupes
+            || self.find_hash.is_some()
+            || !self.reports.is_empty()
+            || (self.print_stats && self.stats_format == StatsFormat::Json)
+            || self.stats_log.is_some()
+            || self.newer_than.is_some()
+            || self.filter.as_ref().is_some_and(Expr::needs_metadata)
+            || (!self.silent
+                && (self.sort.as_ref().is_some_and(Sort::needs_metadata)
+                    || self.output.needs_metadata()))
+    }
+
+    // Stats are kept for --stats-log even when they aren't printed.
+    fn keeps_stats(&self) -> bool {
+        self.print_stats || self.stats_log.is_some()
+    }
+
+    // Anything listed along with entries, rather than as an entry itself.
+    fn has_forks(&self) -> bool {
+        self.ads || self.mac_metadata || self.xattrs
+    }
+
+    fn finish_stats(&self, roots: &[RootStats]) {
+        let summary = stats::summary(roots, self.stats_format).filter(|_| self.print_stats);
+
+        if let Some(summary) = summary {
+            self.stats_to.report(&summary);
+        }
+
+        if let Some(log) = &self.stats_log {
+            log.report(&stats::log_record(roots));
+        }
+    }
+
+    // The roots, walked one after another by whichever mode it is, with the
+    // same stats kept for each of them, and --progress shown throughout. The
+    // mode is given each root, where it is among them, and the counts to add
+    // its entries to, and says what else there is to know about it.
+    fn each_root<F>(&self, mut walk_root: F) -> Vec<RootStats>
+    where
+        F: FnMut(usize, &Path, &mut Counts) -> Walked,
+    {
+        let display = self
+            .show_progress
+            .map(|interval| progress::Display::start(self.progress.clone(), interval));
+
+        let mut roots = Vec::new();
+
+        for (i, dir) in self.directories.iter().enumerate() {
+            let started = Instant::now();
+            let bytes_before = self.progress.bytes_read();
+            let errors_before = self.errors.count();
+            let mut counts = Counts::default();
+
+            let walked = walk_root(i, dir, &mut counts);
+
+            if self.keeps_stats() {
+                roots.push(RootStats {
+                    root: dir.display().to_string(),
+                    counts: Some(counts),
+                    hashed: walked.hashed.map(|files| Throughput {
+                        files,
+                        bytes: self.progress.bytes_read() - bytes_before,
+                        elapsed: started.elapsed(),
+                    }),
+                    errors: self.errors.count() - errors_before,
+                    elapsed: started.elapsed(),
+                });
+
+                if self.print_stats && self.stats_format == StatsFormat::Text {
+                    self.stats_to.report(&roots[roots.len() - 1].to_string());
                 }
             }
+
+            for report in &walked.reports {
+                self.stats_to.report(report);
+            }
+
+            if let Some(listing) = self.errors.listing() {
+                self.stats_to.report(&listing);
+            }
+        }
+
+        drop(display);
+        roots
+    }
+
+    // With --dedup-paths, a predicate for the given root that rejects whatever
+    // was already produced, whether by an earlier root or this one.
+    fn unseen<'a>(&'a self, dir: &'a Path) -> impl Fn(&Entry) -> bool + Sync + 'a {
+        let canonical_root = self
+            .seen
+            .as_ref()
+            .map(|_| std::fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir)));
+
+        move |entry| match (&self.seen, &canonical_root) {
+            (Some(seen), Some(canonical_root)) => seen.first_sighting(dir, canonical_root, entry),
+            _ => true,
+        }
+    }
+
+    // Sorting only applies to what's collected; live output is printed in
+    // whatever order the walk produces it.
+    fn sort(&self) -> Option<Sort> {
+        self.sort.filter(|_| !self.live_print)
+    }
+
+    // What --explain says these options come to, starting with the mode,
+    // which is picked in the same order it's dispatched in, along with
+    // whatever turned checksum mode on, since that's easily done by accident.
+    fn explained(&self, checksum_triggers: &[&str]) -> Vec<(&'static str, String)> {
+        let mode = if let Some((destination, _)) = &self.mirror {
+            format!("mirror, to {}", destination.display())
+        } else if self.find_hash.is_some() {
+            "find hashes".to_string()
+        } else if self.checksum.is_some() {
+            format!("checksum, because of {}", checksum_triggers.join(", "))
+        } else if self.dupes {
+            "dupes".to_string()
+        } else if self.pick {
+            "pick".to_string()
+        } else if let Some(destination) = &self.link_farm {
+            format!("link farm, in {}", destination.display())
+        } else if self.exec.is_some() {
+            "exec".to_string()
+        } else if self.exec_batch.is_some() {
+            "exec batch".to_string()
+        } else if let Some((mode, destination)) = &self.relocate {
+            format!("{:?}, to {}", mode, destination.display()).to_lowercase()
+        } else if self.du.is_some() || self.summarize_dirs.is_some() {
+            "disk usage".to_string()
         } else {
-            let results = walker.collect::<Vec<_>>();
-
-            if options.print_stats {
-                if options.silent {
-                    for entry in results {
-                        let path = entry.path();
-
-                        if path.is_file() {
-                            file_count += 1;
-                        } else if path.is_dir() {
-                            dir_count += 1;
-                        } else {
-                            other_count += 1;
-                        }
-                    }
-                } else {
-                    for entry in results {
-                        let path = entry.path();
-
-                        if path.is_file() {
-                            file_count += 1;
-                        } else if path.is_dir() {
-                            dir_count += 1;
-                        } else {
-                            other_count += 1;
-                        }
+            "walk".to_string()
+        };
+
+        let algorithm = self
+            .checksum
+            .as_ref()
+            .or(self.mirror.as_ref().map(|(_, algorithm)| algorithm))
+            .or(self.find_hash.as_ref().map(|(_, algorithm)| algorithm));
+
+        let excluded: Vec<&str> = [
+            (EXCLUDE_FILES, "files"),
+            (EXCLUDE_DIRS, "dirs"),
+            (EXCLUDE_HIDDEN, "dot"),
+            (EXCLUDE_OTHER, "other"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.exclude & flag != 0)
+        .map(|(_, name)| name)
+        .collect();
 
-                        println!("{}", entry.path().display());
+        let yes_no = |yes: bool| if yes { "yes" } else { "no" }.to_string();
+
+        let mut explained = vec![
+            ("mode", mode),
+            ("roots", format!("{:?}", self.directories)),
+            (
+                "depth",
+                match self.depth {
+                    0 => "unlimited".to_string(),
+                    depth => depth.to_string(),
+                },
+            ),
+            ("follow links", yes_no(self.follow_links)),
+            (
+                "excluded",
+                match excluded.is_empty() {
+                    true => "nothing".to_string(),
+                    false => excluded.join(", "),
+                },
+            ),
+            ("filtered", yes_no(self.filter.is_some())),
+            ("threads", rayon::current_num_threads().to_string()),
+            (
+                "walking on",
+                match self.walk_strategy {
+                    WalkStrategy::Serial => "the thread that's walking".to_string(),
+                    WalkStrategy::Shared => "the same threads as everything else".to_string(),
+                    WalkStrategy::Dedicated(_) => {
+                        format!("{} threads of its own", self.walk_strategy.threads())
                     }
-                }
-            } else if !options.silent {
-                for entry in results {
-                    println!("{}", entry.path().display());
-                }
-            }
+                },
+            ),
+            ("open files", self.open_files.to_string()),
+        ];
+
+        if let Some(newer_than) = self.newer_than {
+            explained.push(("newer than", record::iso8601(newer_than)));
         }
 
-        if options.print_stats {
-            println!(
-                "\nCounted {} files, {} directories, and {} misc entries.",
-                file_count, dir_count, other_count,
-            );
+        if let Some(algorithm) = algorithm {
+            let read = &self.read;
+
+            explained.extend([
+                ("algorithm", algorithm.name().to_string()),
+                ("reads", format!("{:?}", read.backend).to_lowercase()),
+                ("mapping files", yes_no(read.mmap)),
+                ("buffer size", read.buffer_size.to_string()),
+                ("retries", read.retries.to_string()),
+                ("rehashes", read.rehash.to_string()),
+                ("bandwidth limit", yes_no(read.bwlimit.is_some())),
+                ("no atime", yes_no(read.noatime)),
+                ("direct i/o", yes_no(read.direct_io)),
+            ]);
         }
+
+        explained.extend([
+            ("format", format!("{:?}", self.output.format).to_lowercase()),
+            (
+                "path style",
+                format!("{:?}", self.output.path_style).to_lowercase(),
+            ),
+            (
+                "sorted",
+                match self.sort() {
+                    Some(sort) => format!("{:?}", sort),
+                    None => "no".to_string(),
+                },
+            ),
+            (
+                "printed",
+                match (self.silent, self.live_print) {
+                    (true, _) => "nothing".to_string(),
+                    (false, true) => "as soon as each entry is ready".to_string(),
+                    (false, false) => "buffered".to_string(),
+                },
+            ),
+            ("colors", yes_no(self.output.colors.is_some())),
+            (
+                "stats",
+                match self.print_stats {
+                    true => format!("{:?}", self.stats_format).to_lowercase(),
+                    false => "no".to_string(),
+                },
+            ),
+            ("progress", yes_no(self.show_progress.is_some())),
+            ("log level", log::level().name().to_lowercase()),
+        ]);
+
+        explained
     }
 }
 
-fn checksum_rayon(options: &Options, algorithm: &HashAlgorithm) {
-    for dir in &options.directories {
-        let max_depth = if options.depth == 0 {
+// Every mode walks through here, so depth and hidden-skipping behave the same
+// no matter what's done with the entries afterwards, and so does stopping once
+// jw's been interrupted, which ends the walk as if it had run out of entries.
+fn walk<'a>(dir: &Path, options: &'a Options) -> impl Iterator<Item = jwalk::Result<Entry>> + 'a {
+    log::debug(format_args!("Walking {}", dir.display()));
+
+    // Disk usage has to go all the way down for the totals to add up, with
+    // --depth only limiting which directories get reported.
+    let max_depth =
+        if options.depth == 0 || options.du.is_some() || options.summarize_dirs.is_some() {
             usize::MAX
         } else {
             options.depth
         };
 
-        let walker = WalkDir::new(dir)
-            .skip_hidden((options.exclude & EXCLUDE_HIDDEN) != 0)
-            .max_depth(max_depth)
-            .into_iter()
-            .par_bridge()
-            .filter_map(|e| {
-                e.ok().and_then(|e| {
-                    e.path()
-                        .is_file()
-                        .then_some(e.path().to_str())
-                        .flatten()
-                        .map(str::to_string)
-                })
-            });
+    // Junctions are links as far as std is concerned, and symlinks to
+    // directories on Windows too, so they're only ever descended into with
+    // --follow-links, just the same as symlinks elsewhere. Following them,
+    // jwalk stops at any that lead back to one of their own ancestors.
+    let mut walker = WalkDirGeneric::<EntryState>::new(dir)
+        .sort(options.output.format == Format::Tree)
+        .skip_hidden((options.exclude & EXCLUDE_HIDDEN) != 0)
+        .follow_links(options.follow_links);
 
-        let hashes: Vec<(String, String)> = if options.live_print {
-            walker
-                .filter_map(|file_path| {
-                    hash_file!(algorithm, &file_path)
-                        .map(|hash| {
-                            println!("{}{}", file_path, hash);
-                            (file_path, hash)
-                        })
-                        .ok()
-                })
-                .collect()
-        } else {
-            walker
-                .filter_map(|file_path| {
-                    hash_file!(algorithm, &file_path)
-                        .map(|hash| (file_path, hash))
-                        .ok()
-                })
-                .collect()
-        };
+    if let Some(parallelism) = options.walk_strategy.parallelism() {
+        walker = walker.parallelism(parallelism);
+    }
 
-        if !options.silent && !options.live_print {
-            for (file_path, hash) in hashes {
-                println!("{}{}", hash, file_path);
-            }
-        }
+    // There's nothing to spread across threads in a file, and the thousands
+    // of them a --files-from list can have shouldn't each start a parallel
+    // walk that's over as soon as it's begun.
+    if !dir.is_dir() {
+        walker = walker.parallelism(jwalk::Parallelism::Serial);
     }
-}
 
-fn checksum_diff(algorithm: HashAlgorithm, paths: &[String], print_stats: bool) {
-    let mut paths = paths.iter();
+    // After the parallelism, since jwalk goes serial by itself for shallow
+    // walks, which is always the better choice there.
+    let walker = walker.max_depth(max_depth);
 
-    let convert = |path: &String| -> Option<PathBuf> {
-        Some(PathBuf::from(path))
-            .filter(|p| p.is_file())
-            .or_else(|| {
-                eprintln!("Doesn't exist/not a file: {:?}", path);
-                exit(1);
+    let prefetch = options.needs_metadata();
+
+    // A tree is drawn in walk order, so it's sorted one directory at a time
+    // rather than as a whole afterwards.
+    let sort = options
+        .sort
+        .filter(|_| options.output.format == Format::Tree);
+
+    let errors = options.errors.clone();
+    let follow_links = options.follow_links;
+    let read = options.read.clone();
+    let walk_threads = options.walk_strategy.threads();
+
+    let entries = if prefetch || sort.is_some() || follow_links {
+        walker
+            .process_read_dir(move |_, _, _, children| {
+                if follow_links {
+                    children.iter_mut().flatten().for_each(|entry| {
+                        if entry.path_is_symlink() && entry.read_children_path.is_some() {
+                            if let Some(ancestor) = leads_back(&entry.path()) {
+                                entry.read_children_path = None;
+
+                                errors.add(
+                                    Some(&entry.path()),
+                                    format!(
+                                        "leads back to {}, so it isn't followed",
+                                        ancestor.display()
+                                    ),
+                                );
+                            }
+                        }
+                    });
+                }
+
+                // Whatever vanished between being listed and being looked at.
+                // A directory of millions would leave a single thread to stat
+                // every one of them, so a directory of more than DIR_BATCH is
+                // split between as many threads as the walk has. Those are
+                // threads of their own rather than rayon's, which would take
+                // on whatever else is queued while they wait for each other,
+                // hashing included, and that waits on this very directory.
+                if prefetch {
+                    let prefetch = |batch: &mut [jwalk::Result<Entry>]| {
+                        batch.iter_mut().flatten().for_each(|entry| {
+                            entry.client_state = metadata(entry, &read)
+                                .map_err(|e| walk_error(&errors, &e))
+                                .ok()
+                                .map(Box::new)
+                        })
+                    };
+
+                    let batch = children.len().div_ceil(walk_threads).max(DIR_BATCH);
+
+                    std::thread::scope(|scope| {
+                        let mut batches = children.chunks_mut(batch);
+                        let first = batches.next();
+
+                        batches.for_each(|batch| {
+                            scope.spawn(|| prefetch(batch));
+                        });
+
+                        if let Some(first) = first {
+                            prefetch(first);
+                        }
+                    });
+                }
+
+                if let Some(sort) = sort {
+                    children.sort_by(|a, b| match (a, b) {
+                        (Ok(a), Ok(b)) => {
+                            sort.compare(&Record::new(a, None), &Record::new(b, None))
+                        }
+                        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                        (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+                    });
+                }
             })
+            .into_iter()
+    } else {
+        walker.into_iter()
     };
 
-    let base_file: PathBuf = paths.next().and_then(convert).unwrap_or_else(|| {
-        eprintln!("Not enough files to perform a diff. Missing the first.");
-        exit(1);
-    });
+    // With --strict, the first error is as good as an interrupt.
+    let errors = options.errors.clone();
+    let strict = options.strict;
 
-    let subsequent_files: Vec<PathBuf> = paths
-        .next()
-        .or_else(|| {
-            eprintln!("Not enough files to perform a diff. Missing the second.");
-            exit(1);
-        })
-        .into_iter()
-        .chain(paths)
-        .filter_map(convert)
-        .collect();
+    entries.take_while(move |_| !(interrupt::interrupted() || (strict && errors.count() > 0)))
+}
 
-    let digest_length: usize = algorithm.digest_size() * 2;
+// How many threads hash from a network filesystem, and how many more times a
+// read that fails there is tried, when neither was given.
+const NETWORK_THREADS: usize = 4;
+const NETWORK_RETRIES: u32 = 3;
 
-    let read_hashes = |file: &PathBuf| -> HashMap<String, String> {
-        let parse_line = |line: String| -> Option<(String, String)> {
-            line.split_at_checked(digest_length)
-                .map(|(hash, line)| (line.to_string(), hash.to_string()))
-        };
+// The fewest entries of a directory that are worth a thread to stat.
+const DIR_BATCH: usize = 4096;
 
-        let line_reader = BufReader::new(File::open(file).unwrap_or_else(|e| {
-            eprintln!("Failed to open file: {}", e);
-            exit(1);
-        }))
-        .lines()
-        .map_while(Result::ok);
+// jwalk only catches links that spell out the very path of a directory they're
+// in, so wherever a followed link leads is checked against where each of the
+// directories above it really is, the same as find -L does.
+fn leads_back(path: &Path) -> Option<PathBuf> {
+    let target = std::fs::canonicalize(path).ok()?;
 
-        line_reader.filter_map(parse_line).collect()
-    };
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| std::fs::canonicalize(ancestor).is_ok_and(|real| real == target))
+        .map(Path::to_path_buf)
+}
 
-    let base_hashes: HashMap<String, String> = read_hashes(&base_file);
+// Reported by the underlying io::Error, when there is one, since jwalk's own
+// message repeats the path.
+fn walk_error(errors: &Errors, e: &jwalk::Error) {
+    match e.io_error() {
+        Some(io_error) => errors.add(e.path(), io_error),
+        None => errors.add(e.path(), e),
+    }
+}
 
-    let subsequent_hash_files: Vec<(HashMap<String, String>, PathBuf)> = subsequent_files
-        .into_iter()
-        .map(|pb| (read_hashes(&pb), pb))
-        .collect();
+enum Kind {
+    File,
+    Dir,
+    Other,
+}
 
-    let mut discrepancies: usize = 0;
+// What an entry counts as for --exclude and --stats. jwalk already knows the
+// type of every entry from reading its directory, so it's only symlinks that
+// take a stat, to classify them by whatever they point to.
+fn kind(entry: &Entry) -> Kind {
+    let file_type = entry.file_type();
 
-    let mut msg_mismatches: Vec<String> = vec![];
-    let mut msg_missing: Vec<String> = vec![];
-    let mut msg_excess: Vec<String> = vec![];
+    let (is_file, is_dir) = if file_type.is_symlink() {
+        let path = entry.path();
+        (path.is_file(), path.is_dir())
+    } else {
+        (file_type.is_file(), file_type.is_dir())
+    };
 
-    for (file_name, base_hash) in &base_hashes {
-        for (other_hashes, hash_file) in &subsequent_hash_files {
-            if let Some(other_hash) = other_hashes.get(file_name) {
-                if *other_hash != *base_hash {
-                    msg_mismatches.push(format!(
-                        "[!({})] {} != {} == {}",
-                        hash_file.display(),
-                        other_hash,
-                        base_hash,
-                        file_name,
-                    ));
+    if is_file {
+        Kind::File
+    } else if is_dir {
+        Kind::Dir
+    } else {
+        Kind::Other
+    }
+}
 
-                    discrepancies += 1;
-                }
-            } else {
-                msg_missing.push(format!("[-({})] {}", hash_file.display(), file_name));
-                discrepancies += 1;
+// Likewise for filtering; checksum mode applies the exact same predicate
+// before it narrows things down to regular files.
+fn filter_entry(entry: jwalk::Result<Entry>, options: &Options) -> Option<Entry> {
+    let exclude = options.exclude;
+
+    entry
+        .map_err(|e| walk_error(&options.errors, &e))
+        .ok()
+        // The directory itself is still there to be listed, even if what's in
+        // it couldn't be read.
+        .inspect(|e| {
+            if let Some(error) = &e.read_children_error {
+                walk_error(&options.errors, error);
+            }
+        })
+        .filter(|e| {
+            if exclude & (EXCLUDE_FILES | EXCLUDE_DIRS | EXCLUDE_OTHER) == 0 {
+                return true;
             }
-        }
-    }
 
-    for (other_hashes, hash_file) in &subsequent_hash_files {
-        for (file_name, other_hash) in other_hashes {
-            if !base_hashes.contains_key(file_name) {
-                msg_excess.push(format!(
-                    "[+({})] {} {}",
-                    hash_file.display(),
-                    other_hash,
-                    file_name
-                ));
+            let excluded = match kind(e) {
+                Kind::File => EXCLUDE_FILES,
+                Kind::Dir => EXCLUDE_DIRS,
+                Kind::Other => EXCLUDE_OTHER,
+            };
 
-                discrepancies += 1;
+            exclude & excluded == 0
+        })
+        .filter(|e| {
+            if options.newer_than.is_none() && options.filter.is_none() {
+                return true;
             }
-        }
-    }
 
-    for msg in msg_mismatches
-        .iter()
-        .chain(msg_missing.iter())
-        .chain(msg_excess.iter())
-    {
-        println!("{}", msg);
-    }
+            let record = Record::new(e, None);
 
-    if print_stats {
-        if discrepancies == 0 {
-            println!("All entries validated without any discrepancies.");
-            exit(0);
-        } else {
-            println!("\nFound {} total discrepancies!", discrepancies);
-            println!(
-                "  {} Mismatching Hashes\n  {} Missing Files\n  {} Excess Files",
-                msg_mismatches.len(),
-                msg_missing.len(),
-                msg_excess.len()
-            );
-            exit(1);
+            options
+                .newer_than
+                .is_none_or(|reference| record.mtime().is_some_and(|mtime| mtime > reference))
+                && options
+                    .filter
+                    .as_ref()
+                    .is_none_or(|expr| expr.matches(&record))
+        })
+}
+
+// With --retries, a stat that fails the way reads on network filesystems do
+// is tried again, just the same as the reads are.
+fn metadata(entry: &Entry, read: &ReadOptions) -> jwalk::Result<Metadata> {
+    let mut result = entry.metadata();
+
+    for wait in read.waits() {
+        match &result {
+            Err(e) if e.io_error().is_some_and(hashutil::transient) => {
+                std::thread::sleep(wait);
+                result = entry.metadata();
+            }
+            _ => break,
         }
     }
+
+    result
 }
 
-fn main() {
-    let matches = Command::new("jw")
-        .version("2.2.8")
-        .about("A CLI frontend to jwalk for blazingly fast filesystem traversal!")
-        .arg(Arg::new("live-print")
-            .long("live")
-            .short('l')
-            .action(ArgAction::SetTrue)
-            .help("Display results in realtime, rather than collecting first and displaying later.")
-            .long_help("Display results in realtime, rather than collecting first and displaying later.
-This will result in a significant drop in performance due to the constant terminal output."))
+// What --allow-special lets checksum mode hash, whatever a symlink points to
+// included. Sockets can't be opened at all, so they're never among them.
+#[cfg(unix)]
+fn is_special(entry: &Entry) -> bool {
+    use std::os::unix::fs::FileTypeExt;
 
-        .arg(Arg::new("checksum")
-            .long("checksum")
-            .short('c')
-            .action(ArgAction::SetTrue)
-            .help("Generate an index of file hashes and their associated file names, and print it.")
-            .long_help("Generate an index of file hashes and their associated file names, and print it.
-The algorithm used by default is Xxh3, which is the recommended choice. Though
-if you want to use a different algorithm, use --checksum-with (-C) instead."))
+    entry.path().metadata().is_ok_and(|metadata| {
+        let file_type = metadata.file_type();
+        file_type.is_fifo() || file_type.is_char_device() || file_type.is_block_device()
+    })
+}
 
-        .arg(Arg::new("checksum-algo")
-            .long("checksum-with")
+#[cfg(not(unix))]
+fn is_special(_: &Entry) -> bool {
+    false
+}
+
+// What's left to know about a root once a mode's walked it, besides the
+// entries it counted: how many files it hashed, for the modes that hash, and
+// the --report reports on it, which are printed after its stats.
+#[derive(Default)]
+struct Walked {
+    hashed: Option<usize>,
+    reports: Vec<String>,
+}
+
+fn traverse(options: Options) {
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+
+        let mut reports: Vec<Report> = options
+            .reports
+            .iter()
+            .map(|kind| Report::new(kind, &options.read, &options.errors))
+            .collect();
+
+        let walker = walk(dir, &options)
+            .filter_map(|e| filter_entry(e, &options))
+            .filter(|e| unseen(e))
+            .inspect(|e| options.progress.entry(|| e.path()))
+            .inspect(|e| reports.iter_mut().for_each(|report| report.add(e)));
+
+        let usage = options.du.or(options.summarize_dirs.map(|_| Usage::Files));
+
+        *counts = if let Some(usage) = usage {
+            let mut counts = Counts::default();
+
+            let counted = walker.inspect(|entry| {
+                if options.keeps_stats() {
+                    counts.add(entry);
+                }
+            });
+
+            let totals = du::tally(counted, usage);
+
+            let mut out = Sink::new(std::io::stdout().lock(), false);
+
+            match options.summarize_dirs {
+                _ if options.silent => {}
+                Some(depth) => du::summarize(
+                    totals,
+                    depth,
+                    options.sort(),
+                    options.output.format,
+                    options.output.size_style,
+                    &mut out,
+                ),
+                None => du::report(
+                    totals,
+                    options.depth,
+                    options.sort(),
+                    options.output.size_style,
+                    &mut out,
+                ),
+            }
+
+            counts
+        } else {
+            match options.sample {
+                None => emit_entries(walker, &options),
+                Some(Sample::Percent(probability)) => {
+                    let mut rng = Rng::new();
+                    emit_entries(walker.filter(|_| rng.chance(probability)), &options)
+                }
+                Some(Sample::Count(size)) => {
+                    let sampled = reservoir(walker, size, &mut Rng::new());
+                    emit_entries(sampled.into_iter(), &options)
+                }
+            }
+        };
+
+        // Rendered before the stats are, since finding duplicates can still
+        // run into files that can't be read.
+        Walked {
+            hashed: None,
+            reports: reports
+                .iter()
+                .map(|report| report.render(options.output.size_style))
+                .collect(),
+        }
+    });
+
+    options.finish_stats(&roots);
+}
+
+// Every root is farmed into the same destination, so two roots with the same
+// relative path in them collide, and only the first gets its link; the rest
+// are counted as errors, like anything else that couldn't be linked.
+fn link_farm(options: &Options, destination: &Path) {
+    let mut out = Sink::new(std::io::stdout().lock(), false);
+
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+
+        let mut farm = Farm::new(destination, dir).unwrap_or_else(|e| {
+            log::error(format_args!(
+                "Failed to set up a link farm of {} in {}: {}",
+                dir.display(),
+                destination.display(),
+                e
+            ));
+            exit(1);
+        });
+
+        for entry in walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| unseen(e))
+        {
+            options.progress.entry(|| entry.path());
+
+            let path = entry.path();
+
+            match farm.link(&path, entry.file_type().is_dir()) {
+                Ok(Some(link)) => {
+                    if options.keeps_stats() {
+                        counts.add(&entry);
+                    }
+
+                    if !options.silent {
+                        writeln!(out, "{}", link.display());
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => options.errors.add(Some(&path), e),
+            }
+        }
+
+        Walked::default()
+    });
+
+    drop(out);
+
+    options.finish_stats(&roots);
+}
+
+// The walk goes on as usual on its own threads, while the commands are run
+// on the global pool, as many at once as it has threads. A command that fails
+// is counted as an error, rather than stopping the rest from running, and any
+// failure at all makes for a non-zero exit status at the end.
+fn run_commands(options: &Options, template: &Template) {
+    let mut ran = 0;
+    let mut failed = 0;
+
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+        let commands = AtomicUsize::new(0);
+        let failures = AtomicUsize::new(0);
+
+        // The root itself is only where the entries come from.
+        walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| e.depth() > 0 && unseen(e))
+            .inspect(|entry| {
+                options.progress.entry(|| entry.path());
+
+                if options.keeps_stats() {
+                    counts.add(entry);
+                }
+            })
+            .par_bridge()
+            .for_each(|entry| {
+                let path = entry.path();
+                commands.fetch_add(1, Ordering::Relaxed);
+
+                if let Err(e) = template.run(&path) {
+                    failures.fetch_add(1, Ordering::Relaxed);
+                    options.errors.add(Some(&path), e);
+                }
+            });
+
+        ran += commands.into_inner();
+        failed += failures.into_inner();
+
+        Walked::default()
+    });
+
+    options.finish_stats(&roots);
+
+    if failed > 0 {
+        log::error(format_args!("{} of {} commands failed.", failed, ran));
+        exit(1);
+    }
+}
+
+// Batches carry on from one root to the next, so the command runs as few
+// times as it can, which means a failed batch is counted against whichever
+// root it was run during, even if its paths came from an earlier one too.
+fn run_batches(options: &Options, template: &Template) {
+    let mut batch = Batch::new(template, &options.errors);
+
+    let roots = options.each_root(|i, dir, counts| {
+        let unseen = options.unseen(dir);
+
+        for entry in walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| e.depth() > 0 && unseen(e))
+        {
+            options.progress.entry(|| entry.path());
+
+            if options.keeps_stats() {
+                counts.add(&entry);
+            }
+
+            batch.push(entry.path());
+        }
+
+        if i == options.directories.len() - 1 {
+            batch.finish();
+        }
+
+        Walked::default()
+    });
+
+    options.finish_stats(&roots);
+
+    if batch.failures > 0 {
+        log::error(format_args!(
+            "{} of {} commands failed.",
+            batch.failures, batch.commands
+        ));
+        exit(1);
+    }
+}
+
+// Like a link farm, every root goes into the same destination, so the same
+// relative path under two roots is a collision like any other.
+fn relocate(options: &Options, mode: Mode, destination: &Path) {
+    // Shared by the threads placing files, like --live's, so the stdout lock
+    // is only taken whenever the buffer's flushed.
+    let out = Mutex::new(Sink::new(std::io::stdout(), true));
+    let placed = AtomicUsize::new(0);
+    let bytes = AtomicU64::new(0);
+    let collided = AtomicUsize::new(0);
+
+    let verify = options
+        .verify_after
+        .clone()
+        .map(|algorithm| Verify::new(algorithm, options.read.clone()));
+
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+
+        let relocate = Relocate::new(mode, options.collision, destination, dir, verify.as_ref())
+            .unwrap_or_else(|e| {
+                log::error(format_args!(
+                    "Failed to set up {} as the destination for {}: {}",
+                    destination.display(),
+                    dir.display(),
+                    e
+                ));
+                exit(1);
+            });
+
+        walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| unseen(e))
+            .inspect(|entry| {
+                options.progress.entry(|| entry.path());
+
+                if options.keeps_stats() {
+                    counts.add(entry);
+                }
+            })
+            .par_bridge()
+            .for_each(|entry| {
+                let path = entry.path();
+
+                match relocate.place(&path, entry.file_type()) {
+                    Ok(Placed::Done(target, size)) => {
+                        placed.fetch_add(1, Ordering::Relaxed);
+                        bytes.fetch_add(size, Ordering::Relaxed);
+
+                        if !options.silent {
+                            writeln!(out.lock().unwrap(), "{}", target.display());
+                        }
+                    }
+                    Ok(Placed::Collided) => {
+                        collided.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(Placed::Ignored) => {}
+                    Err(e) => options.errors.add(Some(&path), e),
+                }
+            });
+
+        // So that every path placed is out before the root's stats are.
+        out.lock().unwrap().flush();
+        Walked::default()
+    });
+
+    drop(out);
+
+    options.finish_stats(&roots);
+
+    if options.print_stats && options.stats_format == StatsFormat::Text {
+        let mut summary = format!(
+            "{} {} files, {} in all.",
+            mode.verb(),
+            placed.into_inner(),
+            options.output.size_style.format(bytes.into_inner())
+        );
+
+        match collided.into_inner() {
+            0 => {}
+            collided => summary.push_str(&format!(
+                " Skipped {} that were already in {}.",
+                collided,
+                destination.display()
+            )),
+        }
+
+        options.stats_to.report(&summary);
+    }
+    if let Some(failed) = verify.as_ref().map(Verify::failures).filter(|&n| n > 0) {
+        log::error(format_args!(
+            "{} files didn't hash the same once {}.",
+            failed,
+            mode.verb().to_lowercase()
+        ));
+        exit(1);
+    }
+}
+
+// What --pick picked is printed once the finder's closed, a line each, and
+// nothing being picked is the same as nothing matching.
+#[cfg(unix)]
+fn pick(options: &Options) {
+    let Some(picked) = pick::run(options) else {
+        exit(130);
+    };
+
+    if picked.is_empty() {
+        exit(EXIT_EMPTY);
+    }
+
+    let mut out = Sink::new(std::io::stdout().lock(), false);
+
+    for path in picked {
+        writeln!(out, "{}", path.display());
+    }
+}
+
+#[cfg(not(unix))]
+fn pick(_: &Options) {}
+
+// Regular files are all that's mirrored, in parallel like everything else
+// that's hashed, with directories only ever created to hold them. Nothing is
+// ever removed from dst, even if it isn't in src.
+fn mirror(options: &Options, destination: &Path, algorithm: &HashAlgorithm) {
+    let out = Mutex::new(Sink::new(std::io::stdout(), true));
+    let matched = AtomicUsize::new(0);
+    let copied = AtomicUsize::new(0);
+    let copied_bytes = AtomicU64::new(0);
+    let failed = AtomicUsize::new(0);
+
+    // There's only the one root, the source.
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+
+        walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| unseen(e))
+            .inspect(|entry| {
+                options.progress.entry(|| entry.path());
+
+                if options.keeps_stats() {
+                    counts.add(entry);
+                }
+            })
+            .filter(|entry| entry.file_type().is_file())
+            .par_bridge()
+            .for_each(|entry| {
+                let path = entry.path();
+
+                let Ok(relative) = path.strip_prefix(dir) else {
+                    return;
+                };
+
+                let target = destination.join(relative);
+
+                match mirror::mirror_file(algorithm, &path, &target, &options.read) {
+                    Ok(Synced::Matched) => {
+                        matched.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(Synced::Copied(bytes)) => {
+                        copied.fetch_add(1, Ordering::Relaxed);
+                        copied_bytes.fetch_add(bytes, Ordering::Relaxed);
+
+                        if !options.silent {
+                            writeln!(out.lock().unwrap(), "{}", target.display());
+                        }
+                    }
+                    Err(e) => {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        options.errors.add(Some(&path), e);
+                    }
+                }
+
+                options.progress.hashed(1);
+            });
+
+        out.lock().unwrap().flush();
+
+        Walked {
+            hashed: Some(
+                matched.load(Ordering::Relaxed)
+                    + copied.load(Ordering::Relaxed)
+                    + failed.load(Ordering::Relaxed),
+            ),
+            reports: Vec::new(),
+        }
+    });
+
+    drop(out);
+
+    options.finish_stats(&roots);
+
+    let (matched, copied, failed) = (
+        matched.into_inner(),
+        copied.into_inner(),
+        failed.into_inner(),
+    );
+
+    // Said whether or not --stats is given, since it's the whole point.
+    let mut report = format!(
+        "Mirrored {} files to {}: {} already matched, {} copied and verified ({}).",
+        matched + copied + failed,
+        destination.display(),
+        matched,
+        copied,
+        options.output.size_style.format(copied_bytes.into_inner())
+    );
+
+    if failed > 0 {
+        report.push_str(&format!(" Failed on {} files.", failed));
+    }
+
+    options.stats_to.report(&report);
+
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+// Only files of a size that one of the digests could be of are hashed, and
+// the rest are passed over without being read. Like grep, jw exits with 1
+// when nothing matched at all.
+fn find_hashes(options: &Options, wanted: &Wanted, algorithm: &HashAlgorithm) {
+    let found = Mutex::new(HashSet::new());
+    let matches = AtomicUsize::new(0);
+
+    let roots = options.each_root(|_, dir, counts| {
+        let unseen = options.unseen(dir);
+        let hashed = AtomicUsize::new(0);
+
+        walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| unseen(e))
+            .inspect(|entry| {
+                options.progress.entry(|| entry.path());
+
+                if options.keeps_stats() {
+                    counts.add(entry);
+                }
+            })
+            .filter(|entry| entry.file_type().is_file() && wanted.could_match(file_size(entry)))
+            .par_bridge()
+            .for_each(|entry| {
+                let path = entry.path();
+                let hash = hash_file!(algorithm, &path, &options.read);
+
+                options.progress.hashed(1);
+                hashed.fetch_add(1, Ordering::Relaxed);
+
+                match hash {
+                    Ok(hash) if wanted.matches(&hash, file_size(&entry)) => {
+                        matches.fetch_add(1, Ordering::Relaxed);
+
+                        if !options.silent {
+                            let line = options.output.line(&Record::new(&entry, None));
+                            Sink::new(std::io::stdout().lock(), false).write_str(&line);
+                        }
+
+                        found.lock().unwrap().insert(hash);
+                    }
+                    Ok(_) => {}
+                    Err(e) => options.errors.add(Some(&path), e),
+                }
+            });
+
+        Walked {
+            hashed: Some(hashed.into_inner()),
+            reports: Vec::new(),
+        }
+    });
+
+    options.finish_stats(&roots);
+
+    let matches = matches.into_inner();
+
+    if options.print_stats && options.stats_format == StatsFormat::Text {
+        options.stats_to.report(&format!(
+            "Found {} files, matching {} of {} digests.",
+            matches,
+            found.into_inner().unwrap().len(),
+            wanted.count()
+        ));
+    }
+
+    // Errors, or being interrupted, are still what main exits with, since
+    // the file could've been one of those that went unread.
+    if matches == 0 && options.errors.count() == 0 && !interrupt::interrupted() {
+        exit(EXIT_EMPTY);
+    }
+}
+
+// A file can have duplicates under any of the roots, so every one of them is
+// walked before anything is compared.
+fn find_duplicates(options: &Options) {
+    let display = options
+        .show_progress
+        .map(|interval| progress::Display::start(options.progress.clone(), interval));
+
+    let mut roots = Vec::new();
+    let mut candidates = Vec::new();
+
+    for (root, dir) in options.directories.iter().enumerate() {
+        let started = Instant::now();
+        let errors_before = options.errors.count();
+        let unseen = options.unseen(dir);
+        let mut counts = Counts::default();
+
+        walk(dir, options)
+            .filter_map(|e| filter_entry(e, options))
+            .filter(|e| unseen(e))
+            .for_each(|entry| {
+                options.progress.entry(|| entry.path());
+
+                if options.keeps_stats() {
+                    counts.add(&entry);
+                }
+
+                candidates.extend(dupes::Candidate::of(&entry, root));
+            });
+
+        if options.keeps_stats() {
+            roots.push(RootStats {
+                root: dir.display().to_string(),
+                counts: Some(counts),
+                hashed: None,
+                errors: options.errors.count() - errors_before,
+                elapsed: started.elapsed(),
+            });
+
+            if options.print_stats && options.stats_format == StatsFormat::Text {
+                options.stats_to.report(&roots[roots.len() - 1].to_string());
+            }
+        }
+    }
+
+    let errors_before = options.errors.count();
+    let groups = dupes::find(&candidates, &options.read, &options.errors);
+
+    // Groups are only ever found by xxh3, whatever -C says.
+    let verify = options
+        .verify_after
+        .as_ref()
+        .map(|_| Verify::new(HashAlgorithm::Xxh3, options.read.clone()));
+
+    drop(display);
+
+    let outcome = match options.action {
+        Some(action) if options.silent => Some(dedupe::apply(
+            &groups,
+            action,
+            options.keep,
+            options.yes,
+            verify.as_ref(),
+            &options.errors,
+            &mut Sink::new(std::io::sink(), false),
+        )),
+        Some(action) => Some(dedupe::apply(
+            &groups,
+            action,
+            options.keep,
+            options.yes,
+            verify.as_ref(),
+            &options.errors,
+            &mut Sink::new(std::io::stdout().lock(), true),
+        )),
+        None => {
+            if !options.silent {
+                dupes::write(
+                    &groups,
+                    options.output.format,
+                    &mut Sink::new(std::io::stdout().lock(), false),
+                );
+            }
+
+            None
+        }
+    };
+
+    options.finish_stats(&roots);
+
+    if options.print_stats && options.stats_format == StatsFormat::Text {
+        let mut summary = format!(
+            "Duplicates: {}",
+            dupes::summary(&groups, options.output.size_style)
+        );
+
+        match options.errors.count() - errors_before {
+            0 => {}
+            errors => summary.push_str(&format!(" Couldn't read {} files.", errors)),
+        }
+
+        options.stats_to.report(&summary);
+    }
+
+    // Said whether or not --stats is given, since it's the only place that
+    // says nothing was done without --yes.
+    if let (Some(action), Some(outcome)) = (options.action, outcome) {
+        options
+            .stats_to
+            .report(&outcome.describe(action, options.yes, options.output.size_style));
+    }
+
+    if let Some(listing) = options.errors.listing() {
+        options.stats_to.report(&listing);
+    }
+
+    if let Some(failed) = verify.as_ref().map(Verify::failures).filter(|&n| n > 0) {
+        log::error(format_args!(
+            "{} duplicates didn't hash the same once linked.",
+            failed
+        ));
+        exit(1);
+    }
+}
+
+fn emit_entries<I>(walker: I, options: &Options) -> Counts
+where
+    I: Iterator<Item = Entry>,
+{
+    let mut counts = Counts::default();
+
+    // Nothing gets printed, so it makes no difference what it would've looked
+    // like, which makes --stats --silent a plain and fast counter.
+    if options.silent {
+        if options.keeps_stats() {
+            walker.for_each(|entry| counts.add(&entry));
+        } else {
+            walker.for_each(drop);
+        }
+
+        return counts;
+    }
+
+    if options.output.format == Format::Tree {
+        return emit_tree(walker, options);
+    }
+
+    if !options.output.is_plain() || options.sort().is_some() || options.has_forks() {
+        return emit_records(walker, options);
+    }
+
+    // The choice to repeat myself by nesting the same for loop under
+    // several branches, rather than putting those branches into the
+    // for loop is a deliberate one. Applying DRY to everything will
+    // result in shittier code in some scenarios. Apply DRY where it
+    // makes sense. In this case, it would reduce performance of each
+    // iteration at a rate of O(N). For what? A handful of fewer lines?
+    if options.live_print {
+        let mut out = Sink::new(std::io::stdout().lock(), true);
+
+        if options.keeps_stats() {
+            for entry in walker {
+                counts.add(&entry);
+                writeln!(out, "{}", entry.path().display());
+            }
+        } else {
+            for entry in walker {
+                writeln!(out, "{}", entry.path().display());
+            }
+        }
+    } else if options.keeps_stats() {
+        let counted = walker.inspect(|entry| counts.add(entry));
+
+        sink::stream(counted, |out, entry| {
+            writeln!(out, "{}", entry.path().display())
+        });
+    } else {
+        sink::stream(walker, |out, entry| {
+            writeln!(out, "{}", entry.path().display())
+        });
+    }
+
+    counts
+}
+
+// With --ads or --mac-metadata, whatever else there is to a file, each listed
+// right after the file itself. Directories can have them too, but only files'
+// are looked for, since those are the ones that could be hiding anything. The
+// exception is --xattrs, since directories and symlinks carry labels and ACLs
+// in theirs just the same, though only files' are hashed.
+fn forks(entry: &Entry, options: &Options) -> Vec<Fork> {
+    if !options.has_forks() {
+        return Vec::new();
+    }
+
+    let path = entry.path();
+    let is_file = entry.file_type().is_file();
+
+    if !is_file && !options.xattrs {
+        return Vec::new();
+    }
+
+    forks::of(
+        &path,
+        options.ads && is_file,
+        options.mac_metadata && is_file,
+        options.xattrs,
+    )
+    .unwrap_or_else(|e| {
+        options.errors.add(Some(&path), e);
+        Vec::new()
+    })
+}
+
+// Anything other than plain paths needs the entry's metadata anyway, at which
+// point the branching is noise compared to formatting the line itself.
+fn emit_records<I>(walker: I, options: &Options) -> Counts
+where
+    I: Iterator<Item = Entry>,
+{
+    let mut counts = Counts::default();
+
+    // Streams aren't counted as entries of their own.
+    let mut emit = |out: &mut Sink<std::io::StdoutLock>, entry: &Entry| {
+        if options.keeps_stats() {
+            counts.add(entry);
+        }
+
+        out.write_str(&options.output.line(&Record::new(entry, None)));
+
+        for fork in forks(entry, options) {
+            let record = Record::at(entry, fork.path().to_path_buf(), None);
+            out.write_str(&options.output.line(&record));
+        }
+    };
+
+    // Only sorting needs every entry at once, and even then only up to
+    // --sort-memory worth of them.
+    if options.live_print {
+        let mut out = Sink::new(std::io::stdout().lock(), true);
+        walker.for_each(|entry| emit(&mut out, &entry));
+    } else if let Some(sort) = options.sort() {
+        let mut sorted = ExternalSort::new(sort, options.sort_memory);
+
+        for entry in walker {
+            if options.keeps_stats() {
+                counts.add(&entry);
+            }
+
+            let records = std::iter::once(Record::new(&entry, None)).chain(
+                forks(&entry, options)
+                    .into_iter()
+                    .map(|fork| Record::at(&entry, fork.path().to_path_buf(), None)),
+            );
+
+            for record in records {
+                let line = options.output.line(&record);
+                sorted.push(Keyed::new(&sort, &record, line));
+            }
+        }
+
+        sorted.finish(&mut Sink::new(std::io::stdout().lock(), false));
+    } else {
+        sink::stream(walker, |out, entry| emit(out, &entry));
+    }
+
+    counts
+}
+
+fn emit_tree<I>(walker: I, options: &Options) -> Counts
+where
+    I: Iterator<Item = Entry>,
+{
+    let mut counts = Counts::default();
+
+    let entries: Vec<(Entry, Option<String>)> = walker
+        .inspect(|entry| {
+            if options.keeps_stats() {
+                counts.add(entry);
+            }
+        })
+        .map(|entry| (entry, None))
+        .collect();
+
+    tree::render(
+        &entries,
+        &options.output,
+        &mut Sink::new(std::io::stdout().lock(), false),
+    );
+
+    counts
+}
+
+// A tree needs every entry, not just the files, and in walk order, so the
+// walk is collected up front and only the hashing is done in parallel.
+fn checksum_tree(dir: &Path, options: &Options, algorithm: &HashAlgorithm) -> usize {
+    let unseen = options.unseen(dir);
+
+    let entries: Vec<Entry> = walk(dir, options)
+        .filter_map(|e| filter_entry(e, options))
+        .filter(|e| unseen(e))
+        .collect();
+
+    let hashed: Vec<(Entry, Option<String>)> = entries
+        .into_par_iter()
+        .map(|entry| {
+            let path = entry.path();
+
+            let hash = matches!(kind(&entry), Kind::File)
+                .then(|| {
+                    let hash = match hash_file!(algorithm, &path, &options.read) {
+                        Err(e) if unstable(&e) => Some(flag_unstable(&path, &e, algorithm)),
+                        hash => hash.map_err(|e| options.errors.add(Some(&path), e)).ok(),
+                    };
+                    options.progress.hashed(1);
+                    hash
+                })
+                .flatten();
+
+            (entry, hash)
+        })
+        .collect();
+
+    if !options.silent {
+        tree::render(
+            &hashed,
+            &options.output,
+            &mut Sink::new(std::io::stdout().lock(), false),
+        );
+    }
+
+    hashed.iter().filter(|(_, hash)| hash.is_some()).count()
+}
+
+fn checksum_rayon(options: &Options, algorithm: &HashAlgorithm) {
+    if options.prescan {
+        let (files, bytes) = options
+            .directories
+            .iter()
+            .map(|dir| prescan(dir, options))
+            .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+        options.progress.set_totals(files, bytes);
+    }
+
+    let display = options
+        .show_progress
+        .map(|interval| progress::Display::start(options.progress.clone(), interval));
+
+    let mut roots = Vec::new();
+
+    // The paths in a --files-from list are usually files, one apiece, which
+    // would be hashed one after the other if each was a root of its own, so
+    // the files among them are all hashed as one, and their stats are the
+    // list's. Directories in it are still walked one by one.
+    let (files, dirs): (Vec<&PathBuf>, Vec<&PathBuf>) = match &options.files_from {
+        Some(_) if options.output.format != Format::Tree => {
+            options.directories.iter().partition(|dir| !dir.is_dir())
+        }
+        _ => (Vec::new(), options.directories.iter().collect()),
+    };
+
+    let mut groups: Vec<(String, Vec<PathBuf>)> = Vec::new();
+
+    if let (Some(list), false) = (&options.files_from, files.is_empty()) {
+        groups.push((list.clone(), files.into_iter().cloned().collect()));
+    }
+
+    groups.extend(
+        dirs.into_iter()
+            .map(|dir| (dir.display().to_string(), vec![dir.clone()])),
+    );
+
+    for (root, dirs) in groups {
+        let started = Instant::now();
+        let bytes_before = options.progress.bytes_read();
+        let errors_before = options.errors.count();
+
+        let hashed = if options.output.format == Format::Tree {
+            checksum_tree(&dirs[0], options, algorithm)
+        } else {
+            checksum_walk(&dirs, options, algorithm)
+        };
+
+        if options.keeps_stats() {
+            let throughput = Throughput {
+                files: hashed,
+                bytes: options.progress.bytes_read() - bytes_before,
+                elapsed: started.elapsed(),
+            };
+
+            roots.push(RootStats {
+                root,
+                counts: None,
+                hashed: Some(throughput),
+                errors: options.errors.count() - errors_before,
+                elapsed: throughput.elapsed,
+            });
+
+            if options.print_stats && options.stats_format == StatsFormat::Text {
+                options.stats_to.report(&roots[roots.len() - 1].to_string());
+            }
+        }
+
+        if let Some(listing) = options.errors.listing() {
+            options.stats_to.report(&listing);
+        }
+    }
+
+    options.finish_stats(&roots);
+
+    drop(display);
+
+    if let Some(store) = &options.cas {
+        if let Err(e) = store.finish() {
+            log::error(format_args!(
+                "Failed to write the manifest of the store: {}",
+                e
+            ));
+            exit(1);
+        }
+
+        if options.print_stats && options.stats_format == StatsFormat::Text {
+            options.stats_to.report(&format!(
+                "Store: {} new objects taking up {}, and {} already in the store.",
+                store.stored.load(Ordering::Relaxed),
+                options
+                    .output
+                    .size_style
+                    .format(store.stored_bytes.load(Ordering::Relaxed)),
+                store.present.load(Ordering::Relaxed)
+            ));
+        }
+    }
+
+    if let Some(checkpoint) = &options.checkpoint {
+        if let Err(e) = checkpoint.finish() {
+            log::error(format_args!("Failed to write the checkpoint: {}", e));
+            exit(1);
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(tags) = &options.tags {
+        if options.print_stats && options.stats_format == StatsFormat::Text {
+            options.stats_to.report(&format!(
+                "Tags: {} verified, {} outdated, {} untagged, {} corrupt, and {} stored.",
+                tags.verified.load(Ordering::Relaxed),
+                tags.outdated.load(Ordering::Relaxed),
+                tags.untagged.load(Ordering::Relaxed),
+                tags.corrupt.load(Ordering::Relaxed),
+                tags.stored.load(Ordering::Relaxed)
+            ));
+        }
+
+        let corrupt = tags.corrupt.load(Ordering::Relaxed);
+
+        if corrupt > 0 {
+            log::error(format_args!(
+                "{} files didn't hash to what they were tagged with.",
+                corrupt
+            ));
+            exit(1);
+        }
+    }
+}
+
+// Counts up the files that are going to be hashed, and their sizes, for the
+// sake of --progress. This uses the same filters, but doesn't account for
+// --dedup-paths, or anything that changes before the files are gotten to.
+fn prescan(dir: &Path, options: &Options) -> (u64, u64) {
+    walk(dir, options)
+        .filter_map(|e| filter_entry(e, options))
+        .filter(|e| matches!(kind(e), Kind::File))
+        .fold((0, 0), |(files, bytes), e| {
+            (files + 1, bytes + file_size(&e))
+        })
+}
+
+// From the metadata the walk prefetches in checksum mode.
+fn file_size(entry: &Entry) -> u64 {
+    entry.client_state.as_deref().map_or(0, Metadata::len)
+}
+
+fn checksum_walk(dirs: &[PathBuf], options: &Options, algorithm: &HashAlgorithm) -> usize {
+    // Started here rather than as the last one runs out, since that would be
+    // on one of rayon's threads, with jwalk waiting for one to be free.
+    let walks: Vec<_> = dirs
+        .iter()
+        .map(|dir| {
+            let unseen = options.unseen(dir);
+
+            walk(dir, options)
+                .filter_map(|e| filter_entry(e, options))
+                .filter(move |e| unseen(e))
+        })
+        .collect();
+
+    let entries = walks.into_iter().flatten();
+
+    let file_path = |e: &Entry| {
+        (matches!(kind(e), Kind::File) || (options.read.allow_special && is_special(e)))
+            && !options
+                .checkpoint
+                .as_ref()
+                .is_some_and(|c| c.done(&e.path()))
+    };
+
+    // io_uring works through a whole batch at once, with a buffer for each
+    // file in it, so its batches are kept smaller.
+    // Each of them is open until the batch is done, so they're kept to what
+    // the limit on open files leaves for every thread.
+    let max_files = match options.read.backend {
+        IoBackend::Std => BATCH_FILES,
+        IoBackend::Uring => URING_BATCH_FILES.min(limits::files_per_thread(
+            options.open_files,
+            rayon::current_num_threads(),
+            options.walk_strategy.threads(),
+        )),
+    };
+
+    // Sampling has to see the files in sequence, so the stat calls move
+    // in front of par_bridge in that case.
+    match options.sample {
+        None => hash_files(
+            batches(entries.filter(file_path), max_files).par_bridge(),
+            options,
+            algorithm,
+        ),
+        Some(Sample::Percent(probability)) => {
+            let mut rng = Rng::new();
+
+            let sampled = entries
+                .filter(file_path)
+                .filter(|_| rng.chance(probability));
+
+            hash_files(batches(sampled, max_files).par_bridge(), options, algorithm)
+        }
+        Some(Sample::Count(size)) => {
+            let sampled = reservoir(entries.filter(file_path), size, &mut Rng::new());
+            hash_files(
+                batches(sampled.into_iter(), max_files).par_bridge(),
+                options,
+                algorithm,
+            )
+        }
+    }
+}
+
+// How much goes into one batch of files to hash, by size and by count.
+const BATCH_BYTES: u64 = 4 * 1024 * 1024;
+const BATCH_FILES: usize = 256;
+const URING_BATCH_FILES: usize = 32;
+
+// Handing a file over to another thread costs about as much as hashing a tiny
+// one, so small files are grouped into batches, each one a single task for
+// rayon. A batch is closed once the next file would take it past BATCH_BYTES,
+// which leaves bigger files in batches of their own, spread across threads.
+// The sizes come from the metadata prefetched by the walk.
+fn batches(
+    files: impl Iterator<Item = Entry>,
+    max_files: usize,
+) -> impl Iterator<Item = Vec<Entry>> {
+    let mut files = files.peekable();
+
+    std::iter::from_fn(move || {
+        let mut batch = Vec::new();
+        let mut bytes = 0;
+
+        while let Some(file) = files.next_if(|entry| {
+            batch.is_empty() || (batch.len() < max_files && bytes + file_size(entry) <= BATCH_BYTES)
+        }) {
+            bytes += file_size(&file);
+            batch.push(file);
+        }
+
+        (!batch.is_empty()).then_some(batch)
+    })
+}
+
+// Entries only keep their file name, and share the path of their parent with
+// their siblings, so the full path is only joined for as long as it takes to
+// open the file.
+fn hash_entries<'a>(
+    batch: Vec<Entry>,
+    options: &'a Options,
+    algorithm: &'a HashAlgorithm,
+) -> impl Iterator<Item = Hashed> + 'a {
+    let paths: Vec<PathBuf> = batch.iter().map(Entry::path).collect();
+    let hashes = hash_batch!(algorithm, &paths, &options.read);
+
+    options.progress.hashed(batch.len());
+
+    // Whatever couldn't be put in the store is left out of the manifest
+    // printed too, so the two always agree, which goes for a file that kept
+    // changing as well, since there's nothing to store it as. Extended
+    // attributes have no file of their own to put there.
+    let keep = move |path: &Path, hash: std::io::Result<String>, stored: bool| {
+        let hash = match hash {
+            Err(e) if unstable(&e) && options.cas.is_none() => {
+                return Some(flag_unstable(path, &e, algorithm));
+            }
+            hash => hash.map_err(|e| options.errors.add(Some(path), e)).ok()?,
+        };
+
+        match options.cas.as_ref().filter(|_| stored) {
+            Some(store) => store
+                .add(path, &hash, &options.read)
+                .map(|_| hash)
+                .map_err(|e| options.errors.add(Some(path), e))
+                .ok(),
+            None => Some(hash),
+        }
+    };
+
+    let hashes: Vec<Option<String>> = paths
+        .iter()
+        .zip(hashes)
+        .map(|(path, hash)| keep(path, hash, true))
+        .collect();
+
+    batch
+        .into_iter()
+        .zip(hashes)
+        .filter_map(move |(entry, hash)| {
+            let streams: Vec<(PathBuf, String)> = forks(&entry, options)
+                .into_iter()
+                .filter_map(|fork| {
+                    options.progress.hashed(1);
+
+                    match fork {
+                        Fork::Stream(path) => {
+                            let hash = hash_file!(algorithm, &path, &options.read);
+                            keep(&path, hash, true).map(|hash| (path, hash))
+                        }
+                        Fork::Xattr(path, value) => {
+                            let hash = Ok(algorithm.hash_bytes(&value));
+                            keep(&path, hash, false).map(|hash| (path, hash))
+                        }
+                    }
+                })
+                .collect();
+
+            // An unstable file has no hash to be tagged with.
+            #[cfg(unix)]
+            if let (Some(tags), Some(hash)) = (
+                &options.tags,
+                hash.as_ref().filter(|hash| !is_unstable_digest(hash)),
+            ) {
+                tag(tags, &entry, hash, options);
+            }
+
+            if let (Some(checkpoint), Some(hash)) = (&options.checkpoint, &hash) {
+                let path = entry.path();
+
+                let lines = std::iter::once((path.as_path(), hash.as_str())).chain(
+                    streams
+                        .iter()
+                        .map(|(path, hash)| (path.as_path(), hash.as_str())),
+                );
+
+                if let Err(e) = checkpoint.record(lines) {
+                    log::error(format_args!("Failed to write to the checkpoint: {}", e));
+                    exit(1);
+                }
+            }
+
+            if let (Some(hash), true) = (&hash, tui::active()) {
+                tui::hashed(&entry.path(), hash);
+                streams
+                    .iter()
+                    .for_each(|(path, hash)| tui::hashed(path, hash));
+            }
+
+            hash.map(|hash| (entry, hash, streams))
+        })
+}
+
+// Warned about, as well as flagged, since it's an entry of no use to --diff.
+fn flag_unstable(path: &Path, e: &std::io::Error, algorithm: &HashAlgorithm) -> String {
+    log::warn(format_args!(
+        "Flagging {} as unstable, since it {}",
+        path.display(),
+        e
+    ));
+
+    unstable_digest(algorithm)
+}
+
+// A symlink's tag would have to be on the link itself, which Linux doesn't
+// allow for user.* attributes, so only the file it points to is tagged, if it's
+// walked too. What it was like before it was hashed comes from the walk.
+#[cfg(unix)]
+fn tag(tags: &tags::Tags, entry: &Entry, hash: &str, options: &Options) {
+    if entry.file_type().is_symlink() {
+        return;
+    }
+
+    let path = entry.path();
+
+    let tagged = match entry.client_state.as_deref() {
+        Some(metadata) => tags.tag(&path, hash, metadata),
+        None => path
+            .symlink_metadata()
+            .and_then(|metadata| tags.tag(&path, hash, &metadata)),
+    };
+
+    if let Err(e) = tagged {
+        options.errors.add(Some(&path), e);
+    }
+}
+
+// A hashed file, and the path and hash of each of its forks.
+type Hashed = (Entry, String, Vec<(PathBuf, String)>);
+
+// Forks come right after the file they're of, as lines of their own.
+fn hashed_records<'a>(
+    entry: &'a Entry,
+    hash: &'a str,
+    streams: &'a [(PathBuf, String)],
+) -> impl Iterator<Item = Record<'a>> {
+    std::iter::once(Record::new(entry, Some(hash))).chain(
+        streams
+            .iter()
+            .map(move |(path, hash)| Record::at(entry, path.clone(), Some(hash))),
+    )
+}
+
+// Only sorting needs to hold on to hashes, up to --sort-memory worth of them;
+// otherwise each line is written out as soon as it's ready, and nothing is
+// kept around once it has been.
+fn hash_files<I>(walker: I, options: &Options, algorithm: &HashAlgorithm) -> usize
+where
+    I: ParallelIterator<Item = Vec<Entry>>,
+{
+    let hashed = walker.flat_map_iter(|batch| hash_entries(batch, options, algorithm));
+
+    if options.silent {
+        return hashed.map(|(_, _, streams)| 1 + streams.len()).sum();
+    }
+
+    if options.live_print {
+        // Shared by the hashing threads, so it can't hold the stdout lock for
+        // its whole lifetime; it's only taken whenever the buffer is flushed.
+        let out = Mutex::new(Sink::new(std::io::stdout(), true));
+
+        return hashed
+            .map(|(entry, hash, streams)| {
+                for record in hashed_records(&entry, &hash, &streams) {
+                    let line = options.output.line(&record);
+                    out.lock().unwrap().write_str(&line);
+                }
+
+                1 + streams.len()
+            })
+            .sum();
+    }
+
+    if let Some(sort) = options.sort() {
+        let sorted = Mutex::new(ExternalSort::new(sort, options.sort_memory));
+
+        let count = hashed
+            .map(|(entry, hash, streams)| {
+                for record in hashed_records(&entry, &hash, &streams) {
+                    let keyed = Keyed::new(&sort, &record, options.output.line(&record));
+                    sorted.lock().unwrap().push(keyed);
+                }
+
+                1 + streams.len()
+            })
+            .sum();
+
+        let sorted = sorted.into_inner().unwrap();
+        sorted.finish(&mut Sink::new(std::io::stdout().lock(), false));
+
+        return count;
+    }
+
+    let (sender, receiver) = crossbeam_channel::bounded::<String>(4096);
+
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let mut out = Sink::new(std::io::stdout().lock(), false);
+            receiver.iter().for_each(|line| out.write_str(&line));
+        });
+
+        hashed
+            .map_with(sender, |sender, (entry, hash, streams)| {
+                for record in hashed_records(&entry, &hash, &streams) {
+                    let _ = sender.send(options.output.line(&record));
+                }
+
+                1 + streams.len()
+            })
+            .sum()
+    })
+}
+
+// Every line of a manifest is a hash followed right away by its path, and the
+// hashes all have the same length, which is all it takes to split them apart.
+// Paths are kept as the bytes they are, since names don't have to be UTF-8,
+// and are only made readable, lossily, when they're printed. Only the ones
+// that had to be escaped need unescaping into bytes of their own.
+fn read_hashes(manifest: &[u8], digest_length: usize) -> Hashes<'_> {
+    manifest
+        .par_split(|&byte| byte == b'\n')
+        .filter_map(|line| {
+            let (hash, path) = manifest::parse(line, digest_length)?;
+            Some((path, hash))
+        })
+        .collect()
+}
+
+// Each path in a manifest, and the hash it's listed with.
+type Hashes<'a> = HashMap<Cow<'a, [u8]>, &'a str>;
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+// Elsewhere, paths that aren't UTF-8 can't be spelled out as bytes at all.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+// Returns what to exit with, once everything's been reported. walked is what
+// to call the last of the manifests in the report, for `jw verify`, where it's
+// the one that was just written by hashing the directories.
+fn checksum_diff(
+    algorithm: HashAlgorithm,
+    paths: &[String],
+    walked: Option<(&str, &Mmap)>,
+    stats_to: Option<&StatsTarget>,
+    stats_format: StatsFormat,
+    quarantine: Option<(&Path, Mode)>,
+    verify: Option<&Verify>,
+) -> i32 {
+    let mut paths = paths.iter();
+
+    // Only the first manifest is needed no matter what; any of the others
+    // that can't be read are left out of the comparison, which still fails at
+    // the end, since it wasn't checked against everything it was meant to be.
+    let too_few = || -> ! {
+        Diagnostic::new("needs at least two manifests, the one that's right, and one to check")
+            .tip("to check directories against a manifest, use jw verify <manifest> <directory>")
+            .exit(1)
+    };
+
+    let Some(base_path) = paths.next() else {
+        too_few();
+    };
+
+    if let Some(problem) = not_a_manifest(base_path) {
+        problem.exit(1);
+    }
+
+    let base_file = PathBuf::from(base_path);
+    let subsequent_paths: Vec<&String> = paths.collect();
+
+    if subsequent_paths.is_empty() && walked.is_none() {
+        too_few();
+    }
+
+    let subsequent_files: Vec<PathBuf> = subsequent_paths
+        .iter()
+        .filter(|path| match not_a_manifest(path) {
+            Some(problem) => {
+                problem.print();
+                false
+            }
+            None => true,
+        })
+        .map(PathBuf::from)
+        .collect();
+
+    let digest_length: usize = algorithm.digest_size() * 2;
+
+    // Manifests are mapped rather than read line by line, so every hash and
+    // path is a slice of the mapping instead of a String of its own, and the
+    // lines can be split apart and parsed across threads. Mapping a file is
+    // only sound as long as nothing truncates it in the meantime, which is a
+    // given for a manifest that's being checked against.
+    let map_manifest = |file: &PathBuf| -> Option<Mmap> {
+        File::open(file)
+            .and_then(|file| unsafe { Mmap::map(&file) })
+            .map_err(|e| log::error(format_args!("Failed to open {}: {}", file.display(), e)))
+            .ok()
+    };
+
+    let base_manifest = map_manifest(&base_file).unwrap_or_else(|| exit(1));
+    let base_hashes: Hashes = read_hashes(&base_manifest, digest_length);
+
+    let subsequent_manifests: Vec<(Mmap, PathBuf)> = subsequent_files
+        .into_iter()
+        .filter_map(|pb| Some((map_manifest(&pb)?, pb)))
+        .collect();
+
+    let unread = subsequent_paths.len() - subsequent_manifests.len();
+
+    if subsequent_manifests.is_empty() && walked.is_none() {
+        Diagnostic::new("none of the manifests to compare against could be read").exit(1);
+    }
+
+    // jw verify's own manifest comes last, and goes by the directories it's
+    // of, since it was never anywhere anyone could see it.
+    let subsequent_hash_files: Vec<(Hashes, String)> = subsequent_manifests
+        .iter()
+        .map(|(manifest, pb)| (&manifest[..], pb.display().to_string()))
+        .chain(walked.map(|(walked, manifest)| (&manifest[..], walked.to_string())))
+        .map(|(manifest, name)| (read_hashes(manifest, digest_length), name))
+        .collect();
+
+    let mut discrepancies: usize = 0;
+    let mut out = Sink::new(std::io::stdout().lock(), false);
+
+    let mut msg_mismatches: Vec<String> = vec![];
+    let mut msg_unstable: Vec<String> = vec![];
+    let mut msg_missing: Vec<String> = vec![];
+    let mut msg_excess: Vec<String> = vec![];
+    let mut mismatched: HashSet<&[u8]> = HashSet::new();
+
+    for (file_name, base_hash) in &base_hashes {
+        for (other_hashes, hash_file) in &subsequent_hash_files {
+            if let Some(other_hash) = other_hashes.get(file_name) {
+                // A file that was changing as it was hashed, on either side,
+                // can't be said to match or not.
+                if is_unstable_digest(other_hash) || is_unstable_digest(base_hash) {
+                    let msg = format!(
+                        "[~({})] {}",
+                        hash_file,
+                        forks::shown(&path_from_bytes(file_name)).display(),
+                    );
+
+                    tui::discrepancy(&msg);
+                    msg_unstable.push(msg);
+                    discrepancies += 1;
+                } else if *other_hash != *base_hash {
+                    let msg = format!(
+                        "[!({})] {} != {} == {}",
+                        hash_file,
+                        other_hash,
+                        base_hash,
+                        forks::shown(&path_from_bytes(file_name)).display(),
+                    );
+
+                    tui::discrepancy(&msg);
+                    msg_mismatches.push(msg);
+
+                    discrepancies += 1;
+                    mismatched.insert(file_name.as_ref());
+                }
+            } else {
+                let msg = format!(
+                    "[-({})] {}",
+                    hash_file,
+                    forks::shown(&path_from_bytes(file_name)).display()
+                );
+
+                tui::discrepancy(&msg);
+                msg_missing.push(msg);
+                discrepancies += 1;
+            }
+        }
+    }
+
+    for (other_hashes, hash_file) in &subsequent_hash_files {
+        for (file_name, other_hash) in other_hashes {
+            if !base_hashes.contains_key(file_name) {
+                let msg = format!(
+                    "[+({})] {} {}",
+                    hash_file,
+                    other_hash,
+                    forks::shown(&path_from_bytes(file_name)).display()
+                );
+
+                tui::discrepancy(&msg);
+                msg_excess.push(msg);
+
+                discrepancies += 1;
+            }
+        }
+    }
+
+    // The manifests are read into hash maps, which would otherwise leave the
+    // order up to their hashing.
+    msg_mismatches.sort_unstable();
+    msg_unstable.sort_unstable();
+    msg_missing.sort_unstable();
+    msg_excess.sort_unstable();
+
+    for msg in msg_mismatches
+        .iter()
+        .chain(msg_unstable.iter())
+        .chain(msg_missing.iter())
+        .chain(msg_excess.iter())
+    {
+        writeln!(out, "{}", msg);
+    }
+
+    // The files that no longer match are set aside under the quarantine, at
+    // the path they're listed under, with a leading / or ./ dropped. Anything
+    // already there from an earlier time is kept, and the file goes next to it.
+    let mut quarantined = 0;
+
+    if let Some((dir, mode)) = quarantine {
+        let mut mismatched: Vec<&[u8]> = mismatched.into_iter().collect();
+        mismatched.sort_unstable();
+
+        for file_name in mismatched {
+            let path = &path_from_bytes(file_name);
+            let file_name = forks::shown(path).display().to_string();
+
+            let relative: PathBuf = path
+                .components()
+                .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                .collect();
+
+            let target = dir.join(relative);
+
+            let placed = target
+                .parent()
+                .map_or(Ok(()), mutate::create_dir_all)
+                .and_then(|_| path.symlink_metadata())
+                .and_then(|metadata| {
+                    relocate::place_at(
+                        mode,
+                        Collision::Rename,
+                        path,
+                        &target,
+                        metadata.file_type(),
+                        verify,
+                    )
+                });
+
+            match placed {
+                Ok(Placed::Done(target, _)) => {
+                    writeln!(out, "[q({})] {}", target.display(), file_name);
+                    quarantined += 1;
+                }
+                Ok(_) => {}
+                Err(e) => log::error(format_args!("Failed to quarantine {}: {}", file_name, e)),
+            }
+        }
+    }
+
+    drop(out);
+
+    // Only ever quarantined files, each of which is a discrepancy already.
+    let unverified = verify.map_or(0, Verify::failures);
+
+    if let Some(stats_to) = stats_to.filter(|_| stats_format == StatsFormat::Json) {
+        stats_to.report(&format!(
+            "{{\"discrepancies\":{},\"mismatches\":{},\"unstable\":{},\"missing\":{},\"excess\":{},\"quarantined\":{},\"unverified\":{}}}",
+            discrepancies,
+            msg_mismatches.len(),
+            msg_unstable.len(),
+            msg_missing.len(),
+            msg_excess.len(),
+            quarantined,
+            unverified
+        ));
+        return if discrepancies == 0 && unverified == 0 && unread == 0 {
+            0
+        } else {
+            1
+        };
+    }
+
+    if let Some(stats_to) = stats_to {
+        if discrepancies == 0 {
+            stats_to.report("All entries validated without any discrepancies.");
+            return if unread == 0 { 0 } else { 1 };
+        } else {
+            let mut report = format!(
+                "Found {} total discrepancies!\n  {} Mismatching Hashes\n  {} Unstable Files\n  {} Missing Files\n  {} Excess Files",
+                discrepancies,
+                msg_mismatches.len(),
+                msg_unstable.len(),
+                msg_missing.len(),
+                msg_excess.len()
+            );
+
+            if quarantine.is_some() {
+                report.push_str(&format!("\n  {} Quarantined Files", quarantined));
+            }
+
+            if verify.is_some() {
+                report.push_str(&format!("\n  {} Failed Verification", unverified));
+            }
+
+            stats_to.report(&report);
+            return 1;
+        }
+    }
+
+    // The same as the exit status says, whether or not there are stats.
+    if discrepancies > 0 || unverified > 0 || unread > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// The algorithm -C gives, which is xxh3 unless it's given.
+fn checksum_algorithm(matches: &clap::ArgMatches) -> HashAlgorithm {
+    parse_algorithm(
+        matches
+            .get_one::<String>("checksum-algo")
+            .map_or("xxh3", String::as_str),
+    )
+}
+
+fn parse_algorithm(name: &str) -> HashAlgorithm {
+    name.parse().unwrap_or_else(|e| Diagnostic::new(e).exit(1))
+}
+
+// What's wrong with a path that's meant to be a manifest, if anything. The
+// likeliest mistake is a directory, which is what jw verify is for.
+fn not_a_manifest(path: &str) -> Option<Diagnostic> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => return None,
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return Some(
+                Diagnostic::new(format!("isn't a manifest that can be read: {}", e)).about(path),
+            )
+        }
+    };
+
+    let problem = Diagnostic::new("isn't a manifest, which is a file jw -c wrote").about(path);
+
+    Some(match metadata.is_dir() {
+        true => problem.tip(format!(
+            "did you mean to check the directory against a manifest? That's jw verify <manifest> {}",
+            path
+        )),
+        false => problem,
+    })
+}
+
+// The options that only make sense, or can only be done, on some platforms.
+fn unsupported(flag: &str, platforms: &str) -> ! {
+    Diagnostic::new(format!("is only supported on {}", platforms))
+        .about(flag)
+        .exit(1)
+}
+
+fn read_options(matches: &clap::ArgMatches, progress: Arc<Progress>) -> ReadOptions {
+    ReadOptions {
+        backend: matches
+            .get_one::<String>("io-backend")
+            .map(IoBackend::from)
+            .unwrap_or(IoBackend::Std),
+        noatime: !matches.get_flag("atime"),
+        no_cache_pollution: matches.get_flag("no-cache-pollution"),
+        direct_io: matches.get_flag("direct-io"),
+        bwlimit: matches
+            .get_one::<f64>("bwlimit")
+            .map(|&rate| Arc::new(Throttle::new(rate))),
+        progress,
+        buffer_size: *matches.get_one::<usize>("buffer-size").unwrap(),
+        retries: matches.get_one::<u32>("retries").copied().unwrap_or(0),
+        rehash: matches.get_one::<u32>("rehash").copied().unwrap_or(0),
+        allow_special: matches.get_flag("allow-special"),
+        mmap: matches
+            .get_one::<String>("mmap")
+            .is_none_or(|when| !when.eq_ignore_ascii_case("never")),
+    }
+}
+
+// The whole of jw's command line, which --man renders as well.
+fn cli() -> Command {
+    Command::new("jw")
+        .version("2.2.8")
+        .about("A CLI frontend to jwalk for blazingly fast filesystem traversal!")
+        .after_long_help("Exit status:
+  0    Everything went fine, and something was found.
+  1    Something failed outright, --strict saw an error, or --diff found
+       discrepancies.
+  2    The arguments didn't make sense.
+  3    The run finished, but some entries couldn't be read or acted on.
+  4    The run finished without errors, but nothing matched.
+  130  Interrupted by Ctrl+C, or 143 by SIGTERM.
+
+Environment:
+  JW_THREADS, JW_ALGO, JW_EXCLUDE, and the others listed by the options they're
+  for, set those options, for when there's no wrapping every invocation, as in
+  containers and CI. Whatever's given on the command line takes precedence
+  over them, and they take precedence over the config file.
+  JW_NO_COLOR  The same as --color never, unless --color or JW_COLOR is given.
+  JW_CONFIG    The config file to read, the same as --config.")
+        .arg(Arg::new("live-print")
+            .long("live")
+            .short('l')
+            .action(ArgAction::SetTrue)
+            .help("Display results in realtime, rather than collecting first and displaying later.")
+            .long_help("Display results in realtime, rather than collecting first and displaying later.
+This will result in a significant drop in performance due to the constant terminal output."))
+
+        .arg(Arg::new("checksum")
+            .long("checksum")
+            .short('c')
+            .action(ArgAction::SetTrue)
+            .help("Generate an index of file hashes and their associated file names, and print it.")
+            .long_help("Generate an index of file hashes and their associated file names, and print it.
+The algorithm used by default is Xxh3, which is the recommended choice. Though
+if you want to use a different algorithm, use --checksum-with (-C) instead.
+Each line is a hash followed right away by a path. Names with a newline or a
+carriage return in them, or that aren't UTF-8, start their line with a \\ and
+have \\\\, \\n, \\r, and \\xHH escapes, as sha256sum does, so --diff reads every
+path back exactly as it is on disk."))
+
+        .arg(Arg::new("checksum-algo")
+            .long("checksum-with")
+            .env("JW_ALGO")
             .short('C')
             .value_parser(["xxh3", "sha224", "sha256", "sha384", "sha512", "md5"])
             .default_value("xxh3")
@@ -362,78 +2566,1600 @@ in the subseqeunt files will be reported. If entries from the first file are
 missing in the subsequent files, or if the subsequent files have entries not 
 present in the first file, that will be reported as well.
 
-The hash length must be known for -D to parse the input files and separate
-hashes from file paths. A length of 16 is assumed by default as that's how
-long Xxh3 hashes are. If you used a different algorithm however, then you
-must specify the algorithm before -D, e.g. `jw -C sha256 -D file1 file2`
+The hash length must be known for -D to parse the input files and separate
+hashes from file paths. A length of 16 is assumed by default as that's how
+long Xxh3 hashes are. If you used a different algorithm however, then you
+must specify the algorithm before -D, e.g. `jw -C sha256 -D file1 file2`
+
+If you stuck with defaults: `jw -c`, then you can just `jw -D file1 file2`"))
+
+        .arg(Arg::new("quarantine")
+            .long("quarantine")
+            .value_name("dir")
+            .value_parser(value_parser!(PathBuf))
+            .requires("hdiff")
+            .help("Move the files whose hashes don't match the first manifest of --diff into dir.")
+            .long_help("Move the files whose hashes don't match the first manifest of --diff into dir.
+Each one goes to the path it's listed under, with any leading / or ./ dropped,
+so `jw -D baseline.txt today.txt --quarantine /quarantine` moves /srv/a/b.db to
+/quarantine/srv/a/b.db, out of the way of anything that would use it, and
+somewhere it can be looked at. If something is already there, from an earlier
+run say, it's kept, and the file goes next to it as b.1.db. A line is printed
+for each file quarantined. See also --quarantine-copy."))
+
+        .arg(Arg::new("quarantine-copy")
+            .long("quarantine-copy")
+            .action(ArgAction::SetTrue)
+            .requires("quarantine")
+            .help("Copy the files into the quarantine, rather than moving them, leaving them where they are."))
+
+        .arg(Arg::new("verify-after")
+            .long("verify-after")
+            .value_parser(["xxh3", "sha224", "sha256", "sha384", "sha512", "md5"])
+            .ignore_case(true)
+            .num_args(0..=1)
+            .default_missing_value("xxh3")
+            .value_name("algorithm")
+            .help("Hash everything that's copied, moved or linked again afterwards, and fail if it differs.")
+            .long_help("Hash everything that's copied, moved or linked again afterwards, and fail if it differs.
+Covers --copy-to, --move-to and --quarantine, where each file is hashed before
+it's put anywhere, and then read back from where it ended up, and --action
+hardlink and reflink, where each duplicate is read back through its own name
+and has to hash the same as its group did. Those that don't are listed as
+errors, and jw exits with 1 once everything else is done. The hash is xxh3,
+unless another algorithm is given, e.g. `--verify-after sha256`, except for
+--dupes, which always goes by xxh3. --mirror and --cas-export always read back
+what they copy, so they don't need it."))
+
+        .arg(Arg::new("depth")
+            .long("depth")
+            .short('d')
+            .value_parser(value_parser!(usize))
+            .value_name("limit")
+            .default_value("0")
+            .help("The recursion depth limit. Setting this to 1 effectively disables recursion.")
+            .long_help("The recursion depth limit. Setting this to 1 effectively disables recursion.
+0, the default, means there's no limit. 1 is each root and what's directly in
+it, which --no-recurse is a shorthand for, 2 goes a directory further, and so
+on."))
+
+        .arg(Arg::new("no-recurse")
+            .long("no-recurse")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("depth")
+            .help("Only list what's directly in each root, without descending any further, the same as -d 1.")
+            .long_help("Only list what's directly in each root, without descending any further, the same as -d 1.
+In checksum mode, that's the files directly in each root that are hashed, and
+the directories next to them are left out, along with everything in them,
+the same as they would be at any other depth. A file given as a root is still
+hashed, whether it's an argument, or from --files-from or stdin."))
+
+        .arg(Arg::new("threads")
+            .long("threads")
+            .env("JW_THREADS")
+            .short('j')
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .help("The number of threads to walk and hash with, 0 meaning one per CPU.")
+            .long_help("The number of threads to walk and hash with, 0 meaning one per CPU.
+By default, walking and hashing share a pool with one thread per CPU. This
+resizes that pool, e.g. to cap how much of a shared server jw can take up, or
+to go beyond one per CPU when hashing from storage with a deep queue, where
+threads are mostly waiting on reads."))
+
+        .arg(Arg::new("walk-threads")
+            .long("walk-threads")
+            .env("JW_WALK_THREADS")
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .help("Walk with a dedicated pool of this many threads, separate from hashing.")
+            .long_help("Walk with a dedicated pool of this many threads, separate from hashing.
+This implies --walk-strategy dedicated, where each root gets a pool of its own
+rather than sharing the one sized by --threads. 0 means one per CPU."))
+
+        .arg(Arg::new("walk-strategy")
+            .long("walk-strategy")
+            .value_parser(["serial", "shared", "dedicated"])
+            .value_name("strategy")
+            .help("How directories are read in parallel: serial, shared, or dedicated.")
+            .long_help("How directories are read in parallel: serial, shared, or dedicated.
+serial reads one directory at a time on the thread consuming the walk, which
+avoids thread handoffs but can't overlap the latency of one read with another.
+shared, the default, spreads directories across the same pool used to hash.
+dedicated gives each root a pool of its own, sized with --walk-threads, which
+is worth trying on high latency network filesystems, where plenty of reads in
+flight matter more than CPU. Walks with a --depth under 2 are always serial."))
+
+        .arg(Arg::new("hash-threads")
+            .long("hash-threads")
+            .env("JW_HASH_THREADS")
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .help("The number of threads to hash with, overriding --threads for hashing.")
+            .long_help("The number of threads to hash with, overriding --threads for hashing.
+Unless --walk-threads is given as well, walking still happens on these same
+threads. 0 means one per CPU."))
+
+        .arg(Arg::new("io-backend")
+            .long("io-backend")
+            .env("JW_IO_BACKEND")
+            .value_parser(["std", "uring"])
+            .ignore_case(true)
+            .value_name("backend")
+            .default_value("std")
+            .help("How files are read when hashing: std or uring.")
+            .long_help("How files are read when hashing: std or uring.
+std reads each file with regular read calls, one file after another per thread.
+uring, on Linux only, hands files to the hashing threads in batches and queues
+the reads for a whole batch with io_uring at once, which pays off with lots of
+small files on fast storage. Files over 20 MiB are memory mapped either way.
+If io_uring isn't available, e.g. blocked in a container, it falls back to std."))
+
+        .arg(Arg::new("noatime")
+            .long("noatime")
+            .action(ArgAction::SetTrue)
+            .overrides_with("atime")
+            .help("Don't update access times of the files hashed, which is the default.")
+            .long_help("Don't update access times of the files hashed, which is the default.
+On Linux files are opened with O_NOATIME, so verifying millions of files doesn't
+turn into writing the metadata of every single one of them back out. That's only
+permitted on files owned by the user running jw, or for root; any other file is
+read the regular way, as is every file on other platforms. Mount options like
+relatime or noatime already avoid most of these writes."))
+
+        .arg(Arg::new("atime")
+            .long("atime")
+            .action(ArgAction::SetTrue)
+            .overrides_with("noatime")
+            .help("Let reading files for hashing update their access times, as usual."))
+
+        .arg(Arg::new("no-cache-pollution")
+            .long("no-cache-pollution")
+            .action(ArgAction::SetTrue)
+            .help("Keep the files hashed from pushing everything else out of the page cache.")
+            .long_help("Keep the files hashed from pushing everything else out of the page cache.
+On Linux, each file is announced to the kernel as read sequentially before it's
+hashed, and dropped from the page cache afterwards with posix_fadvise, so that
+hashing a whole volume doesn't evict the working set of everything else running
+on the machine. The flip side is that hashing the same files again has to read
+them from disk again. Has no effect on other platforms."))
+
+        .arg(Arg::new("direct-io")
+            .long("direct-io")
+            .action(ArgAction::SetTrue)
+            .help("Read files for hashing with O_DIRECT, bypassing the page cache entirely.")
+            .long_help("Read files for hashing with O_DIRECT, bypassing the page cache entirely.
+Files are read straight from the device in 1 MiB reads, never memory mapped, and
+nothing they contain ends up cached. That makes throughput on huge files more
+predictable on a dedicated verification host, but is usually slower for small
+files, and reads with std even under --io-backend uring. Filesystems that don't
+support O_DIRECT are read the regular way. Only on Linux, elsewhere files are
+still read in large chunks without being mapped, but through the page cache."))
+
+        .arg(Arg::new("mmap")
+            .long("mmap")
+            .env("JW_MMAP")
+            .value_parser(["auto", "always", "never"])
+            .ignore_case(true)
+            .value_name("when")
+            .default_value("auto")
+            .help("Whether big files are memory mapped to be hashed: auto, always, or never.")
+            .long_help("Whether big files are memory mapped to be hashed: auto, always, or never.
+Files over 20 MiB are mapped rather than read, which is the fastest way to get
+through them on a local disk. auto does that unless a root is on a network
+filesystem, where every page touched is a round trip of its own, with nothing
+read ahead, so files there are read in chunks instead. always maps them even
+then, and never reads everything in chunks."))
+
+        .arg(Arg::new("buffer-size")
+            .long("buffer-size")
+            .env("JW_BUFFER_SIZE")
+            .value_parser(hashutil::parse_buffer_size)
+            .value_name("size")
+            .default_value("128K")
+            .help("How much to read at a time when hashing files, with the std backend.")
+            .long_help("How much to read at a time when hashing files, with the std backend.
+Accepts the same sizes as --filter, e.g. 64K or 1M. It only applies to regular
+reads; files over 20 MiB are memory mapped, --direct-io reads 1 MiB at a time,
+and --io-backend uring 64 KiB per read. `jw bench` tries a few of these out."))
+
+        .arg(Arg::new("rehash")
+            .long("rehash")
+            .value_name("n")
+            .value_parser(value_parser!(u32))
+            .help("Hash files that changed while they were being hashed again, up to n times.")
+            .long_help("Hash files that changed while they were being hashed again, up to n times.
+Every file's size and mtime are compared from before it's read and after, and
+one that's changed in between, because something was writing to it, hashes to
+a mix of old and new contents. Without this, it's flagged as unstable, with a
+warning, and listed with a row of ~ in place of its hash, so that a manifest
+never holds a hash that's of neither version, and --diff reports it as
+unstable, with [~], rather than as a mismatch. With it, the file's hashed
+again, until a read gets through it without it changing, or it's been n more
+times, in which case it's flagged after all. With --cas-export, which has
+nothing to store it as, it's reported as an error instead. Files that keep
+being written to, like logs, are best left out with --filter or --exclude."))
+
+        .arg(Arg::new("allow-special")
+            .long("allow-special")
+            .action(ArgAction::SetTrue)
+            .help("Hash FIFOs and device nodes too, reading each until it ends.")
+            .long_help("Hash FIFOs and device nodes too, reading each until it ends.
+Without it, checksum mode only ever hashes regular files, and anything that's
+turned out to be something else by the time it's opened, like a FIFO, which
+would have jw wait for a writer that may never come, or a device, which could
+be endless, or do something just by being opened, is reported as an error, and
+left alone. With it, FIFOs, character and block devices are read like files,
+whether they're found by the walk or a symlink points to one, which is how a
+whole disk can be hashed, e.g. `jw -c --allow-special /dev/disk/by-id`. Do keep
+/dev/zero and friends out of it."))
+
+        .arg(Arg::new("retries")
+            .long("retries")
+            .env("JW_RETRIES")
+            .value_name("n")
+            .value_parser(value_parser!(u32))
+            .help("Try reads and stats that fail the way network filesystems do up to n more times.")
+            .long_help("Try reads and stats that fail the way network filesystems do up to n more times.
+NFS and SMB mounts hand out errors like EIO, ESTALE or ETIMEDOUT whenever the
+server's unreachable for a moment, which fail the file, even though it'd read
+fine a second later. With this, those are retried, waiting a tenth of a second
+the first time, and twice as long each time after, up to 10 seconds, before
+the file is given up on, and reported as an error like any other, saying how
+many times it was tried. Errors that say something about the file itself, like
+it not being there, or not being readable, are never retried."))
+
+        .arg(Arg::new("bwlimit")
+            .long("bwlimit")
+            .env("JW_BWLIMIT")
+            .value_parser(throttle::parse_bwlimit)
+            .value_name("MB/s")
+            .help("Limit how fast files are read for hashing, in MB/s across all threads.")
+            .long_help("Limit how fast files are read for hashing, in MB/s across all threads.
+The limit applies to all reads combined, no matter how many threads hash, so an
+integrity check can run in the background on production storage without taking
+all of its bandwidth. It's kept on average over each read, which are up to 1 MiB
+at a time, so short spikes above the limit are possible. Fractions are allowed,
+e.g. --bwlimit 0.5 for 500 KB/s."))
+
+        .arg(Arg::new("background")
+            .long("background")
+            .action(ArgAction::SetTrue)
+            .help("Run at the lowest CPU and I/O priority, yielding to everything else.")
+            .long_help("Run at the lowest CPU and I/O priority, yielding to everything else.
+Sets the process to the lowest CPU priority, like nice -n 19, and on Linux to the
+idle I/O scheduling class, like ionice -c 3, so its reads only go through when
+nothing else is using the disk; on macOS it's placed in background mode, which
+throttles its I/O instead. Meant for scheduled verification sweeps that should
+never get in the way of interactive use, at the cost of taking far longer on a
+busy machine. It can be combined with --bwlimit. The idle I/O class is only
+honored by I/O schedulers that support priorities, such as BFQ."))
+
+        .arg(Arg::new("progress")
+            .long("progress")
+            .action(ArgAction::SetTrue)
+            .group("progress-display")
+            .conflicts_with("live-print")
+            .help("Show how far along the run is on stderr.")
+            .long_help("Show how far along the run is on stderr.
+In checksum mode, that's the number of files and bytes hashed so far, and how
+fast, otherwise the number of entries walked. It's drawn on stderr, so stdout
+can still be redirected into a manifest without it getting in there. On a
+terminal the line is redrawn in place, otherwise it's printed every 5 seconds."))
+
+        .arg(Arg::new("prescan")
+            .long("prescan")
+            .action(ArgAction::SetTrue)
+            .requires("progress-display")
+            .help("Count the files to hash up front, so progress can show a percentage and ETA.")
+            .long_help("Count the files to hash up front, so progress can show a percentage and ETA.
+This walks everything once before hashing starts, with the same filters, just to
+add up the number of files and their sizes, which is quick next to hashing them.
+Requires --progress or --progress-every, and only has an effect in checksum mode."))
+
+        .arg(Arg::new("progress-every")
+            .long("progress-every")
+            .value_parser(progress::parse_every)
+            .value_name("n|duration")
+            .group("progress-display")
+            .conflicts_with_all(["progress", "live-print"])
+            .help("Print a line with the running count to stderr every n entries, or every so often.")
+            .long_help("Print a line with the running count to stderr every n entries, or every so often.
+A plain number counts entries walked, or files hashed in checksum mode, while
+a number with a unit, e.g. 500ms, 10s, 5m or 1h, prints a line that often. It's
+the same line --progress draws, printed one after the other instead, which
+gives the reassurance of --live without the cost of printing every entry."))
+
+        .arg(Arg::new("tui")
+            .long("tui")
+            .action(ArgAction::SetTrue)
+            .group("progress-display")
+            .conflicts_with_all(["progress", "progress-every", "live-print", "pick"])
+            .help("Show a full-screen live view of the run, for keeping an eye on long ones.")
+            .long_help("Show a full-screen live view of the run, for keeping an eye on long ones.
+It has the same counts and rates --progress does, the directory the walk or the
+hashing is in, what each hashing thread is on and for how long, the latest of
+the errors and skipped entries, and with jw diff and jw verify, a list of the
+discrepancies that scrolls with Up, Down, PgUp and PgDn. jw verify lists the
+mismatches and excess files as they're hashed, and the full diff once it's
+done, like without --tui. Whatever else would be printed to stderr is held
+until the view closes. It's drawn on the terminal, so stdout has to be
+redirected, unless nothing is printed there, as with -s, or jw verify. Ctrl+C
+stops the run as usual. With --prescan, there's a percentage and ETA too. Only
+supported on Unix."))
+
+        .arg(Arg::new("exclude")
+            .long("exclude")
+            .env("JW_EXCLUDE")
+            .short('x')
+            .value_parser(["files", "dirs", "dot", "other"])
+            .value_name("t1,t2")
+            .value_delimiter(',')
+            .help("Exclude one more types of entries, separated by coma.")
+            .long_help("Exclude one more types of entries, separated by coma.
+Exclusions apply identically in checksum mode, e.g. `jw -c -x dot` hashes
+everything except hidden entries, and `-x files` leaves nothing to hash.")
+            .num_args(0..=4))
+
+        .arg(Arg::new("follow-links")
+            .long("follow-links")
+            .action(ArgAction::SetTrue)
+            .help("Descend into directories that symlinks, and junctions on Windows, point to.")
+            .long_help("Descend into directories that symlinks, and junctions on Windows, point to.
+Without it, symlinks are listed but never followed, and the same goes for
+junctions and directory symlinks on Windows, which are links rather than
+directories there too, so a junction that leads back up the tree can't send the
+walk around in circles, and nothing is counted twice. With it, what they point
+to is walked as if it were there, and links that lead back to one of their own
+parent directories are reported as errors rather than followed. Other kinds of
+reparse points, like OneDrive's placeholders, are files and directories like
+any other either way."))
+
+        .arg(Arg::new("ads")
+            .long("ads")
+            .action(ArgAction::SetTrue)
+            .help("List and hash the alternate data streams of files on NTFS, as file:stream. Windows only.")
+            .long_help("List and hash the alternate data streams of files on NTFS, as file:stream. Windows only.
+Streams are extra contents that can be attached to any file, under a name of
+their own, and that nothing which only lists directories ever sees, which makes
+them a favorite for hiding things in. Windows keeps some metadata in them too,
+like the Zone.Identifier saying where a download came from. Each one is listed
+right after its file, and in checksum mode, hashed like a file of its own, so
+they end up in manifests, and --diff notices any that were changed, added, or
+removed. They're left out of --tree, and --stats counts them as files hashed,
+but not as entries."))
+
+        .arg(Arg::new("mac-metadata")
+            .long("mac-metadata")
+            .action(ArgAction::SetTrue)
+            .help("List and hash the resource forks and Finder metadata of files too. macOS only.")
+            .long_help("List and hash the resource forks and Finder metadata of files too. macOS only.
+This is what Finder copies along with a file, on top of its contents, so
+it's what verifying a copy, or what came out of an archive made on a Mac, has
+to cover as well. A resource fork that isn't empty is listed as
+file/..namedfork/rsrc, which is where it can be read from, and the
+com.apple.FinderInfo and com.apple.quarantine extended attributes as
+file@com.apple.FinderInfo and so on, and in checksum mode, each is hashed like a
+file of its own, so they end up in manifests, and --diff notices any that
+changed. Attributes aren't stored by --cas-export, which only takes files."))
+
+        .arg(Arg::new("xattrs")
+            .long("xattrs")
+            .action(ArgAction::SetTrue)
+            .help("List and hash the extended attributes of every entry, as file@name. Linux and macOS only.")
+            .long_help("List and hash the extended attributes of every entry, as file@name. Linux and macOS only.
+Extended attributes are where SELinux labels, capabilities, ACLs and the like
+are kept, on top of whatever programs put in user.* ones, so changing them can
+matter as much as changing a file's contents, without changing a byte of
+those. Each one is listed right after its entry, sorted by name, and in
+checksum mode, files' are hashed by their values, so the manifest gets a line
+for each name along with a digest of its value, and --diff notices any that
+changed, appeared or went away. In a manifest, it's written as file\\@name,
+which keeps it apart from a file that's actually called file@name.
+Directories' and symlinks' are listed, but not hashed, since checksum mode only
+hashes files. Which ones can be read depends on who's asking; trusted.* ones
+are only there for root. With --mac-metadata, the Finder's attributes are
+listed once, along with the rest."))
+
+        .arg(Arg::new("silent")
+            .long("silent")
+            .short('S')
+            .action(ArgAction::SetTrue)
+            .help("Suppress output, useful for benchmarking, or just counting files via --stats"))
+
+        .arg(Arg::new("stats")
+            .long("stats")
+            .short('s')
+            .action(ArgAction::SetTrue)
+            .help("Count the number of files, dirs, and other entries, and print at the end.")
+            .long_help("Count the number of files, dirs, and other entries, and print at the end.
+Entries are counted by the type jwalk already read from their directory, so
+this costs next to nothing; only symlinks take an extra stat, since they're
+counted as whatever they point to. Together with --silent, nothing but the
+counting happens, whatever the output format, which makes for a fast counter.
+In checksum mode, it reports the number of files hashed instead, along with the
+bytes read, the wall time, and the throughput in MB/s and files/s. Each root
+gets a block of its own, headed by its path, as soon as it's done, and with more
+than one root, a block of their totals follows at the end. Entries that
+couldn't be read are counted too, with --list-errors to see which they were.")
+            )
+
+        .arg(Arg::new("list-errors")
+            .long("list-errors")
+            .action(ArgAction::SetTrue)
+            .help("Print every path that couldn't be read, and why, once its root is done.")
+            .long_help("Print every path that couldn't be read, and why, once its root is done.
+That takes in directories that couldn't be listed, entries that vanished before
+they could be looked at, and files that couldn't be hashed, all of which are
+otherwise left out of the output, logged as warnings as they happen, and
+counted in the --stats block. The list goes wherever --stats-to points, stderr
+by default, whether or not --stats is given, and sorted, so a run's can be
+compared with another's."))
+
+        .arg(Arg::new("verbose")
+            .long("verbose")
+            .short('v')
+            .action(ArgAction::Count)
+            .help("Log more of what happens along the way, -v for info, -vv for debug.")
+            .long_help("Log more of what happens along the way, -v for info, -vv for debug.
+By default, only errors and warnings are logged, a warning being something
+that was done differently than asked, like falling back to regular reads when
+io_uring isn't available, or an entry that's skipped since it couldn't be read,
+as it happens. Info adds every read that's retried, and debug adds what jw
+settled on, such as how many threads it's using, and each root as it starts
+being walked."))
+
+        .arg(Arg::new("log-level")
+            .long("log-level")
+            .value_parser(["error", "warn", "info", "debug"])
+            .ignore_case(true)
+            .value_name("level")
+            .help("How much to log, the same as -v and -vv, but able to turn it down as well.")
+            .long_help("How much to log, the same as -v and -vv, but able to turn it down as well.
+Each level includes those before it, and takes precedence over -v."))
+
+        .arg(Arg::new("log-file")
+            .long("log-file")
+            .value_parser(log::parse_log_file)
+            .value_name("file")
+            .help("Append the log to the given file, rather than printing it to stderr.")
+            .long_help("Append the log to the given file, rather than printing it to stderr.
+Each line starts with when it was logged, in UTC, and its level, e.g.
+`2024-05-01T03:12:45Z INFO Retrying /mnt/nas/a.iso in 0.1s, after: ...`, so a
+long unattended run leaves a record of everything that happened along the
+way. Errors are still printed to stderr as well; nothing less serious is, so
+none of it is mixed in with the output. The file is created if it doesn't
+exist yet, as soon as the arguments are parsed."))
+
+        .arg(Arg::new("stats-format")
+            .long("stats-format")
+            .env("JW_STATS_FORMAT")
+            .value_parser(["text", "json"])
+            .ignore_case(true)
+            .value_name("format")
+            .help("Print stats as text, or as a single JSON object at the end. Implies --stats.")
+            .long_help("Print stats as text, or as a single JSON object at the end. Implies --stats.
+The JSON object has a roots array, with an object for every root walked, and a
+total object with every root's stats added up. Each has the number of errors,
+entries that couldn't be read or files that couldn't be hashed, and the elapsed
+time in seconds. In traversal mode, they count files, directories, and other
+entries, and the bytes the files take up, which takes a stat of every entry;
+in checksum mode, the files hashed, the bytes read, as bytes_hashed, and the
+throughput. --mirror and --find-hash have both. With --diff, the object has
+the number of discrepancies of each kind instead."))
+
+        .arg(Arg::new("report")
+            .long("report")
+            .value_parser(["extensions", "sizes", "duplicates", "ages", "owners", "depth"])
+            .ignore_case(true)
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .value_name("kind")
+            .conflicts_with("checksum")
+            .help("Print a summary of the files walked at the end, of their extensions, sizes, etc.")
+            .long_help("Print a summary of the files walked at the end, of their extensions, sizes, etc.
+extensions counts the files of each extension, and the bytes they take up, from
+the biggest down; extensions are compared case insensitively. sizes is a
+histogram of files by size, in buckets from under 4K up to over 1G, along with
+the share of all the bytes up to each bucket. duplicates finds files with the
+same contents, grouping them by size, then by a hash of their first 4K, and only
+hashing in full what's still alike after that, and reports how many bytes are
+taken up by redundant copies, with the ten groups that waste the most. Empty
+files, and other names of the same hard linked file, don't count as duplicates.
+ages buckets files by how long ago they were modified, from the last day, week,
+month (30 days) and year to anything older. owners adds up the files and bytes
+of every user and every group that owns any, on Unix. depth counts entries of
+every kind at each depth, and gives the deepest one reached, which --depth
+limits as usual. Other than for depth, only regular files are tallied. Reports
+are of what's left after --filter and --exclude, and go wherever --stats-to
+says, stderr by default, so -S --report gives just the report. Several can be
+given at once, separated by commas or with --report more than once."))
+
+        .arg(Arg::new("top-size")
+            .long("top-size")
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .conflicts_with("checksum")
+            .help("List the n largest files at the end, with their sizes.")
+            .long_help("List the n largest files at the end, with their sizes.
+Only the n largest files seen so far are ever kept, rather than sorting every
+file in the tree, so this takes no more memory on millions of files than on a
+few; --sort size would have to hold on to all of them. Like --report, the list
+goes wherever --stats-to says."))
+
+        .arg(Arg::new("top-mtime")
+            .long("top-mtime")
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .conflicts_with("checksum")
+            .help("List the n most recently modified files at the end.")
+            .long_help("List the n most recently modified files at the end.
+Answers what changed last, with the mtime of each in UTC. Like --top-size, only
+the n newest files seen so far are kept along the way."))
+
+        .arg(Arg::new("bottom-mtime")
+            .long("bottom-mtime")
+            .value_parser(value_parser!(usize))
+            .value_name("n")
+            .conflicts_with("checksum")
+            .help("List the n least recently modified files at the end.")
+            .long_help("List the n least recently modified files at the end.
+Answers what's been left untouched the longest, with the mtime of each in UTC.
+Like --top-size, only the n oldest files seen so far are kept along the way."))
+
+        .arg(Arg::new("stats-to")
+            .long("stats-to")
+            .value_parser(stats::parse_stats_target)
+            .value_name("stdout|stderr|file")
+            .default_value("stderr")
+            .help("Where to print --stats to: stdout, stderr, or the given file.")
+            .long_help("Where to print --stats to: stdout, stderr, or the given file.
+Stats go to stderr by default so that redirecting stdout, e.g. `jw -c -s >
+manifest`, gets you nothing but entries. A file is created, or truncated, as
+soon as the arguments are parsed."))
+
+        .arg(Arg::new("stats-log")
+            .long("stats-log")
+            .value_parser(stats::parse_stats_log)
+            .value_name("file")
+            .help("Append a line of JSON stats for the whole run to the given file.")
+            .long_help("Append a line of JSON stats for the whole run to the given file.
+It's the same object --stats-format json prints, with every root's counts,
+bytes, errors and elapsed time, and their totals, along with a timestamp of when
+the run finished, in UTC, so that running jw routinely over the same trees
+collects a history of how they grow. Stats are kept for the log whether or not
+--stats is given, and the file is created if it doesn't exist yet, as soon as
+the arguments are parsed."))
+
+        .arg(Arg::new("sample")
+            .long("sample")
+            .value_parser(parse_sample)
+            .value_name("N|P%")
+            .help("Only emit a uniform random sample of N entries, or P percent of them.")
+            .long_help("Only emit a uniform random sample of N entries, or P percent of them.
+A fixed count is chosen via reservoir sampling over the entire stream, so the
+results can only be printed once the traversal is complete, even with --live.
+A percentage is decided per entry and streams just fine. In checksum mode the
+sample is drawn from files, and only the sampled files are hashed, which makes
+for quick spot checks of very large archives, e.g. `jw -c --sample 1000 /mnt`"))
+
+        .arg(Arg::new("newer-than-file")
+            .long("newer-than-file")
+            .value_name("ref")
+            .help("Only match entries modified more recently than the reference file.")
+            .long_help("Only match entries modified more recently than the reference file.
+Equivalent to find's -newer; handy for incremental processing keyed off a
+marker file, e.g. `jw -c --newer-than-file .last-run && touch .last-run`"))
+
+        .arg(Arg::new("filter")
+            .long("filter")
+            .env("JW_FILTER")
+            .short('f')
+            .value_parser(filter::parse)
+            .value_name("expr")
+            .help("Only match entries for which the given filter expression holds true.")
+            .long_help("Only match entries for which the given filter expression holds true.
+e.g. `jw --filter '(ext == \"log\" && size > 100M) || mtime < 2023-01-01'`
+
+Fields:    name, path, ext, size, depth, mtime, atime, ctime, btime, type, owner,
+           group, inode, dev, nlink
+Operators: == != < <= > >= (and ~ !~ for glob matching with * and ?)
+Logic:     && || ! (or and, or, not) and parentheses for grouping
+
+Sizes accept K/M/G/T (1024-based), KB/MB/GB/TB (1000-based), and KiB/MiB/..
+Times are dates, YYYY-MM-DD with an optional THH:MM[:SS], taken as UTC.
+Types are file, dir, symlink, or other. Owner and group take a name or an id.
+e.g. `nlink > 1 && type == file` finds files with hardlinks elsewhere.
+Strings can be quoted with \" or ', and only need to be if they contain spaces
+or operator characters."))
+
+        .arg(Arg::new("format")
+            .long("format")
+            .env("JW_FORMAT")
+            .short('F')
+            .value_parser(["plain", "jsonl", "csv", "tsv"])
+            .ignore_case(true)
+            .value_name("format")
+            .default_value("plain")
+            .help("The output format to print entries in.")
+            .long_help("The output format to print entries in.
+plain prints bare paths, or digests followed by paths in checksum mode.
+jsonl prints one JSON object per line, with the path, type, size, mtime (in
+seconds since the epoch), depth, and in checksum mode the hash of each entry.
+csv and tsv print a header row followed by one row per entry, with the fields
+chosen by --columns. CSV fields are quoted per RFC 4180 where necessary, TSV
+fields have tabs, newlines, and backslashes escaped instead."))
+
+        .arg(Arg::new("printf")
+            .long("printf")
+            .value_parser(template::parse)
+            .value_name("template")
+            .help("Print each entry according to a template, overriding --format.")
+            .long_help("Print each entry according to a template, overriding --format.
+Like find's -printf, no newline is added, so end the template with \\n.
+e.g. `jw --printf '%p\\t%s\\t%TY-%Tm-%Td\\n'` or `jw -c --printf '{hash} {path}\\n'`
+
+  %p {path}     Path            %f {name}     File name
+  %h {parent}   Parent dir      %P {relpath}  Path relative to the root
+  %e {ext}      Extension       %s {size}     Size in bytes
+  %d {depth}    Depth           %y            Type as f, d, l, or o
+     {type}     Type name       %m {mode}     Permission bits in octal
+  %U {uid}      Owner id        %G {gid}      Group id
+  %u {user}     Owner name      %g {group}    Group name
+  %i {inode}    Inode number    %n {nlink}    Hard link count
+  %D {dev}      Device id of the filesystem the entry is on
+  %H {hash}     Digest, in checksum mode
+  %t {mtime}    Modification time as ISO 8601, %a {atime} and %c {ctime} alike
+  %Tk           Modification time part k, %Ak and %Ck alike, where k is one of
+                Y m d H M S, @ for epoch seconds, F for Y-m-d, T for H:M:S
+  %% {{ }}      Literal %, {, and }
+                \\n \\t \\r \\0 \\\\ are escapes for the usual characters
+
+Times are in UTC. Fields that can't be determined are left empty."))
+
+        .arg(Arg::new("long")
+            .long("long")
+            .short('L')
+            .action(ArgAction::SetTrue)
+            .help("Print a long listing with mode, owner, group, size, and mtime, like ls -l.")
+            .long_help("Print a long listing with mode, owner, group, size, and mtime, like ls -l.
+This overrides --format. The metadata is fetched during the parallel walk, so
+this is a fast recursive `ls -l`. Times are in UTC. In checksum mode the digest
+is printed in front of the path."))
+
+        .arg(Arg::new("path-style")
+            .long("path-style")
+            .value_parser(["relative", "absolute", "canonical"])
+            .ignore_case(true)
+            .value_name("style")
+            .default_value("relative")
+            .help("Print paths as walked, absolutized, or fully canonicalized.")
+            .long_help("Print paths as walked, absolutized, or fully canonicalized.
+relative prints paths as they were walked, i.e. relative to however the target
+directories were given. absolute prepends the working directory where needed.
+canonical additionally resolves symlinks and any . or .. components."))
+
+        .arg(Arg::new("strip-prefix")
+            .long("strip-prefix")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("prefix")
+            .help("Strip a leading prefix from printed and manifest paths.")
+            .long_help("Strip a leading prefix from printed and manifest paths.
+The prefix is matched by whole path components; paths that don't start with
+it are left as they are. Applied after --path-style, before --prepend-prefix."))
+
+        .arg(Arg::new("prepend-prefix")
+            .long("prepend-prefix")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("prefix")
+            .help("Prepend a prefix to printed and manifest paths.")
+            .long_help("Prepend a prefix to printed and manifest paths.
+Combined with --strip-prefix, this lets a manifest created from a staging mount
+match paths at their final location, e.g.
+`jw -c /mnt/staging --strip-prefix /mnt/staging --prepend-prefix /srv/data`"))
+
+        .arg(Arg::new("tree")
+            .long("tree")
+            .short('T')
+            .value_parser(["plain", "size"])
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("plain")
+            .value_name("annotation")
+            .help("Render the traversal as an indented tree, optionally annotated with sizes.")
+            .long_help("Render the traversal as an indented tree, optionally annotated with sizes.
+This overrides --format, and like `tree`, entries are sorted by name within
+each directory. `--tree=size` annotates each node with its size in bytes, and
+in checksum mode files are annotated with their digests. The annotation has to
+be given with the =, as in -T=size, so that `jw --tree dir` takes dir as the
+directory to walk. The whole traversal has to be collected before it can be
+drawn, so --live has no effect here."))
+
+        .arg(Arg::new("color")
+            .long("color")
+            .env("JW_COLOR")
+            .value_parser(["auto", "always", "never"])
+            .ignore_case(true)
+            .value_name("when")
+            .default_value("auto")
+            .help("Colorize paths by entry type according to LS_COLORS.")
+            .long_help("Colorize paths by entry type according to LS_COLORS.
+Applies to the plain, --long, and --tree outputs; the machine readable formats
+are never colored. auto only colors when stdout is a terminal and NO_COLOR is
+unset, so piping jw into other tools always gets you clean paths. If LS_COLORS
+isn't set, the same defaults as dircolors are used."))
+
+        .arg(Arg::new("icons")
+            .long("icons")
+            .action(ArgAction::SetTrue)
+            .help("Prefix entries with file type icons; requires a Nerd Font.")
+            .long_help("Prefix entries with file type icons; requires a Nerd Font.
+Applies to the plain, --long, and --tree outputs, the same as --color, but is
+independent of it, so icons are shown even when colors are off. Icons are
+picked by file name and extension first, then by entry type."))
+
+        .arg(Arg::new("dedup-paths")
+            .long("dedup-paths")
+            .action(ArgAction::SetTrue)
+            .help("Only list or hash each entry once, even if several roots overlap.")
+            .long_help("Only list or hash each entry once, even if several roots overlap.
+Entries are compared by their canonical location, so passing the same directory
+twice, a directory alongside one of its subdirectories, or a symlink to a
+directory alongside the directory itself won't produce duplicates. Whichever
+root reaches an entry first gets to keep it. Every path seen has to be kept in
+memory until the walk is done."))
+
+        .arg(Arg::new("sort")
+            .long("sort")
+            .value_parser(["name", "natural", "size", "mtime", "depth", "ext"])
+            .ignore_case(true)
+            .value_name("key")
+            .help("Sort the collected results by name, natural, size, mtime, depth, or ext.")
+            .long_help("Sort the collected results by name, natural, size, mtime, depth, or ext.
+Sorting is ascending, with ties broken by path, so name is really sorting by
+the whole path. natural is the same, except numbers within names are compared
+by value, so file2 sorts before file10. This applies to checksum mode as well,
+but since results have to be collected first, --live output is never sorted.
+With --tree, entries are sorted among their siblings instead, so depth has no
+effect there."))
+
+        .arg(Arg::new("reverse")
+            .long("reverse")
+            .short('r')
+            .action(ArgAction::SetTrue)
+            .requires("sort")
+            .help("Reverse the order given by --sort, e.g. to list the largest files first."))
+
+        .arg(Arg::new("reproducible")
+            .long("reproducible")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("live-print")
+            .help("Make the output the same, byte for byte, every time it's run over the same tree.")
+            .long_help("Make the output the same, byte for byte, every time it's run over the same tree.
+Entries are sorted, by path unless --sort says otherwise, and compared byte by
+byte, so the order doesn't depend on the locale, or on which thread got to what
+first. Stats leave out how long the run took, and how fast it went, and
+--stats-log its timestamp, so a manifest, listing, or --stats-format json can
+be committed to version control, or signed, and only changes when the tree
+does. Can't be used with --live, since that prints before anything can be
+sorted."))
+
+        .arg(Arg::new("sorted")
+            .long("sorted")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["sort", "live-print"])
+            .help("Sort the output by path, so identical trees produce identical manifests.")
+            .long_help("Sort the output by path, so identical trees produce identical manifests.
+Checksum mode otherwise prints files in whatever order the hashing threads get
+through them, which changes from run to run. This is shorthand for --sort name,
+and since the whole manifest has to be collected first, can't be used with
+--live."))
+
+        .arg(Arg::new("group-directories-first")
+            .long("group-directories-first")
+            .action(ArgAction::SetTrue)
+            .help("List directories before anything else, like ls does.")
+            .long_help("List directories before anything else, like ls does.
+On its own this sorts by name, otherwise it's combined with --sort, and just
+like --sort it has no effect on --live output. In flat listings every directory
+comes before every file, which also guarantees that a file's parent directory
+has been listed by the time the file is. With --tree, directories come first
+among their siblings instead."))
+
+        .arg(Arg::new("sort-memory")
+            .long("sort-memory")
+            .value_parser(spill::parse_sort_memory)
+            .value_name("size")
+            .default_value("512M")
+            .help("How much sorted output to keep in memory before spilling it to disk.")
+            .long_help("How much sorted output to keep in memory before spilling it to disk.
+Past this, lines are sorted a chunk at a time into temporary files, which are
+merged back together once everything has been collected, so trees too big to
+sort in memory still produce a sorted manifest, as long as there's disk space
+for a copy of it. Temporary files go wherever TMPDIR points, /tmp by default on
+Unix. Accepts the same sizes as --filter, e.g. 64M or 2G; it's an estimate, so
+actual memory use can be somewhat higher."))
+
+        .arg(Arg::new("du")
+            .long("du")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["checksum", "live-print", "tree", "printf", "long", "sample"])
+            .help("Print the total disk usage of every directory, like du.")
+            .long_help("Print the total disk usage of every directory, like du.
+Every size is added up into each of the directories above it, with a line per
+directory of its total in bytes, a tab, and its path, the same as du -B1 prints.
+Sizes are what's allocated on disk by default, or the size of the contents with
+--apparent-size, and files with several hard links only count once. The walk
+always goes all the way down, with --depth only limiting which directories are
+reported, so --depth 1 gives a total for each directory in the root, and the
+root itself. Lines are in path order, or by total with --sort size, and -H or
+--si make the sizes human readable. --filter and --exclude decide which entries
+count towards the totals."))
+
+        .arg(Arg::new("summarize-dirs")
+            .long("summarize-dirs")
+            .value_name("depth")
+            .value_parser(clap::value_parser!(usize))
+            .conflicts_with_all(["du", "checksum", "live-print", "tree", "printf", "long", "sample"])
+            .help("Print the recursive file count and byte total of every directory up to depth.")
+            .long_help("Print the recursive file count and byte total of every directory up to depth.
+Like --du, the walk always goes all the way down, and every file is added up
+into each of the directories above it, but only directories no deeper than the
+given depth are reported, or all of them with 0, each with its path, how many
+regular files are below it, and the size of their contents in bytes, leaving
+out what directories and symlinks themselves take up. Plain output separates
+the three with tabs, and --format jsonl, csv and tsv give the same thing as
+objects or rows with a header. Rows are in path order, or by total with --sort
+size. A file with several hard links counts as a file wherever it appears, but
+its size only counts once. --filter and --exclude decide which entries count."))
+
+        .arg(Arg::new("dupes")
+            .long("dupes")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Find files with the same contents, across all the given roots.")
+            .long_help("Find files with the same contents, across all the given roots.
+Files are grouped by size first, which takes no reading, then by a hash of their
+first 4K, and only what's still alike after that is hashed in full, with xxh3's
+128 bit hash, so most files are never read at all. Each group is printed as its
+paths, a line each, with a blank line between groups, the way fdupes prints
+them, or with --format jsonl, as an object per group of its size, hash and
+paths. Groups wasting the most space come first. Empty files, and other names
+of the same hard linked file, don't count as duplicates. --filter and --exclude
+decide which files are compared, e.g. --filter 'size > 1M' to only bother with
+big ones, and --stats adds up how much space the duplicates take up."))
+
+        .arg(Arg::new("pick")
+            .long("pick")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["checksum", "dupes", "du", "summarize-dirs", "report", "live-print", "tree", "printf", "long", "silent"])
+            .help("Pick entries with a fuzzy finder, as they're walked, and print the ones picked.")
+            .long_help("Pick entries with a fuzzy finder, as they're walked, and print the ones picked.
+Typing narrows the entries down to those with every typed character in their
+path, in the same order, the way fzf does, with the closest matches first. It's
+case insensitive, unless something uppercase is typed. Up and Down, or Ctrl+P
+and Ctrl+N, move between them, Tab marks more than one, Enter prints whatever
+is marked, or the entry under the cursor, and Esc or Ctrl+C gives up, exiting
+with 130. The finder is drawn on the terminal rather than stdout, so it works
+inside shell functions, e.g. `cd \"$(jw --pick -x files)\"`. --filter and
+--exclude decide what there is to pick from. Only supported on Unix."))
+
+        .arg(Arg::new("action")
+            .long("action")
+            .value_parser(["hardlink", "reflink", "delete"])
+            .ignore_case(true)
+            .value_name("action")
+            .requires("dupes")
+            .help("What to do about the duplicates --dupes finds: hardlink, reflink or delete.")
+            .long_help("What to do about the duplicates --dupes finds: hardlink, reflink or delete.
+One copy of each group is kept, chosen by --keep, and every other copy is acted
+on. hardlink replaces each copy with a hard link to the kept
+one, so they all share the kept file's inode, and with it its mtime, permissions
+and owner; copies on a different filesystem from the kept one are skipped. Files
+are compared byte for byte right before, rather than trusting the hash, and the
+link is renamed into place over the copy, so its name never goes missing.
+reflink has the kernel share the kept file's extents with each copy, through
+FIDEDUPERANGE, on filesystems that support it, like Btrfs and XFS on Linux.
+Every copy stays a file of its own, with its own metadata, so nothing changes
+about them other than the space they take up, and since the kernel compares
+them itself, it never dedupes anything that isn't identical. delete removes
+every copy but the kept one, again only once they've been compared byte for
+byte, and only while the kept one is still there. By default, nothing is
+changed, and a line is printed for each copy of what would happen to it, along
+with a total at the end; --yes goes through with it."))
+
+        .arg(Arg::new("keep")
+            .long("keep")
+            .value_parser(["first-root", "oldest", "newest"])
+            .ignore_case(true)
+            .default_value("first-root")
+            .requires("action")
+            .help("Which copy of each group of duplicates --action keeps.")
+            .long_help("Which copy of each group of duplicates --action keeps.
+first-root keeps the copy under whichever root was given first, so
+`jw --dupes ~/photos ~/downloads --action delete` keeps what's in ~/photos.
+oldest and newest go by mtime. Ties go to the first copy by path, so the same one is picked every
+time."))
+
+        .arg(Arg::new("yes")
+            .long("yes")
+            .action(ArgAction::SetTrue)
+            .requires("action")
+            .help("Carry out --action, rather than only printing what it would do."))
+
+        .arg(Arg::new("strict")
+            .long("strict")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Stop at the first entry that can't be read or acted on, and exit with 1.")
+            .long_help("Stop at the first entry that can't be read or acted on, and exit with 1.
+Without it, those are reported, what can be done is done regardless, and jw
+exits with 3 at the end, so that a walk of a tree with an unreadable corner or
+two still produces everything else. With it, the walk stops at the first one,
+the same way it does for Ctrl+C, letting what's underway finish, so that a run
+which didn't cover everything it was meant to never passes for one that did.
+Either way, a run that finishes without errors but produces nothing at all
+exits with 4, rather than 0, so it can be told apart from one that found
+something; see the end of --help for the rest."))
+
+        .arg(Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Print what would be done, without changing anything at all.")
+            .long_help("Print what would be done, without changing anything at all.
+Covers everything that would otherwise change something: --action, with or
+without --yes and --trash, --link-farm, --copy-to and --move-to, --mirror,
+--cas-export and --quarantine print the same lines and totals they would have,
+and --exec and --exec-batch print each command, quoted as for a shell, rather
+than running it. Files are still read and hashed, where that's how the outcome
+is decided, so --mirror still says which files already match."))
+
+        .arg(Arg::new("trash")
+            .long("trash")
+            .action(ArgAction::SetTrue)
+            .requires("action")
+            .help("Move whatever --action delete removes to the trash, rather than deleting it for good.")
+            .long_help("Move whatever --action delete removes to the trash, rather than deleting it for good.
+The trash is the one desktops use, laid out as in the FreeDesktop.org trash
+spec, so anything in it can be restored from a file manager, or with a tool like
+trash-restore. Files on the same filesystem as the home directory go to
+$XDG_DATA_HOME/Trash, or ~/.local/share/Trash, and files on other filesystems go
+to a .Trash-$UID directory at the top of their own, so they're moved rather than
+copied. On macOS, they go to ~/.Trash. Nothing is freed up until the trash is
+emptied."))
+
+        .arg(Arg::new("link-farm")
+            .long("link-farm")
+            .value_name("dest")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "live-print", "tree", "printf", "long", "sample"])
+            .help("Mirror every matching entry as a symlink under dest, keeping the layout of the root.")
+            .long_help("Mirror every matching entry as a symlink under dest, keeping the layout of the root.
+Each entry that isn't a directory gets a symlink at the same path relative to
+dest as it has relative to its root, pointing to the entry's absolute path,
+with directories created only as they're needed to hold links. Along with
+--filter, that makes a view of part of a tree without copying anything, e.g.
+`jw ~/photos --filter 'ext == \"raw\" && mtime >= 2022-01-01' --link-farm
+~/views/raw-2022`. Every root goes into the same dest; links that already exist,
+including ones from an earlier root with the same relative path, are left alone
+and counted as errors. A dest inside the root is never farmed itself. The path
+of every link made is printed, which -S turns off."))
+
+        .arg(Arg::new("exec")
+            .long("exec")
+            .value_name("command")
+            .value_parser(exec::parse_exec)
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "link-farm", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Run a command for every matching entry, several at once, on the thread pool.")
+            .long_help("Run a command for every matching entry, several at once, on the thread pool.
+The command is split into words like a shell would, with quotes and
+backslashes, but never goes through one, and each entry's path is substituted
+into it as arguments of its own, so there's nothing to quote. For /a/b/c.txt,
+{} is /a/b/c.txt, {.} is /a/b/c, {/} is c.txt, {//} is /a/b, and {/.} is c.
+Without any of those, the path is added to the end, so `jw src --filter
+'ext == \"rs\"' --exec rustfmt` formats every Rust file. As many commands run at
+once as --threads allows, each with its output printed in one piece once it
+exits, and nothing on stdin. The roots themselves are left out. Commands that
+fail don't stop the rest; they're counted as errors, which --list-errors names
+along with how they failed, and jw exits with 1 if any did."))
+
+        .arg(Arg::new("exec-batch")
+            .long("exec-batch")
+            .value_name("command")
+            .value_parser(exec::parse_exec)
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "link-farm", "exec", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Run a command once with all the matching entries, or as few times as ARG_MAX allows.")
+            .long_help("Run a command once with all the matching entries, or as few times as ARG_MAX allows.
+Like --exec, but each command gets a whole batch of paths, for commands like
+chmod, tar or du that are far cheaper to run once for many files than once per
+file, as with xargs or fd -X. A word with a placeholder in it is repeated once
+for every path in the batch, and the other words appear once, so
+`--exec-batch 'tar -rf out.tar {}'` adds everything to out.tar. Without any
+placeholders, the paths go at the end. A batch is as big as what's left of
+ARG_MAX after the environment, and when there are more paths than that, the
+command runs again for the rest; batches run one after the other, with jw's own
+stdin, stdout and stderr. Commands that fail are counted as errors, and jw exits
+with 1 if any did."))
+
+        .arg(Arg::new("copy-to")
+            .long("copy-to")
+            .value_name("dest")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Copy every matching entry to dest, at the same path relative to dest as to its root.")
+            .long_help("Copy every matching entry to dest, at the same path relative to dest as to its root.
+Directories are only created as they're needed to hold what's copied, so along
+with --filter, it gathers up part of a tree, e.g. `jw /var/log --filter 'ext ==
+\"log\" && age > 90d' --move-to /archive`. Files keep their permissions and mtime,
+and symlinks are copied as symlinks. Entries are copied in parallel, on the
+thread pool. What happens when something's already there is up to
+--on-collision. A dest inside the root is never copied into itself. The path of
+everything copied is printed, which -S turns off, and --stats adds up how much
+was copied."))
 
-If you stuck with defaults: `jw -c`, then you can just `jw -D file1 file2`"))
+        .arg(Arg::new("move-to")
+            .long("move-to")
+            .value_name("dest")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Move every matching entry to dest, at the same path relative to dest as to its root.")
+            .long_help("Move every matching entry to dest, at the same path relative to dest as to its root.
+The same as --copy-to, except entries are renamed into place, or on another
+filesystem, copied and then removed once the copy is complete. Directories are
+left where they are, even once they're empty."))
 
-        .arg(Arg::new("depth")
-            .long("depth")
-            .short('d')
-            .value_parser(value_parser!(usize))
-            .value_name("limit")
-            .default_value("0")
-            .help("The recursion depth limit. Setting this to 1 effectively disables recursion."))
+        .arg(Arg::new("on-collision")
+            .long("on-collision")
+            .value_parser(["skip", "overwrite", "rename"])
+            .ignore_case(true)
+            .default_value("skip")
+            .help("What --copy-to and --move-to do when there's already something at the path: skip, overwrite or rename.")
+            .long_help("What --copy-to and --move-to do when there's already something at the path: skip, overwrite or rename.
+skip leaves both alone, and counts what was skipped in --stats. overwrite
+replaces what's there. rename puts the entry next to it instead, with a number
+before its extension, as name.1.ext, name.2.ext and so on. Nothing is checked
+ahead of time; a collision is found by failing to create the file, so two
+threads, or two roots, never end up writing to the same path."))
 
+        .arg(Arg::new("mirror")
+            .long("mirror")
+            .value_names(["src", "dst"])
+            .num_args(2)
+            .conflicts_with_all(["directories", "checksum", "du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "report", "live-print", "tree", "printf", "long", "sample"])
+            .help("Copy every file in src that's missing or different in dst over to dst, and prove the copies.")
+            .long_help("Copy every file in src that's missing or different in dst over to dst, and prove the copies.
+Files already in dst with the same size are hashed on both sides, and left as
+they are if they match. Everything else is copied to a temporary name next to
+where it goes, hashed as it's copied, synced to disk, then read back and hashed
+again, and only renamed into place if the two hashes agree, so dst never holds
+anything but a complete copy that's been checked. The hash is xxh3, or whatever
+-C gives, e.g. `jw -C sha256 --mirror ~/photos /mnt/backup/photos`. Only regular
+files are mirrored, in parallel, and nothing in dst is ever deleted. --filter and
+--exclude decide which files in src count. The path of every file copied is
+printed, which -S turns off, and a report at the end says how many matched,
+were copied, or failed, in which case jw exits with 1."))
 
-        .arg(Arg::new("exclude")
-            .long("exclude")
-            .short('x')
-            .value_parser(["files", "dirs", "dot", "other"])
-            .value_name("t1,t2")
-            .value_delimiter(',')
-            .help("Exclude one more types of entries, separated by coma.")
-            .num_args(0..=4))
+        .arg(Arg::new("cas-export")
+            .long("cas-export")
+            .value_name("dir")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "mirror", "tree"])
+            .help("Hash every file, as with --checksum, and store it in dir under its hash.")
+            .long_help("Hash every file, as with --checksum, and store it in dir under its hash.
+Each file is copied to dir/<first two digits of its hash>/<hash>, unless
+something with the same hash is already there, so every distinct file is only
+ever stored once, however many copies of it there are, and however many times
+it's exported. Copies are hashed again before they're put in place, in case a
+file changed in between. Every file exported is appended to dir/manifest, as a
+line like --checksum prints, hash followed by path, which is also what's
+printed; the algorithm is xxh3, or whatever -C gives. --stats says how many
+files were new to the store. See also --cas-link."))
 
-        .arg(Arg::new("silent")
-            .long("silent")
-            .short('S')
+        .arg(Arg::new("cas-link")
+            .long("cas-link")
             .action(ArgAction::SetTrue)
-            .help("Suppress output, useful for benchmarking, or just counting files via --stats"))
+            .requires("cas-export")
+            .help("Hard link files into the store with --cas-export, rather than copying them.")
+            .long_help("Hard link files into the store with --cas-export, rather than copying them.
+That takes no extra space, but since the object and the original are then the
+same file, writing to the original later changes the object too, and it no
+longer matches its hash. Files on a different filesystem from the store are
+copied anyway."))
 
-        .arg(Arg::new("stats")
-            .long("stats")
-            .short('s')
+        .arg(Arg::new("xattr-store")
+            .long("xattr-store")
             .action(ArgAction::SetTrue)
-            .help("Count the number of files, dirs, and other entries, and print at the end.")
-            .long_help("Count the number of files, dirs, and other entries, and print at the end.
-This will decrease performance. This will cause a significant slowdown
-and is primarily here for debugging or benchmarking. A more efficient
-method to do this will be implemented in the future.")
-            )
+            .conflicts_with_all(["du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "mirror", "tree"])
+            .help("Hash every file, as with --checksum, and keep its hash in an extended attribute of its own.")
+            .long_help("Hash every file, as with --checksum, and keep its hash in an extended attribute of its own.
+The hash goes in user.jw.<algorithm>, e.g. user.jw.xxh3, or whatever -C gives,
+along with the file's mtime, so that --xattr-verify can check the file later
+on, with no manifest needed, and wherever it's been copied to, as long as its
+attributes went with it. Files that change while they're hashed aren't tagged,
+and neither are symlinks, only what they point to. The filesystem has to
+support user.* attributes, which most do, though tmpfs only has since Linux
+6.6, and a file has to be writable to be tagged. Linux and macOS only."))
+
+        .arg(Arg::new("xattr-verify")
+            .long("xattr-verify")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "mirror", "tree"])
+            .help("Hash every file, as with --checksum, and check it against the hash --xattr-store kept.")
+            .long_help("Hash every file, as with --checksum, and check it against the hash --xattr-store kept.
+A file whose hash doesn't match the one it was tagged with, yet still has the
+same mtime, was never written to, and has been corrupted on disk instead, which
+is counted as an error, listed by --list-errors, and makes jw exit with 1 at
+the end. A file with a different mtime was changed since, on purpose
+presumably, and its tag is just outdated. Along with --xattr-store, outdated
+tags are updated, and files without one are tagged, but corrupt files keep
+theirs, so they can be checked again once they've been restored. The usual manifest is printed as well, which
+-S turns off, and --stats says how many were verified, outdated, untagged and
+corrupt. Linux and macOS only."))
+
+        .arg(Arg::new("checkpoint")
+            .long("checkpoint")
+            .value_name("file")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "mirror", "tree", "sort"])
+            .help("Hash every file, as with --checksum, writing the manifest to file as it goes, and skip what's in it already.")
+            .long_help("Hash every file, as with --checksum, writing the manifest to file as it goes, and skip what's in it already.
+Every file is added to file as soon as it's been hashed, so a run that's
+interrupted, with Ctrl+C, or killed outright, or that the machine went down in
+the middle of, can be carried on with by running the same command again, which
+skips every file the checkpoint already has, and adds the rest. Once a run gets
+all the way through, file holds the manifest of everything, in the same format
+as --checksum prints, hash followed by path, whatever the output format. Only
+this run's files are printed. Give the same -C each time, since the hashes'
+length is what tells them apart from the paths. Ctrl+C, in any mode, stops jw
+from starting on anything new, lets what's underway finish, flushes what's
+been printed, and exits with 130, or 143 for SIGTERM; a second one kills it."))
+
+        .arg(Arg::new("find-hash")
+            .long("find-hash")
+            .value_name("digest|file")
+            .action(ArgAction::Append)
+            .conflicts_with_all(["checksum", "du", "summarize-dirs", "dupes", "link-farm", "exec", "exec-batch", "copy-to", "move-to", "mirror", "cas-export", "report", "tree", "sample"])
+            .help("Print the files whose hash is the given digest, or one of the digests in the given file.")
+            .long_help("Print the files whose hash is the given digest, or one of the digests in the given file.
+Each file gets hashed, with xxh3 or whatever -C gives, unless its size rules it
+out, and is printed if it matches, e.g. to find where a known bad file ended up,
+with `jw -C sha256 --find-hash 9f86d08... /`. A file is given as a digest a
+line, and anything after a digest on a line is ignored, so a manifest from
+--checksum, or what sha256sum prints, works as it is. Following a digest with
+:size, e.g. 9f86d08...:1024, means only files of that size are hashed for it,
+and when every digest has a size, nothing else is read at all. It can be given
+more than once. jw exits with 4 when nothing matched, as it does whenever a
+run finds nothing, or 3 if some files couldn't be read, since any of them
+could've been the one."))
+
+        .arg(Arg::new("apparent-size")
+            .long("apparent-size")
+            .action(ArgAction::SetTrue)
+            .requires("du")
+            .help("Count the size of the contents with --du, rather than what's allocated."))
+
+        .arg(Arg::new("human-readable")
+            .long("human-readable")
+            .short('H')
+            .action(ArgAction::SetTrue)
+            .help("Print sizes in powers of 1024, e.g. 1.5 MiB, in human oriented output.")
+            .long_help("Print sizes in powers of 1024, e.g. 1.5 MiB, in human oriented output.
+This applies to --long and `--tree=size`; machine readable formats and --printf
+always get the exact byte count."))
+
+        .arg(Arg::new("si")
+            .long("si")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("human-readable")
+            .help("Like --human-readable, but in powers of 1000, e.g. 1.6 MB."))
+
+        .arg(Arg::new("quote")
+            .long("quote")
+            .value_parser(["none", "shell", "c"])
+            .ignore_case(true)
+            .value_name("style")
+            .default_value("none")
+            .help("Quote paths so they're safe to paste into a shell, or to parse line by line.")
+            .long_help("Quote paths so they're safe to paste into a shell, or to parse line by line.
+Applies to the plain, --long, and --tree outputs. shell leaves paths that need
+no quoting alone, single quotes the rest, and falls back to $'..' for names
+with control characters or bytes that aren't valid UTF-8, so the result always
+refers to the exact same file. c always double quotes, with C style escapes,
+the same as ls --quoting-style=c. The default, none, prints paths as they are,
+except for bytes that aren't valid UTF-8, which come out as U+FFFD. Files with
+names like that are still listed and hashed like any other, but only shell and
+c spell their names out exactly."))
+
+        .arg(Arg::new("columns")
+            .long("columns")
+            .value_parser(["path", "type", "size", "mtime", "inode", "dev", "nlink", "hash"])
+            .ignore_case(true)
+            .value_name("c1,c2")
+            .value_delimiter(',')
+            .num_args(1..)
+            .help("The columns to include with --format csv/tsv, separated by coma.")
+            .long_help("The columns to include with --format csv/tsv, separated by coma.
+Defaults to path,type,size,mtime, with hash added on in checksum mode. The
+mtime column is printed as an ISO 8601 timestamp in UTC. inode, dev, and nlink
+are only available on Unix, and left empty elsewhere."))
 
         .arg(Arg::new("directories")
             .default_value(".")
             .num_args(1..)
-            .help("The target directories to traverse, can be multiple. Use -- to read paths from stdin."))
-        .get_matches();
+            .help("The target directories to traverse, can be multiple. Use -- or - to read paths from stdin."))
+
+        .arg(Arg::new("files-from")
+            .long("files-from")
+            .value_name("file")
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with("directories")
+            .help("Walk the paths listed in file, one per line, rather than the ones given as arguments.")
+            .long_help("Walk the paths listed in file, one per line, rather than the ones given as arguments.
+Or separated by NULs, with -0. Every path is walked as a root of its own,
+files included, so a list of files that some other tool came up with can be
+listed, or hashed, without running into the limit on how long a command line
+can be, e.g. `jw -c --files-from changed.txt`. - reads the list from stdin,
+the same as -- does."))
+
+        .arg(Arg::new("null")
+            .long("null")
+            .short('0')
+            .action(ArgAction::SetTrue)
+            .help("Paths read from stdin or --files-from are separated by NULs, as find -print0 writes them, rather than newlines.")
+            .long_help("Paths read from stdin or --files-from are separated by NULs, as find -print0 writes them, rather than newlines.
+Either way, every path is taken as it is, spaces and all, one per line, or one
+between each NUL, e.g. `find . -name '*.iso' -print0 | jw -0 -c --`, which is
+the only way of passing names with newlines in them along."))
+
+        .arg(Arg::new("config")
+            .long("config")
+            .env("JW_CONFIG")
+            .value_name("path")
+            .value_parser(value_parser!(PathBuf))
+            .help("Read default settings from path, rather than ~/.config/jw/config.toml.")
+            .long_help("Read default settings from path, rather than ~/.config/jw/config.toml.
+The config file sets the default of any of the long options, one to a line, by
+its name without the --, the way TOML writes them, e.g.
+
+  checksum-with = \"sha256\"
+  threads = 8
+  exclude = [\"dot\", \"other\"]
+  filter = 'size > 0'
+  format = \"jsonl\"
+  stats = true
+
+Anything given on the command line, or by the JW_ environment variables, takes
+precedence over it. Setting the
+algorithm like this doesn't mean every run hashes, the way -C does; it's just
+what -c, -D, and the subcommands that hash use. Flags are true or false, and
+options that can be given more than once take a list. On Windows, the file is
+%APPDATA%\\jw\\config.toml, and with XDG_CONFIG_HOME set elsewhere, it's
+$XDG_CONFIG_HOME/jw/config.toml. Without --config, it's fine for there to be
+no file at all."))
+
+        .arg(Arg::new("man")
+            .long("man")
+            .action(ArgAction::SetTrue)
+            .help("Print a man page for jw, written from the same definitions as --help, and exit.")
+            .long_help("Print a man page for jw, written from the same definitions as --help, and exit.
+It's roff, for packaging as jw.1, e.g. `jw --man > jw.1`, or reading straight
+away with `jw --man | man -l -`. The defaults in it are jw's own, whatever the
+config file says."))
+
+        .arg(Arg::new("explain")
+            .long("explain")
+            .action(ArgAction::SetTrue)
+            .help("Print the options as they've been resolved, and what they come to, then exit.")
+            .long_help("Print the options as they've been resolved, and what they come to, then exit.
+Each option that's set is printed with where it came from: the command line, a
+JW_ environment variable, the config file, or its default, in that order of
+precedence. That part can be pasted into a config file as it is. After it comes
+what jw makes of them together: the mode it would run in, and what turned it
+on, such as -C for checksum mode, with what algorithm, and how it'd walk, read,
+and print. Nothing is walked, hashed, or written."))
+
+        // Every flag can be given after a subcommand as well as before it,
+        // and means the same either way.
+        .mut_args(|arg| match arg.is_positional() {
+            true => arg,
+            false => arg.global(true),
+        })
+
+        .subcommand(Command::new("walk")
+            .about("List the entries under the directories, the same as jw does without a subcommand.")
+            .long_about("List the entries under the directories, the same as jw does without a subcommand.
+Each mode also has a subcommand of its own, so it's clear which one a flag
+belongs to, e.g. `jw diff -C sha256 a b` rather than `jw -C sha256 -D a b`,
+where -C only picks the algorithm the manifests were written with. The flags
+that choose a mode, -c, -D, and --dupes, still work without one. A directory
+that's actually called walk, hash, diff, verify, dupes, or bench has to be
+given as ./walk and so on to be walked like any other.")
+            .arg(Arg::new("directories")
+                .default_value(".")
+                .num_args(1..)
+                .help("The target directories to traverse, can be multiple. Use -- or - to read paths from stdin.")))
+
+        .subcommand(Command::new("hash")
+            .about("Print a manifest of the files under the directories, the same as -c.")
+            .long_about("Print a manifest of the files under the directories, the same as -c.
+Hashed with xxh3, unless -C picks another algorithm, e.g.
+`jw hash -C sha256 photos > photos.sha256`.")
+            .arg(Arg::new("directories")
+                .default_value(".")
+                .num_args(1..)
+                .help("The target directories to hash, can be multiple. Use -- or - to read paths from stdin.")))
+
+        .subcommand(Command::new("diff")
+            .about("Compare manifests against the first one, the same as -D.")
+            .long_about("Compare manifests against the first one, the same as -D.
+-C gives the algorithm they were written with, if it isn't xxh3, e.g.
+`jw diff -C sha256 old.sha256 new.sha256`.")
+            // Called directories, the same as every other subcommand's
+            // positional arguments, since that's what the flags that can't be
+            // used with them refer to.
+            .arg(Arg::new("directories")
+                .required(true)
+                .num_args(2..)
+                .value_names(["base", "others"])
+                .help("The manifest to compare against, then the ones to compare with it.")))
+
+        .subcommand(Command::new("verify")
+            .about("Hash the directories, and compare what comes out against a manifest.")
+            .long_about("Hash the directories, and compare what comes out against a manifest.
+The same as hashing them into a second manifest, and diffing the two, without
+the second manifest. The directories have to be given the way they were when
+the manifest was written, since that's how its paths start, e.g.
+`jw hash photos > photos.xxh3`, then later, `jw verify photos.xxh3 photos`.
+-C gives the algorithm it was written with, if it isn't xxh3, and --quarantine
+and --verify-after work the same as they do with -D.")
+            .arg(Arg::new("manifest")
+                .required(true)
+                .help("The manifest to check the directories against."))
+            .arg(Arg::new("directories")
+                .default_value(".")
+                .num_args(1..)
+                .help("The directories the manifest was written from.")))
+
+        .subcommand(Command::new("dupes")
+            .about("Find files with the same contents, the same as --dupes.")
+            .arg(Arg::new("directories")
+                .default_value(".")
+                .num_args(1..)
+                .help("The target directories to compare the files of, can be multiple.")))
+
+        .subcommand(Command::new("bench")
+            .about("Measure walking and hashing throughput on this machine, and recommend settings.")
+            .long_about("Measure walking and hashing throughput on this machine, and recommend settings.
+Walks the directory a few times, with and without stat calls, then hashes a
+sample of its files with every algorithm, a range of thread counts, and a range
+of read sizes, and prints what came out fastest. Point it at the kind of tree
+you'd actually hash, on the storage it'd be on. A directory that's actually
+called bench has to be given as ./bench to be walked like any other.")
+            .arg(Arg::new("directories")
+                .default_value(".")
+                .value_name("directory")
+                .help("The directory to benchmark with."))
+            .arg(Arg::new("sample-size")
+                .long("sample-size")
+                .value_parser(filter::parse_size)
+                .value_name("size")
+                .default_value("256M")
+                .help("How much of the directory's files to hash with each setting.")))
+}
+
+fn main() {
+    let command = cli();
+    let matches = config::apply(command.clone()).get_matches();
+
+    if matches.get_flag("man") {
+        Sink::new(std::io::stdout().lock(), false).write_str(&man::render(command.clone()));
+        return;
+    }
+
+    if let Some(bench) = matches.subcommand_matches("bench") {
+        bench::run(
+            bench.get_one::<String>("directories").unwrap(),
+            *bench.get_one::<u64>("sample-size").unwrap(),
+        );
+
+        return;
+    }
+
+    log::init(
+        matches
+            .get_one::<String>("log-level")
+            .map(Level::from)
+            .unwrap_or_else(|| Level::verbosity(matches.get_count("verbose"))),
+        matches.get_one::<Arc<Mutex<File>>>("log-file").cloned(),
+    );
+
+    // Before anything starts a thread, so that every thread inherits it.
+    if matches.get_flag("background") {
+        background::lower_priority();
+    }
+
+    let stats_format = matches
+        .get_one::<String>("stats-format")
+        .map(StatsFormat::from)
+        .unwrap_or(StatsFormat::Text);
+
+    mutate::set_dry_run(matches.get_flag("dry-run"));
+
+    // The flags are all global, so they're in matches either way, and only
+    // the arguments after a subcommand are in its own.
+    let (subcommand, positional) = match matches.subcommand() {
+        Some((name, sub)) => (Some(name), sub),
+        None => (None, &matches),
+    };
+
+    // The flags that pick a mode are a different mode than the subcommand's,
+    // which is the ambiguity subcommands are there to be rid of.
+    if let Some(subcommand) = subcommand.filter(|name| *name != "bench") {
+        let modes = [
+            ("checksum", "-c", "hash"),
+            ("hdiff", "-D", "diff"),
+            ("dupes", "--dupes", "dupes"),
+        ];
+
+        for (id, flag, mode) in modes {
+            if mode != subcommand && matches.value_source(id) == Some(ValueSource::CommandLine) {
+                Diagnostic::new(format!(
+                    "can't be used with jw {}, since it means jw {}",
+                    subcommand, mode
+                ))
+                .about(flag)
+                .tip(format!("leave out {}, or run jw {} instead", flag, mode))
+                .exit(2);
+            }
+        }
+    }
+
+    let diff_stats_to = (matches.get_flag("stats") || matches.contains_id("stats-format"))
+        .then(|| matches.get_one::<StatsTarget>("stats-to").unwrap());
+
+    let quarantine = matches.get_one::<PathBuf>("quarantine").map(|dir| {
+        let mode = match matches.get_flag("quarantine-copy") {
+            true => Mode::Copy,
+            false => Mode::Move,
+        };
+
+        (dir.as_path(), mode)
+    });
+
+    let verify_after = matches.get_one::<String>("verify-after").map(|algorithm| {
+        Verify::new(
+            parse_algorithm(algorithm),
+            read_options(&matches, Arc::new(Progress::new(false, None))),
+        )
+    });
+
+    let diff_files = match subcommand {
+        Some("diff") => positional.get_many::<String>("directories"),
+        _ => matches.get_many::<String>("hdiff"),
+    };
+
+    let tui = matches.get_flag("tui");
 
-    if let Some(checksum_files) = matches.get_many::<String>("hdiff").map(|fp| {
+    if cfg!(not(unix)) && tui {
+        unsupported("--tui", "Unix");
+    }
+
+    // The view would be drawn over by whatever's printed to stdout, were it
+    // the same terminal, which jw verify only prints to once it's closed.
+    if tui
+        && std::io::IsTerminal::is_terminal(&std::io::stdout())
+        && !*matches.get_one::<bool>("silent").unwrap_or(&false)
+        && subcommand != Some("verify")
+        && !matches.get_flag("explain")
+    {
+        Diagnostic::new(
+            "is drawn on the same terminal stdout is on, which the output would draw over",
+        )
+        .about("--tui")
+        .tip("redirect stdout, e.g. > manifest.txt, or give -s, so nothing's printed")
+        .exit(2);
+    }
+
+    // Along with the directories, the view's title is whatever jw was run
+    // with, which says what it's doing better than anything made up for it.
+    let title = std::env::args().collect::<Vec<String>>().join(" ");
+
+    if let Some(checksum_files) = diff_files.map(|fp| {
         fp.into_iter()
             .map(|s| s.to_string())
             .collect::<Vec<String>>()
     }) {
-        checksum_diff(
-            HashAlgorithm::from(
-                matches
-                    .get_one::<String>("checksum-algo")
-                    .unwrap_or(&"xxh3".to_string()),
-            ),
+        if matches.get_flag("explain") {
+            let resolved = [
+                ("mode", "diff".to_string()),
+                ("manifests", format!("{:?}", checksum_files)),
+                ("algorithm", checksum_algorithm(&matches).name().to_string()),
+                ("log level", log::level().name().to_lowercase()),
+            ];
+
+            Sink::new(std::io::stdout().lock(), false)
+                .write_str(&explain::render(&command, &matches, &resolved));
+
+            return;
+        }
+
+        let screen = tui.then(|| tui::Screen::start(title, None));
+
+        let status = checksum_diff(
+            checksum_algorithm(&matches),
             &checksum_files,
-            *matches.get_one("stats").unwrap_or(&false),
+            None,
+            diff_stats_to,
+            stats_format,
+            quarantine,
+            verify_after.as_ref(),
         );
-        exit(0);
+
+        drop(screen);
+        exit(status);
     }
 
-    let mut walk_dirs: Vec<String> = matches
+    // `jw verify` hashes the directories into a manifest of its own, as a
+    // checkpoint would, which is diffed against the one given once it's done.
+    // Checked before anything is hashed, since there's no point otherwise.
+    let verifying: Option<(String, Scratch)> = (subcommand == Some("verify")).then(|| {
+        let manifest = positional.get_one::<String>("manifest").unwrap();
+
+        if let Some(problem) = not_a_manifest(manifest) {
+            problem.exit(1);
+        }
+
+        if matches.contains_id("checkpoint") {
+            Diagnostic::new("can't be used with jw verify, which keeps one of its own")
+                .about("--checkpoint")
+                .tip("for a check that can be picked up where it left off, run jw hash --checkpoint <file> <directory>, then jw diff <manifest> <file>")
+                .exit(1);
+        }
+
+        let hashed = Scratch::create().unwrap_or_else(|e| {
+            Diagnostic::new(format!("couldn't be written to, which jw verify hashes into: {}", e))
+                .about(std::env::temp_dir().display())
+                .tip("point TMPDIR somewhere that can be written to")
+                .exit(1)
+        });
+
+        (manifest.clone(), hashed)
+    });
+
+    let mut walk_dirs: Vec<PathBuf> = positional
         .get_many::<String>("directories")
-        .map(|dirs| dirs.into_iter().map(|s| s.to_string()).collect())
+        .map(|dirs| dirs.into_iter().map(PathBuf::from).collect())
         .expect("No directories provided!");
 
-    if walk_dirs.first().is_some_and(|s| s == "--") {
-        walk_dirs = read_stdin();
+    // clap swallows a lone --, so it's looked for among the arguments, for
+    // when nothing follows it.
+    let from_stdin = walk_dirs
+        .first()
+        .is_some_and(|s| s.as_os_str() == "--" || s.as_os_str() == "-")
+        || (positional.value_source("directories") == Some(ValueSource::DefaultValue)
+            && std::env::args_os().any(|arg| arg == "--"));
+
+    if from_stdin {
+        walk_dirs = read_paths(std::io::stdin().lock(), matches.get_flag("null"), "stdin");
+    }
+
+    if let Some(list) = matches.get_one::<PathBuf>("files-from") {
+        let null = matches.get_flag("null");
+
+        walk_dirs = match list.as_os_str() == "-" {
+            true => read_paths(std::io::stdin().lock(), null, "stdin"),
+            false => match File::open(list) {
+                Ok(file) => read_paths(file, null, &list.display().to_string()),
+                Err(e) => Diagnostic::new(format!("couldn't be opened for --files-from: {}", e))
+                    .about(list.display())
+                    .exit(1),
+            },
+        };
+    }
+
+    // The source side of --mirror is the one root that's walked.
+    if let Some(mut sides) = matches.get_many::<String>("mirror") {
+        walk_dirs = vec![PathBuf::from(sides.next().unwrap())];
     }
 
     let exclude_flags = matches.get_many::<String>("exclude").map_or(0, |flags| {
@@ -448,32 +4174,586 @@ method to do this will be implemented in the future.")
             })
     });
 
-    let checksum_mode = matches!(
-        matches.value_source("checksum"),
-        Some(ValueSource::CommandLine)
-    ) || matches!(
-        matches.value_source("checksum-algo"),
-        Some(ValueSource::CommandLine)
-    );
+    // Whatever asked for checksum mode, which --explain lists, since it's
+    // more than the one flag.
+    let checksum_triggers: Vec<&str> = [
+        (
+            matches.value_source("checksum") == Some(ValueSource::CommandLine),
+            "-c",
+        ),
+        (
+            matches.value_source("checksum-algo") == Some(ValueSource::CommandLine),
+            "-C",
+        ),
+        (subcommand == Some("hash"), "jw hash"),
+        (subcommand == Some("verify"), "jw verify"),
+        (matches.contains_id("cas-export"), "--cas-export"),
+        (matches.contains_id("checkpoint"), "--checkpoint"),
+        (matches.get_flag("xattr-store"), "--xattr-store"),
+        (matches.get_flag("xattr-verify"), "--xattr-verify"),
+    ]
+    .into_iter()
+    .filter_map(|(given, trigger)| given.then_some(trigger))
+    .collect();
+
+    let checksum_mode = !checksum_triggers.is_empty();
+
+    if cfg!(not(target_os = "linux"))
+        && matches
+            .get_one::<String>("io-backend")
+            .is_some_and(|backend| backend.eq_ignore_ascii_case("uring"))
+    {
+        Diagnostic::new("is only supported on Linux, where there's io_uring")
+            .about("--io-backend uring")
+            .tip("leave --io-backend out, for regular reads, which work everywhere")
+            .exit(1);
+    }
+
+    if cfg!(not(unix)) && matches.get_flag("pick") {
+        unsupported("--pick", "Unix");
+    }
+
+    if cfg!(not(unix)) && matches.get_flag("xattr-store") {
+        unsupported("--xattr-store", "Linux and macOS");
+    }
+
+    if cfg!(not(unix)) && matches.get_flag("xattr-verify") {
+        unsupported("--xattr-verify", "Linux and macOS");
+    }
+
+    let progress_every = matches.get_one::<Every>("progress-every").copied();
+
+    let progress = Arc::new(Progress::new(
+        checksum_mode,
+        match progress_every {
+            Some(Every::Entries(n)) => Some(n),
+            _ => None,
+        },
+    ));
+
+    let mut shared_threads = matches
+        .get_one::<usize>("hash-threads")
+        .or(matches.get_one::<usize>("threads"))
+        .copied();
+
+    let mut read = read_options(&matches, progress.clone());
+
+    // The defaults are tuned for local disks, and go wrong on network
+    // filesystems, where mapping a file makes a round trip of every page, a
+    // thread for each CPU is more than most servers answer at once, and errors
+    // that'd be gone a moment later fail files. So with a root on one, they're
+    // set for that instead, unless they were given outright.
+    let remote: Vec<(&PathBuf, &str)> = walk_dirs
+        .iter()
+        .filter_map(|dir| Some((dir, fstype::remote(dir)?)))
+        .collect();
+
+    if !remote.is_empty() {
+        let mut changes = Vec::new();
+
+        if read.mmap
+            && matches.value_source("mmap") == Some(ValueSource::DefaultValue)
+            && !config::given("mmap")
+        {
+            read.mmap = false;
+            changes.push("files aren't mapped".to_string());
+        }
+
+        // rayon isn't asked, since that would set up its pool before it's
+        // been sized.
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let threads = NETWORK_THREADS.min(cpus);
+
+        if shared_threads.is_none() && threads < cpus {
+            shared_threads = Some(threads);
+            changes.push(format!("only {} threads are used", threads));
+        }
+
+        if !matches.contains_id("retries") {
+            read.retries = NETWORK_RETRIES;
+            changes.push(format!(
+                "failed reads are tried {} more times",
+                NETWORK_RETRIES
+            ));
+        }
+
+        if let Some(last) = changes.pop() {
+            let changes = match changes.is_empty() {
+                true => last,
+                false => format!("{}, and {}", changes.join(", "), last),
+            };
+
+            for (dir, fs) in &remote {
+                log::warn(format_args!(
+                    "{} is on {}, so {}. --mmap, --threads, and --retries choose otherwise.",
+                    dir.display(),
+                    fs,
+                    changes
+                ));
+            }
+        }
+    }
+
+    let newer_than = matches
+        .get_one::<String>("newer-than-file")
+        .map(|reference| {
+            std::fs::metadata(reference)
+                .and_then(|m| m.modified())
+                .unwrap_or_else(|e| {
+                    Diagnostic::new(format!(
+                        "couldn't be read for the mtime --newer-than-file compares to: {}",
+                        e
+                    ))
+                    .about(reference)
+                    .exit(1)
+                })
+        });
+
+    let path_style = matches
+        .get_one::<String>("path-style")
+        .map(PathStyle::from)
+        .unwrap_or(PathStyle::Relative);
+
+    let walk_dirs: Vec<PathBuf> = walk_dirs
+        .iter()
+        .map(|dir| path_style.apply_to_root(dir))
+        .collect();
+
+    let columns: Vec<Column> = matches
+        .get_many::<String>("columns")
+        .map(|columns| columns.map(Column::from).collect())
+        .unwrap_or_else(|| {
+            let mut columns = vec![Column::Path, Column::Type, Column::Size, Column::Mtime];
+
+            if checksum_mode {
+                columns.push(Column::Hash);
+            }
+
+            columns
+        });
+
+    // What the hashes of the directories are called in what `jw verify`
+    // reports, in place of the manifest they were written to.
+    let walked = walk_dirs
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
 
     let options = Options {
         live_print: *matches.get_one::<bool>("live-print").unwrap_or(&false),
         exclude: exclude_flags,
-        checksum: checksum_mode.then(|| {
+        checksum: checksum_mode.then(|| checksum_algorithm(&matches)),
+        read,
+        silent: *matches.get_one::<bool>("silent").unwrap_or(&false) || verifying.is_some(),
+        depth: match matches.get_flag("no-recurse") {
+            true => 1,
+            false => *matches.get_one("depth").unwrap_or(&0),
+        },
+        follow_links: matches.get_flag("follow-links"),
+        strict: matches.get_flag("strict"),
+        files_from: matches
+            .get_one::<PathBuf>("files-from")
+            .map(|list| list.display().to_string()),
+        open_files: limits::raise_open_files(),
+        ads: matches.get_flag("ads") && {
+            if cfg!(not(windows)) {
+                unsupported("--ads", "Windows, where there's NTFS");
+            }
+
+            true
+        },
+        mac_metadata: matches.get_flag("mac-metadata") && {
+            if cfg!(not(target_os = "macos")) {
+                unsupported("--mac-metadata", "macOS");
+            }
+
+            true
+        },
+        xattrs: matches.get_flag("xattrs") && {
+            if cfg!(not(unix)) {
+                unsupported("--xattrs", "Linux and macOS");
+            }
+
+            true
+        },
+        walk_strategy: match (
             matches
-                .get_one::<String>("checksum-algo")
-                .map(HashAlgorithm::from)
-                .unwrap_or(HashAlgorithm::Xxh3)
-        }),
-        silent: *matches.get_one::<bool>("silent").unwrap_or(&false),
-        depth: *matches.get_one("depth").unwrap_or(&0),
+                .get_one::<String>("walk-strategy")
+                .map(String::as_str),
+            matches.get_one::<usize>("walk-threads"),
+        ) {
+            (Some("serial"), _) => WalkStrategy::Serial,
+            (Some("shared"), _) => WalkStrategy::Shared,
+            (Some(_), threads) | (None, threads @ Some(_)) => {
+                WalkStrategy::Dedicated(threads.copied().unwrap_or(0))
+            }
+            // jwalk gives up on a pool after waiting a second for a free
+            // thread, which is all a pool of one does while its thread hashes.
+            (None, None) if shared_threads == Some(1) => WalkStrategy::Serial,
+            (None, None) => WalkStrategy::Shared,
+        },
         directories: walk_dirs,
-        print_stats: *matches.get_one("stats").unwrap_or(&false),
+        print_stats: matches.get_flag("stats") || matches.contains_id("stats-format"),
+        reports: matches
+            .get_many::<String>("report")
+            .into_iter()
+            .flatten()
+            .map(ReportKind::from)
+            .chain(
+                matches
+                    .get_one::<usize>("top-size")
+                    .map(|&n| ReportKind::Largest(n)),
+            )
+            .chain(
+                matches
+                    .get_one::<usize>("top-mtime")
+                    .map(|&n| ReportKind::Newest(n)),
+            )
+            .chain(
+                matches
+                    .get_one::<usize>("bottom-mtime")
+                    .map(|&n| ReportKind::Oldest(n)),
+            )
+            .collect(),
+        stats_to: matches
+            .get_one::<StatsTarget>("stats-to")
+            .cloned()
+            .unwrap_or(StatsTarget::Stderr),
+        stats_format,
+        stats_log: matches.get_one::<StatsTarget>("stats-log").cloned(),
+        errors: Arc::new(Errors::new(matches.get_flag("list-errors"))),
+        sample: matches.get_one::<Sample>("sample").copied(),
+        newer_than,
+        progress: progress.clone(),
+        show_progress: match progress_every {
+            Some(Every::Interval(interval)) => Some(Some(interval)),
+            Some(Every::Entries(_)) => None,
+            None => matches.get_flag("progress").then_some(None),
+        },
+        prescan: matches.get_flag("prescan"),
+        seen: matches
+            .get_flag("dedup-paths")
+            .then(|| Arc::new(SeenPaths::default())),
+        filter: matches.get_one::<Expr>("filter").cloned(),
+        sort: (matches.contains_id("sort")
+            || matches.get_flag("sorted")
+            || matches.get_flag("reproducible")
+            || matches.get_flag("group-directories-first"))
+        .then(|| Sort {
+            key: matches
+                .get_one::<String>("sort")
+                .map(SortKey::from)
+                .unwrap_or(SortKey::Name),
+            reverse: matches.get_flag("reverse"),
+            directories_first: matches.get_flag("group-directories-first"),
+        }),
+        du: matches.get_flag("du").then(|| {
+            if matches.get_flag("apparent-size") {
+                Usage::Apparent
+            } else {
+                Usage::Allocated
+            }
+        }),
+        summarize_dirs: matches.get_one::<usize>("summarize-dirs").copied(),
+        dupes: matches.get_flag("dupes") || subcommand == Some("dupes"),
+        pick: matches.get_flag("pick"),
+        action: matches
+            .get_one::<String>("action")
+            .map(Action::from)
+            .map(|action| match action {
+                Action::Delete if matches.get_flag("trash") => Action::Trash,
+                action => action,
+            }),
+        keep: Keep::from(matches.get_one::<String>("keep").unwrap()),
+        // Nothing is carried out with --dry-run, --yes or not.
+        yes: matches.get_flag("yes") && !matches.get_flag("dry-run"),
+        link_farm: matches.get_one::<PathBuf>("link-farm").cloned(),
+        exec: matches.get_one::<Template>("exec").cloned(),
+        exec_batch: matches.get_one::<Template>("exec-batch").cloned(),
+        relocate: matches
+            .get_one::<PathBuf>("copy-to")
+            .map(|dest| (Mode::Copy, dest.clone()))
+            .or_else(|| {
+                matches
+                    .get_one::<PathBuf>("move-to")
+                    .map(|dest| (Mode::Move, dest.clone()))
+            }),
+        collision: Collision::from(matches.get_one::<String>("on-collision").unwrap()),
+        verify_after: matches
+            .get_one::<String>("verify-after")
+            .map(|algorithm| parse_algorithm(algorithm)),
+        find_hash: matches.get_many::<String>("find-hash").map(|arguments| {
+            let algorithm = checksum_algorithm(&matches);
+
+            let wanted = Wanted::parse(arguments, algorithm.digest_size() * 2)
+                .unwrap_or_else(|e| Diagnostic::new(e).about("--find-hash").exit(1));
+
+            (Arc::new(wanted), algorithm)
+        }),
+        cas: matches.get_one::<PathBuf>("cas-export").map(|dir| {
+            let algorithm = checksum_algorithm(&matches);
+
+            let store =
+                Store::open(dir, matches.get_flag("cas-link"), algorithm).unwrap_or_else(|e| {
+                    Diagnostic::new(format!(
+                        "couldn't be opened as a store for --cas-export: {}",
+                        e
+                    ))
+                    .about(dir.display())
+                    .exit(1)
+                });
+
+            Arc::new(store)
+        }),
+        #[cfg(unix)]
+        tags: (matches.get_flag("xattr-store") || matches.get_flag("xattr-verify")).then(|| {
+            let algorithm = checksum_algorithm(&matches);
+
+            Arc::new(tags::Tags::new(
+                &algorithm,
+                matches.get_flag("xattr-store"),
+                matches.get_flag("xattr-verify"),
+            ))
+        }),
+        checkpoint: match &verifying {
+            Some((_, hashed)) => Some(hashed.checkpoint().unwrap_or_else(|e| {
+                Diagnostic::new(format!("couldn't be opened again to hash into: {}", e))
+                    .about(std::env::temp_dir().display())
+                    .exit(1)
+            })),
+            None => matches.get_one::<PathBuf>("checkpoint").map(|path| {
+                let algorithm = checksum_algorithm(&matches);
+
+                Checkpoint::open(path, &algorithm).unwrap_or_else(|e| {
+                    Diagnostic::new(format!("couldn't be opened as a checkpoint: {}", e))
+                        .about(path.display())
+                        .exit(1)
+                })
+            }),
+        }
+        .map(Arc::new),
+        mirror: matches.get_many::<String>("mirror").map(|mut sides| {
+            let algorithm = checksum_algorithm(&matches);
+            (PathBuf::from(sides.nth(1).unwrap()), algorithm)
+        }),
+        sort_memory: *matches.get_one::<usize>("sort-memory").unwrap(),
+        output: Output {
+            format: if matches.contains_id("printf") {
+                Format::Template
+            } else if matches.get_flag("long") {
+                Format::Long
+            } else if matches.contains_id("tree") {
+                Format::Tree
+            } else {
+                matches
+                    .get_one::<String>("format")
+                    .map(Format::from)
+                    .unwrap_or(Format::Plain)
+            },
+            columns,
+            path_style,
+            strip_prefix: matches.get_one::<PathBuf>("strip-prefix").cloned(),
+            prepend_prefix: matches.get_one::<PathBuf>("prepend-prefix").cloned(),
+            // JW_NO_COLOR is the same as NO_COLOR, apart from being taken
+            // over the config file, rather than only over auto.
+            colors: match std::env::var_os("JW_NO_COLOR").is_some_and(|v| !v.is_empty())
+                && matches.value_source("color") == Some(ValueSource::DefaultValue)
+            {
+                true => ColorChoice::Never,
+                false => matches
+                    .get_one::<String>("color")
+                    .map(ColorChoice::from)
+                    .unwrap_or(ColorChoice::Auto),
+            }
+            .resolve(),
+            icons: matches.get_flag("icons"),
+            quote: matches
+                .get_one::<String>("quote")
+                .map(QuoteStyle::from)
+                .unwrap_or(QuoteStyle::None),
+            size_style: if matches.get_flag("si") {
+                SizeStyle::Si
+            } else if matches.get_flag("human-readable") {
+                SizeStyle::Binary
+            } else {
+                SizeStyle::Bytes
+            },
+            tree_annotation: matches
+                .get_one::<String>("tree")
+                .map(Annotation::from)
+                .unwrap_or(Annotation::None),
+            template: matches
+                .get_one::<Vec<template::Piece>>("printf")
+                .cloned()
+                .unwrap_or_default(),
+        },
+    };
+
+    if matches.get_flag("reproducible") {
+        stats::leave_out_timings();
+    }
+
+    progress::print_on_signal(options.progress.clone());
+    interrupt::install();
+
+    if let Some(threads) = shared_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap_or_else(|e| {
+                log::error(format_args!("Failed to set up the thread pool: {}", e));
+                exit(1);
+            });
+    }
+
+    log::debug(format_args!(
+        "Using {} threads, {} of them walking, and {:?} reads of {} bytes, with up to {} files open.",
+        rayon::current_num_threads(),
+        options.walk_strategy.threads(),
+        options.read.backend,
+        options.read.buffer_size,
+        options.open_files
+    ));
+
+    if matches.get_flag("explain") {
+        let mut resolved = options.explained(&checksum_triggers);
+
+        if let Some((manifest, _)) = &verifying {
+            resolved.push(("against", manifest.clone()));
+        }
+
+        Sink::new(std::io::stdout().lock(), false)
+            .write_str(&explain::render(&command, &matches, &resolved));
+
+        return;
+    }
+
+    let header = match options.summarize_dirs {
+        Some(_) => du::summary_header(options.output.format),
+        None if options.dupes
+            || options.pick
+            || options.link_farm.is_some()
+            || options.exec.is_some()
+            || options.exec_batch.is_some()
+            || options.relocate.is_some()
+            || options.mirror.is_some()
+            || options.find_hash.is_some() =>
+        {
+            None
+        }
+        None => options.output.header(),
     };
 
-    if let Some(algorithm) = &options.checksum {
+    if let Some(header) = header.filter(|_| !options.silent) {
+        Sink::new(std::io::stdout().lock(), false).write_str(&header);
+    }
+
+    let errors = options.errors.clone();
+    let progress = options.progress.clone();
+    let strict = options.strict;
+
+    // For jw verify, the manifest is checked against as files are hashed, as
+    // well as once they all have been, so there's something to show. It's
+    // mapped for the rest of the run, which is how long the view needs it.
+    if let (Some((manifest, _)), true) = (&verifying, tui) {
+        let mapped = File::open(manifest).and_then(|file| unsafe { Mmap::map(&file) });
+
+        if let (Ok(mapped), Some(algorithm)) = (mapped, &options.checksum) {
+            let mapped: &'static Mmap = Box::leak(Box::new(mapped));
+            tui::expect(
+                read_hashes(mapped, algorithm.digest_size() * 2),
+                walked.clone(),
+            );
+        }
+    }
+
+    if tui && options.checksum.is_some() {
+        progress.track_workers(rayon::current_num_threads());
+    }
+
+    let screen = tui.then(|| tui::Screen::start(title, Some((progress.clone(), errors.clone()))));
+
+    if let Some((destination, algorithm)) = &options.mirror {
+        mirror(&options, destination, algorithm);
+    } else if let Some((wanted, algorithm)) = &options.find_hash {
+        find_hashes(&options, wanted, algorithm);
+    } else if let Some(algorithm) = &options.checksum {
         checksum_rayon(&options, algorithm);
+    } else if options.dupes {
+        find_duplicates(&options);
+    } else if options.pick {
+        pick(&options);
+    } else if let Some(destination) = &options.link_farm {
+        link_farm(&options, destination);
+    } else if let Some(template) = &options.exec {
+        run_commands(&options, template);
+    } else if let Some(template) = &options.exec_batch {
+        run_batches(&options, template);
+    } else if let Some((mode, destination)) = &options.relocate {
+        relocate(&options, *mode, destination);
     } else {
         traverse(options);
     }
+
+    drop(screen);
+
+    if let Some((manifest, hashed)) = verifying {
+        if !interrupt::interrupted() {
+            let mapped = hashed.map().unwrap_or_else(|e| {
+                Diagnostic::new(format!("couldn't be read back once it was hashed: {}", e))
+                    .about(&walked)
+                    .exit(1)
+            });
+
+            let status = checksum_diff(
+                checksum_algorithm(&matches),
+                &[manifest],
+                Some((&walked, &mapped)),
+                diff_stats_to,
+                stats_format,
+                quarantine,
+                verify_after.as_ref(),
+            );
+
+            drop(mapped);
+            drop(hashed);
+            exit(status);
+        }
+    }
+
+    if interrupt::interrupted() {
+        log::error("Interrupted, so the walk was cut short.");
+        exit(interrupt::exit_code());
+    }
+
+    // Whatever couldn't be read, or done, was reported as it happened, and
+    // the rest carried on regardless, unless it was --strict.
+    if errors.count() > 0 && strict {
+        log::error("Stopped at the first error, since --strict was given.");
+        exit(1);
+    }
+
+    if errors.count() > 0 {
+        exit(EXIT_ERRORS);
+    }
+
+    if progress.matched() == 0 {
+        exit(EXIT_EMPTY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walk_threads_0_is_a_thread_per_cpu() {
+        let cpus = std::thread::available_parallelism().unwrap().get();
+
+        assert_eq!(WalkStrategy::Dedicated(0).threads(), cpus);
+        assert_eq!(WalkStrategy::Dedicated(3).threads(), 3);
+        assert_eq!(WalkStrategy::Serial.threads(), 1);
+        assert!(limits::files_per_thread(1024, 4, WalkStrategy::Dedicated(0).threads()) > 0);
+    }
 }
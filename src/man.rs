@@ -0,0 +1,311 @@
+use std::fmt::Write as _;
+
+use clap::{Arg, Command};
+
+// A man page for --man, written from the same definitions --help is, so it
+// never says anything the binary doesn't. Each option gets its long help, as
+// --help prints it, along with its default, possible values, and environment
+// variable, and each subcommand its own section of the same, after which
+// what --help prints at the end, the exit statuses and the environment
+// variables, becomes sections of their own.
+pub fn render(mut command: Command) -> String {
+    // Builds the --help and --version arguments, and copies the global ones
+    // to every subcommand.
+    command.build();
+
+    let name = command.get_name().to_string();
+    let mut page = String::new();
+
+    let _ = writeln!(
+        page,
+        ".TH {} 1 \"\" \"{} {}\"",
+        name.to_uppercase(),
+        name,
+        command.get_version().unwrap_or_default()
+    );
+
+    page.push_str(".SH NAME\n");
+    let _ = writeln!(
+        page,
+        "{} \\- {}",
+        name,
+        escape(
+            &command
+                .get_about()
+                .map(|about| about.to_string())
+                .unwrap_or_default()
+        )
+    );
+
+    page.push_str(".SH SYNOPSIS\n");
+    let _ = writeln!(page, "{}", synopsis(&command, &name));
+
+    let subcommands: Vec<&Command> = command
+        .get_subcommands()
+        .filter(|subcommand| subcommand.get_name() != "help")
+        .collect();
+
+    for subcommand in &subcommands {
+        let _ = writeln!(
+            page,
+            ".br\n{}",
+            synopsis(subcommand, &format!("{} {}", name, subcommand.get_name()))
+        );
+    }
+
+    if let Some(about) = command.get_long_about().or(command.get_about()) {
+        page.push_str(".SH DESCRIPTION\n");
+        text(&mut page, &about.to_string());
+    }
+
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .partition(|arg| arg.is_positional());
+
+    if !positionals.is_empty() {
+        page.push_str(".SH ARGUMENTS\n");
+        positionals.iter().for_each(|arg| item(&mut page, arg));
+    }
+
+    page.push_str(".SH OPTIONS\n");
+    options.iter().for_each(|arg| item(&mut page, arg));
+
+    if !subcommands.is_empty() {
+        page.push_str(".SH COMMANDS\n");
+    }
+
+    // The global options are the same as they are above, so only what's the
+    // subcommand's own is repeated.
+    for subcommand in subcommands {
+        let _ = writeln!(page, ".SS \"{} {}\"", name, escape(subcommand.get_name()));
+
+        if let Some(about) = subcommand.get_long_about().or(subcommand.get_about()) {
+            text(&mut page, &about.to_string());
+        }
+
+        subcommand
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set() && !arg.is_global_set())
+            .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+            .for_each(|arg| item(&mut page, arg));
+    }
+
+    // Headings in it are the lines with nothing in front of them that end
+    // with a colon, and everything under them is kept exactly as it's laid
+    // out, since that's what lines up the columns.
+    if let Some(after) = command.get_after_long_help() {
+        let mut open = false;
+
+        for line in after.to_string().lines() {
+            match line.strip_suffix(':').filter(|_| !line.starts_with(' ')) {
+                Some(heading) => {
+                    if open {
+                        page.push_str(".fi\n");
+                    }
+
+                    let _ = writeln!(page, ".SH {}\n.nf", escape(&heading.to_uppercase()));
+                    open = true;
+                }
+                None => {
+                    let _ = writeln!(page, "{}", line_of(line));
+                }
+            }
+        }
+
+        if open {
+            page.push_str(".fi\n");
+        }
+    }
+
+    page
+}
+
+fn synopsis(command: &Command, name: &str) -> String {
+    let mut synopsis = format!("\\fB{}\\fR [\\fIOPTIONS\\fR]", escape(name));
+
+    for arg in command.get_arguments().filter(|arg| arg.is_positional()) {
+        let value = value_name(arg);
+
+        let _ = match arg.is_required_set() {
+            true => write!(synopsis, " \\fI{}\\fR", value),
+            false => write!(synopsis, " [\\fI{}\\fR]", value),
+        };
+
+        if arg
+            .get_num_args()
+            .is_some_and(|range| range.max_values() > 1)
+        {
+            synopsis.push_str("...");
+        }
+    }
+
+    synopsis
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .map(|names| {
+            names
+                .iter()
+                .map(|name| escape(name))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_else(|| escape(arg.get_id().as_str()))
+}
+
+// An option, or an argument, as a tagged paragraph: the ways of giving it,
+// then its help, then what --help adds in brackets after it.
+fn item(page: &mut String, arg: &Arg) {
+    let mut tag = Vec::new();
+
+    if let Some(short) = arg.get_short() {
+        tag.push(format!("\\fB\\-{}\\fR", escape(&short.to_string())));
+    }
+
+    if let Some(long) = arg.get_long() {
+        tag.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+
+    let mut tag = tag.join(", ");
+
+    if arg.is_positional() {
+        tag = format!("\\fI{}\\fR", value_name(arg));
+    } else if arg.get_action().takes_values() {
+        let _ = write!(tag, " \\fI{}\\fR", value_name(arg));
+    }
+
+    let _ = writeln!(page, ".TP\n{}", tag);
+
+    if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+        text(page, &help.to_string());
+    }
+
+    let mut notes = Vec::new();
+
+    if arg.get_action().takes_values() && !arg.get_default_values().is_empty() {
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+
+        notes.push(format!("[default: {}]", defaults.join(", ")));
+    }
+
+    let possible: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+
+    if !possible.is_empty() && arg.get_action().takes_values() {
+        notes.push(format!("[possible values: {}]", possible.join(", ")));
+    }
+
+    if let Some(env) = arg.get_env() {
+        notes.push(format!("[env: {}]", env.to_string_lossy()));
+    }
+
+    for note in notes {
+        let _ = writeln!(page, ".br\n{}", escape(&note));
+    }
+}
+
+// Help text is wrapped the way it's meant to be read in a terminal, which
+// roff fills again to whatever width the page is shown at. Blank lines stay
+// paragraph breaks, and indented lines, the examples and lists, start lines
+// of their own, since roff breaks before a line that starts with a space.
+fn text(page: &mut String, text: &str) {
+    for line in text.lines() {
+        match line.trim().is_empty() {
+            true => page.push_str(".sp\n"),
+            false => {
+                let _ = writeln!(page, "{}", line_of(line));
+            }
+        }
+    }
+}
+
+// A line that would be read as a request, for starting with a . or a ', is
+// kept from being one.
+fn line_of(line: &str) -> String {
+    let line = escape(line);
+
+    match line.starts_with(['.', '\'']) {
+        true => format!("\\&{}", line),
+        false => line,
+    }
+}
+
+// Backslashes are roff's escapes, and every - is a hyphen to it, unless it's
+// written as \-, which is what every option needs to be copied from a page.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What render writes as requests of its own; any other line that starts
+    // with a . or a ' would be taken as one by roff.
+    const REQUESTS: [&str; 8] = [".TH ", ".SH ", ".SS ", ".TP", ".br", ".sp", ".nf", ".fi"];
+
+    fn unescaped(page: &str) -> Vec<&str> {
+        page.lines()
+            .filter(|line| line.starts_with(['.', '\'']))
+            .filter(|line| !REQUESTS.iter().any(|request| line.starts_with(request)))
+            .collect()
+    }
+
+    #[test]
+    fn jws_page_has_no_stray_requests() {
+        let page = render(crate::cli());
+        assert!(page.starts_with(".TH JW 1 "));
+        assert_eq!(unescaped(&page), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn help_lines_that_look_like_requests_are_escaped() {
+        let command = Command::new("test")
+            .about(".about")
+            .long_about(".starts with a dot\n'starts with a quote\n  .indented")
+            .after_long_help("Heading:\n.in after help\n'quoted too")
+            .arg(
+                Arg::new("dot")
+                    .long("dot")
+                    .long_help(".hidden files\n\n'quoted' ones\n.br isn't a break"),
+            )
+            .subcommand(
+                Command::new("sub")
+                    .about(".sub")
+                    .arg(Arg::new("x").help("'x")),
+            );
+
+        let page = render(command);
+        assert_eq!(unescaped(&page), Vec::<&str>::new());
+
+        for line in [
+            "\\&.starts with a dot",
+            "\\&'starts with a quote",
+            "  .indented",
+            "\\&.hidden files",
+            "\\&'quoted' ones",
+            "\\&.br isn't a break",
+            "\\&.in after help",
+            "\\&'quoted too",
+            "\\&.sub",
+            "\\&'x",
+        ] {
+            assert!(
+                page.lines().any(|l| l == line),
+                "{:?} isn't in:\n{}",
+                line,
+                page
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+// A manifest line is a hash followed right away by the path, with nothing in
+// between, so a name that starts or ends with spaces keeps them. That leaves
+// the names that can't be written out as they are: one with a newline in it
+// would end the line early, one ending in a carriage return would lose it to
+// the \r\n a manifest may have picked up along the way, and one that isn't
+// valid UTF-8 would be printed lossily. Lines for those start with a
+// backslash instead, the same as sha256sum does it, and the path in them has
+// \\, \n, and \r for those characters, and \xHH for every byte that isn't
+// part of a UTF-8 character. An extended attribute's line is one of them too,
+// with \@ for the NUL between its file and its name, which no name can have.
+// Every other line is left exactly as it's always been, backslashes and all,
+// since that's how Windows separates its paths.
+pub fn line(hash: &str, path: &[u8]) -> String {
+    let mut line = String::with_capacity(hash.len() + path.len() + 2);
+
+    if !needs_escaping(path) {
+        line.push_str(hash);
+        // Checked by needs_escaping already.
+        line.push_str(std::str::from_utf8(path).unwrap());
+        line.push('\n');
+        return line;
+    }
+
+    line.push('\\');
+    line.push_str(hash);
+
+    for chunk in path.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\0' => line.push_str("\\@"),
+                c => line.push(c),
+            }
+        }
+
+        for byte in chunk.invalid() {
+            write!(line, "\\x{:02x}", byte).unwrap();
+        }
+    }
+
+    line.push('\n');
+    line
+}
+
+fn needs_escaping(path: &[u8]) -> bool {
+    path.contains(&b'\n')
+        || path.contains(&b'\r')
+        || path.contains(&0)
+        || std::str::from_utf8(path).is_err()
+}
+
+// The other way around, given a line without its \n, or with the \r of a
+// \r\n still on it. None if it isn't a line for a hash of this length, or an
+// escape in it is anything other than the ones above.
+pub fn parse(line: &[u8], digest_length: usize) -> Option<(&str, Cow<'_, [u8]>)> {
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(line) => (true, line),
+        None => (false, line.strip_suffix(b"\r").unwrap_or(line)),
+    };
+
+    let (hash, path) = line.split_at_checked(digest_length)?;
+    let hash = std::str::from_utf8(hash).ok()?;
+
+    if !escaped {
+        return Some((hash, Cow::Borrowed(path)));
+    }
+
+    // Whatever \r is at the end of an escaped line can only have come from
+    // the line ending, since one in the name is written as \r.
+    let path = path.strip_suffix(b"\r").unwrap_or(path);
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut bytes = path.iter();
+
+    while let Some(&byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+
+        match bytes.next()? {
+            b'\\' => unescaped.push(b'\\'),
+            b'n' => unescaped.push(b'\n'),
+            b'r' => unescaped.push(b'\r'),
+            b'@' => unescaped.push(0),
+            b'x' => {
+                let digits = [*bytes.next()?, *bytes.next()?];
+
+                // from_str_radix would take a + as a sign.
+                if !digits.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+
+                let digits = std::str::from_utf8(&digits).ok()?;
+                unescaped.push(u8::from_str_radix(digits, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+
+    Some((hash, Cow::Owned(unescaped)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    fn round_trip(path: &[u8]) {
+        let line = line(HASH, path);
+        let line = line.strip_suffix('\n').unwrap();
+
+        assert!(!line.contains('\n'), "{:?} spans more than a line", line);
+
+        let (hash, parsed) = parse(line.as_bytes(), HASH.len()).unwrap();
+        assert_eq!(hash, HASH);
+        assert_eq!(&*parsed, path, "{:?} came back as {:?}", line, parsed);
+
+        // The same again, as it'd be read from a manifest with \r\n endings.
+        let crlf = format!("{}\r", line);
+        let (_, parsed) = parse(crlf.as_bytes(), HASH.len()).unwrap();
+        assert_eq!(&*parsed, path);
+    }
+
+    #[test]
+    fn plain_names_are_left_as_they_are() {
+        assert_eq!(line(HASH, b"dir/file"), format!("{}dir/file\n", HASH));
+        assert_eq!(
+            line(HASH, b"C:\\dir\\file"),
+            format!("{}C:\\dir\\file\n", HASH)
+        );
+        round_trip(b"dir/file");
+        round_trip(b"C:\\dir\\file");
+        round_trip("dir/\u{e9}t\u{e9}/\u{1f600}".as_bytes());
+    }
+
+    #[test]
+    fn newlines() {
+        round_trip(b"dir/two\nlines");
+        round_trip(b"dir/trailing\n");
+        round_trip(b"\n");
+        round_trip(b"dir/back\\slash\nand a newline");
+        round_trip(b"dir/literal \\n, then a real\n");
+    }
+
+    #[test]
+    fn carriage_returns() {
+        round_trip(b"dir/trailing\r");
+        round_trip(b"dir/crlf\r\n");
+        round_trip(b"dir/in\rthe middle");
+    }
+
+    #[test]
+    fn non_utf8() {
+        round_trip(b"dir/\xff\xfe");
+        round_trip(b"dir/latin1 caf\xe9");
+        round_trip(b"dir/\xe2\x82");
+        round_trip(b"dir/\\x41 \xff");
+        round_trip(b"\x80");
+    }
+
+    #[test]
+    fn spaces() {
+        round_trip(b"dir/trailing ");
+        round_trip(b"dir/trailing   ");
+        round_trip(b" leading");
+        round_trip(b" ");
+        round_trip(b"dir/\t tab and space \t");
+        round_trip(b"dir/trailing \xff ");
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            line(HASH, b"dir/file\0user.x"),
+            format!("\\{}dir/file\\@user.x\n", HASH)
+        );
+        round_trip(b"dir/file\0user.x");
+        round_trip(b"dir/a@b\0user.c@d");
+        round_trip(b"dir/\xff\0user.x");
+
+        let attribute = line(HASH, b"dir/a\0user.x");
+        let file = line(HASH, b"dir/a@user.x");
+        assert_ne!(
+            parse(attribute.trim_end().as_bytes(), HASH.len()),
+            parse(file.trim_end().as_bytes(), HASH.len())
+        );
+    }
+
+    #[test]
+    fn leading_backslash() {
+        round_trip(b"\\dir\\file");
+        round_trip(b"\\\n");
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(parse(b"0123", HASH.len()).is_none());
+        assert!(parse(format!("\\{}bad \\q", HASH).as_bytes(), HASH.len()).is_none());
+        assert!(parse(format!("\\{}cut \\x4", HASH).as_bytes(), HASH.len()).is_none());
+        assert!(parse(format!("\\{}cut \\", HASH).as_bytes(), HASH.len()).is_none());
+        assert!(parse(format!("\\{}sign \\x+1", HASH).as_bytes(), HASH.len()).is_none());
+    }
+}
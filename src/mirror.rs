@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::dedupe::temporary_name;
+use crate::hashutil::*;
+use crate::mutate;
+
+pub enum Synced {
+    // Already the same on both sides, going by the hash.
+    Matched,
+    Copied(u64),
+}
+
+// For --mirror, makes sure dst has the same contents as src, copying src over
+// when it's missing or different. The copy is hashed as it's written, from
+// what's read out of src, and then read back from disk and hashed again, and
+// it's only renamed into place once the two agree, so dst is never left
+// holding anything but a complete and proven copy.
+pub fn mirror_file(
+    algorithm: &HashAlgorithm,
+    src: &Path,
+    dst: &Path,
+    read: &ReadOptions,
+) -> io::Result<Synced> {
+    match algorithm {
+        HashAlgorithm::Xxh3 => mirror_with::<Xxh3Default>(src, dst, read),
+        HashAlgorithm::Sha224 => mirror_with::<Sha224>(src, dst, read),
+        HashAlgorithm::Sha256 => mirror_with::<Sha256>(src, dst, read),
+        HashAlgorithm::Sha384 => mirror_with::<Sha384>(src, dst, read),
+        HashAlgorithm::Sha512 => mirror_with::<Sha512>(src, dst, read),
+        HashAlgorithm::Md5 => mirror_with::<Md5Context>(src, dst, read),
+    }
+}
+
+fn mirror_with<H: Hasher>(src: &Path, dst: &Path, read: &ReadOptions) -> io::Result<Synced> {
+    let metadata = src.metadata()?;
+
+    match dst.symlink_metadata() {
+        // A different size is as good as a different hash.
+        Ok(existing) if existing.is_file() && existing.len() == metadata.len() => {
+            if hash_file::<H>(src, read)? == hash_file::<H>(dst, read)? {
+                return Ok(Synced::Matched);
+            }
+        }
+        Ok(existing) if !existing.is_file() => {
+            return Err(io::Error::other(format!(
+                "{} is in the way, and isn't a regular file",
+                dst.display()
+            )))
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = dst.parent() {
+                mutate::create_dir_all(parent)?;
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    let copy = || {
+        let temporary = temporary_name(dst);
+
+        let copied =
+            copy_hashing::<H>(src, &temporary, &metadata, read).and_then(|(hash, bytes)| {
+                if hash_file::<H>(&temporary, read)? != hash {
+                    return Err(io::Error::other(
+                        "the copy didn't hash the same as the source when read back",
+                    ));
+                }
+
+                std::fs::rename(&temporary, dst)?;
+                Ok(Synced::Copied(bytes))
+            });
+
+        if copied.is_err() {
+            let _ = std::fs::remove_file(&temporary);
+        }
+
+        copied
+    };
+
+    mutate::perform(|| Ok(Synced::Copied(metadata.len())), copy)
+}
+
+// Copies src to dst, hashing what's read along the way, and returns the hash
+// and how much was copied. The copy keeps src's permissions and mtime, and is
+// synced to disk, then dropped from the page cache where that's possible, so
+// that reading it back means reading what's actually on disk.
+fn copy_hashing<H: Hasher>(
+    src: &Path,
+    dst: &Path,
+    metadata: &std::fs::Metadata,
+    read: &ReadOptions,
+) -> io::Result<(String, u64)> {
+    read.progress.at(src);
+
+    // Opened plainly, since O_DIRECT would need an aligned buffer.
+    let mut source = File::open(src)?;
+
+    let mut destination = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+
+    let mut hasher = H::create();
+    let mut buffer = vec![0; read.buffer_size];
+    let mut bytes = 0;
+
+    loop {
+        let bytes_read = source.read(&mut buffer)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        read.chunk_read(bytes_read);
+        hasher.update(&buffer[..bytes_read]);
+        destination.write_all(&buffer[..bytes_read])?;
+        bytes += bytes_read as u64;
+    }
+
+    destination.set_permissions(metadata.permissions())?;
+    destination.set_modified(metadata.modified()?)?;
+    destination.sync_all()?;
+
+    #[cfg(target_os = "linux")]
+    unsafe {
+        use std::os::fd::AsRawFd;
+        libc::posix_fadvise(destination.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+
+    Ok((hexlify(hasher.finalize()), bytes))
+}
@@ -0,0 +1,42 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Every change jw makes, whether to the filesystem or by running a command,
+// goes through perform, so --dry-run is enforced here and only here, rather
+// than by each feature remembering to check for it. Each change comes along
+// with what to do in its place, which is to work out, without touching
+// anything, what the change would have come to, so everything after it can
+// carry on and print the same lines it otherwise would.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+pub fn perform<T>(
+    pretend: impl FnOnce() -> io::Result<T>,
+    act: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    match dry_run() {
+        true => pretend(),
+        false => act(),
+    }
+}
+
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    perform(|| Ok(()), || std::fs::create_dir_all(path))
+}
+
+// With nothing actually being created, the only thing there is to collide
+// with is whatever was already there.
+pub fn vacant(path: &Path) -> io::Result<()> {
+    match path.symlink_metadata() {
+        Ok(_) => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(_) => Ok(()),
+    }
+}
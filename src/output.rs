@@ -0,0 +1,551 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::color::LsColors;
+use crate::forks;
+use crate::icons;
+use crate::manifest;
+use crate::record::{
+    group_name, iso8601, mode_string, unix_seconds, user_name, utc_fields, Record,
+};
+use crate::template::{self, Piece};
+use crate::tree::Annotation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Plain,
+    Jsonl,
+    Csv,
+    Tsv,
+    Template,
+    Long,
+    Tree,
+}
+
+impl From<&String> for Format {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "plain" => Self::Plain,
+            "jsonl" => Self::Jsonl,
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            _ => panic!("Invalid output format! '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Path,
+    Type,
+    Size,
+    Mtime,
+    Inode,
+    Dev,
+    Nlink,
+    Hash,
+}
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::Type => "type",
+            Self::Size => "size",
+            Self::Mtime => "mtime",
+            Self::Inode => "inode",
+            Self::Dev => "dev",
+            Self::Nlink => "nlink",
+            Self::Hash => "hash",
+        }
+    }
+}
+
+impl From<&String> for Column {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "path" => Self::Path,
+            "type" => Self::Type,
+            "size" => Self::Size,
+            "mtime" => Self::Mtime,
+            "inode" => Self::Inode,
+            "dev" => Self::Dev,
+            "nlink" => Self::Nlink,
+            "hash" => Self::Hash,
+            _ => panic!("Invalid column! '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathStyle {
+    Relative,
+    Absolute,
+    Canonical,
+}
+
+impl From<&String> for PathStyle {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "relative" => Self::Relative,
+            "absolute" => Self::Absolute,
+            "canonical" => Self::Canonical,
+            _ => panic!("Invalid path style! '{}'", s),
+        }
+    }
+}
+
+impl PathStyle {
+    // Walking from an absolutized or canonicalized root makes every entry
+    // below it come out the same way for free, instead of paying for a
+    // getcwd or realpath on each and every entry.
+    pub fn apply_to_root(&self, root: &Path) -> PathBuf {
+        let styled = match self {
+            Self::Relative => return root.to_path_buf(),
+            Self::Absolute => std::path::absolute(root),
+            Self::Canonical => std::fs::canonicalize(root),
+        };
+
+        styled.unwrap_or_else(|_| root.to_path_buf())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeStyle {
+    Bytes,
+    Binary,
+    Si,
+}
+
+impl SizeStyle {
+    pub fn format(&self, bytes: u64) -> String {
+        let (base, units) = match self {
+            Self::Bytes => return bytes.to_string(),
+            Self::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"]),
+            Self::Si => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB", "EB"]),
+        };
+
+        let mut value = bytes as f64;
+        let mut unit = 0;
+
+        while value >= base && unit < units.len() - 1 {
+            value /= base;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            format!("{} {}", bytes, units[0])
+        } else {
+            format!("{:.1} {}", value, units[unit])
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteStyle {
+    None,
+    Shell,
+    C,
+}
+
+impl From<&String> for QuoteStyle {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "none" => Self::None,
+            "shell" => Self::Shell,
+            "c" => Self::C,
+            _ => panic!("Invalid quoting style! '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub format: Format,
+    pub columns: Vec<Column>,
+    pub template: Vec<Piece>,
+    pub path_style: PathStyle,
+    pub strip_prefix: Option<PathBuf>,
+    pub prepend_prefix: Option<PathBuf>,
+    pub tree_annotation: Annotation,
+    pub colors: Option<LsColors>,
+    pub icons: bool,
+    pub size_style: SizeStyle,
+    pub quote: QuoteStyle,
+}
+
+impl Output {
+    // Whether entries can go straight to stdout as bare paths, untouched.
+    pub fn is_plain(&self) -> bool {
+        self.format == Format::Plain
+            && self.path_style != PathStyle::Canonical
+            && self.strip_prefix.is_none()
+            && self.prepend_prefix.is_none()
+            && self.colors.is_none()
+            && !self.icons
+            && self.quote == QuoteStyle::None
+    }
+
+    pub fn needs_metadata(&self) -> bool {
+        match self.format {
+            // Colors and icons need the mode for telling executables apart.
+            Format::Plain => self.colors.is_some() || self.icons,
+            Format::Jsonl | Format::Long => true,
+            Format::Csv | Format::Tsv => self
+                .columns
+                .iter()
+                .any(|c| !matches!(c, Column::Path | Column::Type | Column::Hash)),
+            Format::Template => self.template.iter().any(Piece::needs_metadata),
+            Format::Tree => {
+                self.tree_annotation == Annotation::Size || self.colors.is_some() || self.icons
+            }
+        }
+    }
+
+    // Only the human oriented formats are ever colored; ANSI escapes have no
+    // business in JSON, CSV, or whatever a template is building.
+    pub fn paint(&self, record: &Record, text: &str) -> String {
+        match &self.colors {
+            Some(colors) => colors.paint(record, text),
+            None => text.to_string(),
+        }
+    }
+
+    // The icon stays outside of the color escapes, so it's drawn in the
+    // terminal's own foreground rather than the file's color.
+    pub fn decorate(&self, record: &Record, text: &str) -> String {
+        let painted = self.paint(record, text);
+
+        if self.icons {
+            format!("{} {}", icons::icon(record), painted)
+        } else {
+            painted
+        }
+    }
+
+    // Quoting works on the raw bytes, so that names which aren't valid UTF-8
+    // can still be reproduced exactly rather than lossily.
+    pub fn quote<'s>(&self, text: &'s OsStr) -> Cow<'s, str> {
+        match self.quote {
+            QuoteStyle::None => text.to_string_lossy(),
+            QuoteStyle::Shell => Cow::Owned(shell_quote(text.as_encoded_bytes())),
+            QuoteStyle::C => Cow::Owned(c_quote(text.as_encoded_bytes())),
+        }
+    }
+
+    pub fn header(&self) -> Option<String> {
+        let separator = match self.format {
+            Format::Csv => ",",
+            Format::Tsv => "\t",
+            _ => return None,
+        };
+
+        let mut header = self
+            .columns
+            .iter()
+            .map(Column::name)
+            .collect::<Vec<_>>()
+            .join(separator);
+
+        header.push('\n');
+        Some(header)
+    }
+
+    // The path as it should be printed. The root was already styled before
+    // the walk, so only symlinks are left to resolve for canonical paths.
+    pub fn path<'r>(&self, record: &'r Record) -> Cow<'r, Path> {
+        let mut path = Cow::Borrowed(record.path().as_path());
+
+        if self.path_style == PathStyle::Canonical && record.entry.file_type().is_symlink() {
+            if let Ok(resolved) = std::fs::canonicalize(&path) {
+                path = Cow::Owned(resolved);
+            }
+        }
+
+        // Prefixes are matched and joined by component, so stripping /mnt/a
+        // won't eat into /mnt/ab, and prepending doesn't care about slashes.
+        if let Some(prefix) = &self.strip_prefix {
+            if let Ok(stripped) = path.strip_prefix(prefix) {
+                path = Cow::Owned(stripped.to_path_buf());
+            }
+        }
+
+        if let Some(prefix) = &self.prepend_prefix {
+            let relative: PathBuf = path
+                .components()
+                .filter(|c| !matches!(c, Component::Prefix(_) | Component::RootDir))
+                .collect();
+
+            path = Cow::Owned(prefix.join(relative));
+        }
+
+        path
+    }
+
+    // Lines come with their terminator, since templates bring their own.
+    pub fn line(&self, record: &Record) -> String {
+        let raw = self.path(record);
+        let path = forks::shown(&raw);
+
+        match self.format {
+            // Trees are rendered as a whole by the tree module, since a line
+            // depends on the entries around it.
+            Format::Plain | Format::Tree => {
+                // Checksums that are printed as they are make a manifest, so
+                // the paths in them are written so as to be read back exactly.
+                if let (Some(hash), QuoteStyle::None, None, false) =
+                    (record.hash, self.quote, &self.colors, self.icons)
+                {
+                    return manifest::line(hash, raw.as_os_str().as_encoded_bytes());
+                }
+
+                let path = self.decorate(record, &self.quote(path.as_os_str()));
+
+                match record.hash {
+                    Some(hash) => format!("{}{}\n", hash, path),
+                    None => format!("{}\n", path),
+                }
+            }
+
+            Format::Template => {
+                let mut line = String::with_capacity(128);
+                template::render(&self.template, record, &path, &mut line);
+                line
+            }
+
+            // Widths are fixed rather than fitted to the whole listing, so that
+            // it can still be streamed; they're generous enough for most trees.
+            Format::Long => {
+                let mut line = String::with_capacity(128);
+
+                let mode = record
+                    .mode()
+                    .map(|mode| mode_string(mode, record.entry.file_type()))
+                    .unwrap_or_else(|| "?".repeat(10));
+
+                let user = record
+                    .uid()
+                    .map(|uid| user_name(uid).unwrap_or_else(|| uid.to_string()))
+                    .unwrap_or_else(|| "?".to_string());
+
+                let group = record
+                    .gid()
+                    .map(|gid| group_name(gid).unwrap_or_else(|| gid.to_string()))
+                    .unwrap_or_else(|| "?".to_string());
+
+                let size = record
+                    .size()
+                    .map(|s| self.size_style.format(s))
+                    .unwrap_or_default();
+
+                let mtime = record
+                    .mtime()
+                    .map(|mtime| {
+                        let (year, month, day, hour, minute, _) = utc_fields(mtime);
+                        format!(
+                            "{:04}-{:02}-{:02} {:02}:{:02}",
+                            year, month, day, hour, minute
+                        )
+                    })
+                    .unwrap_or_else(|| " ".repeat(16));
+
+                write!(
+                    line,
+                    "{} {:<8} {:<8} {:>12} {} ",
+                    mode, user, group, size, mtime
+                )
+                .unwrap();
+
+                if let Some(hash) = record.hash {
+                    write!(line, "{} ", hash).unwrap();
+                }
+
+                line.push_str(&self.decorate(record, &self.quote(path.as_os_str())));
+
+                if record.entry.file_type().is_symlink() && self.path_style != PathStyle::Canonical
+                {
+                    if let Ok(target) = std::fs::read_link(record.path()) {
+                        write!(line, " -> {}", self.quote(target.as_os_str())).unwrap();
+                    }
+                }
+
+                line.push('\n');
+                line
+            }
+
+            Format::Jsonl => {
+                let mut line = String::with_capacity(128);
+
+                line.push_str("{\"path\":");
+                json_string(&mut line, &path.to_string_lossy());
+                write!(line, ",\"type\":\"{}\"", record.type_name()).unwrap();
+
+                match record.size() {
+                    Some(size) => write!(line, ",\"size\":{}", size),
+                    None => write!(line, ",\"size\":null"),
+                }
+                .unwrap();
+
+                match record.mtime() {
+                    Some(mtime) => write!(line, ",\"mtime\":{}", unix_seconds(mtime)),
+                    None => write!(line, ",\"mtime\":null"),
+                }
+                .unwrap();
+
+                write!(line, ",\"depth\":{}", record.entry.depth()).unwrap();
+
+                if let Some(hash) = record.hash {
+                    write!(line, ",\"hash\":\"{}\"", hash).unwrap();
+                }
+
+                line.push_str("}\n");
+                line
+            }
+
+            Format::Csv | Format::Tsv => {
+                let mut line = String::with_capacity(128);
+
+                for (i, column) in self.columns.iter().enumerate() {
+                    if i > 0 {
+                        line.push(if self.format == Format::Csv {
+                            ','
+                        } else {
+                            '\t'
+                        });
+                    }
+
+                    let value = match column {
+                        Column::Path => path.to_string_lossy().into_owned(),
+                        Column::Type => record.type_name().to_string(),
+                        Column::Size => record.size().map(|s| s.to_string()).unwrap_or_default(),
+                        Column::Mtime => record.mtime().map(iso8601).unwrap_or_default(),
+                        Column::Inode => record.ino().map(|i| i.to_string()).unwrap_or_default(),
+                        Column::Dev => record.dev().map(|d| d.to_string()).unwrap_or_default(),
+                        Column::Nlink => record.nlink().map(|n| n.to_string()).unwrap_or_default(),
+                        Column::Hash => record.hash.unwrap_or_default().to_string(),
+                    };
+
+                    if self.format == Format::Csv {
+                        csv_field(&mut line, &value);
+                    } else {
+                        tsv_field(&mut line, &value);
+                    }
+                }
+
+                line.push('\n');
+                line
+            }
+        }
+    }
+}
+
+pub fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+// RFC 4180; only quote when the field would otherwise be ambiguous.
+pub fn csv_field(out: &mut String, s: &str) {
+    if s.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&s.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(s);
+    }
+}
+
+// TSV has no quoting, so tabs and newlines are escaped the way most TSV
+// readers (and PostgreSQL's text format) expect.
+pub fn tsv_field(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+}
+
+// Bare when nothing in it means anything to a shell, single quoted when only
+// printable characters need protecting, and $'..' otherwise, since that's the
+// only form that can spell out control characters and arbitrary bytes.
+pub fn shell_quote(bytes: &[u8]) -> String {
+    let is_safe = |b: &u8| b.is_ascii_alphanumeric() || b"_@%+=:,./-".contains(b);
+
+    if !bytes.is_empty() && bytes.iter().all(is_safe) {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(char::is_control) => {
+            format!("'{}'", text.replace('\'', "'\\''"))
+        }
+        _ => {
+            let mut out = String::from("$'");
+            escape_bytes(&mut out, bytes, '\'');
+            out.push('\'');
+            out
+        }
+    }
+}
+
+// Double quoted with C escapes, the same as ls --quoting-style=c.
+pub fn c_quote(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    escape_bytes(&mut out, bytes, '"');
+    out.push('"');
+    out
+}
+
+// Octal rather than \x escapes for anything unprintable, since \x swallows
+// as many hex digits as follow it in C, which would eat into the next char.
+fn escape_bytes(out: &mut String, bytes: &[u8], quote: char) {
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                '\x07' => out.push_str("\\a"),
+                '\x08' => out.push_str("\\b"),
+                '\x0b' => out.push_str("\\v"),
+                '\x0c' => out.push_str("\\f"),
+                c if c == quote => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c if c.is_control() => {
+                    let mut buffer = [0; 4];
+
+                    for byte in c.encode_utf8(&mut buffer).bytes() {
+                        write!(out, "\\{:03o}", byte).unwrap();
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+
+        for byte in chunk.invalid() {
+            write!(out, "\\{:03o}", byte).unwrap();
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::TryRecvError;
+
+use crate::diagnostic::Diagnostic;
+use crate::record::Record;
+use crate::{filter_entry, walk, Options};
+
+// For --pick, the entries are matched against what's typed as they're walked,
+// the way fzf does it, so there's something to pick from the moment the first
+// of them turns up, rather than once the whole tree has been walked. What's
+// picked is returned, in the order it was walked in, or None if it was given
+// up on, with Esc or Ctrl+C.
+//
+// The picker is drawn on the terminal itself, rather than stdout or stderr,
+// which are left for the picked paths, so that `cd "$(jw --pick)"` works.
+pub(crate) fn run(options: &Options) -> Option<Vec<PathBuf>> {
+    let mut tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .unwrap_or_else(|e| {
+            Diagnostic::new(format!("needs a terminal to draw on: {}", e))
+                .about("--pick")
+                .tip("run jw from a terminal, since the finder is drawn on it rather than stdout")
+                .exit(1)
+        });
+
+    let (sender, receiver) = crossbeam_channel::unbounded::<PathBuf>();
+    let stop = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        let stop = &stop;
+
+        scope.spawn(move || {
+            for dir in &options.directories {
+                let unseen = options.unseen(dir);
+
+                for entry in walk(dir, options)
+                    .filter_map(|e| filter_entry(e, options))
+                    .filter(|e| unseen(e))
+                {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    options.progress.entry(|| entry.path());
+
+                    let path = options.output.path(&Record::new(&entry, None)).into_owned();
+
+                    if sender.send(path).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let picked = Terminal::raw(&tty).and_then(|terminal| {
+            let picked = Picker::default().run(&mut tty, &receiver);
+            drop(terminal);
+            picked
+        });
+
+        stop.store(true, Ordering::Relaxed);
+        drop(receiver);
+
+        picked.unwrap_or_else(|e| {
+            Diagnostic::new(format!("failed to draw on the terminal: {}", e))
+                .about("--pick")
+                .exit(1)
+        })
+    })
+}
+
+// The terminal as it was, put back however the picker, or --tui, ends, along
+// with whatever was on the screen before it.
+pub(crate) struct Terminal {
+    fd: i32,
+    saved: libc::termios,
+}
+
+impl Terminal {
+    pub(crate) fn raw(tty: &File) -> std::io::Result<Self> {
+        let fd = tty.as_raw_fd();
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // Keys are read as they're pressed, without being echoed, and Ctrl+C
+        // is a key like any other, rather than SIGINT.
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut tty: &File = tty;
+        tty.write_all(b"\x1b[?1049h")?;
+
+        Ok(Self { fd, saved })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = unsafe { libc::write(self.fd, b"\x1b[?1049l".as_ptr().cast(), 8) };
+        unsafe { libc::tcsetattr(self.fd, libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+pub(crate) enum Key {
+    Char(char),
+    Backspace,
+    ClearQuery,
+    DeleteWord,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Toggle,
+    Accept,
+    Abort,
+}
+
+// Everything that's been read from the terminal at once, which is more than a
+// key when it's pasted into, or when a key is an escape sequence. An Esc
+// that's all there is, rather than the start of one, is Esc itself.
+pub(crate) fn keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+
+        let (key, length) = match rest {
+            [0x1b, b'[' | b'O', b'A', ..] => (Some(Key::Up), 3),
+            [0x1b, b'[' | b'O', b'B', ..] => (Some(Key::Down), 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Some(Key::PageUp), 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Some(Key::PageDown), 4),
+            // Whatever other sequence it is, it's skipped, up to its last
+            // byte, which is the first that isn't a digit or a ;.
+            [0x1b, b'[', more @ ..] => {
+                let end = more
+                    .iter()
+                    .position(|b| !b.is_ascii_digit() && *b != b';')
+                    .map_or(more.len(), |end| end + 1);
+
+                (None, 2 + end)
+            }
+            [0x1b] | [0x1b, 0x1b, ..] => (Some(Key::Abort), 1),
+            [0x1b, ..] => (None, 2),
+            [0x03 | 0x07, ..] => (Some(Key::Abort), 1),
+            [b'\r' | b'\n', ..] => (Some(Key::Accept), 1),
+            [0x7f | 0x08, ..] => (Some(Key::Backspace), 1),
+            [0x15, ..] => (Some(Key::ClearQuery), 1),
+            [0x17, ..] => (Some(Key::DeleteWord), 1),
+            [0x10 | 0x0b, ..] => (Some(Key::Up), 1),
+            [0x0e, ..] => (Some(Key::Down), 1),
+            [b'\t', ..] => (Some(Key::Toggle), 1),
+            [byte, ..] if *byte < 0x20 => (None, 1),
+            _ => {
+                let length = match rest[0] {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                }
+                .min(rest.len());
+
+                let key = std::str::from_utf8(&rest[..length])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map(Key::Char);
+
+                (key, length)
+            }
+        };
+
+        keys.extend(key);
+        i += length;
+    }
+
+    keys
+}
+
+#[derive(Default)]
+struct Picker {
+    query: String,
+    candidates: Vec<PathBuf>,
+    // The index of each candidate that matches, with its score, best first.
+    matches: Vec<(i64, usize)>,
+    cursor: usize,
+    scroll: usize,
+    marked: BTreeSet<usize>,
+    walking: bool,
+}
+
+// How long to wait for a key before checking for more entries, and how often
+// the screen is redrawn while they're coming in and nothing's being typed.
+const TICK: Duration = Duration::from_millis(30);
+
+impl Picker {
+    fn run(
+        &mut self,
+        tty: &mut File,
+        entries: &crossbeam_channel::Receiver<PathBuf>,
+    ) -> std::io::Result<Option<Vec<PathBuf>>> {
+        let mut size = window_size(tty);
+        let mut drawn = Instant::now() - TICK;
+        let mut dirty = true;
+        let mut buffer = [0u8; 1024];
+
+        self.walking = true;
+
+        loop {
+            let mut poll = libc::pollfd {
+                fd: tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            let ready = unsafe { libc::poll(&mut poll, 1, TICK.as_millis() as i32) };
+
+            if ready > 0 {
+                let read = tty.read(&mut buffer)?;
+
+                for key in keys(&buffer[..read]) {
+                    match key {
+                        Key::Abort => return Ok(None),
+                        Key::Accept => return Ok(self.picked()),
+                        key => self.press(key, size.0),
+                    }
+                }
+
+                dirty = true;
+            }
+
+            // Entries are matched as they come in, against whatever's been
+            // typed so far, and sorted in among the others.
+            let arrived = self.candidates.len();
+
+            loop {
+                match entries.try_recv() {
+                    Ok(path) => self.candidates.push(path),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        dirty |= self.walking;
+                        self.walking = false;
+                        break;
+                    }
+                }
+            }
+
+            if self.candidates.len() > arrived {
+                self.rematch(arrived..self.candidates.len());
+                dirty = true;
+            }
+
+            let resized = window_size(tty);
+            dirty |= resized != size;
+            size = resized;
+
+            if dirty && drawn.elapsed() >= TICK {
+                self.draw(tty, size)?;
+                drawn = Instant::now();
+                dirty = false;
+            }
+        }
+    }
+
+    fn press(&mut self, key: Key, rows: usize) {
+        let page = rows.saturating_sub(1).max(1);
+
+        match key {
+            Key::Char(c) => {
+                self.query.push(c);
+                self.narrow();
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.rematch_all();
+            }
+            Key::ClearQuery => {
+                self.query.clear();
+                self.rematch_all();
+            }
+            Key::DeleteWord => {
+                let kept = self.query.trim_end().rfind([' ', '/']).map_or(0, |i| i + 1);
+                self.query.truncate(kept);
+                self.rematch_all();
+            }
+            Key::Up => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down => self.cursor += 1,
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            Key::PageDown => self.cursor += page,
+            Key::Toggle => {
+                if let Some(&(_, index)) = self.matches.get(self.cursor) {
+                    if !self.marked.remove(&index) {
+                        self.marked.insert(index);
+                    }
+                }
+
+                self.cursor += 1;
+            }
+            Key::Accept | Key::Abort => {}
+        }
+
+        self.cursor = self.cursor.min(self.matches.len().saturating_sub(1));
+    }
+
+    // Whatever's marked, or the one under the cursor if nothing is, or
+    // nothing at all if nothing matches, which isn't the same as giving up.
+    fn picked(&self) -> Option<Vec<PathBuf>> {
+        let indices: Vec<usize> = match self.marked.is_empty() {
+            false => self.marked.iter().copied().collect(),
+            true => self
+                .matches
+                .get(self.cursor)
+                .map(|&(_, i)| i)
+                .into_iter()
+                .collect(),
+        };
+
+        Some(
+            indices
+                .into_iter()
+                .map(|i| self.candidates[i].clone())
+                .collect(),
+        )
+    }
+
+    // A longer query only ever matches fewer of what matched already, so
+    // those are all that are scored again.
+    fn narrow(&mut self) {
+        let query = Query::new(&self.query);
+        let candidates = &self.candidates;
+
+        self.matches = self
+            .matches
+            .iter()
+            .filter_map(|&(_, i)| Some((query.score(&candidates[i].to_string_lossy())?, i)))
+            .collect();
+
+        self.sort();
+        self.cursor = 0;
+    }
+
+    fn rematch_all(&mut self) {
+        self.matches.clear();
+        self.rematch(0..self.candidates.len());
+        self.cursor = 0;
+    }
+
+    fn rematch(&mut self, range: std::ops::Range<usize>) {
+        let query = Query::new(&self.query);
+
+        for i in range {
+            if let Some(score) = query.score(&self.candidates[i].to_string_lossy()) {
+                self.matches.push((score, i));
+            }
+        }
+
+        self.sort();
+    }
+
+    // Best first, and in the order they were walked in among equals, which
+    // with nothing typed yet is all of them.
+    fn sort(&mut self) {
+        self.matches.sort_unstable_by_key(|&(score, i)| (-score, i));
+    }
+
+    // The query on the top line, with how many entries match it out of how
+    // many there are, and the matches under it, as many as fit, the one under
+    // the cursor in reverse video, and those that are marked with a *.
+    fn draw(&mut self, tty: &mut File, (rows, columns): (usize, usize)) -> std::io::Result<()> {
+        let visible = rows.saturating_sub(1).max(1);
+
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + visible {
+            self.scroll = self.cursor + 1 - visible;
+        }
+
+        let mut screen = String::from("\x1b[?25l\x1b[H");
+
+        let count = format!(
+            "  {}/{}{}",
+            self.matches.len(),
+            self.candidates.len(),
+            if self.walking { "..." } else { "" }
+        );
+
+        let prompt = format!("> {}", self.query);
+        screen.push_str(&fit(&prompt, columns.saturating_sub(count.len())));
+        screen.push_str(&count);
+        screen.push_str("\x1b[K");
+
+        for row in 0..visible {
+            screen.push_str("\r\n");
+
+            let Some(&(_, index)) = self.matches.get(self.scroll + row) else {
+                screen.push_str("\x1b[K");
+                continue;
+            };
+
+            let mark = if self.marked.contains(&index) {
+                "* "
+            } else {
+                "  "
+            };
+            let path = self.candidates[index].to_string_lossy();
+            let line = format!("{}{}", mark, fit(&path, columns.saturating_sub(2)));
+
+            match self.scroll + row == self.cursor {
+                true => screen.push_str(&format!("\x1b[7m{}\x1b[0m\x1b[K", line)),
+                false => screen.push_str(&format!("{}\x1b[K", line)),
+            }
+        }
+
+        let column = prompt
+            .chars()
+            .count()
+            .min(columns.saturating_sub(count.len()))
+            + 1;
+        screen.push_str(&format!("\x1b[1;{}H\x1b[?25h", column));
+
+        tty.write_all(screen.as_bytes())?;
+        tty.flush()
+    }
+}
+
+// The end of a path says more about it than the start, so that's what's kept
+// of one too long to fit, after an ellipsis.
+pub(crate) fn fit(text: &str, columns: usize) -> String {
+    let length = text.chars().count();
+
+    if length <= columns {
+        return text.to_string();
+    }
+
+    let kept: String = text.chars().skip(length + 1 - columns.max(1)).collect();
+    format!("…{}", kept)
+}
+
+pub(crate) fn window_size(tty: &File) -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_row == 0
+    {
+        return (24, 80);
+    }
+
+    (size.ws_row as usize, size.ws_col as usize)
+}
+
+// What's typed, matched the way fzf matches it: every character of it has to
+// be in the path, in that order, but not necessarily next to each other.
+// It's case insensitive unless there's an uppercase letter in it. Apart from
+// matching at all, it's better to match the characters in a run, just after a
+// /, or at the start of a word, and in a shorter path.
+struct Query {
+    chars: Vec<char>,
+    sensitive: bool,
+}
+
+impl Query {
+    fn new(query: &str) -> Self {
+        Self {
+            chars: query.chars().filter(|c| !c.is_whitespace()).collect(),
+            sensitive: query.chars().any(char::is_uppercase),
+        }
+    }
+
+    fn score(&self, candidate: &str) -> Option<i64> {
+        if self.chars.is_empty() {
+            return Some(0);
+        }
+
+        let mut wanted = self.chars.iter().peekable();
+        let mut score = 0i64;
+        let mut previous: Option<char> = None;
+        let mut run = 0i64;
+        let mut first = None;
+
+        for (i, c) in candidate.chars().enumerate() {
+            let Some(&&want) = wanted.peek() else {
+                break;
+            };
+
+            let same = match self.sensitive {
+                true => c == want,
+                false => c.to_lowercase().eq(want.to_lowercase()),
+            };
+
+            if same {
+                wanted.next();
+                first.get_or_insert(i);
+                run += 1;
+                score += 16 + 4 * (run - 1);
+
+                if previous.is_none_or(|p| matches!(p, '/' | '_' | '-' | '.' | ' ')) {
+                    score += 8;
+                }
+            } else {
+                run = 0;
+
+                if first.is_some() {
+                    score -= 1;
+                }
+            }
+
+            previous = Some(c);
+        }
+
+        if wanted.peek().is_some() {
+            return None;
+        }
+
+        Some(score - candidate.len() as i64 / 8)
+    }
+}
@@ -0,0 +1,426 @@
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+
+use crate::output::SizeStyle;
+
+// A file a thread is hashing, and since when.
+pub type Working = (PathBuf, Instant);
+
+// Counts what a run has gotten through so far, from whichever threads do the
+// work. Entries are what the walk has produced, files and bytes what has been
+// hashed, so in checksum mode all three move, otherwise only entries.
+#[derive(Debug)]
+pub struct Progress {
+    checksum: bool,
+    every: Option<u64>,
+    started: Instant,
+    entries: AtomicU64,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    totals: OnceLock<(u64, u64, Instant)>,
+    current: Mutex<Option<PathBuf>>,
+    wants_current: AtomicBool,
+    workers: OnceLock<Vec<Mutex<Option<Working>>>>,
+}
+
+impl Progress {
+    // With every, a line is printed each time that many more entries have
+    // been walked, or files hashed in checksum mode.
+    pub fn new(checksum: bool, every: Option<u64>) -> Self {
+        Self {
+            checksum,
+            every,
+            started: Instant::now(),
+            entries: AtomicU64::new(0),
+            files: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            totals: OnceLock::new(),
+            current: Mutex::new(None),
+            wants_current: AtomicBool::new(false),
+            workers: OnceLock::new(),
+        }
+    }
+
+    // For --tui, which shows what each of the hashing threads is on, and
+    // since when, so a file that's taking forever stands out. It's a slot per
+    // thread in the pool, rather than a map, so no thread waits on another.
+    pub fn track_workers(&self, threads: usize) {
+        let _ = self
+            .workers
+            .set((0..threads).map(|_| Mutex::new(None)).collect());
+    }
+
+    pub fn workers(&self) -> Option<Vec<Option<Working>>> {
+        let workers = self.workers.get()?;
+        Some(
+            workers
+                .iter()
+                .map(|slot| slot.lock().unwrap().clone())
+                .collect(),
+        )
+    }
+
+    fn worker(&self) -> Option<&Mutex<Option<Working>>> {
+        self.workers.get()?.get(rayon::current_thread_index()?)
+    }
+
+    // The path the walk, or the hashing, was last at. Without hashing, it's
+    // only filled in by the next entry walked, so it's that of a moment ago,
+    // which is as good as it gets for something redrawn every so often.
+    pub fn current(&self) -> Option<PathBuf> {
+        if !self.checksum {
+            self.wants_current.store(true, Ordering::Relaxed);
+        }
+
+        self.current.lock().unwrap().clone()
+    }
+
+    // Walking is too quick for every entry's path to be put aside in case a
+    // snapshot asks for it, so it's only done once one has.
+    pub fn entry(&self, path: impl FnOnce() -> PathBuf) {
+        if self.wants_current.load(Ordering::Relaxed)
+            && self.wants_current.swap(false, Ordering::Relaxed)
+        {
+            *self.current.lock().unwrap() = Some(path());
+        }
+
+        let before = self.entries.fetch_add(1, Ordering::Relaxed);
+
+        if !self.checksum {
+            self.counted(before, before + 1);
+        }
+    }
+
+    pub fn hashed(&self, files: usize) {
+        if let Some(worker) = self.worker() {
+            *worker.lock().unwrap() = None;
+        }
+
+        let before = self.files.fetch_add(files as u64, Ordering::Relaxed);
+
+        if self.checksum {
+            self.counted(before, before + files as u64);
+        }
+    }
+
+    // What a run produced, which in checksum mode is the files it hashed,
+    // and otherwise the entries it walked and kept.
+    pub fn matched(&self) -> u64 {
+        match self.checksum {
+            true => self.files.load(Ordering::Relaxed),
+            false => self.entries.load(Ordering::Relaxed),
+        }
+    }
+
+    // The file about to be hashed, which is always kept track of, since
+    // taking a lock costs nothing next to opening and reading the file.
+    pub fn at(&self, path: &Path) {
+        if let Some(worker) = self.worker() {
+            *worker.lock().unwrap() = Some((path.to_path_buf(), Instant::now()));
+        }
+
+        let mut current = self.current.lock().unwrap();
+
+        match current.as_mut() {
+            Some(current) => {
+                current.as_mut_os_string().clear();
+                current.push(path);
+            }
+            None => *current = Some(path.to_path_buf()),
+        }
+    }
+
+    fn counted(&self, before: u64, after: u64) {
+        if let Some(every) = self.every {
+            if before / every != after / every {
+                self.print();
+            }
+        }
+    }
+
+    fn snapshot(&self) {
+        if !self.checksum {
+            // Whoever walks the next entry fills it in, if that happens soon.
+            self.wants_current.store(true, Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(50));
+            self.wants_current.store(false, Ordering::Relaxed);
+        }
+
+        let mut line = self.line();
+
+        if let Some(current) = self.current.lock().unwrap().as_ref() {
+            line.push_str(&format!(", at {}", current.display()));
+        }
+
+        let mut stderr = std::io::stderr().lock();
+        clear_line(&mut stderr);
+        let _ = writeln!(stderr, "{}", line);
+    }
+
+    pub fn print(&self) {
+        let mut stderr = std::io::stderr().lock();
+        clear_line(&mut stderr);
+        let _ = writeln!(stderr, "{}", self.line());
+    }
+
+    // Bytes are counted as they're read, rather than once a file is done, so
+    // the count keeps moving through big files.
+    pub fn read(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    // The number of files and bytes there are to hash, from a pre-scan. The
+    // clock starts over once they're known, so the time it took to count
+    // doesn't drag down the rates.
+    pub fn set_totals(&self, files: u64, bytes: u64) {
+        let _ = self.totals.set((files, bytes, Instant::now()));
+    }
+
+    pub fn line(&self) -> String {
+        let elapsed = match self.totals.get() {
+            Some(&(_, _, started)) => started.elapsed(),
+            None => self.started.elapsed(),
+        };
+
+        let seconds = elapsed.as_secs_f64().max(0.001);
+        let entries = self.entries.load(Ordering::Relaxed);
+
+        if !self.checksum {
+            return format!(
+                "{} entries in {}, {:.0} entries/s",
+                entries,
+                format_duration(elapsed),
+                entries as f64 / seconds,
+            );
+        }
+
+        let files = self.files.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let rate = bytes as f64 / seconds;
+
+        let mut line = format!(
+            "{} files, {} hashed in {}, {}/s",
+            files,
+            SizeStyle::Binary.format(bytes),
+            format_duration(elapsed),
+            SizeStyle::Binary.format(rate as u64),
+        );
+
+        if let Some(&(total_files, total_bytes, _)) = self.totals.get() {
+            // Going by bytes where there are any, since those are what the
+            // time goes into; by files for a tree of nothing but empty ones.
+            let done = if total_bytes > 0 {
+                (bytes as f64 / total_bytes as f64).min(1.0)
+            } else {
+                (files as f64 / total_files.max(1) as f64).min(1.0)
+            };
+
+            let filled = (done * 20.0) as usize;
+
+            line.insert_str(
+                0,
+                &format!(
+                    "[{}{}] {:>3.0}% ",
+                    "#".repeat(filled),
+                    "-".repeat(20 - filled),
+                    done * 100.0
+                ),
+            );
+
+            if done > 0.0 {
+                let remaining = seconds * (1.0 - done) / done;
+                line.push_str(&format!(
+                    ", ETA {}",
+                    format_duration(Duration::from_secs_f64(remaining))
+                ));
+            }
+        }
+
+        line
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+    }
+}
+
+// Sent SIGUSR1, or SIGINFO (Ctrl+T) where there is one, jw prints a snapshot
+// of where it's at to stderr, whatever else it's been told to show. The handler
+// can't do much more than write to a pipe, so a thread of its own waits on the
+// other end of that pipe to do the printing.
+#[cfg(unix)]
+pub fn print_on_signal(progress: Arc<Progress>) {
+    use std::sync::atomic::AtomicI32;
+
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handler(_: libc::c_int) {
+        let fd = PIPE.load(Ordering::Relaxed);
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return;
+    }
+
+    PIPE.store(fds[1], Ordering::Relaxed);
+
+    let signals = [
+        libc::SIGUSR1,
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "dragonfly"
+        ))]
+        libc::SIGINFO,
+    ];
+
+    for signal in signals {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // Reads being hashed shouldn't be interrupted by it.
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+
+    thread::spawn(move || {
+        let mut byte = 0u8;
+
+        while unsafe { libc::read(fds[0], (&mut byte as *mut u8).cast(), 1) } == 1 {
+            progress.snapshot();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn print_on_signal(_: Arc<Progress>) {}
+
+// Whether there's a progress line on the terminal that anything else printed
+// to stderr would end up tacked onto.
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+// For whatever else prints to stderr while the progress line is up. The
+// stderr lock should be held across this and the printing, since the line
+// would otherwise get redrawn in between.
+pub fn clear_line(stderr: &mut impl Write) {
+    if DRAWN.swap(false, Ordering::Relaxed) {
+        let _ = write!(stderr, "\r\x1b[K");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Every {
+    Entries(u64),
+    Interval(Duration),
+}
+
+// Either a plain count, or a number followed by one of ms, s, m or h.
+pub fn parse_every(s: &str) -> Result<Every, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| "expected a number, optionally followed by ms, s, m or h".to_string())?;
+
+    if number == 0 {
+        return Err("must be above 0".to_string());
+    }
+
+    match unit {
+        "" => Ok(Every::Entries(number)),
+        "ms" => Ok(Every::Interval(Duration::from_millis(number))),
+        "s" => Ok(Every::Interval(Duration::from_secs(number))),
+        "m" => Ok(Every::Interval(Duration::from_secs(number * 60))),
+        "h" => Ok(Every::Interval(Duration::from_secs(number * 3600))),
+        _ => Err(format!("unknown unit '{}', expected ms, s, m or h", unit)),
+    }
+}
+
+// Draws the progress line on stderr while it's alive, so it never gets mixed
+// into a manifest being redirected from stdout. On a terminal the line is
+// redrawn in place, and cleared at the end; otherwise a line is printed every
+// few seconds, with one last one when the run is done. Given an interval, it
+// just prints a line every interval, wherever stderr goes.
+pub struct Display {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Display {
+    pub fn start(progress: Arc<Progress>, interval: Option<Duration>) -> Self {
+        let (stop, stopped) = bounded::<()>(0);
+        let terminal = interval.is_none() && std::io::stderr().is_terminal();
+
+        let interval = interval.unwrap_or(if terminal {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(5)
+        });
+
+        let thread = thread::spawn(move || loop {
+            let finished = !matches!(
+                stopped.recv_timeout(interval),
+                Err(RecvTimeoutError::Timeout)
+            );
+
+            if !terminal {
+                progress.print();
+            } else if finished {
+                clear_line(&mut std::io::stderr().lock());
+            } else {
+                let mut stderr = std::io::stderr().lock();
+                clear_line(&mut stderr);
+                let _ = write!(stderr, "{}", progress.line());
+                DRAWN.store(true, Ordering::Relaxed);
+            }
+
+            if finished {
+                break;
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
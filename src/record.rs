@@ -0,0 +1,367 @@
+use std::cell::OnceCell;
+use std::fs::{FileType, Metadata};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Entry;
+
+// Everything that gets printed or filtered on goes through a Record, so the
+// path is only joined once and metadata is only fetched if something asks.
+pub struct Record<'a> {
+    pub entry: &'a Entry,
+    pub hash: Option<&'a str>,
+    path: OnceCell<PathBuf>,
+    metadata: OnceCell<Option<Metadata>>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(entry: &'a Entry, hash: Option<&'a str>) -> Self {
+        Self {
+            entry,
+            hash,
+            path: OnceCell::new(),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    // For what's listed under a path of its own, rather than the entry's,
+    // like an alternate data stream, which goes by file:stream.
+    pub fn at(entry: &'a Entry, path: PathBuf, hash: Option<&'a str>) -> Self {
+        Self {
+            entry,
+            hash,
+            path: OnceCell::from(path),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        self.path.get_or_init(|| self.entry.path())
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.entry.client_state.as_deref().or_else(|| {
+            self.metadata
+                .get_or_init(|| self.entry.metadata().ok())
+                .as_ref()
+        })
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.entry.file_type())
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.metadata().map(|m| m.len())
+    }
+
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.metadata().and_then(|m| m.modified().ok())
+    }
+
+    pub fn atime(&self) -> Option<SystemTime> {
+        self.metadata().and_then(|m| m.accessed().ok())
+    }
+
+    pub fn btime(&self) -> Option<SystemTime> {
+        self.metadata().and_then(|m| m.created().ok())
+    }
+
+    // Status change time on Unix; Windows has no such thing, so creation
+    // time is the closest stand in.
+    #[cfg(unix)]
+    pub fn ctime(&self) -> Option<SystemTime> {
+        use std::os::unix::fs::MetadataExt;
+
+        self.metadata()
+            .map(|m| from_unix_seconds(m.ctime(), m.ctime_nsec() as u32))
+    }
+
+    #[cfg(not(unix))]
+    pub fn ctime(&self) -> Option<SystemTime> {
+        self.btime()
+    }
+
+    #[cfg(unix)]
+    pub fn uid(&self) -> Option<u32> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::uid)
+    }
+
+    #[cfg(unix)]
+    pub fn gid(&self) -> Option<u32> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::gid)
+    }
+
+    #[cfg(unix)]
+    pub fn mode(&self) -> Option<u32> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::mode)
+    }
+
+    #[cfg(unix)]
+    pub fn ino(&self) -> Option<u64> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::ino)
+    }
+
+    #[cfg(unix)]
+    pub fn dev(&self) -> Option<u64> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::dev)
+    }
+
+    #[cfg(unix)]
+    pub fn nlink(&self) -> Option<u64> {
+        self.metadata().map(std::os::unix::fs::MetadataExt::nlink)
+    }
+
+    #[cfg(not(unix))]
+    pub fn uid(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn gid(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn mode(&self) -> Option<u32> {
+        None
+    }
+
+    // Windows does have file indices and volume serial numbers, but std only
+    // exposes them on nightly.
+    #[cfg(not(unix))]
+    pub fn ino(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn dev(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn nlink(&self) -> Option<u64> {
+        None
+    }
+}
+
+pub fn type_name(file_type: FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+// Same as ls -l, e.g. drwxr-sr-x
+pub fn mode_string(mode: u32, file_type: FileType) -> String {
+    let mut string = String::with_capacity(10);
+
+    string.push(if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_dir() {
+        'd'
+    } else if file_type.is_file() {
+        '-'
+    } else {
+        special_type_char(file_type)
+    });
+
+    let special = [(0o4000, 's', 'S'), (0o2000, 's', 'S'), (0o1000, 't', 'T')];
+
+    for (i, (bit, set, unset)) in special.iter().enumerate() {
+        let shift = 6 - i * 3;
+        let bits = (mode >> shift) & 0o7;
+
+        string.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        string.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+
+        string.push(match (bits & 0o1 != 0, mode & bit != 0) {
+            (true, true) => *set,
+            (false, true) => *unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    string
+}
+
+#[cfg(unix)]
+fn special_type_char(file_type: FileType) -> char {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        '?'
+    }
+}
+
+#[cfg(not(unix))]
+fn special_type_char(_file_type: FileType) -> char {
+    '?'
+}
+
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+pub fn from_unix_seconds(seconds: i64, nanoseconds: u32) -> SystemTime {
+    let since_epoch = Duration::new(seconds.unsigned_abs(), nanoseconds);
+
+    if seconds >= 0 {
+        UNIX_EPOCH + since_epoch
+    } else {
+        UNIX_EPOCH - since_epoch
+    }
+}
+
+// Howard Hinnant's days_from_civil; days since 1970-01-01.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// And its inverse, civil_from_days; (year, month, day) from days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// (year, month, day, hour, minute, second) in UTC.
+pub fn utc_fields(time: SystemTime) -> (i64, i64, i64, i64, i64, i64) {
+    let seconds = unix_seconds(time);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let of_day = seconds.rem_euclid(86400);
+    (
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60,
+    )
+}
+
+pub fn iso8601(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_fields(time);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+#[cfg(unix)]
+thread_local! {
+    static USER_NAMES: std::cell::RefCell<std::collections::HashMap<u32, Option<String>>> =
+        Default::default();
+    static GROUP_NAMES: std::cell::RefCell<std::collections::HashMap<u32, Option<String>>> =
+        Default::default();
+}
+
+// Lookups are cached per thread; there's rarely more than a handful of
+// distinct owners in a tree, and getpwuid_r isn't cheap.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    USER_NAMES.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(uid)
+            .or_insert_with(|| {
+                let mut buffer = vec![0 as libc::c_char; 4096];
+                let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+                let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+                let status = unsafe {
+                    libc::getpwuid_r(
+                        uid,
+                        &mut passwd,
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        &mut result,
+                    )
+                };
+
+                (status == 0 && !result.is_null()).then(|| unsafe {
+                    std::ffi::CStr::from_ptr(passwd.pw_name)
+                        .to_string_lossy()
+                        .into_owned()
+                })
+            })
+            .clone()
+    })
+}
+
+#[cfg(unix)]
+pub fn group_name(gid: u32) -> Option<String> {
+    GROUP_NAMES.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(gid)
+            .or_insert_with(|| {
+                let mut buffer = vec![0 as libc::c_char; 4096];
+                let mut group: libc::group = unsafe { std::mem::zeroed() };
+                let mut result: *mut libc::group = std::ptr::null_mut();
+
+                let status = unsafe {
+                    libc::getgrgid_r(
+                        gid,
+                        &mut group,
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        &mut result,
+                    )
+                };
+
+                (status == 0 && !result.is_null()).then(|| unsafe {
+                    std::ffi::CStr::from_ptr(group.gr_name)
+                        .to_string_lossy()
+                        .into_owned()
+                })
+            })
+            .clone()
+    })
+}
+
+#[cfg(not(unix))]
+pub fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn group_name(_gid: u32) -> Option<String> {
+    None
+}
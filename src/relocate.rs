@@ -0,0 +1,347 @@
+use std::fs::{File, FileType, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::mutate;
+use crate::verify::Verify;
+
+// Whether --copy-to or --move-to was given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Copy,
+    Move,
+}
+
+impl Mode {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Self::Copy => "Copied",
+            Self::Move => "Moved",
+        }
+    }
+}
+
+// What happens when something is already where an entry would go.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collision {
+    Skip,
+    Overwrite,
+    // Next to it, as name.1.ext, name.2.ext and so on.
+    Rename,
+}
+
+impl From<&String> for Collision {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "skip" => Self::Skip,
+            "overwrite" => Self::Overwrite,
+            "rename" => Self::Rename,
+            _ => panic!("Invalid collision policy! '{}'", s),
+        }
+    }
+}
+
+pub enum Placed {
+    // Directories, which are only ever created to hold what's put in them,
+    // the root itself, and anything already in the destination.
+    Ignored,
+    Collided,
+    Done(PathBuf, u64),
+}
+
+// Copies or moves the entries of a root to the same paths under the
+// destination as they have under the root, for --copy-to and --move-to.
+pub struct Relocate<'a> {
+    mode: Mode,
+    collision: Collision,
+    destination: PathBuf,
+    root: PathBuf,
+    // Where the destination is within the root, when it's inside the very tree
+    // being walked, so what's already been put there isn't put there again.
+    inside: Option<PathBuf>,
+    verify: Option<&'a Verify>,
+}
+
+impl<'a> Relocate<'a> {
+    pub fn new(
+        mode: Mode,
+        collision: Collision,
+        destination: &Path,
+        root: &Path,
+        verify: Option<&'a Verify>,
+    ) -> io::Result<Self> {
+        mutate::create_dir_all(destination)?;
+
+        let canonical_root = std::fs::canonicalize(root)?;
+
+        // A destination that doesn't exist yet, with --dry-run, has nothing in
+        // it to leave out.
+        let inside = std::fs::canonicalize(destination).ok().and_then(|dest| {
+            dest.strip_prefix(&canonical_root)
+                .ok()
+                .map(Path::to_path_buf)
+        });
+
+        Ok(Self {
+            mode,
+            collision,
+            destination: destination.to_path_buf(),
+            root: root.to_path_buf(),
+            inside,
+            verify,
+        })
+    }
+
+    pub fn place(&self, path: &Path, file_type: FileType) -> io::Result<Placed> {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Ok(Placed::Ignored);
+        };
+
+        let in_destination = self
+            .inside
+            .as_ref()
+            .is_some_and(|inside| relative.starts_with(inside));
+
+        if file_type.is_dir() || relative.as_os_str().is_empty() || in_destination {
+            return Ok(Placed::Ignored);
+        }
+
+        let target = self.destination.join(relative);
+
+        if let Some(parent) = target.parent() {
+            mutate::create_dir_all(parent)?;
+        }
+
+        place_at(
+            self.mode,
+            self.collision,
+            path,
+            &target,
+            file_type,
+            self.verify,
+        )
+    }
+}
+
+// Puts what's at path at target, or next to it, as the collision policy says.
+// Nothing is checked for up front; anything that's there is found out about
+// by failing to create the file, which leaves no room for another thread to
+// get there in between. With --verify-after, files are hashed before they're
+// put anywhere, since once moved, there's no original left to compare with.
+pub fn place_at(
+    mode: Mode,
+    collision: Collision,
+    path: &Path,
+    target: &Path,
+    file_type: FileType,
+    verify: Option<&Verify>,
+) -> io::Result<Placed> {
+    let Some(verify) = verify.filter(|_| file_type.is_file()) else {
+        return place_unverified(mode, collision, path, target, file_type);
+    };
+
+    let expected = verify.hash(path)?;
+    let placed = place_unverified(mode, collision, path, target, file_type)?;
+
+    if let Placed::Done(target, _) = &placed {
+        verify.check(target, &expected)?;
+    }
+
+    Ok(placed)
+}
+
+fn place_unverified(
+    mode: Mode,
+    collision: Collision,
+    path: &Path,
+    target: &Path,
+    file_type: FileType,
+) -> io::Result<Placed> {
+    match collision {
+        Collision::Overwrite => {
+            put(mode, path, target, file_type, true).map(|bytes| Placed::Done(target.into(), bytes))
+        }
+        Collision::Skip => match put(mode, path, target, file_type, false) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(Placed::Collided),
+            result => result.map(|bytes| Placed::Done(target.into(), bytes)),
+        },
+        Collision::Rename => {
+            for n in 0.. {
+                let candidate = numbered(target, n);
+
+                match put(mode, path, &candidate, file_type, false) {
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    result => return result.map(|bytes| Placed::Done(candidate, bytes)),
+                }
+            }
+
+            unreachable!()
+        }
+    }
+}
+
+// A move within a filesystem is a rename, and across filesystems, a copy
+// followed by removing the original, only once the copy is complete.
+fn put(
+    mode: Mode,
+    from: &Path,
+    to: &Path,
+    file_type: FileType,
+    overwrite: bool,
+) -> io::Result<u64> {
+    let pretend = || {
+        if !overwrite {
+            mutate::vacant(to)?;
+        }
+
+        Ok(from.symlink_metadata()?.len())
+    };
+
+    mutate::perform(pretend, || match mode {
+        Mode::Copy => copy(from, to, file_type, overwrite),
+        Mode::Move => match rename(from, to, overwrite) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let bytes = copy(from, to, file_type, overwrite)?;
+                std::fs::remove_file(from)?;
+                Ok(bytes)
+            }
+            result => result,
+        },
+    })
+}
+
+// Symlinks are copied as symlinks, pointing to the same thing, rather than
+// followed. Files keep their permissions and their mtime.
+fn copy(from: &Path, to: &Path, file_type: FileType, overwrite: bool) -> io::Result<u64> {
+    if file_type.is_symlink() {
+        let target = std::fs::read_link(from)?;
+
+        if overwrite {
+            match std::fs::remove_file(to) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        symlink(&target, to)?;
+        return Ok(0);
+    }
+
+    if !file_type.is_file() {
+        return Err(io::Error::other("not a regular file or a symlink"));
+    }
+
+    let mut source = File::open(from)?;
+    let metadata = source.metadata()?;
+
+    let mut options = OpenOptions::new();
+
+    match overwrite {
+        true => options.write(true).create(true).truncate(true),
+        false => options.write(true).create_new(true),
+    };
+
+    let mut destination = options.open(to)?;
+
+    let copied = io::copy(&mut source, &mut destination).and_then(|bytes| {
+        destination.set_permissions(metadata.permissions())?;
+        destination.set_modified(metadata.modified()?)?;
+        Ok(bytes)
+    });
+
+    // Rather than leave half a file behind under the name of a whole one.
+    if copied.is_err() {
+        let _ = std::fs::remove_file(to);
+    }
+
+    copied
+}
+
+fn rename(from: &Path, to: &Path, overwrite: bool) -> io::Result<u64> {
+    let bytes = from.symlink_metadata()?.len();
+
+    if overwrite {
+        return std::fs::rename(from, to).map(|_| bytes);
+    }
+
+    rename_noreplace(from, to).map(|_| bytes)
+}
+
+// A plain rename replaces whatever's there, so without overwriting, it's
+// renameat2 with RENAME_NOREPLACE, where the filesystem supports it, and
+// otherwise a check right before, which is as close as other systems get.
+#[cfg(target_os = "linux")]
+fn rename_noreplace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_from = CString::new(from.as_os_str().as_bytes())?;
+    let c_to = CString::new(to.as_os_str().as_bytes())?;
+
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_from.as_ptr(),
+            libc::AT_FDCWD,
+            c_to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EINVAL) => checked_rename(from, to),
+            e => Err(e),
+        },
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_noreplace(from: &Path, to: &Path) -> io::Result<()> {
+    checked_rename(from, to)
+}
+
+fn checked_rename(from: &Path, to: &Path) -> io::Result<()> {
+    if to.symlink_metadata().is_ok() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    std::fs::rename(from, to)
+}
+
+// The number goes before the extension, if there is one, so the file still
+// opens with whatever it opened with before.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks aren't supported on this platform",
+    ))
+}
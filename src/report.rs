@@ -0,0 +1,489 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::dupes::{self, Candidate, Group};
+use crate::hashutil::ReadOptions;
+use crate::output::SizeStyle;
+use crate::record::{group_name, iso8601, user_name};
+use crate::stats::Errors;
+use crate::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportKind {
+    Extensions,
+    Sizes,
+    Largest(usize),
+    Newest(usize),
+    Oldest(usize),
+    Duplicates,
+    Ages,
+    Owners,
+    Depth,
+}
+
+impl From<&String> for ReportKind {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "extensions" => Self::Extensions,
+            "sizes" => Self::Sizes,
+            "duplicates" => Self::Duplicates,
+            "ages" => Self::Ages,
+            "owners" => Self::Owners,
+            "depth" => Self::Depth,
+            _ => panic!("Invalid report! '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tally {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+// Summaries of the files in a tree, tallied up as the walk goes, from the
+// metadata it prefetches, and printed along with the stats at the end. Only
+// regular files count, other than for depths; a symlink is reported as
+// whatever it is, not what it points to.
+pub enum Report {
+    // Extensions are compared case insensitively, so .JPG and .jpg are the
+    // same, and files without one are tallied under an empty string.
+    Extensions(HashMap<String, Tally>),
+    // Files by size, a bucket for each power of 16 from 4K up to 1G.
+    Sizes([Tally; SIZE_BUCKETS.len() + 1]),
+    Largest(Top),
+    Newest(Top),
+    // Ranked by their negated mtimes, so the oldest come out on top.
+    Oldest(Top),
+    Duplicates(Duplicates),
+    // Files by how long ago they were last modified, as of when the walk
+    // started, so files touched during it all count as from the last day.
+    Ages(SystemTime, [Tally; AGE_BUCKETS.len() + 1]),
+    // Files by the user and by the group that own them, by ID, which are only
+    // looked up by name once it's time to print them. There's nothing to go
+    // by on platforms without Unix ownership.
+    Owners(HashMap<u32, Tally>, HashMap<u32, Tally>),
+    // Entries of every kind at each depth, the root being at 0.
+    Depth(Vec<u64>),
+}
+
+// Every file that could have a duplicate is held on to until the end, when
+// they're compared, since any two of them could be anywhere in the tree.
+pub struct Duplicates {
+    candidates: Vec<Candidate>,
+    read: ReadOptions,
+    errors: Arc<Errors>,
+}
+
+// How many of the groups that waste the most are listed.
+const DUPLICATE_GROUPS: usize = 10;
+
+// The n files with the highest keys seen so far, in a heap that never holds
+// more than that, with the lowest on top to be pushed out next. Sorting every
+// file just to keep a handful would hold on to all of them until the end. A
+// file's path is only put together once it's known to make the cut.
+pub struct Top {
+    n: usize,
+    heap: BinaryHeap<Reverse<(i128, PathBuf)>>,
+}
+
+impl Top {
+    fn new(n: usize) -> Self {
+        Self {
+            n,
+            heap: BinaryHeap::with_capacity(n + 1),
+        }
+    }
+
+    fn offer(&mut self, key: i128, entry: &Entry) {
+        if self.n == 0 {
+            return;
+        }
+
+        if self.heap.len() == self.n {
+            match self.heap.peek() {
+                Some(Reverse((lowest, _))) if key <= *lowest => return,
+                _ => {
+                    self.heap.pop();
+                }
+            }
+        }
+
+        self.heap.push(Reverse((key, entry.path())));
+    }
+
+    // Highest first.
+    fn sorted(&self) -> Vec<&(i128, PathBuf)> {
+        let mut top: Vec<_> = self.heap.iter().map(|Reverse(item)| item).collect();
+        top.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        top
+    }
+}
+
+// The upper bound of each bucket, with one more for everything from 1G up.
+const SIZE_BUCKETS: [(u64, &str); 6] = [
+    (4 << 10, "< 4K"),
+    (64 << 10, "4K - 64K"),
+    (1 << 20, "64K - 1M"),
+    (16 << 20, "1M - 16M"),
+    (256 << 20, "16M - 256M"),
+    (1 << 30, "256M - 1G"),
+];
+
+const DAY: u64 = 24 * 60 * 60;
+
+// The upper bound of each bucket, with one more for everything older.
+const AGE_BUCKETS: [(u64, &str); 4] = [
+    (DAY, "Last day"),
+    (7 * DAY, "Last week"),
+    (30 * DAY, "Last month"),
+    (365 * DAY, "Last year"),
+];
+
+impl Report {
+    pub fn new(kind: &ReportKind, read: &ReadOptions, errors: &Arc<Errors>) -> Self {
+        match kind {
+            ReportKind::Extensions => Self::Extensions(HashMap::new()),
+            ReportKind::Sizes => Self::Sizes(Default::default()),
+            ReportKind::Largest(n) => Self::Largest(Top::new(*n)),
+            ReportKind::Newest(n) => Self::Newest(Top::new(*n)),
+            ReportKind::Oldest(n) => Self::Oldest(Top::new(*n)),
+            ReportKind::Duplicates => Self::Duplicates(Duplicates {
+                candidates: Vec::new(),
+                read: read.clone(),
+                errors: errors.clone(),
+            }),
+            ReportKind::Ages => Self::Ages(SystemTime::now(), Default::default()),
+            ReportKind::Owners => Self::Owners(HashMap::new(), HashMap::new()),
+            ReportKind::Depth => Self::Depth(Vec::new()),
+        }
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        if let Self::Depth(depths) = self {
+            if depths.len() <= entry.depth() {
+                depths.resize(entry.depth() + 1, 0);
+            }
+
+            depths[entry.depth()] += 1;
+            return;
+        }
+
+        if !entry.file_type().is_file() {
+            return;
+        }
+
+        let bytes = entry.client_state.as_deref().map_or(0, |m| m.len());
+
+        match self {
+            Self::Extensions(extensions) => {
+                let extension = std::path::Path::new(entry.file_name())
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+
+                extensions.entry(extension).or_default().add(bytes);
+            }
+            Self::Sizes(buckets) => {
+                let bucket = SIZE_BUCKETS
+                    .iter()
+                    .position(|&(limit, _)| bytes < limit)
+                    .unwrap_or(SIZE_BUCKETS.len());
+
+                buckets[bucket].add(bytes);
+            }
+            Self::Largest(top) => top.offer(bytes as i128, entry),
+            Self::Newest(top) => {
+                if let Some(mtime) = mtime(entry) {
+                    top.offer(mtime, entry);
+                }
+            }
+            Self::Oldest(top) => {
+                if let Some(mtime) = mtime(entry) {
+                    top.offer(-mtime, entry);
+                }
+            }
+            Self::Duplicates(duplicates) => duplicates.candidates.extend(Candidate::of(entry, 0)),
+            Self::Ages(now, buckets) => {
+                let Some(modified) = entry
+                    .client_state
+                    .as_deref()
+                    .and_then(|m| m.modified().ok())
+                else {
+                    return;
+                };
+
+                let age = now.duration_since(modified).unwrap_or_default().as_secs();
+
+                let bucket = AGE_BUCKETS
+                    .iter()
+                    .position(|&(limit, _)| age < limit)
+                    .unwrap_or(AGE_BUCKETS.len());
+
+                buckets[bucket].add(bytes);
+            }
+            #[cfg(unix)]
+            Self::Owners(users, groups) => {
+                use std::os::unix::fs::MetadataExt;
+
+                if let Some(metadata) = entry.client_state.as_deref() {
+                    users.entry(metadata.uid()).or_default().add(bytes);
+                    groups.entry(metadata.gid()).or_default().add(bytes);
+                }
+            }
+            #[cfg(not(unix))]
+            Self::Owners(..) => {}
+            Self::Depth(_) => {}
+        }
+    }
+
+    pub fn render(&self, size_style: SizeStyle) -> String {
+        match self {
+            Self::Extensions(extensions) => {
+                let mut rows: Vec<(String, Tally)> = extensions
+                    .iter()
+                    .map(|(extension, &tally)| match extension.as_str() {
+                        "" => ("(none)".to_string(), tally),
+                        extension => (format!(".{}", extension), tally),
+                    })
+                    .collect();
+
+                // Biggest first, since that's where the space went.
+                rows.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+
+                table("Extensions", rows, size_style, false)
+            }
+            Self::Sizes(buckets) => {
+                let labels = SIZE_BUCKETS
+                    .iter()
+                    .map(|&(_, label)| label)
+                    .chain([">= 1G"]);
+
+                let rows = labels
+                    .zip(buckets)
+                    .map(|(label, &tally)| (label.to_string(), tally))
+                    .collect();
+
+                table("Sizes", rows, size_style, true)
+            }
+            Self::Largest(top) => listing(
+                "Largest files",
+                top.sorted()
+                    .into_iter()
+                    .map(|(bytes, path)| (size_style.format(*bytes as u64), path)),
+            ),
+            Self::Newest(top) => listing(
+                "Newest files",
+                top.sorted()
+                    .into_iter()
+                    .map(|(mtime, path)| (iso8601(from_nanos(*mtime)), path)),
+            ),
+            Self::Oldest(top) => listing(
+                "Oldest files",
+                top.sorted()
+                    .into_iter()
+                    .map(|(mtime, path)| (iso8601(from_nanos(-*mtime)), path)),
+            ),
+            Self::Duplicates(duplicates) => {
+                let groups =
+                    dupes::find(&duplicates.candidates, &duplicates.read, &duplicates.errors);
+                wasted(&groups, size_style)
+            }
+            Self::Ages(_, buckets) => {
+                let labels = AGE_BUCKETS.iter().map(|&(_, label)| label).chain(["Older"]);
+
+                let rows = labels
+                    .zip(buckets)
+                    .map(|(label, &tally)| (label.to_string(), tally))
+                    .collect();
+
+                table("Ages", rows, size_style, true)
+            }
+            Self::Owners(users, groups) => [
+                table("Users", owners(users, user_name), size_style, false),
+                table("Groups", owners(groups, group_name), size_style, false),
+            ]
+            .join("\n"),
+            Self::Depth(depths) => depth(depths),
+        }
+    }
+}
+
+// In nanoseconds, so files modified within the same second still rank in the
+// right order.
+fn mtime(entry: &Entry) -> Option<i128> {
+    let mtime = entry.client_state.as_deref()?.modified().ok()?;
+
+    Some(match mtime.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    })
+}
+
+fn from_nanos(nanos: i128) -> SystemTime {
+    let offset = Duration::from_secs((nanos.unsigned_abs() / 1_000_000_000) as u64);
+
+    if nanos < 0 {
+        UNIX_EPOCH - offset
+    } else {
+        UNIX_EPOCH + offset
+    }
+}
+
+// A line for every depth down to the deepest, even one with nothing at it,
+// which can happen when --filter leaves out everything at some level.
+fn depth(depths: &[u64]) -> String {
+    let total: u64 = depths.iter().sum();
+
+    let mut text = format!(
+        "Depths:\n  Deepest is {}, of {} entries.",
+        depths.len().saturating_sub(1),
+        total
+    );
+
+    let depth_width = depths.len().saturating_sub(1).to_string().len();
+    let count_width = depths.iter().max().map_or(0, |n| n.to_string().len());
+
+    for (depth, &count) in depths.iter().enumerate() {
+        let _ = write!(
+            text,
+            "\n  {:>depth_width$}  {:>count_width$} {:<7}  {:>5.1}%",
+            depth,
+            count,
+            if count == 1 { "entry" } else { "entries" },
+            100.0 * count as f64 / total.max(1) as f64,
+        );
+    }
+
+    text
+}
+
+// Labeled by name along with the ID, or just the ID when it has no name, and
+// biggest first, like extensions.
+fn owners(ids: &HashMap<u32, Tally>, name: fn(u32) -> Option<String>) -> Vec<(String, Tally)> {
+    let mut rows: Vec<(String, Tally)> = ids
+        .iter()
+        .map(|(&id, &tally)| match name(id) {
+            Some(name) => (format!("{} ({})", name, id), tally),
+            None => (id.to_string(), tally),
+        })
+        .collect();
+
+    rows.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+    rows
+}
+
+// The total that could be reclaimed, then the groups wasting the most of it,
+// each with how much that is, and the paths of all its copies.
+fn wasted(groups: &[Group], size_style: SizeStyle) -> String {
+    let mut text = format!("Duplicates:\n  {}", dupes::summary(groups, size_style));
+
+    let shown = &groups[..groups.len().min(DUPLICATE_GROUPS)];
+
+    let sizes: Vec<String> = shown
+        .iter()
+        .map(|group| size_style.format(group.wasted()))
+        .collect();
+
+    let width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+    for (group, size) in shown.iter().zip(sizes) {
+        let _ = write!(
+            text,
+            "\n  {:>width$}  {} copies of {}",
+            size,
+            group.files.len(),
+            size_style.format(group.size)
+        );
+
+        for file in &group.files {
+            let _ = write!(text, "\n    {}", file.path.display());
+        }
+    }
+
+    if groups.len() > shown.len() {
+        let _ = write!(text, "\n  and {} more groups.", groups.len() - shown.len());
+    }
+
+    text
+}
+
+// A heading, then a line for each file, of whatever it's ranked by, and its
+// path.
+fn listing<'a>(heading: &str, rows: impl Iterator<Item = (String, &'a PathBuf)>) -> String {
+    let rows: Vec<_> = rows.collect();
+    let width = rows.iter().map(|(value, _)| value.len()).max().unwrap_or(0);
+
+    let mut text = format!("{}:", heading);
+
+    for (value, path) in rows {
+        let _ = write!(text, "\n  {:>width$}  {}", value, path.display());
+    }
+
+    text
+}
+
+// A heading, then a row for each label of its files, bytes, and share of all
+// the bytes, lined up. With cumulative, the share of each row and all the ones
+// above it as well.
+fn table(
+    heading: &str,
+    rows: Vec<(String, Tally)>,
+    size_style: SizeStyle,
+    cumulative: bool,
+) -> String {
+    let mut text = format!("{}:", heading);
+
+    let total: u64 = rows.iter().map(|(_, tally)| tally.bytes).sum();
+    let share = |bytes: u64| 100.0 * bytes as f64 / total.max(1) as f64;
+
+    let sizes: Vec<String> = rows
+        .iter()
+        .map(|(_, tally)| size_style.format(tally.bytes))
+        .collect();
+
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let files_width = rows
+        .iter()
+        .map(|(_, tally)| tally.files.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let size_width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+    let mut so_far = 0;
+
+    for ((label, tally), size) in rows.iter().zip(sizes) {
+        so_far += tally.bytes;
+
+        let _ = write!(
+            text,
+            "\n  {:<label_width$}  {:>files_width$} {:<5}  {:>size_width$}  {:>5.1}%",
+            label,
+            tally.files,
+            if tally.files == 1 { "file" } else { "files" },
+            size,
+            share(tally.bytes),
+        );
+
+        if cumulative {
+            let _ = write!(text, "  {:>5.1}% cumulative", share(so_far));
+        }
+    }
+
+    text
+}
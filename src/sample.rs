@@ -0,0 +1,97 @@
+use crate::hashutil::get_random_bytes;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Sample {
+    Count(usize),
+    Percent(f64),
+}
+
+pub fn parse_sample(s: &str) -> Result<Sample, String> {
+    if let Some(percent) = s.strip_suffix('%') {
+        let percent = percent
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("invalid percentage '{}': {}", s, e))?;
+
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(format!("percentage must be between 0 and 100, got '{}'", s));
+        }
+
+        Ok(Sample::Percent(percent / 100.0))
+    } else {
+        s.trim()
+            .parse::<usize>()
+            .map(Sample::Count)
+            .map_err(|e| format!("invalid sample size '{}': {}", s, e))
+    }
+}
+
+// Xorshift64*, more than good enough for picking files at random, and
+// doesn't warrant pulling in the rand crate.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new() -> Self {
+        // Without random bytes to be had, the time and pid will do, since
+        // nothing depends on it being unpredictable.
+        let seed = get_random_bytes(8)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_ne_bytes)
+            .unwrap_or_else(|| {
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |since| since.as_nanos() as u64);
+
+                nanos ^ (std::process::id() as u64) << 32
+            });
+
+        // Xorshift gets stuck on a zero state.
+        Self(if seed == 0 { 0x9e3779b97f4a7c15 } else { seed })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // Uniform in [0, bound), bias is negligible for any bound we'd deal with.
+    pub fn below(&mut self, bound: usize) -> usize {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Algorithm R; one pass over the stream, only ever holds `size` items.
+pub fn reservoir<T, I: Iterator<Item = T>>(items: I, size: usize, rng: &mut Rng) -> Vec<T> {
+    let mut reservoir: Vec<T> = Vec::with_capacity(size.min(1024 * 1024));
+
+    if size == 0 {
+        return reservoir;
+    }
+
+    for (seen, item) in items.enumerate() {
+        if seen < size {
+            reservoir.push(item);
+        } else {
+            let slot = rng.below(seen + 1);
+
+            if slot < size {
+                reservoir[slot] = item;
+            }
+        }
+    }
+
+    reservoir
+}
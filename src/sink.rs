@@ -0,0 +1,120 @@
+use std::fmt;
+use std::io::{self, BufWriter, ErrorKind, StdoutLock, Write};
+use std::time::{Duration, Instant};
+
+use crate::tui::exit;
+
+// How often live output is flushed, as long as entries keep coming.
+const LIVE_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+// Everything bound for stdout goes through a Sink, rather than println!, which
+// takes the stdout lock and makes a write syscall for every single line. That
+// used to be most of the cost of printing millions of entries, and of --live
+// in particular. Live sinks flush on the first write after each interval, so
+// results keep showing up as they're found; a stretch without any new entries
+// can hold back the last few lines until the next one, or the end.
+pub struct Sink<W: Write> {
+    inner: BufWriter<W>,
+    live: bool,
+    last_flush: Instant,
+}
+
+impl<W: Write> Sink<W> {
+    pub fn new(inner: W, live: bool) -> Self {
+        Self {
+            inner: BufWriter::with_capacity(64 * 1024, inner),
+            live,
+            last_flush: Instant::now(),
+        }
+    }
+
+    // Returning () instead of a Result means write! and writeln! can be used
+    // on a Sink directly, with errors handled in one place rather than at
+    // every call site.
+    pub fn write_fmt(&mut self, args: fmt::Arguments) {
+        if let Err(e) = self.inner.write_fmt(args) {
+            fail(e);
+        }
+
+        self.written();
+    }
+
+    pub fn write_str(&mut self, text: &str) {
+        if let Err(e) = self.inner.write_all(text.as_bytes()) {
+            fail(e);
+        }
+
+        self.written();
+    }
+
+    fn written(&mut self) {
+        if self.live && self.last_flush.elapsed() >= LIVE_FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(e) = self.inner.flush() {
+            fail(e);
+        }
+
+        self.last_flush = Instant::now();
+    }
+}
+
+impl<W: Write> Drop for Sink<W> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// How many entries go over the channel at once, and how many of those chunks
+// can be waiting on the writer before the walk has to wait too.
+const STREAM_CHUNK: usize = 1024;
+const STREAM_CHUNKS_QUEUED: usize = 64;
+
+// Hands items over to a thread of their own that writes them out, through a
+// bounded channel. The walk only ever waits on a slow stdout once the channel
+// is full, and memory use stays the same no matter how many entries there are,
+// where collecting all of them first took gigabytes on the biggest trees.
+pub fn stream<T, F>(items: impl Iterator<Item = T>, mut write: F)
+where
+    T: Send,
+    F: FnMut(&mut Sink<StdoutLock<'static>>, T) + Send,
+{
+    let (sender, receiver) = crossbeam_channel::bounded::<Vec<T>>(STREAM_CHUNKS_QUEUED);
+    let mut items = items;
+
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let mut out = Sink::new(io::stdout().lock(), false);
+
+            for chunk in receiver {
+                for item in chunk {
+                    write(&mut out, item);
+                }
+            }
+        });
+
+        loop {
+            let chunk: Vec<T> = items.by_ref().take(STREAM_CHUNK).collect();
+
+            if chunk.is_empty() || sender.send(chunk).is_err() {
+                break;
+            }
+        }
+
+        drop(sender);
+    });
+}
+
+// Whoever was reading having gone away, e.g. `jw | head`, isn't an error on
+// jw's part, so that just ends the run quietly.
+fn fail(e: io::Error) -> ! {
+    if e.kind() == ErrorKind::BrokenPipe {
+        exit(0);
+    }
+
+    crate::log::error(format_args!("Failed to write output: {}", e));
+    exit(1);
+}
@@ -0,0 +1,172 @@
+use std::cmp::Ordering;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::record::Record;
+
+// What a sort looks at, whether that's an entry still in memory or one that
+// was spilled to disk along with its line of output.
+pub trait Sortable {
+    fn path(&self) -> &Path;
+    fn size(&self) -> u64;
+    fn mtime(&self) -> Option<SystemTime>;
+    fn depth(&self) -> usize;
+    fn is_dir(&self) -> bool;
+}
+
+impl Sortable for Record<'_> {
+    fn path(&self) -> &Path {
+        Record::path(self)
+    }
+
+    fn size(&self) -> u64 {
+        Record::size(self).unwrap_or(0)
+    }
+
+    fn mtime(&self) -> Option<SystemTime> {
+        Record::mtime(self)
+    }
+
+    fn depth(&self) -> usize {
+        self.entry.depth()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.entry.file_type().is_dir()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Natural,
+    Size,
+    Mtime,
+    Depth,
+    Ext,
+}
+
+impl From<&String> for SortKey {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "name" => Self::Name,
+            "natural" => Self::Natural,
+            "size" => Self::Size,
+            "mtime" => Self::Mtime,
+            "depth" => Self::Depth,
+            "ext" => Self::Ext,
+            _ => panic!("Invalid sort key! '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub reverse: bool,
+    pub directories_first: bool,
+}
+
+impl Sort {
+    pub fn needs_metadata(&self) -> bool {
+        matches!(self.key, SortKey::Size | SortKey::Mtime)
+    }
+
+    // Ties are always broken by path, so the order never depends on which
+    // of jwalk's threads happened to get there first. Reversing flips the
+    // whole thing, tie breaks included, the same as piping through sort -r.
+    pub fn compare<T: Sortable>(&self, a: &T, b: &T) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Natural => natural_path_cmp(a.path(), b.path()),
+            SortKey::Size => a.size().cmp(&b.size()),
+            SortKey::Mtime => a.mtime().cmp(&b.mtime()),
+            SortKey::Depth => a.depth().cmp(&b.depth()),
+            SortKey::Ext => a.path().extension().cmp(&b.path().extension()),
+        }
+        .then_with(|| a.path().cmp(b.path()));
+
+        let ordering = if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        // Like ls, directories stay in front even when the order is reversed.
+        if self.directories_first {
+            b.is_dir().cmp(&a.is_dir()).then(ordering)
+        } else {
+            ordering
+        }
+    }
+
+    pub fn apply<T: Sortable>(&self, records: &mut [T]) {
+        records.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+// Component by component, like Path's own ordering, so that a directory's
+// contents still come right after it.
+fn natural_path_cmp(a: &Path, b: &Path) -> Ordering {
+    let mut a = a.components();
+    let mut b = b.components();
+
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => {
+                let ordering = natural_cmp(
+                    x.as_os_str().as_encoded_bytes(),
+                    y.as_os_str().as_encoded_bytes(),
+                );
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+// Runs of digits are compared by their numeric value, so file2 comes before
+// file10. Leading zeros don't count towards the value, which keeps this from
+// overflowing on digit runs of any length: a longer run (once the zeros are
+// gone) is simply the bigger number.
+fn natural_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let run = |s: &[u8], start: usize| {
+                let end = start + s[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let zeros = s[start..end].iter().take_while(|&&c| c == b'0').count();
+                (start + zeros, end)
+            };
+
+            let (a_start, a_end) = run(a, i);
+            let (b_start, b_end) = run(b, j);
+
+            let ordering = (a_end - a_start)
+                .cmp(&(b_end - b_start))
+                .then_with(|| a[a_start..a_end].cmp(&b[b_start..b_end]));
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+
+            i = a_end;
+            j = b_end;
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+
+            i += 1;
+            j += 1;
+        }
+    }
+
+    (a.len() - i).cmp(&(b.len() - j))
+}
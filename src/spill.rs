@@ -0,0 +1,548 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::record::Record;
+use crate::sink::Sink;
+use crate::sort::{Sort, Sortable};
+
+// How many runs get merged at once. Once a level has that many, they're merged
+// into one run of the next level up, so every line is only written out again
+// once per level, and there are only ever that many files open per level.
+const MERGE_FAN_IN: usize = 64;
+
+pub fn parse_sort_memory(s: &str) -> Result<usize, String> {
+    match crate::filter::parse_size(s)? {
+        size if size < 1024 * 1024 => Err("must be at least 1M".to_string()),
+        size => Ok(size as usize),
+    }
+}
+
+// A line of output, along with whatever the sort needs to know about the entry
+// it came from, so the entry itself doesn't have to be kept around.
+pub struct Keyed {
+    path: PathBuf,
+    size: u64,
+    mtime: Option<SystemTime>,
+    depth: usize,
+    dir: bool,
+    line: String,
+}
+
+impl Keyed {
+    // The size and mtime are only looked up when the sort goes by them, since
+    // that can mean a stat call per entry.
+    pub fn new(sort: &Sort, record: &Record, line: String) -> Self {
+        let metadata = sort.needs_metadata();
+
+        Self {
+            path: record.path().clone(),
+            size: if metadata { Sortable::size(record) } else { 0 },
+            mtime: if metadata { record.mtime() } else { None },
+            depth: record.entry.depth(),
+            dir: record.entry.file_type().is_dir(),
+            line,
+        }
+    }
+
+    // Roughly what it takes up in memory, allocations included.
+    fn footprint(&self) -> usize {
+        size_of::<Self>() + self.path.as_os_str().len() + self.line.len()
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let mtime: i128 = match self.mtime.map(|t| t.duration_since(UNIX_EPOCH)) {
+            Some(Ok(after)) => after.as_nanos() as i128,
+            Some(Err(before)) => -(before.duration().as_nanos() as i128),
+            None => 0,
+        };
+
+        let path = self.path.as_os_str().as_encoded_bytes();
+
+        out.write_all(&[self.dir as u8, self.mtime.is_some() as u8])?;
+        out.write_all(&self.size.to_le_bytes())?;
+        out.write_all(&mtime.to_le_bytes())?;
+        out.write_all(&(self.depth as u64).to_le_bytes())?;
+        out.write_all(&(path.len() as u64).to_le_bytes())?;
+        out.write_all(path)?;
+        out.write_all(&(self.line.len() as u64).to_le_bytes())?;
+        out.write_all(self.line.as_bytes())
+    }
+
+    // None once the run has been read to the end.
+    fn read_from(input: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut flags = [0u8; 2];
+
+        match input.read_exact(&mut flags) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+
+        let size = read_u64(input)?;
+
+        let mut mtime = [0u8; 16];
+        input.read_exact(&mut mtime)?;
+        let mtime = i128::from_le_bytes(mtime);
+
+        let depth = read_u64(input)? as usize;
+        let path = read_bytes(input)?;
+        let line = read_bytes(input)?;
+
+        Ok(Some(Self {
+            // Safe, since these are bytes that as_encoded_bytes gave this
+            // very process, on the same platform.
+            path: PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(path) }),
+            size,
+            mtime: (flags[1] != 0).then(|| {
+                let nanos = mtime.unsigned_abs();
+                let offset = Duration::new(
+                    (nanos / 1_000_000_000) as u64,
+                    (nanos % 1_000_000_000) as u32,
+                );
+
+                if mtime < 0 {
+                    UNIX_EPOCH - offset
+                } else {
+                    UNIX_EPOCH + offset
+                }
+            }),
+            depth,
+            dir: flags[0] != 0,
+            line: String::from_utf8(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        }))
+    }
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; read_u64(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Sortable for Keyed {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn mtime(&self) -> Option<SystemTime> {
+        self.mtime
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn is_dir(&self) -> bool {
+        self.dir
+    }
+}
+
+// A sorted stretch of lines, in a temporary file of its own.
+struct Run {
+    file: File,
+    #[cfg(not(unix))]
+    path: PathBuf,
+}
+
+impl Run {
+    // On Unix the file is unlinked as soon as it's been created, so it's gone
+    // for good once jw is, however it ends.
+    fn create() -> io::Result<Self> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "jw-sort-{}-{}",
+            std::process::id(),
+            CREATED.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        #[cfg(unix)]
+        std::fs::remove_file(&path)?;
+
+        Ok(Self {
+            file,
+            #[cfg(not(unix))]
+            path,
+        })
+    }
+
+    fn write(lines: impl IntoIterator<Item = Keyed>) -> io::Result<Self> {
+        let mut run = Self::create()?;
+        let mut out = BufWriter::with_capacity(256 * 1024, &run.file);
+
+        for keyed in lines {
+            keyed.write_to(&mut out)?;
+        }
+
+        out.flush()?;
+        drop(out);
+
+        run.file.seek(SeekFrom::Start(0))?;
+        Ok(run)
+    }
+
+    fn merged(runs: &[Run], sort: Sort) -> io::Result<Self> {
+        let mut merged = Self::create()?;
+        let mut out = BufWriter::with_capacity(256 * 1024, &merged.file);
+        merge(runs, sort, |keyed| keyed.write_to(&mut out))?;
+        out.flush()?;
+        drop(out);
+
+        merged.file.seek(SeekFrom::Start(0))?;
+        Ok(merged)
+    }
+
+    fn reader(&self) -> BufReader<&File> {
+        BufReader::with_capacity(64 * 1024, &self.file)
+    }
+}
+
+#[cfg(not(unix))]
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// The next line of one of the runs being merged. BinaryHeap is a max-heap, so
+// the ordering is reversed to have the line that comes first on top.
+struct Head {
+    keyed: Keyed,
+    run: usize,
+    sort: Sort,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort.compare(&other.keyed, &self.keyed)
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+// Feeds every line of the runs, in order, to emit.
+fn merge(
+    runs: &[Run],
+    sort: Sort,
+    mut emit: impl FnMut(Keyed) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut readers: Vec<_> = runs.iter().map(Run::reader).collect();
+    let mut heads = BinaryHeap::with_capacity(readers.len());
+
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(keyed) = Keyed::read_from(reader)? {
+            heads.push(Head { keyed, run, sort });
+        }
+    }
+
+    while let Some(Head { keyed, run, .. }) = heads.pop() {
+        emit(keyed)?;
+
+        if let Some(keyed) = Keyed::read_from(&mut readers[run])? {
+            heads.push(Head { keyed, run, sort });
+        }
+    }
+
+    Ok(())
+}
+
+// Sorts lines of output in memory for as long as they fit in the budget, and
+// past that, sorts and writes them out to temporary files a budget's worth at
+// a time, to be merged back together at the end. That way a manifest of any
+// size can be sorted, as long as there's the disk space for a copy of it.
+pub struct ExternalSort {
+    sort: Sort,
+    budget: usize,
+    lines: Vec<Keyed>,
+    bytes: usize,
+    // The runs, by how many times they've been merged: the ones spilled
+    // straight from memory, then the ones made of MERGE_FAN_IN of those, and
+    // so on.
+    levels: Vec<Vec<Run>>,
+}
+
+impl ExternalSort {
+    pub fn new(sort: Sort, budget: usize) -> Self {
+        Self {
+            sort,
+            budget,
+            lines: Vec::new(),
+            bytes: 0,
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, keyed: Keyed) {
+        self.bytes += keyed.footprint();
+        self.lines.push(keyed);
+
+        if self.bytes >= self.budget {
+            if let Err(e) = self.spill() {
+                fail(e);
+            }
+        }
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.sort.apply(&mut self.lines);
+        let mut run = Run::write(self.lines.drain(..))?;
+        self.bytes = 0;
+
+        // Only runs of the same level are merged, so the ones merged already
+        // are left as they are until there are enough of them to be merged
+        // again, rather than being written out again with every merge.
+        for level in 0.. {
+            if level == self.levels.len() {
+                self.levels.push(Vec::new());
+            }
+
+            self.levels[level].push(run);
+
+            if self.levels[level].len() < MERGE_FAN_IN {
+                break;
+            }
+
+            run = Run::merged(&self.levels[level], self.sort)?;
+            self.levels[level].clear();
+        }
+
+        Ok(())
+    }
+
+    pub fn finish<W: Write>(mut self, out: &mut Sink<W>) {
+        self.sort.apply(&mut self.lines);
+
+        // Everything fit, which is all there is to it in the common case.
+        if self.levels.is_empty() {
+            self.lines
+                .iter()
+                .for_each(|keyed| out.write_str(&keyed.line));
+            return;
+        }
+
+        let result = Run::write(self.lines.drain(..)).and_then(|last| {
+            // Smallest first, so that if there are more left than can be
+            // merged at once, it's the smallest that are merged beforehand.
+            let mut runs = vec![last];
+            runs.extend(self.levels.drain(..).flatten());
+
+            while runs.len() > MERGE_FAN_IN {
+                let merged = Run::merged(&runs[..MERGE_FAN_IN], self.sort)?;
+                runs.drain(..MERGE_FAN_IN);
+                runs.push(merged);
+            }
+
+            merge(&runs, self.sort, |keyed| {
+                out.write_str(&keyed.line);
+                Ok(())
+            })
+        });
+
+        if let Err(e) = result {
+            fail(e);
+        }
+    }
+}
+
+fn fail(e: io::Error) -> ! {
+    crate::log::error(format_args!(
+        "Failed to spill sorted output to {}: {}",
+        std::env::temp_dir().display(),
+        e
+    ));
+    crate::tui::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sort::SortKey;
+
+    fn keyed(
+        path: PathBuf,
+        size: u64,
+        mtime: Option<SystemTime>,
+        depth: usize,
+        dir: bool,
+    ) -> Keyed {
+        let line = format!("{}\n", path.display());
+
+        Keyed {
+            path,
+            size,
+            mtime,
+            depth,
+            dir,
+            line,
+        }
+    }
+
+    // Made up, but with sizes, mtimes and depths that repeat, so that ties
+    // are broken by path, and mtimes either side of the epoch or missing.
+    fn lines(count: usize) -> Vec<Keyed> {
+        (0..count)
+            .map(|i| {
+                let mtime = match i % 5 {
+                    0 => None,
+                    1 => Some(UNIX_EPOCH - Duration::new(i as u64 * 1000, 123)),
+                    _ => Some(UNIX_EPOCH + Duration::new((i % 97) as u64, i as u32)),
+                };
+
+                let path =
+                    PathBuf::from(format!("/root/{}/{:x}.{}", i % 7, i * 7919 % 10007, i % 3));
+                keyed(path, (i % 13) as u64, mtime, i % 4, i % 11 == 0)
+            })
+            .collect()
+    }
+
+    fn sorted(sort: Sort, budget: usize, lines: Vec<Keyed>) -> String {
+        let mut external = ExternalSort::new(sort, budget);
+        lines.into_iter().for_each(|line| external.push(line));
+
+        let mut bytes = Vec::new();
+        external.finish(&mut Sink::new(&mut bytes, false));
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn in_memory(sort: Sort, mut lines: Vec<Keyed>) -> String {
+        sort.apply(&mut lines);
+        lines.iter().map(|keyed| keyed.line.as_str()).collect()
+    }
+
+    #[test]
+    fn lines_survive_a_run() {
+        #[cfg(unix)]
+        let odd = {
+            use std::os::unix::ffi::OsStrExt;
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"/dir/not \xff utf-8"))
+        };
+        #[cfg(not(unix))]
+        let odd = PathBuf::from("/dir/not utf-8 \u{1f600}");
+
+        let written = [
+            keyed(PathBuf::from("/a"), 0, None, 0, true),
+            keyed(PathBuf::from("/a/b"), u64::MAX, Some(UNIX_EPOCH), 1, false),
+            keyed(
+                odd,
+                42,
+                Some(UNIX_EPOCH - Duration::new(86400, 5)),
+                7,
+                false,
+            ),
+            keyed(
+                PathBuf::from("/a/\u{e9}"),
+                1,
+                Some(UNIX_EPOCH + Duration::new(1_700_000_000, 999_999_999)),
+                2,
+                true,
+            ),
+        ];
+
+        let mut bytes = Vec::new();
+        written
+            .iter()
+            .try_for_each(|keyed| keyed.write_to(&mut bytes))
+            .unwrap();
+
+        let mut input = bytes.as_slice();
+
+        for expected in &written {
+            let read = Keyed::read_from(&mut input).unwrap().unwrap();
+
+            assert_eq!(read.path, expected.path);
+            assert_eq!(read.size, expected.size);
+            assert_eq!(read.mtime, expected.mtime);
+            assert_eq!(read.depth, expected.depth);
+            assert_eq!(read.dir, expected.dir);
+            assert_eq!(read.line, expected.line);
+        }
+
+        assert!(Keyed::read_from(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn what_fits_is_sorted_in_memory() {
+        let sort = Sort {
+            key: SortKey::Name,
+            reverse: false,
+            directories_first: false,
+        };
+
+        assert_eq!(
+            sorted(sort, usize::MAX, lines(500)),
+            in_memory(sort, lines(500))
+        );
+        assert_eq!(sorted(sort, usize::MAX, Vec::new()), "");
+    }
+
+    #[test]
+    fn merged_runs_match_the_in_memory_sort() {
+        let sorts = [
+            (SortKey::Name, false, false),
+            (SortKey::Size, true, false),
+            (SortKey::Mtime, false, true),
+            (SortKey::Depth, true, true),
+        ];
+
+        for (key, reverse, directories_first) in sorts {
+            let sort = Sort {
+                key,
+                reverse,
+                directories_first,
+            };
+
+            // A few lines to a run, and few enough runs that they're all
+            // merged at the end.
+            assert_eq!(sorted(sort, 2048, lines(300)), in_memory(sort, lines(300)));
+        }
+    }
+
+    #[test]
+    fn more_runs_than_are_merged_at_once() {
+        let sort = Sort {
+            key: SortKey::Mtime,
+            reverse: false,
+            directories_first: false,
+        };
+
+        // A run for every line, which is enough for two levels of merged
+        // runs, with some of each left over to be merged at the end.
+        let count = MERGE_FAN_IN * MERGE_FAN_IN + MERGE_FAN_IN * 3 + 5;
+        assert_eq!(sorted(sort, 1, lines(count)), in_memory(sort, lines(count)));
+    }
+}
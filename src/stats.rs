@@ -0,0 +1,389 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::log;
+use crate::output::{json_string, SizeStyle};
+use crate::progress;
+use crate::record::iso8601;
+use crate::sink::Sink;
+use crate::tui;
+use crate::{kind, Entry, Kind};
+
+// Tallied from the file type jwalk read along with each directory, so keeping
+// count takes no extra syscalls, other than a stat for every symlink.
+// The bytes are those of the regular files, and only add up to anything when
+// the walk prefetched metadata, which --stats-format json makes sure of.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counts {
+    pub files: usize,
+    pub dirs: usize,
+    pub other: usize,
+    pub bytes: u64,
+}
+
+impl Counts {
+    pub fn add(&mut self, entry: &Entry) {
+        match kind(entry) {
+            Kind::File => {
+                self.files += 1;
+
+                // A symlink's metadata is its own, not that of what it
+                // points to, which is already counted wherever it is.
+                if entry.file_type().is_file() {
+                    self.bytes += entry.client_state.as_deref().map_or(0, |m| m.len());
+                }
+            }
+            Kind::Dir => self.dirs += 1,
+            Kind::Other => self.other += 1,
+        }
+    }
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Counted {} files, {} directories, and {} misc entries.",
+            self.files, self.dirs, self.other
+        )
+    }
+}
+
+// With --reproducible, nothing that depends on how long a run took, or when
+// it was, is printed, so two runs over the same tree report the same stats.
+static TIMINGS: AtomicBool = AtomicBool::new(true);
+
+pub fn leave_out_timings() {
+    TIMINGS.store(false, Ordering::Relaxed);
+}
+
+fn timings() -> bool {
+    TIMINGS.load(Ordering::Relaxed)
+}
+
+// What a checksum run got through, and how long it took, which is what it
+// takes to plan for how long the next one will.
+#[derive(Debug, Clone, Copy)]
+pub struct Throughput {
+    pub files: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64().max(0.000_001);
+
+        if !timings() {
+            return write!(
+                f,
+                "Hashed {} files, {} bytes ({}).",
+                self.files,
+                self.bytes,
+                SizeStyle::Binary.format(self.bytes)
+            );
+        }
+
+        write!(
+            f,
+            "Hashed {} files, {} bytes ({}), in {:.3}s: {:.1} MB/s, {:.0} files/s.",
+            self.files,
+            self.bytes,
+            SizeStyle::Binary.format(self.bytes),
+            self.elapsed.as_secs_f64(),
+            self.bytes as f64 / seconds / 1_000_000.0,
+            self.files as f64 / seconds,
+        )
+    }
+}
+
+// Entries the walk couldn't read, and files that couldn't be hashed, which are
+// left out of the output, but shouldn't go unaccounted for. With --list-errors,
+// what went wrong with each is kept as well, until the root it happened under
+// is done and they've been printed.
+#[derive(Debug, Default)]
+pub struct Errors {
+    count: AtomicU64,
+    listed: Option<Mutex<Vec<String>>>,
+}
+
+impl Errors {
+    pub fn new(list: bool) -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            listed: list.then(Mutex::default),
+        }
+    }
+
+    // Each one is logged as a warning as well, as it happens, naming what was
+    // skipped, and is shown on the screen with --tui, even with the log turned
+    // down to errors only.
+    pub fn add(&self, path: Option<&Path>, error: impl fmt::Display) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        if self.listed.is_none() && !log::enabled(log::Level::Warn) && !tui::active() {
+            return;
+        }
+
+        let line = match path {
+            Some(path) => format!("{}: {}", path.display(), error),
+            None => error.to_string(),
+        };
+
+        match log::enabled(log::Level::Warn) {
+            true => log::warn(format_args!("Skipped {}", line)),
+            false => tui::error(&format_args!("Skipped {}", line)),
+        }
+
+        if let Some(listed) = &self.listed {
+            listed.lock().unwrap().push(line);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Everything the listing has gathered since it was last taken, sorted,
+    // since the threads add to it in whatever order they run into things.
+    pub fn listing(&self) -> Option<String> {
+        let mut listed = std::mem::take(&mut *self.listed.as_ref()?.lock().unwrap());
+
+        if listed.is_empty() {
+            return None;
+        }
+
+        listed.sort_unstable();
+
+        let mut text = String::from("Errors:");
+
+        for line in listed {
+            let _ = write!(text, "\n  {}", line);
+        }
+
+        Some(text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+impl From<&String> for StatsFormat {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            _ => panic!("Invalid stats format! '{}'", s),
+        }
+    }
+}
+
+// What came of walking one of the roots, either counted in traversal mode, or
+// hashed in checksum mode.
+#[derive(Debug, Clone)]
+pub struct RootStats {
+    pub root: String,
+    pub counts: Option<Counts>,
+    pub hashed: Option<Throughput>,
+    pub errors: u64,
+    pub elapsed: Duration,
+}
+
+impl RootStats {
+    fn json_fields(&self, out: &mut String) {
+        if let Some(counts) = &self.counts {
+            let _ = write!(
+                out,
+                "\"files\":{},\"directories\":{},\"other\":{},\"bytes\":{},",
+                counts.files, counts.dirs, counts.other, counts.bytes
+            );
+        }
+
+        if let Some(hashed) = self.hashed.filter(|_| !timings()) {
+            let _ = write!(
+                out,
+                "\"hashed\":{},\"bytes_hashed\":{},",
+                hashed.files, hashed.bytes
+            );
+        } else if let Some(hashed) = &self.hashed {
+            let seconds = hashed.elapsed.as_secs_f64().max(0.000_001);
+
+            let _ = write!(
+                out,
+                "\"hashed\":{},\"bytes_hashed\":{},\"mb_per_second\":{:.3},\"files_per_second\":{:.3},",
+                hashed.files,
+                hashed.bytes,
+                hashed.bytes as f64 / seconds / 1_000_000.0,
+                hashed.files as f64 / seconds,
+            );
+        }
+
+        let _ = write!(out, "\"errors\":{}", self.errors);
+
+        if timings() {
+            let _ = write!(
+                out,
+                ",\"elapsed_seconds\":{:.6}",
+                self.elapsed.as_secs_f64()
+            );
+        }
+    }
+}
+
+// A block per root in text mode, headed by the root it's for, the same way
+// reports are headed by what they're of.
+impl fmt::Display for RootStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.root)?;
+
+        if let Some(counts) = &self.counts {
+            write!(f, "\n  {}", counts)?;
+        }
+
+        if let Some(hashed) = &self.hashed {
+            write!(f, "\n  {}", hashed)?;
+        }
+
+        if self.errors > 0 {
+            write!(f, "\n  Couldn't read {} entries.", self.errors)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Every root's stats added up, under "Total". The elapsed times are summed, so
+// with roots walked one after another, it's the time all of them took.
+pub fn total(roots: &[RootStats]) -> RootStats {
+    RootStats {
+        root: "Total".to_string(),
+        counts: roots
+            .iter()
+            .filter_map(|root| root.counts)
+            .reduce(|a, b| Counts {
+                files: a.files + b.files,
+                dirs: a.dirs + b.dirs,
+                other: a.other + b.other,
+                bytes: a.bytes + b.bytes,
+            }),
+        hashed: roots
+            .iter()
+            .filter_map(|root| root.hashed)
+            .reduce(|a, b| Throughput {
+                files: a.files + b.files,
+                bytes: a.bytes + b.bytes,
+                elapsed: a.elapsed + b.elapsed,
+            }),
+        errors: roots.iter().map(|root| root.errors).sum(),
+        elapsed: roots.iter().map(|root| root.elapsed).sum(),
+    }
+}
+
+// A single object for the whole run, with the stats of every root, and their
+// totals, all on one line.
+pub fn json(roots: &[RootStats]) -> String {
+    let mut out = String::from("{\"roots\":[");
+
+    for (i, root) in roots.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        out.push_str("{\"root\":");
+        json_string(&mut out, &root.root);
+        out.push(',');
+        root.json_fields(&mut out);
+        out.push('}');
+    }
+
+    let total = total(roots);
+
+    out.push_str("],\"total\":{");
+    total.json_fields(&mut out);
+    out.push_str("}}");
+    out
+}
+
+// What's printed once every root is done: the grand total in text mode, when
+// there's more than the one root to add up, and everything at once with json.
+pub fn summary(roots: &[RootStats], format: StatsFormat) -> Option<String> {
+    match format {
+        StatsFormat::Json => Some(json(roots)),
+        StatsFormat::Text if roots.len() > 1 => Some(total(roots).to_string()),
+        StatsFormat::Text => None,
+    }
+}
+
+// A line for --stats-log, the same object as --stats-format json prints, along
+// with when the run finished, so a log of them can be charted over time.
+pub fn log_record(roots: &[RootStats]) -> String {
+    if !timings() {
+        return json(roots);
+    }
+
+    let mut out = String::from("{\"timestamp\":\"");
+    out.push_str(&iso8601(SystemTime::now()));
+    out.push_str("\",");
+    out.push_str(&json(roots)[1..]);
+    out
+}
+
+// Stats are diagnostics rather than data, so they go to stderr by default and
+// a redirected listing or manifest only ever contains entries.
+#[derive(Debug, Clone)]
+pub enum StatsTarget {
+    Stdout,
+    Stderr,
+    File(Arc<Mutex<File>>),
+}
+
+// The file is created while parsing arguments, so an unwritable path is
+// reported up front instead of after a long walk.
+pub fn parse_stats_target(s: &str) -> Result<StatsTarget, String> {
+    match s {
+        "stdout" => Ok(StatsTarget::Stdout),
+        "stderr" => Ok(StatsTarget::Stderr),
+        path => File::create(path)
+            .map(|file| StatsTarget::File(Arc::new(Mutex::new(file))))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// Opened up front too, but appended to, one run after another.
+pub fn parse_stats_log(s: &str) -> Result<StatsTarget, String> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(s)
+        .map(|file| StatsTarget::File(Arc::new(Mutex::new(file))))
+        .map_err(|e| e.to_string())
+}
+
+impl StatsTarget {
+    pub fn report(&self, text: &str) {
+        match self {
+            // Set apart from the entries printed before it.
+            Self::Stdout => writeln!(Sink::new(std::io::stdout().lock(), false), "\n{}", text),
+            Self::Stderr if tui::hold(&text) => {}
+            Self::Stderr => {
+                let mut stderr = std::io::stderr().lock();
+                progress::clear_line(&mut stderr);
+                let _ = writeln!(stderr, "{}", text);
+            }
+            Self::File(file) => {
+                if let Err(e) = writeln!(file.lock().unwrap(), "{}", text) {
+                    log::warn(format_args!("Failed to write stats: {}", e));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::ffi::OsString;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+
+use crate::hashutil::*;
+use crate::mutate;
+use crate::xattr;
+
+// For --xattr-store and --xattr-verify, a file's hash kept in an extended
+// attribute of the file itself, as user.jw.<algorithm>, along with the mtime
+// it had when it was hashed, as "<mtime> <hash>". That way a file can be
+// checked on its own, with no manifest to keep next to it, wherever it's been
+// copied to with its attributes. The mtime tells the two reasons a file could
+// hash differently apart: if it changed, so did its mtime, and the new hash is
+// just outdated, but if the mtime is the same and the hash isn't, nothing
+// wrote to it, and whatever's on disk has rotted.
+#[derive(Debug)]
+pub struct Tags {
+    name: OsString,
+    store: bool,
+    verify: bool,
+    pub verified: AtomicUsize,
+    pub outdated: AtomicUsize,
+    pub untagged: AtomicUsize,
+    pub corrupt: AtomicUsize,
+    pub stored: AtomicUsize,
+}
+
+impl Tags {
+    pub fn new(algorithm: &HashAlgorithm, store: bool, verify: bool) -> Self {
+        Self {
+            name: OsString::from(format!("user.jw.{}", algorithm.name())),
+            store,
+            verify,
+            verified: AtomicUsize::new(0),
+            outdated: AtomicUsize::new(0),
+            untagged: AtomicUsize::new(0),
+            corrupt: AtomicUsize::new(0),
+            stored: AtomicUsize::new(0),
+        }
+    }
+
+    // Given the metadata the file had before it was hashed. A corrupt file's
+    // tag is left as it is, so it keeps saying what the file should have been,
+    // and so is the tag of one that changed while it was being hashed, since
+    // the hash could be of neither version of it.
+    pub fn tag(&self, path: &Path, hash: &str, before: &Metadata) -> io::Result<()> {
+        let mtime = mtime(before)?;
+
+        if self.verify {
+            match self.stored(path)? {
+                None => {
+                    self.untagged.fetch_add(1, Ordering::Relaxed);
+                }
+                Some((stored_mtime, stored_hash)) if stored_hash == hash => {
+                    self.verified.fetch_add(1, Ordering::Relaxed);
+
+                    if stored_mtime == mtime {
+                        return Ok(());
+                    }
+                }
+                Some((stored_mtime, stored_hash)) if stored_mtime == mtime => {
+                    self.corrupt.fetch_add(1, Ordering::Relaxed);
+
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "corrupt, it hashed to {} rather than the {} it was tagged with, \
+                             and its mtime is still the same",
+                            hash, stored_hash
+                        ),
+                    ));
+                }
+                Some(_) => {
+                    self.outdated.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if !self.store || mtime != self.mtime_now(path)? {
+            return Ok(());
+        }
+
+        let value = format!("{} {}", mtime, hash);
+
+        mutate::perform(|| Ok(()), || xattr::set(path, &self.name, value.as_bytes()))?;
+        self.stored.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // A tag that isn't in the right shape is as good as none at all.
+    fn stored(&self, path: &Path) -> io::Result<Option<(String, String)>> {
+        Ok(xattr::get(path, &self.name)?.and_then(|value| {
+            let value = String::from_utf8(value).ok()?;
+            let (mtime, hash) = value.split_once(' ')?;
+            Some((mtime.to_string(), hash.to_string()))
+        }))
+    }
+
+    fn mtime_now(&self, path: &Path) -> io::Result<String> {
+        mtime(&path.symlink_metadata()?)
+    }
+}
+
+// To the nanosecond, since a write in the same second as the hashing would
+// otherwise go unnoticed.
+fn mtime(metadata: &Metadata) -> io::Result<String> {
+    let since = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+
+    Ok(format!("{}.{:09}", since.as_secs(), since.subsec_nanos()))
+}
@@ -0,0 +1,345 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::record::{iso8601, unix_seconds, utc_fields, Record};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeField {
+    Modified,
+    Accessed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimePart {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Epoch,
+    Date,
+    Clock,
+    Iso,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    Path,
+    Name,
+    Parent,
+    Relative,
+    Ext,
+    Size,
+    Depth,
+    TypeChar,
+    Type,
+    Mode,
+    Uid,
+    Gid,
+    User,
+    Group,
+    Inode,
+    Device,
+    Links,
+    Hash,
+    Time(TimeField, TimePart),
+}
+
+impl Piece {
+    pub fn needs_metadata(&self) -> bool {
+        matches!(
+            self,
+            Self::Size
+                | Self::Mode
+                | Self::Uid
+                | Self::Gid
+                | Self::User
+                | Self::Group
+                | Self::Inode
+                | Self::Device
+                | Self::Links
+                | Self::Time(_, _)
+        )
+    }
+}
+
+pub fn parse(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces: Vec<Piece> = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    fn push(pieces: &mut Vec<Piece>, literal: &mut String, piece: Piece) {
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(std::mem::take(literal)));
+        }
+
+        pieces.push(piece);
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => literal.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some('\\') => '\\',
+                Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+                None => return Err("template ends with a lone '\\'".to_string()),
+            }),
+
+            '%' => {
+                let directive = chars
+                    .next()
+                    .ok_or("template ends with a lone '%'".to_string())?;
+
+                let piece = match directive {
+                    '%' => {
+                        literal.push('%');
+                        continue;
+                    }
+                    'p' => Piece::Path,
+                    'f' => Piece::Name,
+                    'h' => Piece::Parent,
+                    'P' => Piece::Relative,
+                    'e' => Piece::Ext,
+                    's' => Piece::Size,
+                    'd' => Piece::Depth,
+                    'y' => Piece::TypeChar,
+                    'm' => Piece::Mode,
+                    'U' => Piece::Uid,
+                    'G' => Piece::Gid,
+                    'u' => Piece::User,
+                    'g' => Piece::Group,
+                    'i' => Piece::Inode,
+                    'D' => Piece::Device,
+                    'n' => Piece::Links,
+                    'H' => Piece::Hash,
+                    't' => Piece::Time(TimeField::Modified, TimePart::Iso),
+                    'a' => Piece::Time(TimeField::Accessed, TimePart::Iso),
+                    'c' => Piece::Time(TimeField::Changed, TimePart::Iso),
+                    'T' | 'A' | 'C' => {
+                        let field = match directive {
+                            'T' => TimeField::Modified,
+                            'A' => TimeField::Accessed,
+                            _ => TimeField::Changed,
+                        };
+
+                        let part = match chars.next() {
+                            Some('Y') => TimePart::Year,
+                            Some('m') => TimePart::Month,
+                            Some('d') => TimePart::Day,
+                            Some('H') => TimePart::Hour,
+                            Some('M') => TimePart::Minute,
+                            Some('S') => TimePart::Second,
+                            Some('@') => TimePart::Epoch,
+                            Some('F') => TimePart::Date,
+                            Some('T') => TimePart::Clock,
+                            Some(other) => {
+                                return Err(format!(
+                                    "unknown time format '%{}{}'",
+                                    directive, other
+                                ))
+                            }
+                            None => return Err(format!("'%{}' needs a time format", directive)),
+                        };
+
+                        Piece::Time(field, part)
+                    }
+                    other => return Err(format!("unknown directive '%{}'", other)),
+                };
+
+                push(&mut pieces, &mut literal, piece);
+            }
+
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+
+            '{' => {
+                let mut name = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => name.push(ch),
+                        None => return Err(format!("unterminated placeholder '{{{}'", name)),
+                    }
+                }
+
+                let piece = match name.as_str() {
+                    "path" => Piece::Path,
+                    "name" => Piece::Name,
+                    "parent" => Piece::Parent,
+                    "relpath" => Piece::Relative,
+                    "ext" => Piece::Ext,
+                    "size" => Piece::Size,
+                    "depth" => Piece::Depth,
+                    "type" => Piece::Type,
+                    "mode" => Piece::Mode,
+                    "uid" => Piece::Uid,
+                    "gid" => Piece::Gid,
+                    "user" => Piece::User,
+                    "group" => Piece::Group,
+                    "inode" => Piece::Inode,
+                    "dev" => Piece::Device,
+                    "nlink" => Piece::Links,
+                    "hash" => Piece::Hash,
+                    "mtime" => Piece::Time(TimeField::Modified, TimePart::Iso),
+                    "atime" => Piece::Time(TimeField::Accessed, TimePart::Iso),
+                    "ctime" => Piece::Time(TimeField::Changed, TimePart::Iso),
+                    _ => return Err(format!("unknown placeholder '{{{}}}'", name)),
+                };
+
+                push(&mut pieces, &mut literal, piece);
+            }
+
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+
+    Ok(pieces)
+}
+
+fn time_part(out: &mut String, time: SystemTime, part: TimePart) {
+    let (year, month, day, hour, minute, second) = utc_fields(time);
+
+    match part {
+        TimePart::Year => write!(out, "{:04}", year),
+        TimePart::Month => write!(out, "{:02}", month),
+        TimePart::Day => write!(out, "{:02}", day),
+        TimePart::Hour => write!(out, "{:02}", hour),
+        TimePart::Minute => write!(out, "{:02}", minute),
+        TimePart::Second => write!(out, "{:02}", second),
+        TimePart::Epoch => write!(out, "{}", unix_seconds(time)),
+        TimePart::Date => write!(out, "{:04}-{:02}-{:02}", year, month, day),
+        TimePart::Clock => write!(out, "{:02}:{:02}:{:02}", hour, minute, second),
+        TimePart::Iso => write!(out, "{}", iso8601(time)),
+    }
+    .unwrap();
+}
+
+// Fields that can't be determined (e.g. metadata of a vanished file) render
+// as nothing rather than aborting the line, same as find does.
+pub fn render(pieces: &[Piece], record: &Record, path: &Path, out: &mut String) {
+    for piece in pieces {
+        match piece {
+            Piece::Literal(literal) => out.push_str(literal),
+            Piece::Path => out.push_str(&path.to_string_lossy()),
+            Piece::Name => out.push_str(&record.entry.file_name().to_string_lossy()),
+
+            Piece::Parent => {
+                if let Some(parent) = path.parent() {
+                    out.push_str(&parent.to_string_lossy());
+                }
+            }
+
+            Piece::Relative => {
+                let components: Vec<_> = record.path().components().collect();
+                let depth = record.entry.depth().min(components.len());
+
+                let relative: PathBuf = components[components.len() - depth..].iter().collect();
+                out.push_str(&relative.to_string_lossy());
+            }
+
+            Piece::Ext => {
+                if let Some(ext) = record.path().extension() {
+                    out.push_str(&ext.to_string_lossy());
+                }
+            }
+
+            Piece::Size => {
+                if let Some(size) = record.size() {
+                    write!(out, "{}", size).unwrap();
+                }
+            }
+
+            Piece::Depth => write!(out, "{}", record.entry.depth()).unwrap(),
+
+            Piece::TypeChar => out.push(match record.type_name() {
+                "file" => 'f',
+                "dir" => 'd',
+                "symlink" => 'l',
+                _ => 'o',
+            }),
+
+            Piece::Type => out.push_str(record.type_name()),
+
+            Piece::Mode => {
+                if let Some(mode) = record.mode() {
+                    write!(out, "{:o}", mode & 0o7777).unwrap();
+                }
+            }
+
+            Piece::Uid => {
+                if let Some(uid) = record.uid() {
+                    write!(out, "{}", uid).unwrap();
+                }
+            }
+
+            Piece::Gid => {
+                if let Some(gid) = record.gid() {
+                    write!(out, "{}", gid).unwrap();
+                }
+            }
+
+            Piece::User => {
+                if let Some(uid) = record.uid() {
+                    match crate::record::user_name(uid) {
+                        Some(name) => out.push_str(&name),
+                        None => write!(out, "{}", uid).unwrap(),
+                    }
+                }
+            }
+
+            Piece::Group => {
+                if let Some(gid) = record.gid() {
+                    match crate::record::group_name(gid) {
+                        Some(name) => out.push_str(&name),
+                        None => write!(out, "{}", gid).unwrap(),
+                    }
+                }
+            }
+
+            Piece::Inode | Piece::Device | Piece::Links => {
+                let value = match piece {
+                    Piece::Inode => record.ino(),
+                    Piece::Device => record.dev(),
+                    _ => record.nlink(),
+                };
+
+                if let Some(value) = value {
+                    write!(out, "{}", value).unwrap();
+                }
+            }
+
+            Piece::Hash => out.push_str(record.hash.unwrap_or_default()),
+
+            Piece::Time(field, part) => {
+                let time = match field {
+                    TimeField::Modified => record.mtime(),
+                    TimeField::Accessed => record.atime(),
+                    TimeField::Changed => record.ctime(),
+                };
+
+                if let Some(time) = time {
+                    time_part(out, time, *part);
+                }
+            }
+        }
+    }
+}
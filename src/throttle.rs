@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Limits how fast everything sharing it reads, in aggregate. Each read books
+// the slot of time it takes up at the configured rate, right after whichever
+// read came before it, and whoever made it sleeps until its slot is over.
+// Time spent idle isn't saved up, so there are no bursts after a lull.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_second: f64,
+    next_slot: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_second: f64) -> Self {
+        Self {
+            bytes_per_second,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn consume(&self, bytes: usize) {
+        let now = Instant::now();
+
+        let slot_end = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot_start = (*next_slot).max(now);
+            *next_slot = slot_start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second);
+            *next_slot
+        };
+
+        thread::sleep(slot_end.saturating_duration_since(now));
+    }
+}
+
+// In megabytes (10^6 bytes) per second, fractions included.
+pub fn parse_bwlimit(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate * 1_000_000.0),
+        Ok(_) => Err("must be a number of MB/s above 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
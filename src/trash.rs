@@ -0,0 +1,196 @@
+use std::io;
+use std::path::Path;
+
+// Moves a file to the trash rather than deleting it, for --trash, following
+// the FreeDesktop.org trash spec, so it shows up in, and can be restored from,
+// any desktop's trash can. Files on the same filesystem as the home directory go
+// to the home trash, and anything elsewhere goes to the trash at the top of its
+// own filesystem, since moving it anywhere else would mean copying it.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn trash(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    use std::path::PathBuf;
+
+    let path = std::path::absolute(path)?;
+    let device = path.symlink_metadata()?.dev();
+
+    let home_trash = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|data| data.join("Trash"));
+
+    if let Some(home_trash) = home_trash {
+        let data = home_trash.parent().unwrap();
+
+        if std::fs::create_dir_all(data).is_ok() && data.metadata()?.dev() == device {
+            return move_to(&home_trash, &path, &path);
+        }
+    }
+
+    let top = top_directory(&path, device)?;
+    let uid = unsafe { libc::getuid() };
+
+    // An admin provided $top/.Trash only counts if it's a real directory with
+    // the sticky bit (0o1000) set, or users could read each other's trash.
+    let shared = top.join(".Trash");
+
+    let trash = match shared.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() && metadata.mode() & 0o1000 != 0 => {
+            shared.join(uid.to_string())
+        }
+        _ => top.join(format!(".Trash-{}", uid)),
+    };
+
+    // Paths in a trash at the top of a filesystem are relative to the top, so
+    // they still mean something wherever it's mounted next time.
+    move_to(&trash, &path, path.strip_prefix(&top).unwrap())
+}
+
+// The trash spec doesn't cover macOS, whose trash is a single folder, without
+// a record of where anything came from that another program could write.
+#[cfg(target_os = "macos")]
+pub fn trash(path: &Path) -> io::Result<()> {
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME isn't set"))?;
+
+    let trash = Path::new(&home).join(".Trash");
+    let name = path.file_name().unwrap_or_default();
+
+    for n in 1.. {
+        let target = match n {
+            1 => trash.join(name),
+            n => trash.join(format!("{} {}", name.to_string_lossy(), n)),
+        };
+
+        if target.symlink_metadata().is_err() {
+            return std::fs::rename(path, target);
+        }
+    }
+
+    unreachable!()
+}
+
+#[cfg(not(unix))]
+pub fn trash(_: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--trash isn't supported on this platform",
+    ))
+}
+
+// The mount point the path is under, found by going up for as long as the
+// parent is still on the same device.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn top_directory(path: &Path, device: u64) -> io::Result<std::path::PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut top = path.parent().unwrap_or(Path::new("/"));
+
+    while let Some(parent) = top.parent() {
+        if parent.metadata()?.dev() != device {
+            break;
+        }
+
+        top = parent;
+    }
+
+    Ok(top.to_path_buf())
+}
+
+// The .trashinfo file is created first, exclusively, which is what claims the
+// name in the trash, and only then is the file itself moved in under it.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn move_to(trash: &Path, path: &Path, recorded: &Path) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::DirBuilderExt;
+
+    let (files, info) = (trash.join("files"), trash.join("info"));
+
+    for dir in [&files, &info] {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+
+    let name = path.file_name().unwrap_or_default();
+
+    for n in 1.. {
+        let mut candidate = name.to_os_string();
+
+        if n > 1 {
+            candidate.push(format!(".{}", n));
+        }
+
+        let mut info_name = candidate.clone();
+        info_name.push(".trashinfo");
+
+        let info_path = info.join(&info_name);
+
+        let mut info_file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => result?,
+        };
+
+        let written = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(recorded.as_os_str().as_bytes()),
+            local_time()
+        );
+
+        let moved = written.and_then(|_| std::fs::rename(path, files.join(&candidate)));
+
+        if moved.is_err() {
+            let _ = std::fs::remove_file(&info_path);
+        }
+
+        return moved;
+    }
+
+    unreachable!()
+}
+
+// Everything but the unreserved characters, and the slashes between
+// components, as the spec says the path is to be escaped as in a URL.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes.iter().fold(String::new(), |mut out, &b| {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            b => {
+                let _ = write!(out, "%{:02X}", b);
+            }
+        }
+
+        out
+    })
+}
+
+// The deletion date is in local time, without a zone, per the spec.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn local_time() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
@@ -0,0 +1,107 @@
+use std::fmt::Write;
+use std::io;
+
+use crate::output::Output;
+use crate::record::Record;
+use crate::sink::Sink;
+use crate::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Annotation {
+    None,
+    Size,
+}
+
+impl From<&String> for Annotation {
+    fn from(s: &String) -> Self {
+        match s.to_lowercase().as_str() {
+            "plain" => Self::None,
+            "size" => Self::Size,
+            _ => panic!("Invalid tree annotation! '{}'", s),
+        }
+    }
+}
+
+// Entries have to arrive in sorted depth first order, which is what jwalk
+// yields when sorting is enabled. Whether a node is the last of its siblings
+// can only be known by looking ahead, so that's worked out in a reverse pass
+// first; anything after a node that's at the same depth, before the walk
+// climbs back above it, is a later sibling.
+fn last_siblings(entries: &[(Entry, Option<String>)]) -> Vec<bool> {
+    let mut is_last = vec![false; entries.len()];
+    let mut seen_at_depth: Vec<bool> = Vec::new();
+
+    for (i, (entry, _)) in entries.iter().enumerate().rev() {
+        let depth = entry.depth();
+
+        if seen_at_depth.len() <= depth {
+            seen_at_depth.resize(depth + 1, false);
+        }
+
+        is_last[i] = !seen_at_depth[depth];
+        seen_at_depth[depth] = true;
+        seen_at_depth.truncate(depth + 1);
+    }
+
+    is_last
+}
+
+pub fn render<W: io::Write>(
+    entries: &[(Entry, Option<String>)],
+    output: &Output,
+    out: &mut Sink<W>,
+) {
+    let is_last = last_siblings(entries);
+
+    // Whether the ancestor at each depth was the last of its siblings, which
+    // decides between a continuing branch or blank space in the indentation.
+    let mut ancestors_last: Vec<bool> = Vec::new();
+    let root_depth = entries.first().map_or(0, |(entry, _)| entry.depth());
+
+    for ((entry, hash), &last) in entries.iter().zip(is_last.iter()) {
+        let record = Record::new(entry, hash.as_deref());
+        let depth = entry.depth().saturating_sub(root_depth);
+        let mut line = String::with_capacity(128);
+
+        ancestors_last.truncate(depth);
+
+        if depth > 0 {
+            for &ancestor_last in ancestors_last.iter().skip(1) {
+                line.push_str(if ancestor_last { "    " } else { "│   " });
+            }
+
+            line.push_str(if last { "└── " } else { "├── " });
+        }
+
+        ancestors_last.push(last);
+
+        let size = (output.tree_annotation == Annotation::Size)
+            .then(|| record.size())
+            .flatten()
+            .map(|size| output.size_style.format(size));
+
+        match (size, record.hash) {
+            (Some(size), Some(hash)) => write!(line, "[{:>12} {}]  ", size, hash).unwrap(),
+            (Some(size), None) => write!(line, "[{:>12}]  ", size).unwrap(),
+            (None, Some(hash)) => write!(line, "[{}]  ", hash).unwrap(),
+            (None, None) => (),
+        }
+
+        if depth == 0 {
+            line.push_str(
+                &output.decorate(&record, &output.quote(output.path(&record).as_os_str())),
+            );
+        } else {
+            line.push_str(&output.decorate(&record, &output.quote(entry.file_name())));
+        }
+
+        if entry.file_type().is_symlink() {
+            if let Ok(target) = std::fs::read_link(record.path()) {
+                write!(line, " -> {}", output.quote(target.as_os_str())).unwrap();
+            }
+        }
+
+        line.push('\n');
+        out.write_str(&line);
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(unix)]
+use std::thread::{self, JoinHandle};
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+
+#[cfg(unix)]
+use crate::diagnostic::Diagnostic;
+use crate::forks;
+use crate::hashutil::is_unstable_digest;
+#[cfg(unix)]
+use crate::interrupt;
+#[cfg(unix)]
+use crate::pick::{fit, keys, window_size, Key, Terminal};
+#[cfg(unix)]
+use crate::progress::format_duration;
+use crate::progress::Progress;
+use crate::stats::Errors;
+use crate::Hashes;
+
+// For --tui, a screen of its own that's redrawn every so often with where a
+// run is at, which is what there is to look at when it's going to take hours.
+// Whatever else would have been printed to stderr in the meantime would be
+// drawn over, or draw over it, so it's held until the screen is closed, and
+// printed then, while the errors and warnings among it are shown on the
+// screen as they happen, along with each entry that's skipped.
+#[derive(Default)]
+struct State {
+    active: AtomicBool,
+    held: Mutex<String>,
+    errors: Mutex<VecDeque<String>>,
+    discrepancies: Mutex<Vec<String>>,
+    // The manifest `jw verify` is checking against, and what the directories
+    // it's walking are called in the discrepancies, the same as at the end.
+    expected: OnceLock<(Hashes<'static>, String)>,
+    // What it takes to close the screen, from wherever it has to be closed,
+    // which is usually the end of the run, but could be anything jw stops at.
+    #[cfg(unix)]
+    running: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+}
+
+static STATE: OnceLock<State> = OnceLock::new();
+
+fn state() -> &'static State {
+    STATE.get_or_init(State::default)
+}
+
+pub fn active() -> bool {
+    STATE
+        .get()
+        .is_some_and(|state| state.active.load(Ordering::Relaxed))
+}
+
+// Whether it was held, which is only while the screen is up. The lock is
+// what closing the screen takes to stop holding, so nothing gets held after
+// what's held has already been printed.
+pub fn hold(text: &dyn fmt::Display) -> bool {
+    let Some(state) = STATE.get() else {
+        return false;
+    };
+
+    let mut held = state.held.lock().unwrap();
+
+    if !state.active.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    let _ = writeln!(held, "{}", text);
+    true
+}
+
+// Only so many are kept, since it's the latest of them that are shown.
+const ERRORS_KEPT: usize = 100;
+
+pub fn error(line: &dyn fmt::Display) {
+    if !active() {
+        return;
+    }
+
+    let mut errors = state().errors.lock().unwrap();
+
+    if errors.len() == ERRORS_KEPT {
+        errors.pop_front();
+    }
+
+    errors.push_back(line.to_string());
+}
+
+// A line of the diff, as it's found, which is all of them for `jw diff`, and
+// for `jw verify` the mismatches and excess files as they're hashed. Files
+// that are missing are only known to be once everything has been.
+pub fn discrepancy(line: &str) {
+    if active() {
+        state().discrepancies.lock().unwrap().push(line.to_string());
+    }
+}
+
+pub fn expect(hashes: Hashes<'static>, walked: String) {
+    let _ = state().expected.set((hashes, walked));
+}
+
+pub fn hashed(path: &Path, hash: &str) {
+    let Some((expected, walked)) = STATE.get().and_then(|state| state.expected.get()) else {
+        return;
+    };
+
+    let name = path.as_os_str().as_encoded_bytes();
+
+    match expected.get(name) {
+        Some(base) if is_unstable_digest(base) || is_unstable_digest(hash) => {
+            discrepancy(&format!("[~({})] {}", walked, forks::shown(path).display()))
+        }
+        Some(base) if *base == hash => {}
+        Some(base) => discrepancy(&format!(
+            "[!({})] {} != {} == {}",
+            walked,
+            hash,
+            base,
+            forks::shown(path).display()
+        )),
+        None => discrepancy(&format!(
+            "[+({})] {} {}",
+            walked,
+            hash,
+            forks::shown(path).display()
+        )),
+    }
+}
+
+// Often enough for the rates to look alive, and the keys to feel it.
+#[cfg(unix)]
+const TICK: Duration = Duration::from_millis(250);
+
+// The screen is drawn on the terminal itself, like the picker, so stdout
+// can still be redirected into a manifest. Ctrl+C stops the run the same
+// way it would without the screen, and a second one gives up on it then
+// and there, but only once the terminal's been put back. It's up until
+// this is dropped, or it's closed some other way.
+pub struct Screen;
+
+#[cfg(unix)]
+impl Screen {
+    // What's being watched, which `jw diff` has neither of, since there's
+    // nothing to walk or hash, only manifests to compare.
+    pub fn start(title: String, watched: Option<(Arc<Progress>, Arc<Errors>)>) -> Self {
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .unwrap_or_else(|e| {
+                Diagnostic::new(format!("needs a terminal to draw on: {}", e))
+                    .about("--tui")
+                    .tip("run jw from a terminal, or give --progress, which is drawn on stderr")
+                    .exit(1)
+            });
+
+        let terminal = Terminal::raw(&tty).unwrap_or_else(|e| {
+            Diagnostic::new(format!("failed to set up the terminal: {}", e))
+                .about("--tui")
+                .exit(1)
+        });
+
+        state().active.store(true, Ordering::Relaxed);
+
+        let (stop, stopped) = bounded::<()>(0);
+
+        let view = View {
+            title,
+            watched,
+            started: Instant::now(),
+            scroll: 0,
+            follow: true,
+        };
+
+        let thread = thread::spawn(move || view.run(tty, terminal, stopped));
+        *state().running.lock().unwrap() = Some((stop, thread));
+
+        Self
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        close();
+    }
+}
+
+// Waits for the screen to be drawn for the last time, and the terminal put
+// back, if it's up, after which what was held is printed.
+pub fn close() {
+    #[cfg(unix)]
+    if let Some((stop, thread)) = STATE
+        .get()
+        .and_then(|state| state.running.lock().unwrap().take())
+    {
+        drop(stop);
+        let _ = thread.join();
+    }
+}
+
+// Every exit jw makes of its own accord goes through here, since exiting
+// straight away would leave the terminal as the screen had it, and whatever
+// went wrong held back, never to be printed.
+pub fn exit(code: i32) -> ! {
+    close();
+    std::process::exit(code);
+}
+
+// Stops holding what's printed to stderr, and prints what was.
+#[cfg(unix)]
+fn restore(terminal: Terminal, tty: &mut File) {
+    let _ = tty.write_all(b"\x1b[?25h");
+    drop(terminal);
+
+    let held = {
+        let state = state();
+        let mut held = state.held.lock().unwrap();
+        state.active.store(false, Ordering::Relaxed);
+        std::mem::take(&mut *held)
+    };
+
+    let _ = std::io::stderr().lock().write_all(held.as_bytes());
+}
+
+#[cfg(unix)]
+struct View {
+    title: String,
+    watched: Option<(Arc<Progress>, Arc<Errors>)>,
+    started: Instant,
+    // The first of the discrepancies shown, which follows the latest of
+    // them until it's scrolled up from.
+    scroll: usize,
+    follow: bool,
+}
+
+#[cfg(unix)]
+impl View {
+    fn run(mut self, mut tty: File, terminal: Terminal, stopped: Receiver<()>) {
+        let mut buffer = [0u8; 256];
+        let mut rows = 0;
+
+        loop {
+            let size = window_size(&tty);
+
+            let read = tty.read(&mut buffer).unwrap_or(0);
+
+            for key in keys(&buffer[..read]) {
+                match key {
+                    Key::Up => self.scroll_by(-1, rows),
+                    Key::Down => self.scroll_by(1, rows),
+                    Key::PageUp => self.scroll_by(-(rows.max(1) as isize), rows),
+                    Key::PageDown => self.scroll_by(rows.max(1) as isize, rows),
+                    Key::Abort if interrupt::interrupted() => {
+                        restore(terminal, &mut tty);
+                        unsafe { libc::raise(libc::SIGINT) };
+                        return;
+                    }
+                    Key::Abort => unsafe {
+                        libc::raise(libc::SIGINT);
+                    },
+                    _ => {}
+                }
+            }
+
+            match self.draw(&mut tty, size) {
+                Ok(shown) => rows = shown,
+                Err(_) => break,
+            }
+
+            if !matches!(stopped.recv_timeout(TICK), Err(RecvTimeoutError::Timeout)) {
+                break;
+            }
+        }
+
+        restore(terminal, &mut tty);
+    }
+
+    fn scroll_by(&mut self, by: isize, rows: usize) {
+        let total = state().discrepancies.lock().unwrap().len();
+        let last = total.saturating_sub(rows);
+
+        self.scroll = self.scroll.saturating_add_signed(by).min(last);
+        self.follow = self.scroll == last;
+    }
+
+    // Returns how many rows the discrepancies got, which is what a page
+    // of them is when scrolling.
+    fn draw(&mut self, tty: &mut File, (rows, columns): (usize, usize)) -> std::io::Result<usize> {
+        let state = state();
+        let mut lines: Vec<String> = Vec::new();
+
+        let elapsed = format_duration(self.started.elapsed());
+        let title = fit(&self.title, columns.saturating_sub(elapsed.len() + 3));
+        let padding = columns.saturating_sub(title.chars().count() + elapsed.len() + 2);
+
+        let mut screen = format!(
+            "\x1b[?25l\x1b[H\x1b[7m {}{} {} \x1b[0m",
+            title,
+            " ".repeat(padding),
+            elapsed
+        );
+
+        if let Some((progress, errors)) = &self.watched {
+            lines.push(progress.line());
+
+            let mut counts = format!("{} errors", errors.count());
+
+            if state.expected.get().is_some() {
+                let found = state.discrepancies.lock().unwrap().len();
+                counts.push_str(&format!(", {} discrepancies so far", found));
+            }
+
+            lines.push(counts);
+
+            let at = match interrupt::interrupted() {
+                true => "Stopping, once what's underway is done.".to_string(),
+                false => progress
+                    .current()
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .map(|dir| format!("In {}", dir.display()))
+                    .unwrap_or_default(),
+            };
+
+            lines.push(at);
+
+            if let Some(workers) = progress.workers() {
+                let shown = workers.len().min(rows / 3).max(1);
+                let busy = workers.iter().filter(|worker| worker.is_some()).count();
+
+                lines.push(String::new());
+                lines.push(format!("Threads, {} of {} busy:", busy, workers.len()));
+
+                for (i, worker) in workers.iter().enumerate().take(shown) {
+                    let line = match worker {
+                        Some((path, since)) => {
+                            let prefix =
+                                format!("{:>4} {:>8}  ", i, format_duration(since.elapsed()));
+                            let path = path.to_string_lossy();
+                            let path = fit(&path, columns.saturating_sub(prefix.len()));
+                            format!("{}{}", prefix, path)
+                        }
+                        None => format!("{:>4} {:>8}", i, "idle"),
+                    };
+
+                    lines.push(line);
+                }
+
+                if workers.len() > shown {
+                    lines.push(format!("     and {} more", workers.len() - shown));
+                }
+            }
+        }
+
+        lines.push(String::new());
+        lines.push("Recent errors:".to_string());
+
+        {
+            let errors = state.errors.lock().unwrap();
+
+            match errors.is_empty() {
+                true => lines.push("  None so far.".to_string()),
+                false => lines.extend(
+                    errors
+                        .iter()
+                        .skip(errors.len().saturating_sub(5))
+                        .map(|error| format!("  {}", error)),
+                ),
+            }
+        }
+
+        let discrepancies = state.discrepancies.lock().unwrap();
+        let listed = state.expected.get().is_some() || !discrepancies.is_empty();
+        let mut room = 0;
+
+        if listed {
+            lines.push(String::new());
+
+            // The heading and the title bar take a row each.
+            room = rows.saturating_sub(lines.len() + 2);
+            let last = discrepancies.len().saturating_sub(room);
+
+            if self.follow || self.scroll > last {
+                self.scroll = last;
+            }
+
+            let heading = match discrepancies.len() > room {
+                true => format!(
+                    "Discrepancies, {}-{} of {}, Up/Down/PgUp/PgDn to scroll:",
+                    self.scroll + 1,
+                    (self.scroll + room).min(discrepancies.len()),
+                    discrepancies.len()
+                ),
+                false => "Discrepancies:".to_string(),
+            };
+
+            lines.push(heading);
+
+            lines.extend(
+                discrepancies
+                    .iter()
+                    .skip(self.scroll)
+                    .take(room)
+                    .map(|line| format!("  {}", line)),
+            );
+        }
+
+        drop(discrepancies);
+
+        // Errors and discrepancies say what they are first, and what of,
+        // after, so they're cut short at the end, rather than the start.
+        for line in lines.iter().take(rows.saturating_sub(1)) {
+            let line: String = line.chars().take(columns).collect();
+            screen.push_str("\r\n");
+            screen.push_str(&line);
+            screen.push_str("\x1b[K");
+        }
+
+        screen.push_str("\x1b[J");
+
+        tty.write_all(screen.as_bytes())?;
+        tty.flush()?;
+
+        Ok(room)
+    }
+}
+
+// Never started, since --tui is refused up front where there's no /dev/tty.
+#[cfg(not(unix))]
+impl Screen {
+    pub fn start(_: String, _: Option<(Arc<Progress>, Arc<Errors>)>) -> Self {
+        Self
+    }
+}
@@ -0,0 +1,365 @@
+// Just enough of io_uring to queue plain reads, talking to the kernel through
+// the raw syscalls so it doesn't pull in another dependency. The layouts below
+// are the kernel's, from include/uapi/linux/io_uring.h.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+const IORING_ENTER_GETEVENTS: libc::c_uint = 1;
+const IORING_OP_READ: u8 = 22;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: &OwnedFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr, len })
+    }
+
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+struct Ring {
+    // Field order matters for drop order: the mappings go before the fd.
+    sq: Mapping,
+    cq: Option<Mapping>,
+    sqes: Mapping,
+    fd: OwnedFd,
+    params: Params,
+    pending: u32,
+}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries as libc::c_long,
+                &mut params as *mut Params,
+            )
+        };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+
+        // Newer kernels map both rings in one go.
+        let (sq, cq) = if params.features & IORING_FEAT_SINGLE_MMAP != 0 {
+            (
+                Mapping::new(&fd, sq_len.max(cq_len), IORING_OFF_SQ_RING)?,
+                None,
+            )
+        } else {
+            (
+                Mapping::new(&fd, sq_len, IORING_OFF_SQ_RING)?,
+                Some(Mapping::new(&fd, cq_len, IORING_OFF_CQ_RING)?),
+            )
+        };
+
+        let sqes = Mapping::new(
+            &fd,
+            params.sq_entries as usize * size_of::<Sqe>(),
+            IORING_OFF_SQES,
+        )?;
+
+        Ok(Self {
+            sq,
+            cq,
+            sqes,
+            fd,
+            params,
+            pending: 0,
+        })
+    }
+
+    fn cq(&self) -> &Mapping {
+        self.cq.as_ref().unwrap_or(&self.sq)
+    }
+
+    fn sq_atomic(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.sq.at::<AtomicU32>(offset) }
+    }
+
+    fn cq_atomic(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.cq().at::<AtomicU32>(offset) }
+    }
+
+    // Queues a read of buffer.len() bytes at the given offset. The buffer has
+    // to stay put until its completion has been reaped, which read_all makes
+    // sure of by never touching a buffer with a read in flight.
+    fn push_read(&mut self, file: &File, buffer: &mut [u8], offset: u64, user_data: u64) {
+        let head = self
+            .sq_atomic(self.params.sq_off.head)
+            .load(Ordering::Acquire);
+        let tail = self
+            .sq_atomic(self.params.sq_off.tail)
+            .load(Ordering::Relaxed);
+        assert!(tail.wrapping_sub(head) < self.params.sq_entries);
+
+        let mask = unsafe { *self.sq.at::<u32>(self.params.sq_off.ring_mask) };
+        let index = tail & mask;
+
+        unsafe {
+            self.sqes.at::<Sqe>(0).add(index as usize).write(Sqe {
+                opcode: IORING_OP_READ,
+                fd: file.as_raw_fd(),
+                off: offset,
+                addr: buffer.as_mut_ptr() as u64,
+                len: buffer.len() as u32,
+                user_data,
+                ..Default::default()
+            });
+
+            *self
+                .sq
+                .at::<u32>(self.params.sq_off.array)
+                .add(index as usize) = index;
+        }
+
+        self.sq_atomic(self.params.sq_off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+
+        self.pending += 1;
+    }
+
+    fn submit_and_wait(&mut self) -> io::Result<()> {
+        loop {
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd() as libc::c_long,
+                    self.pending as libc::c_long,
+                    1 as libc::c_long,
+                    IORING_ENTER_GETEVENTS as libc::c_long,
+                    ptr::null::<libc::c_void>(),
+                    0 as libc::c_long,
+                )
+            };
+
+            if result >= 0 {
+                self.pending -= result as u32;
+                return Ok(());
+            }
+
+            let error = io::Error::last_os_error();
+
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<(u64, i32)> {
+        let head = self
+            .cq_atomic(self.params.cq_off.head)
+            .load(Ordering::Relaxed);
+        let tail = self
+            .cq_atomic(self.params.cq_off.tail)
+            .load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let mask = unsafe { *self.cq().at::<u32>(self.params.cq_off.ring_mask) };
+
+        let cqe = unsafe {
+            &*self
+                .cq()
+                .at::<Cqe>(self.params.cq_off.cqes)
+                .add((head & mask) as usize)
+        };
+
+        let completion = (cqe.user_data, cqe.res);
+
+        self.cq_atomic(self.params.cq_off.head)
+            .store(head.wrapping_add(1), Ordering::Release);
+
+        Some(completion)
+    }
+
+    // Reads every file from start to finish, handing each chunk read to
+    // consume in order, and an empty slice once the file has been read in
+    // full. One read per file is kept in flight, so chunks of the same file
+    // can't complete out of order, but reads for all of the files are queued
+    // at once, which is where the win over read(2) one file at a time is.
+    fn read_all<F>(&mut self, files: &[File], chunk_size: usize, mut consume: F) -> io::Result<()>
+    where
+        F: FnMut(usize, io::Result<&[u8]>),
+    {
+        let mut buffers: Vec<Vec<u8>> = files.iter().map(|_| vec![0; chunk_size]).collect();
+        let mut offsets: Vec<u64> = vec![0; files.len()];
+        let mut queue: Vec<usize> = (0..files.len()).rev().collect();
+        let mut in_flight: usize = 0;
+
+        while !queue.is_empty() || in_flight > 0 {
+            while in_flight < self.params.sq_entries as usize {
+                let Some(i) = queue.pop() else { break };
+                self.push_read(&files[i], &mut buffers[i], offsets[i], i as u64);
+                in_flight += 1;
+            }
+
+            if let Err(e) = self.submit_and_wait() {
+                // The kernel may still write into the buffers of reads that
+                // are in flight, so they're leaked rather than freed.
+                std::mem::forget(buffers);
+                return Err(e);
+            }
+
+            while let Some((user_data, result)) = self.pop() {
+                let i = user_data as usize;
+                in_flight -= 1;
+
+                match result {
+                    0 => consume(i, Ok(&[])),
+                    n if n < 0 => consume(i, Err(io::Error::from_raw_os_error(-n))),
+                    n => {
+                        consume(i, Ok(&buffers[i][..n as usize]));
+                        offsets[i] += n as u64;
+                        queue.push(i);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+thread_local! {
+    static RING: RefCell<Option<io::Result<Ring>>> = const { RefCell::new(None) };
+}
+
+// Every hashing thread sets up a ring of its own the first time it needs one.
+// That can fail, because the kernel is too old or io_uring is blocked by a
+// seccomp policy as is common in containers, and the error is handed back so
+// the caller can fall back to regular reads. A ring that fails partway
+// through is thrown away, along with whatever it still had in flight.
+pub fn read_all<F>(entries: u32, files: &[File], chunk_size: usize, consume: F) -> io::Result<()>
+where
+    F: FnMut(usize, io::Result<&[u8]>),
+{
+    RING.with(|slot| {
+        let mut slot = slot.borrow_mut();
+
+        let result = match slot.get_or_insert_with(|| Ring::new(entries)) {
+            Ok(ring) => ring.read_all(files, chunk_size, consume),
+            Err(e) => return Err(io::Error::new(e.kind(), e.to_string())),
+        };
+
+        if result.is_err() {
+            *slot = None;
+        }
+
+        result
+    })
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::hashutil::*;
+use crate::mutate;
+
+// For --verify-after, what's put somewhere by copying, moving or linking it is
+// hashed again from where it ended up, and has to come out the same as the
+// original did, since a copy that's never been read back is only assumed to
+// be one. How many didn't is counted, so each mode can fail over them at the
+// end, on top of every one being listed as an error.
+#[derive(Debug)]
+pub struct Verify {
+    algorithm: HashAlgorithm,
+    read: ReadOptions,
+    failed: AtomicUsize,
+}
+
+impl Verify {
+    pub fn new(algorithm: HashAlgorithm, read: ReadOptions) -> Self {
+        Self {
+            algorithm,
+            read,
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn hash(&self, path: &Path) -> io::Result<String> {
+        hash_file!(&self.algorithm, path, &self.read)
+    }
+
+    // With --dry-run, there's nothing where the result would have been.
+    pub fn check(&self, result: &Path, expected: &str) -> io::Result<()> {
+        if mutate::dry_run() {
+            return Ok(());
+        }
+
+        let actual = self.hash(result)?;
+
+        if actual == expected {
+            return Ok(());
+        }
+
+        self.failed.fetch_add(1, Ordering::Relaxed);
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "verification failed, {} hashed to {} rather than {}",
+                result.display(),
+                actual,
+                expected
+            ),
+        ))
+    }
+
+    pub fn failures(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,136 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+// Extended attributes of whatever's at a path, rather than what it points to,
+// since a symlink's own are as much a part of it as anything else's. Sizes are
+// asked for first, and asked for again if whatever it is grew in between.
+fn sized(mut call: impl FnMut(*mut libc::c_char, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = match call(std::ptr::null_mut(), 0) {
+            -1 => return Err(io::Error::last_os_error()),
+            size => size as usize,
+        };
+
+        let mut buffer = vec![0u8; size];
+
+        match call(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) {
+            -1 => match io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::ERANGE) => continue,
+                e => return Err(e),
+            },
+            read => {
+                buffer.truncate(read as usize);
+                return Ok(buffer);
+            }
+        }
+    }
+}
+
+// The names of all of them, in no particular order.
+pub fn list(path: &Path) -> io::Result<Vec<OsString>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+    let names = sized(|buffer, size| unsafe { listxattr(&c_path, buffer, size) })?;
+
+    Ok(names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| OsString::from_vec(name.to_vec()))
+        .collect())
+}
+
+// Or None if it doesn't have one by that name, which includes it being gone
+// since listing them.
+pub fn get(path: &Path, name: &OsStr) -> io::Result<Option<Vec<u8>>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let c_name = CString::new(name.as_bytes())?;
+
+    match sized(|buffer, size| unsafe { getxattr(&c_path, &c_name, buffer, size) }) {
+        Err(e) if e.raw_os_error() == Some(NO_ATTRIBUTE) => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+// Replacing whatever value it had before, if any.
+pub fn set(path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let c_name = CString::new(name.as_bytes())?;
+
+    match unsafe { setxattr(&c_path, &c_name, value) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+const NO_ATTRIBUTE: i32 = libc::ENODATA;
+
+#[cfg(target_os = "macos")]
+const NO_ATTRIBUTE: i32 = libc::ENOATTR;
+
+#[cfg(target_os = "linux")]
+unsafe fn listxattr(path: &CString, buffer: *mut libc::c_char, size: usize) -> isize {
+    libc::llistxattr(path.as_ptr(), buffer, size)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn listxattr(path: &CString, buffer: *mut libc::c_char, size: usize) -> isize {
+    libc::listxattr(path.as_ptr(), buffer, size, libc::XATTR_NOFOLLOW)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn getxattr(
+    path: &CString,
+    name: &CString,
+    buffer: *mut libc::c_char,
+    size: usize,
+) -> isize {
+    libc::lgetxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        buffer as *mut libc::c_void,
+        size,
+    )
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn getxattr(
+    path: &CString,
+    name: &CString,
+    buffer: *mut libc::c_char,
+    size: usize,
+) -> isize {
+    libc::getxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        buffer as *mut libc::c_void,
+        size,
+        0,
+        libc::XATTR_NOFOLLOW,
+    )
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn setxattr(path: &CString, name: &CString, value: &[u8]) -> i32 {
+    libc::lsetxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        0,
+    )
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn setxattr(path: &CString, name: &CString, value: &[u8]) -> i32 {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        0,
+        libc::XATTR_NOFOLLOW,
+    )
+}